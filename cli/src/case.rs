@@ -0,0 +1,102 @@
+use std::io::{self, Write};
+
+use beet_db::{CaseMismatch, Item, Sourced};
+use beet_query::Query;
+use serde_json::json;
+use structopt::StructOpt;
+
+use crate::library::{self, Source};
+use crate::output::{self, Format};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// Only check items matching a beets query.
+    #[structopt(short, long, parse(try_from_str = "library::parse_query"))]
+    query: Option<Query>,
+    /// Only compare the paths with each other, without reading the
+    /// filesystem.
+    #[structopt(long)]
+    no_disk: bool,
+    /// How to print the conflicts.
+    #[structopt(long, default_value = "text", raw(possible_values = "output::FORMATS"))]
+    format: Format,
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    let library = library::read(libraries, args.query.as_ref())?;
+    let conflicts = library.case_conflicts();
+    let mismatches = if args.no_disk {
+        vec![]
+    } else {
+        library.case_mismatches()
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match args.format {
+        Format::Text => write_text(&mut out, &conflicts, &mismatches)?,
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, &to_json(&conflicts, &mismatches))
+                .map_err(io::Error::from)?;
+            writeln!(out)?;
+        }
+    }
+    eprintln!(
+        "{} groups of paths differ only in case, and {} paths differ in case from the disk",
+        conflicts.len(),
+        mismatches.len()
+    );
+    Ok(())
+}
+
+/// Each group of conflicting items under a `conflict` line, then a line for
+/// each item with the path on disk after the path in the library.
+pub(crate) fn write_text(
+    out: &mut impl Write,
+    conflicts: &[Vec<&Sourced<Item>>],
+    mismatches: &[CaseMismatch<'_, Sourced<Item>>],
+) -> io::Result<()> {
+    for group in conflicts {
+        writeln!(out, "conflict")?;
+        for item in group {
+            writeln!(out, "  {}\t{}", item.global_id(), item.entry.path.display())?;
+        }
+    }
+    for mismatch in mismatches {
+        writeln!(
+            out,
+            "mismatch\t{}\t{}\t{}",
+            mismatch.entry.global_id(),
+            mismatch.entry.entry.path.display(),
+            mismatch.on_disk.display()
+        )?;
+    }
+    Ok(())
+}
+
+pub(crate) fn to_json(
+    conflicts: &[Vec<&Sourced<Item>>],
+    mismatches: &[CaseMismatch<'_, Sourced<Item>>],
+) -> serde_json::Value {
+    let conflicts: Vec<serde_json::Value> = conflicts
+        .iter()
+        .map(|group| {
+            group
+                .iter()
+                .map(|item| json!({ "id": item.global_id().to_string(), "path": item.entry.path }))
+                .collect()
+        })
+        .collect();
+    let mismatches: Vec<_> = mismatches
+        .iter()
+        .map(|mismatch| {
+            json!({
+                "id": mismatch.entry.global_id().to_string(),
+                "path": mismatch.entry.entry.path,
+                "on_disk": mismatch.on_disk,
+            })
+        })
+        .collect();
+    json!({ "conflicts": conflicts, "mismatches": mismatches })
+}
@@ -0,0 +1,59 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use beet_db::{copy_export, export_paths, CopyExportOptions, Item, PathTemplate};
+use beet_query::Query;
+use structopt::StructOpt;
+
+use crate::library::{self, Source};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// Where to copy the files, e.g. the mount point of a USB stick.
+    #[structopt(parse(from_os_str))]
+    destination: PathBuf,
+    /// Only copy items matching a beets query.
+    #[structopt(short, long, parse(try_from_str = "library::parse_query"))]
+    query: Option<Query>,
+    /// Where each item goes under the destination, with `$field` replaced by
+    /// its field, as in the beets `paths` setting.
+    #[structopt(long, raw(default_value = "beet_db::DEFAULT_PATH_TEMPLATE"))]
+    template: PathTemplate,
+    /// Spell names in ASCII alone, for players that cannot show anything else.
+    #[structopt(long)]
+    ascii: bool,
+    /// Print where each file would go, without copying anything.
+    #[structopt(long)]
+    dry_run: bool,
+}
+
+/// Each file and where it goes, separated by a tab.
+pub(crate) fn write_plan(out: &mut impl Write, paths: &[(&Item, PathBuf)]) -> io::Result<()> {
+    for (item, path) in paths {
+        writeln!(out, "{}\t{}", item.path.display(), path.display())?;
+    }
+    Ok(())
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    let library = library::read(libraries, args.query.as_ref())?;
+    let items = library.items.iter().map(|item| &item.entry);
+    let options = CopyExportOptions {
+        template: args.template.clone(),
+        ascii: args.ascii,
+    };
+
+    if args.dry_run {
+        let paths = export_paths(items, &options);
+        let stdout = io::stdout();
+        write_plan(&mut stdout.lock(), &paths)?;
+        return Ok(());
+    }
+    let report = copy_export(items, &args.destination, &options)?;
+    eprintln!(
+        "copied {} files, {} were already there",
+        report.copied, report.skipped
+    );
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use std::io::{self, Write};
+
+use beet_db::{Coverage, CoverageGroup, Item, Sourced};
+use beet_query::Query;
+use serde_json::json;
+use structopt::StructOpt;
+
+use crate::library::{self, Source};
+use crate::output::{self, Format};
+
+/// The names accepted by [`CoverageGroup`], for `possible_values`.
+const GROUPS: &[&str] = &["genre", "folder"];
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// Only check items matching a beets query, e.g. those of a set.
+    #[structopt(short, long, parse(try_from_str = "library::parse_query"))]
+    query: Option<Query>,
+    /// Group the items by genre or by the folder holding them.
+    #[structopt(long, default_value = "genre", raw(possible_values = "GROUPS"))]
+    by: CoverageGroup,
+    /// How to print the items still to analyze.
+    #[structopt(long, default_value = "text", raw(possible_values = "output::FORMATS"))]
+    format: Format,
+}
+
+/// What `item` is missing, e.g. `bpm,key`.
+fn missing(item: &Item) -> String {
+    let fields = [("bpm", item.missing_bpm()), ("key", item.missing_key())];
+    let missing: Vec<_> = fields
+        .iter()
+        .filter(|(_, missing)| *missing)
+        .map(|(field, _)| *field)
+        .collect();
+    missing.join(",")
+}
+
+pub(crate) fn write_text(
+    out: &mut impl Write,
+    groups: &[Coverage<'_, Sourced<Item>>],
+) -> io::Result<()> {
+    for group in groups {
+        let name = if group.group.is_empty() {
+            "(none)"
+        } else {
+            &group.group
+        };
+        writeln!(
+            out,
+            "{name}: {} of {} to analyze ({} without BPM, {} without key)",
+            group.missing.len(),
+            group.items,
+            group.missing_bpm,
+            group.missing_key
+        )?;
+        for item in &group.missing {
+            writeln!(
+                out,
+                "  {}\t{}\t{}",
+                item.global_id(),
+                missing(&item.entry),
+                item.entry.path.display()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Each group with the id, path and what is missing of its items, for
+/// scripts to queue for analysis.
+pub(crate) fn to_json(groups: &[Coverage<'_, Sourced<Item>>]) -> serde_json::Value {
+    groups
+        .iter()
+        .map(|group| {
+            let items: Vec<_> = group
+                .missing
+                .iter()
+                .map(|item| {
+                    json!({
+                        "id": item.global_id().to_string(),
+                        "path": item.entry.path,
+                        "missing_bpm": item.entry.missing_bpm(),
+                        "missing_key": item.entry.missing_key(),
+                    })
+                })
+                .collect();
+            json!({
+                "group": group.group,
+                "items": group.items,
+                "missing_bpm": group.missing_bpm,
+                "missing_key": group.missing_key,
+                "missing": items,
+            })
+        })
+        .collect()
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    let library = library::read(libraries, args.query.as_ref())?;
+    let groups = library.bpm_key_coverage(args.by);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match args.format {
+        Format::Text => write_text(&mut out, &groups)?,
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, &to_json(&groups)).map_err(io::Error::from)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
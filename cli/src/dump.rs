@@ -0,0 +1,59 @@
+use std::io::{self, BufWriter, Write};
+
+use beet_db::{Database, Library};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use structopt::StructOpt;
+
+use crate::library::{self, Source};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// Print the JSON on one line instead of indented.
+    #[structopt(short, long)]
+    compact: bool,
+}
+
+/// Each library by its tag.
+struct Tagged<'a>(&'a [(String, Library)]);
+
+impl Serialize for Tagged<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (tag, library) in self.0 {
+            map.serialize_entry(tag, library)?;
+        }
+        map.end()
+    }
+}
+
+/// Write a single library as it is, which `Library::from_json_bytes` reads
+/// back, or several by their tags.
+pub(crate) fn write_dump(
+    out: &mut impl Write,
+    libraries: &[(String, Library)],
+    compact: bool,
+) -> io::Result<()> {
+    match (libraries, compact) {
+        ([(_, library)], true) => serde_json::to_writer(&mut *out, library)?,
+        ([(_, library)], false) => serde_json::to_writer_pretty(&mut *out, library)?,
+        (libraries, true) => serde_json::to_writer(&mut *out, &Tagged(libraries))?,
+        (libraries, false) => serde_json::to_writer_pretty(&mut *out, &Tagged(libraries))?,
+    }
+    writeln!(out)
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    let libraries = library::resolve(libraries)?
+        .into_iter()
+        .map(|source| {
+            let (albums, items) = Database::open(&source.path)?.read_all()?;
+            Ok((source.tag, Library { albums, items }))
+        })
+        .collect::<crate::Result<Vec<_>>>()?;
+
+    let mut out = BufWriter::new(io::stdout());
+    write_dump(&mut out, &libraries, args.compact)?;
+    out.flush()?;
+    Ok(())
+}
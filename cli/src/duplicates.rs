@@ -0,0 +1,215 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use beet_db::{DuplicateGroup, DuplicatePlan, Item, KeepReason, MatchKind};
+use serde_json::json;
+use structopt::StructOpt;
+
+use crate::library::{self, Source};
+use crate::output::{self, shell_quote, single_line, Format};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// Only report copies held by different libraries.
+    #[structopt(long)]
+    across: bool,
+    /// How to print the groups.
+    #[structopt(long, default_value = "text", raw(possible_values = "output::FORMATS"))]
+    format: Format,
+    /// Print a shell script removing all but the best copy of each
+    /// recording, instead of listing them.
+    #[structopt(long)]
+    delete_plan: bool,
+    /// List which copy of each recording to keep and which to remove, and
+    /// why: lossless over lossy, then the better bit depth, sample rate and
+    /// bitrate, then the more complete tags.
+    #[structopt(long)]
+    plan: bool,
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    let library = library::open(libraries)?.read_all()?;
+    let groups = if args.across {
+        library.duplicates()
+    } else {
+        library.all_duplicates()
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if args.delete_plan {
+        write_delete_plan(&mut out, &groups)?;
+    } else if args.plan {
+        let plans: Vec<_> = groups.iter().map(DuplicateGroup::plan).collect();
+        match args.format {
+            Format::Text => write_plan_text(&mut out, &plans)?,
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut out, &plan_json(&plans))
+                    .map_err(io::Error::from)?;
+                writeln!(out)?;
+            }
+        }
+    } else {
+        match args.format {
+            Format::Text => write_text(&mut out, &groups)?,
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut out, &groups).map_err(io::Error::from)?;
+                writeln!(out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn match_name(kind: MatchKind) -> &'static str {
+    match kind {
+        MatchKind::MusicBrainz => "musicbrainz",
+        MatchKind::AcoustId => "acoustid",
+        MatchKind::Fuzzy => "fuzzy",
+    }
+}
+
+/// Why the kept copy beat another, as printed after it.
+fn reason_name(reason: KeepReason) -> &'static str {
+    match reason {
+        KeepReason::Lossless => "lossless",
+        KeepReason::Bitdepth => "higher bit depth",
+        KeepReason::Samplerate => "higher sample rate",
+        KeepReason::Bitrate => "higher bitrate",
+        KeepReason::Tags => "more complete tags",
+        KeepReason::Tie => "found first",
+    }
+}
+
+/// The "Artist - Title" a group is listed under.
+fn heading(matched_by: MatchKind, best: &Item) -> String {
+    format!(
+        "{}: {} - {}",
+        match_name(matched_by),
+        best.artist,
+        best.title
+    )
+}
+
+/// Format and bitrate, with bit depth and sample rate when known.
+fn quality(item: &Item) -> String {
+    let mut quality = format!("{} {}kbps", item.format, item.bitrate / 1000);
+    if item.bitdepth > 0 && item.samplerate > 0 {
+        let khz = f64::from(item.samplerate) / 1000.0;
+        let _ = write!(quality, " {}bit/{khz}kHz", item.bitdepth);
+    }
+    quality
+}
+
+pub(crate) fn write_text(out: &mut impl Write, groups: &[DuplicateGroup]) -> io::Result<()> {
+    for group in groups {
+        writeln!(out, "{}", heading(group.matched_by, &group.best().entry))?;
+        let ids: Vec<String> = group.ids().map(|id| id.to_string()).collect();
+        let width = ids.iter().map(String::len).max().unwrap_or(0);
+        for (index, (copy, id)) in group.copies.iter().zip(&ids).enumerate() {
+            writeln!(
+                out,
+                "  {} {:width$}  {}  {}",
+                if index == 0 { '*' } else { ' ' },
+                id,
+                quality(&copy.entry),
+                copy.entry.path.display(),
+                width = width
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Each plan with the copy it keeps, then those it removes and why the
+/// kept one is better.
+pub(crate) fn write_plan_text(out: &mut impl Write, plans: &[DuplicatePlan]) -> io::Result<()> {
+    for plan in plans {
+        writeln!(out, "{}", heading(plan.matched_by, &plan.keep.entry))?;
+        writeln!(
+            out,
+            "  keep    {}  {}  {}",
+            plan.keep.global_id(),
+            quality(&plan.keep.entry),
+            plan.keep.entry.path.display()
+        )?;
+        for removal in &plan.remove {
+            writeln!(
+                out,
+                "  remove  {}  {}  {}  (kept: {})",
+                removal.copy.global_id(),
+                quality(&removal.copy.entry),
+                removal.copy.entry.path.display(),
+                reason_name(removal.reason)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// The id and path of the copies each plan keeps and removes, for scripts
+/// to act on.
+pub(crate) fn plan_json(plans: &[DuplicatePlan]) -> serde_json::Value {
+    plans
+        .iter()
+        .map(|plan| {
+            let remove: Vec<_> = plan
+                .remove
+                .iter()
+                .map(|removal| {
+                    json!({
+                        "id": removal.copy.global_id().to_string(),
+                        "path": removal.copy.entry.path,
+                        "reason": removal.reason,
+                    })
+                })
+                .collect();
+            json!({
+                "matched_by": plan.matched_by,
+                "keep": {
+                    "id": plan.keep.global_id().to_string(),
+                    "path": plan.keep.entry.path,
+                },
+                "remove": remove,
+            })
+        })
+        .collect()
+}
+
+/// A script removing every copy but the best, leaving files that the best
+/// copy itself uses alone.
+pub(crate) fn write_delete_plan(out: &mut impl Write, groups: &[DuplicateGroup]) -> io::Result<()> {
+    writeln!(out, "#!/bin/sh")?;
+    writeln!(
+        out,
+        "# Removes all but the best copy of each duplicate recording."
+    )?;
+    writeln!(
+        out,
+        "# Review it before running, then run `beet update` on each library."
+    )?;
+    for plan in groups.iter().map(DuplicateGroup::plan) {
+        writeln!(out)?;
+        writeln!(
+            out,
+            "# {}",
+            single_line(&heading(plan.matched_by, &plan.keep.entry))
+        )?;
+        writeln!(
+            out,
+            "# keeping {} {}",
+            plan.keep.global_id(),
+            single_line(&shell_quote(&plan.keep.entry.path))
+        )?;
+        for removal in &plan.remove {
+            writeln!(
+                out,
+                "rm -- {}  # {}",
+                shell_quote(&removal.copy.entry.path),
+                single_line(&removal.copy.global_id().to_string())
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,556 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[cfg(feature = "parquet")]
+use beet_db::write_parquet;
+use beet_db::{
+    write_itunes_xml, write_rekordbox_xml, Album, Attribute, Database, FederatedLibrary,
+    Federation, FieldValue, GlobalId, Item, RowIter, Sourced,
+};
+use beet_query::Query;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use structopt::StructOpt;
+
+use crate::library::{self, Source};
+
+/// The names accepted by [`ExportFormat`], for `possible_values`.
+const EXPORT_FORMATS: &[&str] = &[
+    "json",
+    "ndjson",
+    "csv",
+    "bundle",
+    #[cfg(feature = "parquet")]
+    "parquet",
+    "itunes",
+    "rekordbox",
+];
+
+/// How `berts export` writes records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON array of records.
+    Json,
+    /// One JSON record per line.
+    Ndjson,
+    /// Comma-separated values with a header row.
+    Csv,
+    /// The complete albums and items as one JSON document, which
+    /// `FederatedLibrary` can be read back from.
+    Bundle,
+    /// A Parquet file of the complete records, with a typed column for
+    /// every field.
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// An iTunes `Library.xml` of the items, for software that imports
+    /// libraries from iTunes.
+    Itunes,
+    /// A Rekordbox XML collection of the items, keeping their BPM and key.
+    Rekordbox,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "ndjson" => Ok(Self::Ndjson),
+            "csv" => Ok(Self::Csv),
+            "bundle" => Ok(Self::Bundle),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(Self::Parquet),
+            "itunes" => Ok(Self::Itunes),
+            "rekordbox" => Ok(Self::Rekordbox),
+            _ => Err(format!("unknown export format `{s}`")),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// How to write the records.
+    #[structopt(long, default_value = "json", raw(possible_values = "EXPORT_FORMATS"))]
+    format: ExportFormat,
+    /// Only export items (or albums) matching a beets query.
+    #[structopt(short, long, parse(try_from_str = "library::parse_query"))]
+    query: Option<Query>,
+    /// Export albums instead of items.
+    #[structopt(short, long)]
+    album: bool,
+    /// The fields to export, separated by commas. `source` names the library
+    /// of each record. Defaults to every field.
+    #[structopt(short, long, raw(use_delimiter = "true"))]
+    fields: Vec<String>,
+    /// Add a column for each flexible attribute set on an exported record,
+    /// after the fields, in order of their names.
+    #[structopt(long)]
+    attributes: bool,
+    /// Write to a file instead of standard output.
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+/// A record with fields that can be looked up by name.
+pub(crate) trait Record {
+    const COLUMNS: &'static [&'static str];
+
+    fn id(&self) -> u32;
+
+    /// The records of a library, read a page at a time.
+    fn rows(db: &Database) -> RowIter<'_, Self>
+    where
+        Self: Sized;
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>>;
+}
+
+impl Record for Album {
+    const COLUMNS: &'static [&'static str] = Album::COLUMNS;
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn rows(db: &Database) -> RowIter<'_, Self> {
+        Album::iter(db.connection())
+    }
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        Album::field(self, name)
+    }
+}
+
+impl Record for Item {
+    const COLUMNS: &'static [&'static str] = Item::COLUMNS;
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn rows(db: &Database) -> RowIter<'_, Self> {
+        Item::iter(db.connection())
+    }
+
+    fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+        Item::field(self, name)
+    }
+}
+
+/// The value of `name` on a record, including the `source` pseudo-field.
+pub(crate) fn value<'a, T: Record>(record: &'a Sourced<T>, name: &str) -> FieldValue<'a> {
+    if name == "source" {
+        FieldValue::Text(record.source.as_str().into())
+    } else {
+        record.entry.field(name).unwrap_or(FieldValue::Null)
+    }
+}
+
+/// The flexible attributes of the exported records, each key written as a
+/// column after the fields.
+#[derive(Debug, Default)]
+pub(crate) struct Attributes {
+    /// Every key set on any of the records, in order.
+    keys: Vec<String>,
+    values: HashMap<GlobalId, HashMap<String, String>>,
+}
+
+impl Attributes {
+    /// The `attributes` of each record, by its id.
+    pub(crate) fn new(attributes: impl IntoIterator<Item = (GlobalId, Vec<Attribute>)>) -> Self {
+        let mut keys = BTreeSet::new();
+        let values = attributes
+            .into_iter()
+            .map(|(id, attributes)| {
+                let values = attributes
+                    .into_iter()
+                    .map(|attribute| {
+                        keys.insert(attribute.key.clone());
+                        (attribute.key, attribute.value)
+                    })
+                    .collect();
+                (id, values)
+            })
+            .collect();
+        Self {
+            keys: keys.into_iter().collect(),
+            values,
+        }
+    }
+
+    /// Those of `records`, read from their libraries in `federation`.
+    fn read<T: Record>(
+        federation: &Federation,
+        records: &[&Sourced<T>],
+        album: bool,
+    ) -> crate::Result<Self> {
+        let mut maps = HashMap::new();
+        for tag in federation.tags() {
+            if let Some(db) = federation.database(tag) {
+                let map = if album {
+                    db.album_attributes()?
+                } else {
+                    db.item_attributes()?
+                };
+                maps.insert(tag.clone(), map);
+            }
+        }
+        Ok(Self::new(records.iter().filter_map(|record| {
+            let id = record.entry.id();
+            let attributes = maps.get_mut(&record.source)?.remove(&id)?;
+            Some((GlobalId::new(record.source.clone(), id), attributes))
+        })))
+    }
+
+    fn value<T: Record>(&self, record: &Sourced<T>, key: &str) -> FieldValue<'_> {
+        let id = GlobalId::new(record.source.clone(), record.entry.id());
+        self.values
+            .get(&id)
+            .and_then(|values| values.get(key))
+            .map_or(FieldValue::Null, |value| {
+                FieldValue::Text(value.as_str().into())
+            })
+    }
+}
+
+/// The selected fields of a record, in the order selected, then its
+/// flexible attributes.
+struct Row<'a, T> {
+    record: &'a Sourced<T>,
+    fields: &'a [String],
+    attributes: &'a Attributes,
+}
+
+impl<T: Record> Row<'_, T> {
+    fn values(&self) -> impl Iterator<Item = (&str, FieldValue<'_>)> {
+        let fields = self
+            .fields
+            .iter()
+            .map(move |name| (name.as_str(), value(self.record, name)));
+        let attributes = self
+            .attributes
+            .keys
+            .iter()
+            .map(move |key| (key.as_str(), self.attributes.value(self.record, key)));
+        fields.chain(attributes)
+    }
+}
+
+impl<T: Record> Serialize for Row<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = self.fields.len() + self.attributes.keys.len();
+        let mut map = serializer.serialize_map(Some(len))?;
+        for (name, value) in self.values() {
+            map.serialize_entry(name, &value)?;
+        }
+        map.end()
+    }
+}
+
+/// The fields to export: the selected ones, checked to exist, or every one.
+pub(crate) fn select_fields<T: Record>(
+    selected: &[String],
+    sources: usize,
+) -> Result<Vec<String>, String> {
+    if selected.is_empty() {
+        let source = if sources > 1 { Some("source") } else { None };
+        return Ok(source
+            .into_iter()
+            .chain(T::COLUMNS.iter().copied())
+            .map(String::from)
+            .collect());
+    }
+    let unknown: Vec<&str> = selected
+        .iter()
+        .map(|name| name.trim())
+        .filter(|name| *name != "source" && !T::COLUMNS.contains(name))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!("unknown fields: {}", unknown.join(", ")));
+    }
+    Ok(selected
+        .iter()
+        .map(|name| name.trim().to_string())
+        .collect())
+}
+
+/// Quote `value` for CSV when it holds a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Write the `fields` of `records` as CSV, then a column for each flexible
+/// attribute, with a header row naming them.
+pub(crate) fn write_csv<T: Record>(
+    out: &mut impl Write,
+    records: &[&Sourced<T>],
+    fields: &[String],
+    attributes: &Attributes,
+) -> io::Result<()> {
+    let header: Vec<String> = fields
+        .iter()
+        .chain(&attributes.keys)
+        .map(|name| csv_field(name))
+        .collect();
+    writeln!(out, "{}", header.join(","))?;
+    for record in records {
+        let row = Row {
+            record,
+            fields,
+            attributes,
+        };
+        let values: Vec<String> = row
+            .values()
+            .map(|(_, value)| csv_field(&value.to_string()))
+            .collect();
+        writeln!(out, "{}", values.join(","))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn write_records<T: Record>(
+    out: &mut impl Write,
+    format: ExportFormat,
+    records: &[&Sourced<T>],
+    fields: &[String],
+    attributes: &Attributes,
+) -> io::Result<()> {
+    let rows = records.iter().map(|record| Row {
+        record,
+        fields,
+        attributes,
+    });
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, &rows.collect::<Vec<_>>())?;
+            writeln!(out)?;
+        }
+        ExportFormat::Ndjson => {
+            for row in rows {
+                serde_json::to_writer(&mut *out, &row)?;
+                writeln!(out)?;
+            }
+        }
+        ExportFormat::Csv => write_csv(out, records, fields, attributes)?,
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet => unreachable!("Parquet files export whole records"),
+        ExportFormat::Bundle | ExportFormat::Itunes | ExportFormat::Rekordbox => {
+            unreachable!("bundles, iTunes and Rekordbox files export whole records")
+        }
+    }
+    Ok(())
+}
+
+/// The matching entries, together with the albums of matching items or the
+/// items of matching albums.
+pub(crate) fn bundle(library: &FederatedLibrary, query: &Query, album: bool) -> FederatedLibrary {
+    let (albums, items): (Vec<_>, Vec<_>) = if album {
+        let albums: Vec<_> = library
+            .albums
+            .iter()
+            .filter(|album| query.match_album(&album.entry))
+            .collect();
+        let ids: HashSet<_> = albums.iter().map(|album| album.global_id()).collect();
+        let items = library
+            .items
+            .iter()
+            .filter(|item| item.album_global_id().is_some_and(|id| ids.contains(&id)))
+            .collect();
+        (albums, items)
+    } else {
+        let items: Vec<_> = library
+            .items
+            .iter()
+            .filter(|item| query.match_item(&item.entry))
+            .collect();
+        let ids: HashSet<_> = items
+            .iter()
+            .filter_map(|item| item.album_global_id())
+            .collect();
+        let albums = library
+            .albums
+            .iter()
+            .filter(|album| ids.contains(&album.global_id()))
+            .collect();
+        (albums, items)
+    };
+    FederatedLibrary {
+        albums: albums.into_iter().cloned().collect(),
+        items: items.into_iter().cloned().collect(),
+    }
+}
+
+/// Write the records of every library in `federation` that `matches`
+/// accepts as NDJSON, each as soon as it is read, so not even one library
+/// is held in memory at once.
+pub(crate) fn stream_ndjson<T: Record>(
+    out: &mut impl Write,
+    federation: &Federation,
+    matches: impl Fn(&T) -> bool,
+    fields: &[String],
+) -> crate::Result<()> {
+    let attributes = Attributes::default();
+    for tag in federation.tags() {
+        let Some(db) = federation.database(tag) else {
+            continue;
+        };
+        for entry in T::rows(db) {
+            let record = Sourced {
+                source: tag.clone(),
+                entry: entry?,
+            };
+            if !matches(&record.entry) {
+                continue;
+            }
+            let row = Row {
+                record: &record,
+                fields,
+                attributes: &attributes,
+            };
+            serde_json::to_writer(&mut *out, &row).map_err(io::Error::from)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// The flexible attributes of `records` if they are to be exported.
+fn read_attributes<T: Record>(
+    libraries: &[Source],
+    records: &[&Sourced<T>],
+    args: &Args,
+) -> crate::Result<Attributes> {
+    if !args.attributes {
+        return Ok(Attributes::default());
+    }
+    Attributes::read(&library::open(libraries)?, records, args.album)
+}
+
+/// Write the matching albums or items of the one library as Parquet.
+#[cfg(feature = "parquet")]
+fn export_parquet(
+    out: &mut (dyn Write + Send),
+    libraries: &[Source],
+    args: &Args,
+) -> crate::Result<()> {
+    let library = library::read(libraries, args.query.as_ref())?;
+    if args.album {
+        let albums: Vec<_> = library
+            .albums
+            .into_iter()
+            .map(|album| album.entry)
+            .collect();
+        write_parquet(out, &albums)?;
+    } else {
+        let items: Vec<_> = library.items.into_iter().map(|item| item.entry).collect();
+        write_parquet(out, &items)?;
+    }
+    Ok(())
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    // check the fields before creating the output file
+    let fields = match args.format {
+        ExportFormat::Bundle if !args.fields.is_empty() => {
+            return Err("bundles always hold every field, so `--fields` cannot be used".into());
+        }
+        ExportFormat::Bundle if args.attributes => {
+            return Err(
+                "bundles hold no flexible attributes, so `--attributes` cannot be used".into(),
+            );
+        }
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet if !args.fields.is_empty() || args.attributes => {
+            return Err(
+                "Parquet files hold every field and no flexible attributes, so \
+                `--fields` and `--attributes` cannot be used"
+                    .into(),
+            );
+        }
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet if args.output.is_none() => {
+            return Err("Parquet files are binary, so give an `--output` file to write".into());
+        }
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet if libraries.len() > 1 => {
+            return Err("Parquet files hold one library, so export each on its own".into());
+        }
+        ExportFormat::Itunes | ExportFormat::Rekordbox
+            if !args.fields.is_empty() || args.attributes || args.album =>
+        {
+            return Err(
+                "iTunes and Rekordbox libraries list every item as a track, so `--fields`, \
+                `--attributes` and `--album` cannot be used"
+                    .into(),
+            );
+        }
+        ExportFormat::Itunes | ExportFormat::Rekordbox if libraries.len() > 1 => {
+            return Err(
+                "iTunes and Rekordbox libraries number tracks by id, so export each on its own"
+                    .into(),
+            );
+        }
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet => vec![],
+        ExportFormat::Bundle | ExportFormat::Itunes | ExportFormat::Rekordbox => vec![],
+        _ if args.album => select_fields::<Album>(&args.fields, libraries.len())?,
+        _ => select_fields::<Item>(&args.fields, libraries.len())?,
+    };
+    let mut out: Box<dyn Write + Send> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+
+    #[cfg(feature = "parquet")]
+    if args.format == ExportFormat::Parquet {
+        export_parquet(&mut out, libraries, args)?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    if args.format == ExportFormat::Bundle {
+        let library = library::open(libraries)?.read_all()?;
+        let bundle = match &args.query {
+            Some(query) => bundle(&library, query, args.album),
+            None => library,
+        };
+        serde_json::to_writer(&mut out, &bundle).map_err(io::Error::from)?;
+        writeln!(out)?;
+    } else if args.format == ExportFormat::Itunes {
+        let library = library::read(libraries, args.query.as_ref())?;
+        write_itunes_xml(&mut out, library.items.iter().map(|item| &item.entry))?;
+    } else if args.format == ExportFormat::Rekordbox {
+        let library = library::read(libraries, args.query.as_ref())?;
+        write_rekordbox_xml(&mut out, library.items.iter().map(|item| &item.entry))?;
+    } else if args.format == ExportFormat::Ndjson && !args.attributes {
+        let federation = library::open(libraries)?;
+        let query = args.query.as_ref();
+        if args.album {
+            let matches = |album: &Album| query.is_none_or(|query| query.match_album(album));
+            stream_ndjson(&mut out, &federation, matches, &fields)?;
+        } else {
+            let matches = |item: &Item| query.is_none_or(|query| query.match_item(item));
+            stream_ndjson(&mut out, &federation, matches, &fields)?;
+        }
+    } else {
+        let library = library::read(libraries, args.query.as_ref())?;
+        if args.album {
+            let records: Vec<_> = library.albums.iter().collect();
+            let attributes = read_attributes(libraries, &records, args)?;
+            write_records(&mut out, args.format, &records, &fields, &attributes)?;
+        } else {
+            let records: Vec<_> = library.items.iter().collect();
+            let attributes = read_attributes(libraries, &records, args)?;
+            write_records(&mut out, args.format, &records, &fields, &attributes)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
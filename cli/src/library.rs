@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use beet_db::{play_counts, FederatedLibrary, Federation, GlobalId};
+use beet_query::Query;
+
+/// A library named on the command line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Source {
+    pub tag: String,
+    pub path: PathBuf,
+}
+
+impl Source {
+    /// Tag a library after its file name, e.g. `library` for `library.db`.
+    fn from_path(path: PathBuf) -> Self {
+        let tag = path
+            .file_stem()
+            .map_or_else(|| "library".into(), |stem| stem.to_string_lossy());
+        Self {
+            tag: tag.into_owned(),
+            path,
+        }
+    }
+
+    /// The `TAG=PATH` argument naming this library.
+    pub fn to_arg(&self) -> OsString {
+        let mut arg = OsString::from(format!("{}=", self.tag));
+        arg.push(&self.path);
+        arg
+    }
+}
+
+/// Parse `PATH` or `TAG=PATH`.
+pub fn parse_source(arg: &str) -> Result<Source, String> {
+    match arg.find('=') {
+        Some(0) => Err(format!("missing tag before `=` in `{arg}`")),
+        // an `=` after a path separator is part of the file name
+        Some(split) if !arg[..split].contains(['/', '\\']) => Ok(Source {
+            tag: arg[..split].to_string(),
+            path: arg[split + 1..].into(),
+        }),
+        _ => Ok(Source::from_path(arg.into())),
+    }
+}
+
+/// Where beets keeps its library unless configured otherwise.
+fn default_path() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("BEETSDIR") {
+        return Some(Path::new(&dir).join("library.db"));
+    }
+    let config = if cfg!(windows) {
+        PathBuf::from(env::var_os("APPDATA")?)
+    } else if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        PathBuf::from(dir)
+    } else {
+        Path::new(&env::var_os("HOME")?).join(".config")
+    };
+    Some(config.join("beets").join("library.db"))
+}
+
+/// Every library given, or the default one if there are none.
+pub fn resolve(sources: &[Source]) -> crate::Result<Vec<Source>> {
+    if sources.is_empty() {
+        let path = default_path().ok_or("no library given, and no beets config directory found")?;
+        return Ok(vec![Source::from_path(path)]);
+    }
+    for (index, source) in sources.iter().enumerate() {
+        if sources[..index].iter().any(|other| other.tag == source.tag) {
+            return Err(format!(
+                "library tag `{}` is used twice, name them with `TAG=PATH`",
+                source.tag
+            )
+            .into());
+        }
+    }
+    Ok(sources.to_vec())
+}
+
+/// Open every library given, or the default one if there are none.
+pub fn open(sources: &[Source]) -> crate::Result<Federation> {
+    let sources = resolve(sources)?;
+    let federation = Federation::open(
+        sources
+            .iter()
+            .map(|source| (source.tag.as_str(), &source.path)),
+    )?;
+    Ok(federation)
+}
+
+/// Parse a beets query.
+pub fn parse_query(arg: &str) -> Result<Query, String> {
+    arg.parse().map_err(|_| format!("invalid query `{arg}`"))
+}
+
+/// Read every library given, keeping only what matches `query`.
+pub fn read(sources: &[Source], query: Option<&Query>) -> crate::Result<FederatedLibrary> {
+    let mut library = open(sources)?.read_all()?;
+    retain(&mut library, query);
+    Ok(library)
+}
+
+/// Keep only what matches `query`, if there is one.
+pub fn retain(library: &mut FederatedLibrary, query: Option<&Query>) {
+    if let Some(query) = query {
+        library
+            .albums
+            .retain(|album| query.match_album(&album.entry));
+        library.items.retain(|item| query.match_item(&item.entry));
+    }
+}
+
+/// The `play_count` of each item of every library, by global id.
+pub fn global_play_counts(federation: &Federation) -> crate::Result<HashMap<GlobalId, u32>> {
+    let mut counts = HashMap::new();
+    for tag in federation.tags() {
+        let Some(db) = federation.database(tag) else {
+            continue;
+        };
+        let plays = play_counts(&db.item_attributes()?);
+        counts.extend(
+            plays
+                .into_iter()
+                .map(|(id, plays)| (GlobalId::new(tag.clone(), id), plays)),
+        );
+    }
+    Ok(counts)
+}
+
+/// The current time as a Unix timestamp, as beets stores them.
+pub fn now() -> crate::Result<f64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64())
+}
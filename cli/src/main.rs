@@ -0,0 +1,143 @@
+#![deny(clippy::pedantic)]
+
+use std::error::Error;
+use std::io;
+use std::process;
+
+use structopt::StructOpt;
+
+mod case;
+mod copy;
+mod coverage;
+mod dump;
+mod duplicates;
+mod export;
+mod library;
+mod missing;
+mod output;
+mod playlist;
+mod presets;
+mod query;
+mod random;
+mod repl;
+mod stats;
+mod sync;
+mod tests;
+mod watch;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "berts")]
+#[structopt(about = "tools for beets libraries")]
+#[structopt(raw(setting = "structopt::clap::AppSettings::ColoredHelp"))]
+#[structopt(rename_all = "kebab-case")]
+struct Cli {
+    /// A library to read, as `PATH` or `TAG=PATH`. Repeat to read several
+    /// at once. Defaults to the library in the beets config directory.
+    #[structopt(
+        short,
+        long = "library",
+        parse(try_from_str = "library::parse_source"),
+        raw(number_of_values = "1")
+    )]
+    libraries: Vec<library::Source>,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+enum Command {
+    /// List items whose paths differ only in case, which collide on
+    /// case-insensitive filesystems, and those spelled in another case on
+    /// disk.
+    #[structopt(name = "case")]
+    Case(case::Args),
+    /// Copy the files of items onto a USB stick or player, named by a path
+    /// template and made safe for FAT32 and exFAT.
+    #[structopt(name = "copy")]
+    Copy(copy::Args),
+    /// List items still missing a BPM or key, by genre or folder.
+    #[structopt(name = "coverage")]
+    Coverage(coverage::Args),
+    /// Print every album and item as JSON.
+    #[structopt(name = "dump")]
+    Dump(dump::Args),
+    /// Find copies of the same recording.
+    #[structopt(name = "duplicates")]
+    Duplicates(duplicates::Args),
+    /// Write items or albums out as JSON, NDJSON, CSV, a bundle, Parquet, or
+    /// an iTunes or Rekordbox library.
+    #[structopt(name = "export")]
+    Export(export::Args),
+    /// List items whose file no longer exists.
+    #[structopt(name = "missing")]
+    Missing(missing::Args),
+    /// Write the items matching a beets query as an M3U8 playlist.
+    #[structopt(name = "playlist")]
+    Playlist(playlist::Args),
+    /// List the preset playlists of the library, by decade, year added and
+    /// forgotten gems, for `berts playlist --preset`.
+    #[structopt(name = "presets")]
+    Presets(presets::Args),
+    /// List the items or albums matching a beets query, like `beet ls`.
+    #[structopt(name = "query")]
+    Query(query::Args),
+    /// Pick random items or albums, like the beets random plugin.
+    #[structopt(name = "random")]
+    Random(random::Args),
+    /// Explore the library with queries interactively.
+    #[structopt(name = "repl")]
+    Repl(repl::Args),
+    /// Summarize the library: counts, total length, genres, formats, years
+    /// and what was added last.
+    #[structopt(name = "stats")]
+    Stats(stats::Args),
+    /// Keep the items of a named profile on a player or USB stick, copying
+    /// new ones and deleting those no longer chosen.
+    #[structopt(name = "sync")]
+    Sync(sync::Args),
+    /// Rerun other subcommands whenever a library changes.
+    #[structopt(name = "watch")]
+    Watch(watch::Args),
+}
+
+fn main() {
+    let Cli { libraries, command } = Cli::from_args();
+
+    let result = match command {
+        Command::Case(args) => case::run(&libraries, &args),
+        Command::Copy(args) => copy::run(&libraries, &args),
+        Command::Coverage(args) => coverage::run(&libraries, &args),
+        Command::Dump(args) => dump::run(&libraries, &args),
+        Command::Duplicates(args) => duplicates::run(&libraries, &args),
+        Command::Export(args) => export::run(&libraries, &args),
+        Command::Missing(args) => missing::run(&libraries, &args),
+        Command::Playlist(args) => playlist::run(&libraries, &args),
+        Command::Presets(args) => presets::run(&libraries, &args),
+        Command::Query(args) => query::run(&libraries, &args),
+        Command::Random(args) => random::run(&libraries, &args),
+        Command::Repl(args) => repl::run(&libraries, &args),
+        Command::Stats(args) => stats::run(&libraries, &args),
+        Command::Sync(args) => sync::run(&libraries, &args),
+        Command::Watch(args) => watch::run(&libraries, &args),
+    };
+
+    if let Err(err) = result {
+        // e.g. piped into `head`, which is not worth reporting
+        if err
+            .downcast_ref::<io::Error>()
+            .is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe)
+        {
+            return;
+        }
+        eprintln!("berts: {err}");
+        let mut source = err.source();
+        while let Some(err) = source {
+            eprintln!("  caused by: {err}");
+            source = err.source();
+        }
+        process::exit(1);
+    }
+}
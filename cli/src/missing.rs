@@ -0,0 +1,67 @@
+use std::io::{self, Write};
+
+use beet_db::{Item, Sourced};
+use beet_query::Query;
+use serde_json::json;
+use structopt::StructOpt;
+
+use crate::library::{self, Source};
+use crate::output::{self, Format};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// Only check items matching a beets query.
+    #[structopt(short, long, parse(try_from_str = "library::parse_query"))]
+    query: Option<Query>,
+    /// How many files to check at once. More help on slow network storage.
+    #[structopt(long, default_value = "16")]
+    threads: usize,
+    /// How to print the missing items.
+    #[structopt(long, default_value = "text", raw(possible_values = "output::FORMATS"))]
+    format: Format,
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    let library = library::read(libraries, args.query.as_ref())?;
+    let missing = library.missing_items(args.threads);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match args.format {
+        Format::Text => write_text(&mut out, &missing)?,
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, &to_json(&missing)).map_err(io::Error::from)?;
+            writeln!(out)?;
+        }
+    }
+    eprintln!(
+        "{} of {} items are missing",
+        missing.len(),
+        library.items.len()
+    );
+    Ok(())
+}
+
+pub(crate) fn write_text(out: &mut impl Write, missing: &[&Sourced<Item>]) -> io::Result<()> {
+    for item in missing {
+        writeln!(out, "{}\t{}", item.global_id(), item.entry.path.display())?;
+    }
+    Ok(())
+}
+
+/// The id, path and names of each item, for scripts to act on.
+pub(crate) fn to_json(missing: &[&Sourced<Item>]) -> serde_json::Value {
+    missing
+        .iter()
+        .map(|item| {
+            json!({
+                "id": item.global_id().to_string(),
+                "path": item.entry.path,
+                "artist": item.entry.artist,
+                "album": item.entry.album,
+                "title": item.entry.title,
+            })
+        })
+        .collect()
+}
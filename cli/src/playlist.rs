@@ -0,0 +1,92 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use beet_db::{write_m3u, PlaylistOptions, PlaylistPreset};
+use beet_query::Query;
+use structopt::StructOpt;
+
+use crate::library::{self, Source};
+use crate::query;
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// Only list items matching a beets query. Its sort, if it has one,
+    /// orders the playlist, which is otherwise in album order.
+    #[structopt(short, long, parse(try_from_str = "library::parse_query"))]
+    query: Option<Query>,
+    /// Only list items in a preset playlist, e.g. `1990s`, `added-2021` or
+    /// `forgotten-gems`. `berts presets` lists those of the library.
+    #[structopt(long)]
+    preset: Option<PlaylistPreset>,
+    /// Write the playlist to a file, e.g. `playlist.m3u8`, instead of
+    /// standard output.
+    #[structopt(short, long, parse(from_os_str))]
+    out: Option<PathBuf>,
+    /// Write each path relative to the directory of the playlist (or the
+    /// current directory, when writing to standard output), for a playlist
+    /// kept beside the music it lists, e.g. on a USB stick.
+    #[structopt(short, long)]
+    relative: bool,
+    /// Replace the directory `FROM` at the start of each path by `TO`, e.g.
+    /// `/srv/music=/media/usb`, as a player sees the music. Repeat for
+    /// several.
+    #[structopt(long, parse(try_from_str = "parse_remap"), raw(number_of_values = "1"))]
+    remap: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Parse a `FROM=TO` pair of directories for `--remap`.
+pub(crate) fn parse_remap(arg: &str) -> Result<(PathBuf, PathBuf), String> {
+    match arg.split_once('=') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => Ok((from.into(), to.into())),
+        _ => Err(format!("invalid remapping `{arg}`, expected `FROM=TO`")),
+    }
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    let mut library = if let Some(preset) = args.preset {
+        let federation = library::open(libraries)?;
+        let plays = library::global_play_counts(&federation)?;
+        let mut library = federation.read_all()?;
+        let now = library::now()?;
+        library.items.retain(|item| {
+            let count = plays.get(&item.global_id()).copied().unwrap_or(0);
+            preset.matches(&item.entry, count, now)
+        });
+        library
+    } else {
+        library::open(libraries)?.read_all()?
+    };
+    library::retain(&mut library, args.query.as_ref());
+    let keys = args
+        .query
+        .as_ref()
+        .map(Query::sort_keys)
+        .unwrap_or_default();
+    let mut items: Vec<_> = library.items.iter().collect();
+    query::sort_listed(&mut items, &keys);
+
+    let relative_to = if args.relative {
+        let dir = match &args.out {
+            Some(path) => path.parent().unwrap_or_else(|| Path::new("")),
+            None => Path::new(""),
+        };
+        Some(env::current_dir()?.join(dir))
+    } else {
+        None
+    };
+    let options = PlaylistOptions {
+        remap: args.remap.clone(),
+        relative_to,
+        ..PlaylistOptions::default()
+    };
+    let mut out: Box<dyn Write> = match &args.out {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    write_m3u(&mut out, items.iter().map(|item| &item.entry), &options)?;
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use std::io::{self, Write};
+
+use beet_db::PlaylistPreset;
+use beet_query::Query;
+use serde_json::json;
+use structopt::StructOpt;
+
+use crate::library::{self, Source};
+use crate::output::{self, Format};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// Only count items matching a beets query.
+    #[structopt(short, long, parse(try_from_str = "library::parse_query"))]
+    query: Option<Query>,
+    /// How to print the presets.
+    #[structopt(long, default_value = "text", raw(possible_values = "output::FORMATS"))]
+    format: Format,
+}
+
+pub(crate) fn write_text(
+    out: &mut impl Write,
+    presets: &[(PlaylistPreset, usize)],
+) -> io::Result<()> {
+    for (preset, items) in presets {
+        writeln!(out, "{preset}\t{items}")?;
+    }
+    Ok(())
+}
+
+pub(crate) fn to_json(presets: &[(PlaylistPreset, usize)]) -> serde_json::Value {
+    presets
+        .iter()
+        .map(|(preset, items)| json!({ "preset": preset.to_string(), "items": items }))
+        .collect()
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    let federation = library::open(libraries)?;
+    let plays = library::global_play_counts(&federation)?;
+    let mut library = federation.read_all()?;
+    library::retain(&mut library, args.query.as_ref());
+    let presets = library.presets(&plays, library::now()?);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match args.format {
+        Format::Text => write_text(&mut out, &presets)?,
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, &to_json(&presets)).map_err(io::Error::from)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use std::cmp::Ordering;
+use std::io::{self, BufWriter, Write};
+
+use beet_db::{Album, FieldValue, Item, SortKey, Sourced};
+use structopt::StructOpt;
+
+use crate::export::{self, select_fields, Attributes, ExportFormat, Record};
+use crate::library::{self, Source};
+use crate::output::{self, Format};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// A beets query, e.g. `artist:lotus year:2010.. year-`. Lists
+    /// everything when left out.
+    query: Vec<String>,
+    /// List albums instead of items.
+    #[structopt(short, long)]
+    album: bool,
+    /// The fields to print, separated by commas. `source` names the library
+    /// of each record. Defaults to those `beet ls` prints.
+    #[structopt(short, long, raw(use_delimiter = "true"))]
+    fields: Vec<String>,
+    /// How to print the matches. As text, selected fields are separated by
+    /// tabs.
+    #[structopt(long, default_value = "text", raw(possible_values = "output::FORMATS"))]
+    format: Format,
+}
+
+/// `value` compared without case, as beets sorts by default.
+fn sort_value(value: FieldValue<'_>) -> FieldValue<'static> {
+    match value {
+        FieldValue::Text(text) => FieldValue::Text(text.to_lowercase().into()),
+        value => value.into_owned(),
+    }
+}
+
+/// Order `records` by each key in turn, keeping the order of ties.
+pub(crate) fn sort<T: Record>(records: &mut [&Sourced<T>], keys: &[SortKey]) {
+    records.sort_by(|a, b| {
+        keys.iter()
+            .map(|key| {
+                let order = sort_value(export::value(a, key.field))
+                    .cmp(&sort_value(export::value(b, key.field)));
+                if key.ascending {
+                    order
+                } else {
+                    order.reverse()
+                }
+            })
+            .find(|order| order.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+/// How `beet ls` lists a kind of record.
+pub(crate) trait Listed: Record {
+    /// The order records are listed in unless the query sorts them.
+    const ORDER: &'static [&'static str];
+    /// The fields printed unless others are selected.
+    const FIELDS: &'static [&'static str];
+}
+
+impl Listed for Item {
+    const ORDER: &'static [&'static str] = &["artist", "album", "disc", "track"];
+    const FIELDS: &'static [&'static str] = &["artist", "album", "title"];
+}
+
+impl Listed for Album {
+    const ORDER: &'static [&'static str] = &["albumartist", "album"];
+    const FIELDS: &'static [&'static str] = &["albumartist", "album"];
+}
+
+/// One line per record, of its `fields` separated by `separator`.
+pub(crate) fn write_text<T: Record>(
+    out: &mut impl Write,
+    records: &[&Sourced<T>],
+    fields: &[String],
+    separator: &str,
+) -> io::Result<()> {
+    for record in records {
+        let values: Vec<_> = fields
+            .iter()
+            .map(|name| match export::value(record, name) {
+                FieldValue::Null => String::new(),
+                // one record per line
+                value => output::single_line(&value.to_string()),
+            })
+            .collect();
+        writeln!(out, "{}", values.join(separator))?;
+    }
+    Ok(())
+}
+
+/// Order `records` by `keys`, or as `beet ls` lists them if there are none.
+pub(crate) fn sort_listed<T: Listed>(records: &mut [&Sourced<T>], keys: &[SortKey]) {
+    let order: Vec<_> = T::ORDER
+        .iter()
+        .map(|field| SortKey {
+            field,
+            ascending: true,
+        })
+        .collect();
+    sort(records, if keys.is_empty() { &order } else { keys });
+}
+
+fn list<T: Listed>(
+    out: &mut impl Write,
+    mut records: Vec<&Sourced<T>>,
+    keys: &[SortKey],
+    args: &Args,
+) -> crate::Result<()> {
+    let fields = if args.fields.is_empty() {
+        T::FIELDS.iter().copied().map(String::from).collect()
+    } else {
+        select_fields::<T>(&args.fields, 1)?
+    };
+    sort_listed(&mut records, keys);
+    match args.format {
+        // as `beet ls` prints them, or for scripts to split
+        Format::Text if args.fields.is_empty() => write_text(out, &records, &fields, " - ")?,
+        Format::Text => write_text(out, &records, &fields, "\t")?,
+        Format::Json => export::write_records(
+            out,
+            ExportFormat::Json,
+            &records,
+            &fields,
+            &Attributes::default(),
+        )?,
+    }
+    Ok(())
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    let query = library::parse_query(&args.query.join(" "))?;
+    let library = library::read(libraries, Some(&query))?;
+    let keys = query.sort_keys();
+
+    let mut out = BufWriter::new(io::stdout());
+    if args.album {
+        list(&mut out, library.albums.iter().collect(), &keys, args)?;
+    } else {
+        list(&mut out, library.items.iter().collect(), &keys, args)?;
+    }
+    out.flush()?;
+    Ok(())
+}
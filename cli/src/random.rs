@@ -0,0 +1,240 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use beet_db::{write_m3u, FederatedLibrary, Item, LibraryTag, PlaylistOptions, Sourced};
+use beet_query::Query;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+use structopt::StructOpt;
+
+use crate::library::{self, Source};
+use crate::output::{self, Format};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// How many items (or albums) to pick.
+    #[structopt(short, long, default_value = "1")]
+    number: usize,
+    /// Pick as many as fit in a time budget such as `60m` or `1h30m`, in
+    /// place of `--number`. A bare number counts minutes.
+    #[structopt(short, long, parse(try_from_str = "parse_duration"))]
+    time: Option<Duration>,
+    /// Only pick from items (or albums) matching a beets query.
+    #[structopt(short, long, parse(try_from_str = "library::parse_query"))]
+    query: Option<Query>,
+    /// Pick albums instead of items.
+    #[structopt(short, long)]
+    album: bool,
+    /// Give each artist an equal chance, however many entries they have.
+    #[structopt(short, long)]
+    equal_chance: bool,
+    /// Seed the random choice, to repeat an earlier pick.
+    #[structopt(long)]
+    seed: Option<u64>,
+    /// How to print the pick.
+    #[structopt(long, default_value = "text", raw(possible_values = "output::FORMATS"))]
+    format: Format,
+    /// Also save the picked items as an M3U playlist.
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+/// Parse a duration like `90m`, `1h30m` or `45s`, or a number of minutes.
+pub fn parse_duration(arg: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration `{arg}`, expected e.g. `60m` or `1h30m`");
+    if let Ok(minutes) = arg.parse::<u64>() {
+        return Ok(Duration::from_secs(minutes * 60));
+    }
+    if arg.is_empty() {
+        return Err(invalid());
+    }
+    let mut seconds = 0;
+    let mut rest = arg;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let value: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        let mut units = rest[digits..].chars();
+        let unit = match units.next() {
+            Some('h') => 3600,
+            Some('m') => 60,
+            Some('s') => 1,
+            _ => return Err(invalid()),
+        };
+        seconds += value * unit;
+        rest = units.as_str();
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Something to pick: an item, or an album with all of its items.
+pub(crate) struct Entry<'a> {
+    pub artist: &'a str,
+    pub label: String,
+    /// In seconds.
+    pub length: f64,
+    pub items: Vec<&'a Sourced<Item>>,
+}
+
+fn item_entries<'a>(library: &'a FederatedLibrary, query: Option<&Query>) -> Vec<Entry<'a>> {
+    library
+        .items
+        .iter()
+        .filter(|item| query.is_none_or(|query| query.match_item(&item.entry)))
+        .map(|item| Entry {
+            artist: &item.entry.artist,
+            label: format!(
+                "{} - {} - {}",
+                item.entry.artist, item.entry.album, item.entry.title
+            ),
+            length: item.entry.length,
+            items: vec![item],
+        })
+        .collect()
+}
+
+fn album_entries<'a>(library: &'a FederatedLibrary, query: Option<&Query>) -> Vec<Entry<'a>> {
+    let mut tracks: HashMap<(&LibraryTag, u32), Vec<&Sourced<Item>>> = HashMap::new();
+    for item in &library.items {
+        if let Some(album_id) = item.entry.album_id {
+            tracks
+                .entry((&item.source, album_id))
+                .or_default()
+                .push(item);
+        }
+    }
+    library
+        .albums
+        .iter()
+        .filter(|album| query.is_none_or(|query| query.match_album(&album.entry)))
+        .map(|album| {
+            let mut items = tracks
+                .remove(&(&album.source, album.entry.id))
+                .unwrap_or_default();
+            items.sort_by_key(|item| (item.entry.disc, item.entry.track));
+            Entry {
+                artist: &album.entry.albumartist,
+                label: format!("{} - {}", album.entry.albumartist, album.entry.album),
+                length: items.iter().map(|item| item.entry.length).sum(),
+                items,
+            }
+        })
+        .collect()
+}
+
+/// Shuffle `entries`, or with `equal_chance` repeatedly pick a random artist
+/// and then one of their entries, like the beets random plugin.
+pub(crate) fn shuffle<'a>(
+    entries: Vec<Entry<'a>>,
+    equal_chance: bool,
+    rng: &mut impl Rng,
+) -> Vec<Entry<'a>> {
+    if !equal_chance {
+        let mut entries = entries;
+        entries.shuffle(rng);
+        return entries;
+    }
+    let mut by_artist: BTreeMap<&str, Vec<Entry>> = BTreeMap::new();
+    for entry in entries {
+        by_artist.entry(entry.artist).or_default().push(entry);
+    }
+    let mut groups: Vec<Vec<Entry>> = by_artist.into_values().collect();
+    for group in &mut groups {
+        group.shuffle(rng);
+    }
+    let mut shuffled = vec![];
+    while !groups.is_empty() {
+        let index = rng.gen_range(0, groups.len());
+        shuffled.extend(groups[index].pop());
+        if groups[index].is_empty() {
+            groups.swap_remove(index);
+        }
+    }
+    shuffled
+}
+
+/// The first `number` entries, or with a `time` budget every entry that
+/// still fits in it, skipping those that would overrun it.
+pub(crate) fn take(entries: Vec<Entry>, number: usize, time: Option<Duration>) -> Vec<Entry> {
+    let Some(time) = time else {
+        return entries.into_iter().take(number).collect();
+    };
+    let budget = time.as_secs_f64();
+    let mut total = 0.0;
+    entries
+        .into_iter()
+        .filter(|entry| {
+            let fits = total + entry.length <= budget;
+            if fits {
+                total += entry.length;
+            }
+            fits
+        })
+        .collect()
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    let library = library::open(libraries)?.read_all()?;
+    let query = args.query.as_ref();
+    let entries = if args.album {
+        album_entries(&library, query)
+    } else {
+        item_entries(&library, query)
+    };
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let picked = take(
+        shuffle(entries, args.equal_chance, &mut rng),
+        args.number,
+        args.time,
+    );
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match args.format {
+        Format::Text => {
+            for entry in &picked {
+                writeln!(out, "{}", entry.label)?;
+            }
+        }
+        Format::Json => {
+            let json: Vec<_> = picked
+                .iter()
+                .map(|entry| {
+                    let ids: Vec<String> = entry
+                        .items
+                        .iter()
+                        .map(|item| item.global_id().to_string())
+                        .collect();
+                    json!({
+                        "label": entry.label,
+                        "length": entry.length,
+                        "items": ids,
+                    })
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut out, &json).map_err(io::Error::from)?;
+            writeln!(out)?;
+        }
+    }
+    if let Some(path) = &args.output {
+        let mut playlist = BufWriter::new(File::create(path)?);
+        let items = picked.iter().flat_map(|entry| &entry.items);
+        write_m3u(
+            &mut playlist,
+            items.map(|item| &item.entry),
+            &PlaylistOptions::default(),
+        )?;
+        playlist.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,368 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use beet_db::{Album, FederatedLibrary, FieldValue, Item, Sourced};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hint, Hinter};
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use structopt::StructOpt;
+
+use crate::export::{self, Attributes, ExportFormat};
+use crate::library::{self, Source};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// How many results to print after each query.
+    #[structopt(long, default_value = "20")]
+    limit: usize,
+    /// Start out querying albums instead of items.
+    #[structopt(short, long)]
+    album: bool,
+}
+
+const HELP: &str = "\
+Type a beets query to list what matches it, e.g. `artist:lotus year:2010`.
+Press Tab to complete field names and values; values complete a word at a
+time, since query terms are split at spaces.
+
+  :items, :albums                query items or albums
+  :limit N                       print at most N results
+  :export FORMAT FILE [FIELDS]   save the last results as json, ndjson or
+                                 csv, optionally only the comma-separated
+                                 FIELDS
+  :help                          show this help
+  :quit                          leave, as does Ctrl-D";
+
+/// A live count of what the line typed so far matches.
+pub(crate) struct Preview(String);
+
+impl Hint for Preview {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+pub(crate) struct ReplHelper<'a> {
+    library: &'a FederatedLibrary,
+    pub album: bool,
+    /// The distinct words of each field looked up so far, by mode.
+    words: RefCell<HashMap<(bool, String), Vec<String>>>,
+}
+
+impl<'a> ReplHelper<'a> {
+    pub fn new(library: &'a FederatedLibrary, album: bool) -> Self {
+        Self {
+            library,
+            album,
+            words: RefCell::default(),
+        }
+    }
+
+    fn columns(&self) -> &'static [&'static str] {
+        if self.album {
+            Album::COLUMNS
+        } else {
+            Item::COLUMNS
+        }
+    }
+
+    /// The first word of each distinct value of `field`, in sorted order.
+    fn words(&self, field: &str) -> Vec<String> {
+        let key = (self.album, field.to_string());
+        if let Some(words) = self.words.borrow().get(&key) {
+            return words.clone();
+        }
+        let values = if self.album {
+            self.library.distinct_album_values(field)
+        } else {
+            self.library.distinct_item_values(field)
+        };
+        let words: BTreeSet<String> = values
+            .unwrap_or_default()
+            .iter()
+            .filter(|value| !matches!(value, FieldValue::Null))
+            .filter_map(|value| {
+                value
+                    .to_string()
+                    .split_whitespace()
+                    .next()
+                    .map(String::from)
+            })
+            .collect();
+        let words: Vec<String> = words.into_iter().collect();
+        self.words.borrow_mut().insert(key, words.clone());
+        words
+    }
+
+    /// Completions for the query term before `pos`: field names, or the
+    /// values of a field after its `:`.
+    pub fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<Pair>) {
+        let start = line[..pos].rfind(' ').map_or(0, |space| space + 1);
+        let word = &line[start..pos];
+        let term = word.trim_start_matches(['^', '-']);
+        let start = start + (word.len() - term.len());
+
+        let candidates = match term.split_once(':') {
+            Some((field, prefix)) => {
+                let prefix = prefix.to_lowercase();
+                self.words(field)
+                    .into_iter()
+                    .filter(|word| word.to_lowercase().starts_with(&prefix))
+                    .map(|word| Pair {
+                        replacement: format!("{field}:{word}"),
+                        display: word,
+                    })
+                    .collect()
+            }
+            None => self
+                .columns()
+                .iter()
+                .filter(|column| column.starts_with(term))
+                .map(|column| Pair {
+                    display: (*column).to_string(),
+                    replacement: format!("{column}:"),
+                })
+                .collect(),
+        };
+        (start, candidates)
+    }
+
+    /// How many entries the query `line` matches, e.g. `  (12 items)`.
+    pub fn preview(&self, line: &str) -> Option<String> {
+        if line.trim().is_empty() || line.starts_with(':') {
+            return None;
+        }
+        let query = library::parse_query(line.trim()).ok()?;
+        let (count, noun) = if self.album {
+            let count = self
+                .library
+                .albums
+                .iter()
+                .filter(|album| query.match_album(&album.entry))
+                .count();
+            (count, "albums")
+        } else {
+            let count = self
+                .library
+                .items
+                .iter()
+                .filter(|item| query.match_item(&item.entry))
+                .count();
+            (count, "items")
+        };
+        Some(format!("  ({count} {noun})"))
+    }
+}
+
+impl Completer for ReplHelper<'_> {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        Ok(self.candidates(line, pos))
+    }
+}
+
+impl Hinter for ReplHelper<'_> {
+    type Hint = Preview;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<Preview> {
+        // only while typing at the end of the line
+        if pos < line.len() {
+            return None;
+        }
+        self.preview(line).map(Preview)
+    }
+}
+
+impl Highlighter for ReplHelper<'_> {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        // dimmed
+        Cow::Owned(format!("\x1b[2m{hint}\x1b[0m"))
+    }
+}
+
+impl Validator for ReplHelper<'_> {}
+
+impl Helper for ReplHelper<'_> {}
+
+/// The entries matched by the last query.
+enum Results<'a> {
+    Albums(Vec<&'a Sourced<Album>>),
+    Items(Vec<&'a Sourced<Item>>),
+}
+
+impl Results<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Self::Albums(albums) => albums.len(),
+            Self::Items(items) => items.len(),
+        }
+    }
+}
+
+fn run_query<'a>(
+    library: &'a FederatedLibrary,
+    line: &str,
+    album: bool,
+) -> Result<Results<'a>, String> {
+    let query = library::parse_query(line)?;
+    Ok(if album {
+        Results::Albums(
+            library
+                .albums
+                .iter()
+                .filter(|album| query.match_album(&album.entry))
+                .collect(),
+        )
+    } else {
+        Results::Items(
+            library
+                .items
+                .iter()
+                .filter(|item| query.match_item(&item.entry))
+                .collect(),
+        )
+    })
+}
+
+fn print_results(results: &Results, limit: usize) {
+    let labels: Vec<String> = match results {
+        Results::Albums(albums) => albums
+            .iter()
+            .take(limit)
+            .map(|album| format!("{} - {}", album.entry.albumartist, album.entry.album))
+            .collect(),
+        Results::Items(items) => items
+            .iter()
+            .take(limit)
+            .map(|item| {
+                let item = &item.entry;
+                format!("{} - {} - {}", item.artist, item.album, item.title)
+            })
+            .collect(),
+    };
+    for label in &labels {
+        println!("{label}");
+    }
+    let noun = match results {
+        Results::Albums(_) => "albums",
+        Results::Items(_) => "items",
+    };
+    if results.len() > labels.len() {
+        println!("... {} {noun} in all", results.len());
+    } else {
+        println!("{} {noun}", results.len());
+    }
+}
+
+/// Run `:export FORMAT FILE [FIELDS]` on the last results.
+fn export(arguments: &str, results: &Results, sources: usize) -> Result<(), String> {
+    let mut arguments = arguments.split_whitespace();
+    let (Some(format), Some(path)) = (arguments.next(), arguments.next()) else {
+        return Err("usage: :export FORMAT FILE [FIELDS]".to_string());
+    };
+    let format: ExportFormat = format.parse()?;
+    if format == ExportFormat::Bundle {
+        return Err("bundles are only exported by `berts export`".to_string());
+    }
+    let fields: Vec<String> = arguments
+        .next()
+        .map(|fields| fields.split(',').map(String::from).collect())
+        .unwrap_or_default();
+
+    let fail = |err: std::io::Error| format!("cannot write {path}: {err}");
+    let write = |out: &mut BufWriter<File>| match results {
+        Results::Albums(albums) => {
+            let fields = export::select_fields::<Album>(&fields, sources)?;
+            export::write_records(out, format, albums, &fields, &Attributes::default())
+                .map_err(fail)
+        }
+        Results::Items(items) => {
+            let fields = export::select_fields::<Item>(&fields, sources)?;
+            export::write_records(out, format, items, &fields, &Attributes::default()).map_err(fail)
+        }
+    };
+    let mut out = BufWriter::new(File::create(path).map_err(fail)?);
+    write(&mut out)?;
+    out.flush().map_err(fail)?;
+    println!("saved {} entries to {path}", results.len());
+    Ok(())
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    let library = library::open(libraries)?.read_all()?;
+    println!(
+        "Read {} albums and {} items. Type :help for help.",
+        library.albums.len(),
+        library.items.len()
+    );
+
+    let mut editor = Editor::<ReplHelper>::new();
+    editor.set_helper(Some(ReplHelper::new(&library, args.album)));
+    let mut album = args.album;
+    let mut limit = args.limit;
+    let mut results = Results::Items(vec![]);
+    loop {
+        let prompt = if album { "albums> " } else { "items> " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line);
+
+        let Some(command) = line.strip_prefix(':') else {
+            match run_query(&library, line, album) {
+                Ok(matched) => {
+                    results = matched;
+                    print_results(&results, limit);
+                }
+                Err(err) => eprintln!("{err}"),
+            }
+            continue;
+        };
+        let (name, arguments) = command.split_once(' ').unwrap_or((command, ""));
+        match name {
+            "q" | "quit" | "exit" => break,
+            "h" | "help" => println!("{HELP}"),
+            "items" | "albums" => {
+                album = name == "albums";
+                if let Some(helper) = editor.helper_mut() {
+                    helper.album = album;
+                }
+            }
+            "limit" => match arguments.trim().parse() {
+                Ok(new) => limit = new,
+                Err(_) => eprintln!("usage: :limit N"),
+            },
+            "export" => {
+                if let Err(err) = export(arguments, &results, libraries.len()) {
+                    eprintln!("{err}");
+                }
+            }
+            _ => eprintln!("unknown command `:{name}`, type :help for help"),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use beet_db::LibraryStats;
+use beet_query::Query;
+use structopt::StructOpt;
+
+use crate::library::{self, Source};
+use crate::output::{self, Format};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// Only count items and albums matching a beets query.
+    #[structopt(short, long, parse(try_from_str = "library::parse_query"))]
+    query: Option<Query>,
+    /// How many genres and formats to list, most common first.
+    #[structopt(long, default_value = "10")]
+    top: usize,
+    /// How to print the summary.
+    #[structopt(long, default_value = "text", raw(possible_values = "output::FORMATS"))]
+    format: Format,
+}
+
+/// A length of time such as `2d 3h 4m`, to the minute.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn format_length(seconds: f64) -> String {
+    let minutes = (seconds / 60.0).round().max(0.0) as u64;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    match (days, hours) {
+        (0, 0) => format!("{minutes}m"),
+        (0, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h {minutes}m"),
+    }
+}
+
+fn write_counts<K: Display>(
+    out: &mut impl Write,
+    heading: &str,
+    counts: impl IntoIterator<Item = (K, u32)>,
+) -> io::Result<()> {
+    writeln!(out, "{heading}")?;
+    for (key, count) in counts {
+        writeln!(out, "  {count:>7}  {key}")?;
+    }
+    Ok(())
+}
+
+/// The summary as text, with the `top` genres and formats, and how long ago
+/// albums were added as of `now`, a Unix timestamp.
+pub(crate) fn write_text(
+    out: &mut impl Write,
+    stats: &LibraryStats,
+    top: usize,
+    now: f64,
+) -> io::Result<()> {
+    writeln!(out, "Items:   {}", stats.items)?;
+    writeln!(out, "Albums:  {}", stats.albums)?;
+    writeln!(out, "Length:  {}", format_length(stats.length))?;
+    let genres = LibraryStats::ranked(&stats.genres).into_iter().take(top);
+    write_counts(out, "Genres:", genres)?;
+    let formats = LibraryStats::ranked(&stats.formats).into_iter().take(top);
+    write_counts(out, "Formats:", formats)?;
+    write_counts(
+        out,
+        "Years:",
+        stats.years.iter().map(|(year, count)| (year, *count)),
+    )?;
+    writeln!(out, "Recently added:")?;
+    for album in &stats.recently_added {
+        writeln!(
+            out,
+            "  {} - {} ({} ago)",
+            album.albumartist,
+            album.album,
+            format_length(now - album.added)
+        )?;
+    }
+    Ok(())
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    let library = library::read(libraries, args.query.as_ref())?;
+    let stats = library.stats();
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match args.format {
+        Format::Text => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0.0, |since| since.as_secs_f64());
+            write_text(&mut out, &stats, args.top, now)?;
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, &stats).map_err(io::Error::from)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
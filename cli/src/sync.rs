@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use beet_db::{
+    Attribute, Database, PathTemplate, SyncExclusion, SyncPlan, SyncPriority, SyncProfile,
+};
+use structopt::StructOpt;
+
+use crate::library::{self, Source};
+
+const PRIORITIES: &[&str] = &["order", "recent", "rating", "plays"];
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub enum Args {
+    /// List the saved profiles.
+    #[structopt(name = "list")]
+    List,
+    /// Save a profile, replacing any other of the same name.
+    #[structopt(name = "save")]
+    Save(SaveArgs),
+    /// Forget a profile, leaving the files it synced.
+    #[structopt(name = "delete")]
+    Delete { name: String },
+    /// Copy the items of a profile that are not at its destination yet, and
+    /// delete those it synced before that it no longer chooses.
+    #[structopt(name = "run")]
+    Run {
+        name: String,
+        /// Print what would be copied and deleted, without changing anything.
+        #[structopt(long)]
+        dry_run: bool,
+        /// Sync to a destination never synced to before. Without this, one
+        /// with no manifest from an earlier sync is refused, as it may be
+        /// the mount point of a player that is not mounted.
+        #[structopt(long)]
+        init: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct SaveArgs {
+    name: String,
+    /// Where the player or stick is mounted.
+    #[structopt(parse(from_os_str))]
+    destination: PathBuf,
+    /// Only sync items matching a beets query.
+    #[structopt(short, long, default_value = "")]
+    query: String,
+    /// A format the player can play, as beets names it, e.g. `MP3`. Repeat
+    /// for several; any format if none are given.
+    #[structopt(long = "format", raw(number_of_values = "1"))]
+    formats: Vec<String>,
+    /// The most to keep at the destination, in bytes or with a `K`, `M`, `G`
+    /// or `T` suffix, e.g. `16G`.
+    #[structopt(long, parse(try_from_str = "parse_size"))]
+    size_cap: Option<u64>,
+    /// Which items to keep first when they do not all fit under the size
+    /// cap: in the order of the query, the most recently added, the highest
+    /// `rating` or the most `play_count`.
+    #[structopt(long, default_value = "order", raw(possible_values = "PRIORITIES"))]
+    priority: SyncPriority,
+    /// Where each item goes under the destination, as for `berts copy`.
+    #[structopt(long, raw(default_value = "beet_db::DEFAULT_PATH_TEMPLATE"))]
+    template: PathTemplate,
+    /// Spell names in ASCII alone.
+    #[structopt(long)]
+    ascii: bool,
+}
+
+/// Parse a size in bytes, with an optional decimal suffix as storage is
+/// sold, e.g. `16G` for 16 billion bytes.
+pub(crate) fn parse_size(arg: &str) -> Result<u64, String> {
+    let (digits, scale) = match arg.char_indices().last() {
+        Some((index, suffix)) if suffix.is_ascii_alphabetic() => {
+            let scale = match suffix.to_ascii_uppercase() {
+                'K' => 1_000,
+                'M' => 1_000_000,
+                'G' => 1_000_000_000,
+                'T' => 1_000_000_000_000,
+                _ => return Err(format!("unknown size suffix in `{arg}`")),
+            };
+            (&arg[..index], scale)
+        }
+        _ => (arg, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|size| size.checked_mul(scale))
+        .ok_or_else(|| format!("invalid size `{arg}`"))
+}
+
+/// A line for each profile: its name, destination, query, formats, size
+/// cap and priority, separated by tabs.
+pub(crate) fn write_profiles(out: &mut impl Write, profiles: &[SyncProfile]) -> io::Result<()> {
+    for profile in profiles {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            profile.name,
+            profile.destination.display(),
+            profile.query,
+            profile.formats.join(","),
+            profile
+                .size_cap
+                .map_or_else(String::new, |cap| cap.to_string()),
+            profile.priority
+        )?;
+    }
+    Ok(())
+}
+
+/// A line for each change: `copy` with the file and where it goes, `delete`
+/// with the file, or `exclude` with why and the file, separated by tabs.
+/// Items left out for the size cap also give their size and the bytes that
+/// were free.
+pub(crate) fn write_plan(out: &mut impl Write, plan: &SyncPlan<'_>) -> io::Result<()> {
+    for (item, path) in &plan.copy {
+        writeln!(out, "copy\t{}\t{}", item.path.display(), path.display())?;
+    }
+    for path in &plan.delete {
+        writeln!(out, "delete\t{}", path.display())?;
+    }
+    for (item, why) in &plan.excluded {
+        write!(out, "exclude\t{why}\t{}", item.path.display())?;
+        if let SyncExclusion::SizeCap { size, free } = why {
+            write!(out, "\t{size}\t{free}")?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    // profiles are kept in the first library
+    let sources = library::resolve(libraries)?;
+    let path = &sources[0].path;
+    match args {
+        Args::List => {
+            let profiles = SyncProfile::list(&Database::open(path)?)?;
+            let stdout = io::stdout();
+            write_profiles(&mut stdout.lock(), &profiles)?;
+        }
+        Args::Save(args) => {
+            if !args.query.is_empty() {
+                library::parse_query(&args.query)?;
+            }
+            let profile = SyncProfile {
+                name: args.name.clone(),
+                query: args.query.clone(),
+                destination: args.destination.clone(),
+                formats: args.formats.clone(),
+                size_cap: args.size_cap,
+                priority: args.priority,
+                template: args.template.clone(),
+                ascii: args.ascii,
+            };
+            profile.save(&Database::open_writable(path)?)?;
+        }
+        Args::Delete { name } => {
+            if !SyncProfile::delete(&Database::open_writable(path)?, name)? {
+                return Err(format!("no sync profile named `{name}`").into());
+            }
+        }
+        Args::Run {
+            name,
+            dry_run,
+            init,
+        } => {
+            let profile = SyncProfile::load(&Database::open(path)?, name)?
+                .ok_or_else(|| format!("no sync profile named `{name}`"))?;
+            if *init {
+                profile.init()?;
+            }
+            let query = if profile.query.is_empty() {
+                None
+            } else {
+                Some(library::parse_query(&profile.query)?)
+            };
+            let federation = library::open(libraries)?;
+            let mut library = federation.read_all()?;
+            library::retain(&mut library, query.as_ref());
+            // only read for the priorities that rank by them
+            let mut attributes = HashMap::new();
+            if profile.priority.needs_attributes() {
+                for tag in federation.tags() {
+                    if let Some(db) = federation.database(tag) {
+                        attributes.insert(tag.clone(), db.item_attributes()?);
+                    }
+                }
+            }
+            let no_attributes: Vec<Attribute> = vec![];
+            let plan = profile.plan_with_attributes(library.items.iter().map(|item| {
+                let attributes = attributes
+                    .get(&item.source)
+                    .and_then(|attributes| attributes.get(&item.entry.id))
+                    .unwrap_or(&no_attributes);
+                (&item.entry, attributes.as_slice())
+            }))?;
+            if *dry_run {
+                let stdout = io::stdout();
+                write_plan(&mut stdout.lock(), &plan)?;
+                return Ok(());
+            }
+            plan.run()?;
+            eprintln!(
+                "copied {} files and deleted {}, {} were already there and {} were left out",
+                plan.copy.len(),
+                plan.delete.len(),
+                plan.keep.len(),
+                plan.excluded.len()
+            );
+        }
+    }
+    Ok(())
+}
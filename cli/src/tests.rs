@@ -0,0 +1,583 @@
+#![cfg(test)]
+
+use std::path::Path;
+use std::time::Duration;
+
+use beet_db::{
+    export_paths, Album, Attribute, CopyExportOptions, CoverageGroup, Database, DuplicateGroup,
+    FederatedLibrary, Federation, GlobalId, Item, Library, LibraryTag, PlaylistPreset, Sourced,
+    SyncPriority, SyncProfile,
+};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::case;
+use crate::copy;
+use crate::coverage;
+use crate::dump::write_dump;
+use crate::duplicates::{plan_json, write_delete_plan, write_plan_text, write_text};
+use crate::export::{self, Attributes, ExportFormat};
+use crate::library::{self, parse_query, parse_source, Source};
+use crate::missing;
+use crate::output::shell_quote;
+use crate::playlist;
+use crate::presets;
+use crate::query;
+use crate::random::{self, parse_duration};
+use crate::repl::ReplHelper;
+use crate::stats;
+use crate::sync::{self, parse_size};
+use crate::watch::split_words;
+
+fn item(source: &str, id: u32, path: &str, format: &str, bitrate: u32) -> Sourced<Item> {
+    Sourced {
+        source: LibraryTag::from(source),
+        entry: Item {
+            id,
+            path: path.into(),
+            title: "Roygbiv".to_string(),
+            artist: "Boards of Canada".to_string(),
+            format: format.to_string(),
+            bitrate,
+            length: 151.0,
+            ..Item::default()
+        },
+    }
+}
+
+#[test]
+fn library_sources() {
+    assert_eq!(
+        parse_source("nas=/mnt/music/library.db"),
+        Ok(Source {
+            tag: "nas".to_string(),
+            path: "/mnt/music/library.db".into(),
+        })
+    );
+    assert_eq!(
+        parse_source("/tmp/a=b/musiclibrary.blb"),
+        Ok(Source {
+            tag: "musiclibrary".to_string(),
+            path: "/tmp/a=b/musiclibrary.blb".into(),
+        })
+    );
+    assert!(parse_source("=library.db").is_err());
+}
+
+#[test]
+fn quotes_shell_paths() {
+    assert_eq!(shell_quote(Path::new("/music/a b")), "'/music/a b'");
+    assert_eq!(
+        shell_quote(Path::new("/music/Don't $(rm -rf ~)")),
+        r"'/music/Don'\''t $(rm -rf ~)'"
+    );
+}
+
+#[test]
+fn dump_output() -> Result<(), Box<dyn std::error::Error>> {
+    let library = Library {
+        albums: vec![],
+        items: vec![item("nas", 1, "/music/a.flac", "FLAC", 900).entry],
+    };
+
+    // a single library reads back as one
+    let mut out = vec![];
+    write_dump(&mut out, &[("nas".to_string(), library.clone())], false)?;
+    assert!(std::str::from_utf8(&out)?.lines().count() > 1);
+    assert_eq!(Library::from_json_bytes(&out)?, library);
+    let mut compact = vec![];
+    write_dump(&mut compact, &[("nas".to_string(), library.clone())], true)?;
+    assert_eq!(std::str::from_utf8(&compact)?.lines().count(), 1);
+    assert_eq!(Library::from_json_bytes(&compact)?, library);
+
+    // several are keyed by their tags
+    let mut out = vec![];
+    let libraries = [
+        ("nas".to_string(), library.clone()),
+        ("laptop".to_string(), Library::default()),
+    ];
+    write_dump(&mut out, &libraries, true)?;
+    let json: serde_json::Value = serde_json::from_slice(&out)?;
+    assert_eq!(json["nas"]["items"][0]["title"], "Roygbiv");
+    assert_eq!(json["laptop"]["items"], serde_json::json!([]));
+    Ok(())
+}
+
+#[test]
+fn duplicates_output() -> Result<(), Box<dyn std::error::Error>> {
+    let library = FederatedLibrary {
+        albums: vec![],
+        items: vec![
+            item("laptop", 7, "/music/it's.mp3", "MP3", 320_000),
+            item("nas", 12, "/mnt/roygbiv.flac", "FLAC", 900_000),
+            item("nas", 13, "/mnt/roygbiv.flac", "FLAC", 900_000),
+        ],
+    };
+    let groups = library.all_duplicates();
+
+    let mut text = vec![];
+    write_text(&mut text, &groups)?;
+    assert_eq!(
+        String::from_utf8(text)?,
+        "\
+fuzzy: Boards of Canada - Roygbiv
+  * nas:12    FLAC 900kbps  /mnt/roygbiv.flac
+    nas:13    FLAC 900kbps  /mnt/roygbiv.flac
+    laptop:7  MP3 320kbps  /music/it's.mp3
+"
+    );
+
+    let mut plan = vec![];
+    write_delete_plan(&mut plan, &groups)?;
+    let plan = String::from_utf8(plan)?;
+    assert!(plan.starts_with("#!/bin/sh\n"));
+    assert!(plan.contains("# keeping nas:12 '/mnt/roygbiv.flac'\n"));
+    // the second nas copy is the same file, so only the laptop copy goes
+    assert!(plan.ends_with("\nrm -- '/music/it'\\''s.mp3'  # laptop:7\n"));
+
+    let plans: Vec<_> = groups.iter().map(DuplicateGroup::plan).collect();
+    let mut text = vec![];
+    write_plan_text(&mut text, &plans)?;
+    assert_eq!(
+        String::from_utf8(text)?,
+        "\
+fuzzy: Boards of Canada - Roygbiv
+  keep    nas:12  FLAC 900kbps  /mnt/roygbiv.flac
+  remove  laptop:7  MP3 320kbps  /music/it's.mp3  (kept: lossless)
+"
+    );
+    let json = plan_json(&plans);
+    assert_eq!(json[0]["keep"]["id"], "nas:12");
+    assert_eq!(json[0]["remove"][0]["reason"], "lossless");
+    Ok(())
+}
+
+#[test]
+fn missing_output() -> Result<(), Box<dyn std::error::Error>> {
+    let library = FederatedLibrary {
+        albums: vec![],
+        items: vec![
+            item("nas", 1, "Cargo.toml", "FLAC", 900_000),
+            item("nas", 2, "/mnt/gone.flac", "FLAC", 900_000),
+        ],
+    };
+    let missing = library.missing_items(2);
+
+    let mut text = vec![];
+    missing::write_text(&mut text, &missing)?;
+    assert_eq!(String::from_utf8(text)?, "nas:2\t/mnt/gone.flac\n");
+    assert_eq!(
+        missing::to_json(&missing),
+        serde_json::json!([{
+            "id": "nas:2",
+            "path": "/mnt/gone.flac",
+            "artist": "Boards of Canada",
+            "album": "",
+            "title": "Roygbiv",
+        }])
+    );
+    Ok(())
+}
+
+#[test]
+fn case_output() -> Result<(), Box<dyn std::error::Error>> {
+    let library = FederatedLibrary {
+        albums: vec![],
+        items: vec![
+            item("nas", 1, "/mnt/A.flac", "FLAC", 900_000),
+            item("laptop", 2, "/mnt/a.flac", "FLAC", 900_000),
+            item("nas", 3, "cargo.toml", "FLAC", 900_000),
+        ],
+    };
+    let conflicts = library.case_conflicts();
+    let mismatches = library.case_mismatches();
+
+    let mut text = vec![];
+    case::write_text(&mut text, &conflicts, &mismatches)?;
+    assert_eq!(
+        String::from_utf8(text)?,
+        "conflict\n  nas:1\t/mnt/A.flac\n  laptop:2\t/mnt/a.flac\n\
+        mismatch\tnas:3\tcargo.toml\tCargo.toml\n"
+    );
+    assert_eq!(
+        case::to_json(&conflicts, &mismatches),
+        serde_json::json!({
+            "conflicts": [[
+                { "id": "nas:1", "path": "/mnt/A.flac" },
+                { "id": "laptop:2", "path": "/mnt/a.flac" },
+            ]],
+            "mismatches": [{ "id": "nas:3", "path": "cargo.toml", "on_disk": "Cargo.toml" }],
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn copy_plan_output() -> Result<(), Box<dyn std::error::Error>> {
+    let mut item = item("nas", 1, "/mnt/music/roygbiv.flac", "FLAC", 900_000).entry;
+    item.albumartist = "Boards of Canada".to_string();
+    item.album = "Music Has the Right to Children".to_string();
+    item.track = 9;
+    let options = CopyExportOptions {
+        template: "$albumartist/$track $title".parse()?,
+        ascii: false,
+    };
+    let paths = export_paths([&item], &options);
+
+    let mut text = vec![];
+    copy::write_plan(&mut text, &paths)?;
+    assert_eq!(
+        String::from_utf8(text)?,
+        "/mnt/music/roygbiv.flac\tBoards of Canada/09 Roygbiv.flac\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn coverage_output() -> Result<(), Box<dyn std::error::Error>> {
+    let mut analyzed = item("nas", 1, "/mnt/idm/a.flac", "FLAC", 900_000);
+    analyzed.entry.bpm = 120.0;
+    analyzed.entry.initial_key = Some("Am".to_string());
+    let mut no_key = item("nas", 2, "/mnt/idm/b.flac", "FLAC", 900_000);
+    no_key.entry.bpm = 98.0;
+    let library = FederatedLibrary {
+        albums: vec![],
+        items: vec![
+            analyzed,
+            no_key,
+            item("nas", 3, "/mnt/idm/c.flac", "FLAC", 900_000),
+            item("nas", 4, "/mnt/ambient/d.flac", "FLAC", 900_000),
+        ],
+    };
+
+    let groups = library.bpm_key_coverage(CoverageGroup::Folder);
+    let mut text = vec![];
+    coverage::write_text(&mut text, &groups)?;
+    assert_eq!(
+        String::from_utf8(text)?,
+        "/mnt/idm: 2 of 3 to analyze (1 without BPM, 2 without key)\n\
+        \x20 nas:2\tkey\t/mnt/idm/b.flac\n\
+        \x20 nas:3\tbpm,key\t/mnt/idm/c.flac\n\
+        /mnt/ambient: 1 of 1 to analyze (1 without BPM, 1 without key)\n\
+        \x20 nas:4\tbpm,key\t/mnt/ambient/d.flac\n"
+    );
+
+    let groups = library.bpm_key_coverage(CoverageGroup::Genre);
+    assert_eq!(coverage::to_json(&groups)[0]["missing"][0]["id"], "nas:2");
+    Ok(())
+}
+
+#[test]
+fn preset_listing() -> Result<(), Box<dyn std::error::Error>> {
+    let db = Database::open_in_memory()?;
+    // 2015-01-01 and 2023-03-10 (UTC)
+    db.connection().execute_batch(
+        "INSERT INTO items (id, path, year, added) VALUES (1, '/music/a.flac', 1994, 1420070400);
+        INSERT INTO items (id, path, year, added) VALUES (2, '/music/b.flac', 1998, 1678406400);
+        INSERT INTO item_attributes (entity_id, key, value) VALUES (1, 'play_count', '3');",
+    )?;
+    let mut federation = Federation::new();
+    federation.add("nas", db);
+    let plays = library::global_play_counts(&federation)?;
+    assert_eq!(plays.get(&GlobalId::new("nas", 1)), Some(&3));
+
+    // 2024-01-01 (UTC), with item 1 played too often to be forgotten
+    let library = federation.read_all()?;
+    let presets = library.presets(&plays, 1_704_067_200.0);
+    assert!(!presets.contains(&(PlaylistPreset::ForgottenGems, 1)));
+    let mut text = vec![];
+    presets::write_text(&mut text, &presets)?;
+    assert_eq!(
+        String::from_utf8(text)?,
+        "1990s\t2\nadded-2015\t1\nadded-2023\t1\n"
+    );
+    assert_eq!(presets::to_json(&presets)[1]["preset"], "added-2015");
+    Ok(())
+}
+
+#[test]
+fn parses_path_queries() -> Result<(), String> {
+    assert!(parse_query("genre:jazz -year:1999").is_ok());
+    let query = parse_query("^path:/mnt/music")?;
+    let item = |path: &str| beet_db::Item {
+        path: path.into(),
+        ..beet_db::Item::default()
+    };
+    assert!(!query.match_item(&item("/mnt/music/a.flac")));
+    assert!(query.match_item(&item("/mnt/musical/a.flac")));
+    Ok(())
+}
+
+#[test]
+fn query_output() -> Result<(), Box<dyn std::error::Error>> {
+    let mut first = item("nas", 2, "/mnt/b.flac", "FLAC", 900_000);
+    first.entry.year = 2004;
+    first.entry.genre = "IDM".to_string();
+    let mut second = item("nas", 1, "/mnt/a.mp3", "MP3", 320_000);
+    second.entry.title = "Dayvan\nCowboy".to_string();
+    second.entry.year = 2006;
+    let items = [first, second];
+
+    let query = parse_query("boards year-")?;
+    let mut records: Vec<_> = items
+        .iter()
+        .filter(|item| query.match_item(&item.entry))
+        .collect();
+    query::sort(&mut records, &query.sort_keys());
+    let fields = ["id".to_string(), "title".to_string(), "genre".to_string()];
+    let mut out = vec![];
+    query::write_text(&mut out, &records, &fields, "\t")?;
+    assert_eq!(
+        String::from_utf8(out)?,
+        "1\tDayvan Cowboy\t\n2\tRoygbiv\tIDM\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn playlist_remapping() {
+    assert_eq!(
+        playlist::parse_remap("/srv/music=/media/usb"),
+        Ok(("/srv/music".into(), "/media/usb".into()))
+    );
+    assert!(playlist::parse_remap("/srv/music").is_err());
+    assert!(playlist::parse_remap("=/media/usb").is_err());
+}
+
+#[test]
+fn stats_output() -> Result<(), Box<dyn std::error::Error>> {
+    let album = Album {
+        id: 1,
+        albumartist: "Boards of Canada".to_string(),
+        album: "Geogaddi".to_string(),
+        added: 1_000.0,
+        ..Album::default()
+    };
+    let mut items = vec![item("nas", 1, "/mnt/a.flac", "FLAC", 900_000).entry];
+    items[0].genre = "IDM".to_string();
+    items[0].year = 2002;
+    let library = Library {
+        albums: vec![album],
+        items,
+    };
+    let mut out = vec![];
+    stats::write_text(&mut out, &library.stats(), 10, 1_000.0 + 90_000.0)?;
+    assert_eq!(
+        String::from_utf8(out)?,
+        "Items:   1\nAlbums:  1\nLength:  3m\n\
+        Genres:\n        1  IDM\n\
+        Formats:\n        1  FLAC\n\
+        Years:\n        1  2002\n\
+        Recently added:\n  Boards of Canada - Geogaddi (1d 1h 0m ago)\n"
+    );
+    assert_eq!(stats::format_length(59.0 * 60.0), "59m");
+    Ok(())
+}
+
+#[test]
+fn durations() {
+    let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+    assert_eq!(parse_duration("60"), Ok(minutes(60)));
+    assert_eq!(parse_duration("60m"), Ok(minutes(60)));
+    assert_eq!(parse_duration("1h30m"), Ok(minutes(90)));
+    assert_eq!(parse_duration("45s"), Ok(Duration::from_secs(45)));
+    for invalid in ["", "h", "1h30", "2d", "1.5h", "5é"] {
+        assert!(parse_duration(invalid).is_err(), "{:?}", invalid);
+    }
+}
+
+#[test]
+fn random_pick_fits_time() {
+    let entry = |artist, length| random::Entry {
+        artist,
+        label: String::new(),
+        length,
+        items: vec![],
+    };
+    let entries = vec![
+        entry("a", 500.0),
+        entry("a", 200.0),
+        entry("a", 200.0),
+        entry("b", 50.0),
+    ];
+    let picked = random::take(entries, 1, Some(Duration::from_mins(10)));
+    let lengths: Vec<f64> = picked.iter().map(|entry| entry.length).collect();
+    assert_eq!(lengths, [500.0, 50.0]);
+
+    let shuffled = random::shuffle(picked, true, &mut StdRng::seed_from_u64(7));
+    assert_eq!(shuffled.len(), 2);
+}
+
+#[test]
+fn export_formats() -> Result<(), Box<dyn std::error::Error>> {
+    let mut quoted = item("nas", 3, "/mnt/a.flac", "FLAC", 900_000);
+    quoted.entry.title = "Hey, \"You\"".to_string();
+    let items = [quoted, item("laptop", 4, "/music/b.mp3", "MP3", 320_000)];
+    let records: Vec<_> = items.iter().collect();
+    let fields = export::select_fields::<Item>(&["source".into(), "title".into()], 2)?;
+
+    let none = Attributes::default();
+
+    let mut csv = vec![];
+    export::write_records(&mut csv, ExportFormat::Csv, &records, &fields, &none)?;
+    assert_eq!(
+        String::from_utf8(csv)?,
+        "source,title\nnas,\"Hey, \"\"You\"\"\"\nlaptop,Roygbiv\n"
+    );
+
+    // flexible attributes follow, in order, empty where they are not set
+    let attribute = |key: &str, value: &str| Attribute {
+        id: 0,
+        entity_id: 3,
+        key: key.to_string(),
+        value: value.to_string(),
+    };
+    let attributes = Attributes::new(vec![(
+        GlobalId::new("nas", 3),
+        vec![
+            attribute("mood", "calm, slow"),
+            attribute("bpm_checked", "1"),
+        ],
+    )]);
+    let mut csv = vec![];
+    export::write_csv(&mut csv, &records, &fields[1..], &attributes)?;
+    assert_eq!(
+        String::from_utf8(csv)?,
+        "title,bpm_checked,mood\n\"Hey, \"\"You\"\"\",1,\"calm, slow\"\nRoygbiv,,\n"
+    );
+
+    let mut ndjson = vec![];
+    export::write_records(&mut ndjson, ExportFormat::Ndjson, &records, &fields, &none)?;
+    assert_eq!(
+        String::from_utf8(ndjson)?.lines().nth(1),
+        Some(r#"{"source":"laptop","title":"Roygbiv"}"#)
+    );
+
+    // streamed from each library in turn, as read
+    let db = Database::open_in_memory()?;
+    db.connection().execute_batch(
+        "INSERT INTO items (id, path, title, format) VALUES (1, '/music/a.flac', 'A', 'FLAC');
+        INSERT INTO items (id, path, title, format) VALUES (2, '/music/b.mp3', 'B', 'MP3');",
+    )?;
+    let mut federation = Federation::new();
+    federation.add("nas", db);
+    let mut ndjson = vec![];
+    let mp3 = |item: &Item| item.format == "MP3";
+    export::stream_ndjson(&mut ndjson, &federation, mp3, &fields)?;
+    assert_eq!(
+        String::from_utf8(ndjson)?,
+        "{\"source\":\"nas\",\"title\":\"B\"}\n"
+    );
+
+    assert!(export::select_fields::<Item>(&["titel".into()], 1).is_err());
+    let all = export::select_fields::<Item>(&[], 2)?;
+    assert_eq!(all[0], "source");
+    assert_eq!(all.len(), Item::COLUMNS.len() + 1);
+    Ok(())
+}
+
+#[test]
+fn repl_completion() {
+    let mut other = item("nas", 2, "/mnt/b.flac", "FLAC", 900_000);
+    other.entry.artist = "Bonobo".to_string();
+    let library = FederatedLibrary {
+        albums: vec![],
+        items: vec![item("nas", 1, "/mnt/a.flac", "FLAC", 900_000), other],
+    };
+    let helper = ReplHelper::new(&library, false);
+    let replacements = |line: &str| {
+        let (start, candidates) = helper.candidates(line, line.len());
+        let replacements: Vec<String> = candidates
+            .into_iter()
+            .map(|pair| pair.replacement)
+            .collect();
+        (start, replacements)
+    };
+
+    assert_eq!(
+        replacements("year:2010 form"),
+        (10, vec!["format:".to_string()])
+    );
+    assert_eq!(
+        replacements("^artist:bo"),
+        (
+            1,
+            vec!["artist:Boards".to_string(), "artist:Bonobo".to_string()]
+        )
+    );
+    assert_eq!(replacements("nonsense:"), (0, vec![]));
+
+    assert_eq!(
+        helper.preview("artist:bonobo").as_deref(),
+        Some("  (1 items)")
+    );
+    assert_eq!(helper.preview(":help"), None);
+}
+
+#[test]
+fn sync_plan_output() -> Result<(), Box<dyn std::error::Error>> {
+    assert_eq!(parse_size("16G"), Ok(16_000_000_000));
+    assert_eq!(parse_size("512k"), Ok(512_000));
+    assert_eq!(parse_size("1024"), Ok(1024));
+    assert!(parse_size("16X").is_err());
+    assert!(parse_size("G").is_err());
+
+    let mut playable = item("nas", 1, "Cargo.toml", "MP3", 320_000).entry;
+    playable.title = "Roygbiv".to_string();
+    let lossless = item("nas", 2, "/mnt/music/roygbiv.flac", "FLAC", 900_000).entry;
+    let too_big = item("nas", 3, "src/main.rs", "MP3", 320_000).entry;
+    let cap = std::fs::metadata("Cargo.toml")?.len();
+    let dir = std::env::temp_dir().join(format!("berts-sync-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let profile = SyncProfile {
+        name: "car".to_string(),
+        destination: dir.clone(),
+        formats: vec!["MP3".to_string()],
+        size_cap: Some(cap),
+        priority: SyncPriority::Recent,
+        template: "$title".parse()?,
+        ..SyncProfile::default()
+    };
+    profile.init()?;
+    let plan = profile.plan([&playable, &lossless, &too_big])?;
+
+    let mut text = vec![];
+    sync::write_plan(&mut text, &plan)?;
+    assert_eq!(
+        String::from_utf8(text)?,
+        format!(
+            "copy\tCargo.toml\tRoygbiv.toml\n\
+            exclude\tformat\t/mnt/music/roygbiv.flac\n\
+            exclude\tsize-cap\tsrc/main.rs\t{}\t0\n",
+            std::fs::metadata("src/main.rs")?.len()
+        )
+    );
+    let mut text = vec![];
+    sync::write_profiles(&mut text, std::slice::from_ref(&profile))?;
+    assert_eq!(
+        String::from_utf8(text)?,
+        format!(
+            "car\t{}\t\tMP3\t{cap}\trecent\n",
+            profile.destination.display()
+        )
+    );
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn watch_command_words() {
+    assert_eq!(
+        split_words(r#"export -q 'artist:boards of' -o "my \"mix\".m3u" a\ b"#),
+        Ok(vec![
+            "export".to_string(),
+            "-q".to_string(),
+            "artist:boards of".to_string(),
+            "-o".to_string(),
+            r#"my "mix".m3u"#.to_string(),
+            "a b".to_string(),
+        ])
+    );
+    assert_eq!(split_words("  ''  "), Ok(vec![String::new()]));
+    assert!(split_words("random -q 'year:1999").is_err());
+}
@@ -0,0 +1,134 @@
+use std::env;
+use std::process;
+use std::time::Duration;
+
+use beet_db::Watcher;
+use structopt::StructOpt;
+
+use crate::library::{self, Source};
+
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Args {
+    /// A berts subcommand to run when a library changes, e.g.
+    /// `export --format csv -o library.csv`. Quote arguments holding spaces
+    /// as in a shell. Repeat to run several in order.
+    #[structopt(long, raw(required = "true", number_of_values = "1"))]
+    on_change: Vec<String>,
+    /// How many seconds to wait between checks for changes.
+    #[structopt(long, default_value = "2")]
+    interval: u64,
+}
+
+/// Split a command line into words like a shell would, with `'single'` and
+/// `"double"` quotes and backslash escapes, but nothing else.
+pub(crate) fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(format!("unclosed `'` in `{line}`")),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(format!("unclosed `\"` in `{line}`")),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(format!("unclosed `\"` in `{line}`")),
+                    }
+                }
+            }
+            '\\' => {
+                let c = chars
+                    .next()
+                    .ok_or_else(|| format!("trailing `\\` in `{line}`"))?;
+                word.get_or_insert_with(String::new).push(c);
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Run each command in turn, reporting those that fail.
+fn run_commands(sources: &[Source], commands: &[(&str, Vec<String>)]) -> crate::Result<()> {
+    let berts = env::current_exe()?;
+    for (line, words) in commands {
+        let mut command = process::Command::new(&berts);
+        for source in sources {
+            command.arg("--library").arg(source.to_arg());
+        }
+        let status = command.args(words).status()?;
+        if !status.success() {
+            eprintln!("berts watch: `{line}` failed ({status})");
+        }
+    }
+    Ok(())
+}
+
+pub fn run(libraries: &[Source], args: &Args) -> crate::Result<()> {
+    let commands = args
+        .on_change
+        .iter()
+        .map(|line| {
+            let words = split_words(line)?;
+            match words.first().map(String::as_str) {
+                None => Err("`--on-change` needs a subcommand to run".to_string()),
+                Some(name @ ("watch" | "repl")) => {
+                    Err(format!("`{name}` cannot be run by `berts watch`"))
+                }
+                Some(_) => Ok((line.as_str(), words)),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let sources = library::resolve(libraries)?;
+    let mut watchers = sources
+        .iter()
+        .map(|source| Watcher::new(&source.path))
+        .collect::<Result<Vec<_>, _>>()?;
+    for watcher in &mut watchers {
+        watcher.set_interval(Duration::from_secs(args.interval));
+    }
+
+    // bring the outputs up to date before waiting for changes
+    run_commands(&sources, &commands)?;
+    loop {
+        wait_any(&mut watchers, Duration::from_secs(args.interval))?;
+        eprintln!("berts watch: library changed");
+        run_commands(&sources, &commands)?;
+    }
+}
+
+/// Block until any of the libraries changes and then settles.
+fn wait_any(watchers: &mut [Watcher], interval: Duration) -> crate::Result<()> {
+    loop {
+        for watcher in watchers.iter_mut() {
+            if watcher.changed()? {
+                // wait out the rest of the write before reporting it
+                watcher.settle()?;
+                return Ok(());
+            }
+        }
+        std::thread::sleep(interval);
+    }
+}
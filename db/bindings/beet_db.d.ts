@@ -0,0 +1,123 @@
+// Generated by beet_db 0.1.0; do not edit.
+
+export interface Album {
+  id: number;
+  /**
+   * This is converted lossily - any invalid UTF-8 will be
+   * [transcribed as the replacement character.](https://doc.rust-lang.org/std/string/struct.String.html#method.from_utf8_lossy)
+   */
+  artpath: string | null;
+  albumartist: string;
+  albumartist_sort?: string;
+  albumartist_credit?: string;
+  album: string;
+  genre?: string;
+  year?: number;
+  month?: number;
+  day?: number;
+  disctotal?: number;
+  comp: boolean;
+  mb_albumid?: string;
+  mb_albumartistid?: string;
+  albumtype?: string;
+  label?: string;
+  mb_releasegroupid?: string;
+  asin?: string;
+  catalognum?: string;
+  script?: string;
+  language?: string;
+  country?: string;
+  albumstatus?: string;
+  albumdisambig?: string;
+  rg_album_gain?: number;
+  rg_album_peak?: number;
+  r128_album_gain?: number;
+  original_year?: number;
+  original_month?: number;
+  original_day?: number;
+}
+
+export interface Item {
+  id: number;
+  /**
+   * This is converted lossily - any invalid UTF-8 will be
+   * [transcribed as the replacement character.](https://doc.rust-lang.org/std/string/struct.String.html#method.from_utf8_lossy)
+   */
+  path: string;
+  album_id?: number;
+  title: string;
+  artist: string;
+  artist_sort?: string;
+  artist_credit?: string;
+  album?: string;
+  albumartist?: string;
+  albumartist_sort?: string;
+  albumartist_credit?: string;
+  genre?: string;
+  lyricist?: string;
+  composer?: string;
+  composer_sort?: string;
+  arranger?: string;
+  grouping?: string;
+  year?: number;
+  month?: number;
+  day?: number;
+  track?: number;
+  tracktotal?: number;
+  disc?: number;
+  disctotal?: number;
+  lyrics?: string;
+  comments?: string;
+  bpm?: number;
+  comp: boolean;
+  mb_trackid?: string;
+  mb_albumid?: string;
+  mb_artistid?: string;
+  mb_albumartistid?: string;
+  mb_releasetrackid?: string;
+  albumtype?: string;
+  label?: string;
+  acoustid_fingerprint?: string;
+  acoustid_id?: string;
+  mb_releasegroupid?: string;
+  asin?: string;
+  catalognum?: string;
+  script?: string;
+  language?: string;
+  country?: string;
+  albumstatus?: string;
+  media?: string;
+  albumdisambig?: string;
+  disctitle?: string;
+  encoder?: string;
+  rg_track_gain?: number;
+  rg_track_peak?: number;
+  rg_album_gain?: number;
+  rg_album_peak?: number;
+  r128_track_gain?: number;
+  r128_album_gain?: number;
+  original_year?: number;
+  original_month?: number;
+  original_day?: number;
+  initial_key?: string;
+  length: number;
+  bitrate?: number;
+  /**
+   * `CBR`, `VBR` or `ABR`, empty if unknown or for libraries from before
+   * beets 2.0.
+   */
+  bitrate_mode?: string;
+  encoder_info?: string;
+  encoder_settings?: string;
+  format?: string;
+  samplerate?: number;
+  bitdepth?: number;
+  channels?: number;
+}
+
+export interface Attribute {
+  id: number;
+  entity_id: number;
+  key: string;
+  value: string;
+}
@@ -0,0 +1,40 @@
+//! Compare reading a library on one connection against chunked parallel reads.
+//!
+//! ```sh
+//! cargo run --release --example parallel_scan -- path/to/library.db
+//! ```
+
+use std::time::{Duration, Instant};
+
+use beet_db::{OpenOptions, Preset};
+
+const RUNS: u32 = 5;
+
+fn main() -> Result<(), beet_db::Error> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "tests/test.db".to_string());
+    let mut options = OpenOptions::new();
+    options.preset(Preset::ReadFast);
+
+    let cores = std::thread::available_parallelism().map_or(1, usize::from);
+    println!("{path}, {cores} cores available, best of {RUNS} runs");
+
+    let mut baseline = None;
+    for chunks in [1, 2, 4, 8, 16] {
+        let mut best = Duration::MAX;
+        let mut count = 0;
+        for _ in 0..RUNS {
+            let start = Instant::now();
+            let (_, items) = options.read_all_parallel(&path, chunks)?;
+            best = best.min(start.elapsed());
+            count = items.len();
+        }
+        let baseline = *baseline.get_or_insert(best);
+        println!(
+            "{chunks:>2} chunks: {count} items in {best:>10.2?} ({:.2}x)",
+            baseline.as_secs_f64() / best.as_secs_f64()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,43 @@
+//! Compare reading a library with default settings against the `ReadFast`
+//! preset.
+//!
+//! ```sh
+//! cargo run --release --example read_fast -- path/to/library.db
+//! ```
+
+use std::time::{Duration, Instant};
+
+use beet_db::{Item, OpenOptions, Preset};
+
+const RUNS: u32 = 5;
+
+fn main() -> Result<(), beet_db::Error> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "tests/test.db".to_string());
+    println!("{path}, best of {RUNS} runs");
+
+    let mut read_fast = OpenOptions::new();
+    read_fast.preset(Preset::ReadFast);
+    for (name, options) in [("default", OpenOptions::new()), ("ReadFast", read_fast)] {
+        let (mut read_all, mut sorted) = (Duration::MAX, Duration::MAX);
+        for _ in 0..RUNS {
+            // a new connection each run, so its page cache starts cold
+            let db = options.open(&path)?;
+            let start = Instant::now();
+            db.read_all()?;
+            read_all = read_all.min(start.elapsed());
+
+            let db = options.open(&path)?;
+            let start = Instant::now();
+            db.query(
+                &Item::query()
+                    .order_by("artist", true)
+                    .order_by("album", true),
+            )?;
+            sorted = sorted.min(start.elapsed());
+        }
+        println!("{name:>8}: read_all {read_all:>10.2?}, sorted query {sorted:>10.2?}");
+    }
+    Ok(())
+}
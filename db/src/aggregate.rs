@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::fs;
+
+use rusqlite::Connection;
+
+use crate::trace::{self, Hooks};
+use crate::{str_or_blob_to_path, Album, Error, LocalRow, TableColumn};
+
+/// Totals over the items of one album, for album lists that show runtime
+/// and size.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct AlbumTotals {
+    pub album_id: u32,
+    pub tracks: u32,
+    /// In seconds.
+    pub length: f64,
+    /// The mean bitrate of the tracks, in bits per second.
+    pub average_bitrate: u32,
+    /// The bytes taken by the files that exist, if they were looked up.
+    pub size: Option<u64>,
+}
+
+const AGGREGATE_SQL: &str = "\
+SELECT albums.id, COUNT(items.id), COALESCE(SUM(items.length), 0),
+    COALESCE(CAST(ROUND(AVG(items.bitrate)) AS INTEGER), 0)
+FROM albums LEFT JOIN items ON items.album_id = albums.id
+GROUP BY albums.id ORDER BY albums.id";
+
+impl Album {
+    /// Count the tracks of every album and total their length in one query,
+    /// in order of album id. Albums without items have zero totals.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn aggregate(conn: &Connection) -> Result<Vec<AlbumTotals>, Error> {
+        trace::query_all(
+            conn,
+            "albums",
+            AGGREGATE_SQL,
+            (),
+            |row| {
+                Ok(AlbumTotals {
+                    album_id: row.get(0)?,
+                    tracks: row.get(1)?,
+                    length: row.get(2)?,
+                    average_bitrate: row.get(3)?,
+                    size: None,
+                })
+            },
+            Hooks::default(),
+        )
+    }
+
+    /// Like [`aggregate`](Self::aggregate), also adding up the size of each
+    /// album's files on disk. Files that cannot be found count for nothing.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn aggregate_with_size(conn: &Connection) -> Result<Vec<AlbumTotals>, Error> {
+        let paths = trace::query_all(
+            conn,
+            "items",
+            "SELECT album_id, path FROM items WHERE album_id IS NOT NULL",
+            (),
+            |row| {
+                let path = str_or_blob_to_path(
+                    LocalRow(
+                        row,
+                        TableColumn {
+                            table: "items",
+                            column: "path",
+                        },
+                    ),
+                    1,
+                )?;
+                Ok((row.get::<_, u32>(0)?, path))
+            },
+            Hooks::default(),
+        )?;
+        let mut sizes: HashMap<u32, u64> = HashMap::new();
+        for (album_id, path) in paths {
+            let size = fs::metadata(path).map_or(0, |metadata| metadata.len());
+            *sizes.entry(album_id).or_default() += size;
+        }
+
+        let mut totals = Self::aggregate(conn)?;
+        for album in &mut totals {
+            album.size = Some(sizes.get(&album.album_id).copied().unwrap_or(0));
+        }
+        Ok(totals)
+    }
+}
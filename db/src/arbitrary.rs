@@ -0,0 +1,355 @@
+//! [`proptest`] strategies for the library structs.
+//!
+//! Generated values respect the invariants beets maintains: ids are non-zero,
+//! track and disc numbers never exceed their totals, and dates are either
+//! unset (zero) or valid calendar components.
+
+use std::path::PathBuf;
+
+use proptest::prelude::*;
+use proptest::{option, strategy::BoxedStrategy};
+
+use crate::{Album, Attribute, Item, R128Gain};
+
+fn id() -> impl Strategy<Value = u32> {
+    1..=u32::MAX
+}
+
+fn text() -> impl Strategy<Value = String> {
+    "[A-Za-z0-9 ,.'&-]{0,24}"
+}
+
+fn name() -> impl Strategy<Value = String> {
+    "[A-Za-z0-9 ,.'&-]{1,24}"
+}
+
+fn mbid() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just(String::new()),
+        "[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}",
+    ]
+}
+
+fn path() -> impl Strategy<Value = PathBuf> {
+    "/music/[A-Za-z0-9 ]{1,12}/[A-Za-z0-9 ]{1,12}\\.(flac|mp3|ogg|m4a)".prop_map(PathBuf::from)
+}
+
+fn timestamp() -> impl Strategy<Value = f64> {
+    0.0..2_000_000_000.0
+}
+
+fn gain() -> impl Strategy<Value = Option<f64>> {
+    option::of(-30.0..30.0)
+}
+
+fn r128_gain() -> impl Strategy<Value = Option<R128Gain>> {
+    option::of(any::<i16>().prop_map(R128Gain::from_q78))
+}
+
+/// Whole numbers as written by older beets, or fractions by newer ones.
+fn bpm() -> impl Strategy<Value = f64> {
+    prop_oneof![(0..=300_u32).prop_map(f64::from), 0.0..300.0]
+}
+
+fn bitrate_mode() -> impl Strategy<Value = String> {
+    prop_oneof![Just(""), Just("CBR"), Just("VBR"), Just("ABR")].prop_map(String::from)
+}
+
+fn peak() -> impl Strategy<Value = Option<f64>> {
+    option::of(0.0..2.0)
+}
+
+/// `(year, month, day)`, where a zero component leaves every later one zero.
+fn date() -> impl Strategy<Value = (u32, u32, u32)> {
+    prop_oneof![
+        Just((0, 0, 0)),
+        (1900..=2100_u32).prop_map(|year| (year, 0, 0)),
+        (1900..=2100_u32, 1..=12_u32).prop_map(|(year, month)| (year, month, 0)),
+        (1900..=2100_u32, 1..=12_u32, 1..=28_u32),
+    ]
+}
+
+/// `(index, total)`, where the index never exceeds the total.
+fn numbering(max_total: u32) -> impl Strategy<Value = (u32, u32)> {
+    (0..=max_total).prop_flat_map(|total| (0..=total, Just(total)))
+}
+
+impl Arbitrary for Attribute {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with((): Self::Parameters) -> Self::Strategy {
+        (id(), id(), "[a-z_]{1,16}", text())
+            .prop_map(|(id, entity_id, key, value)| Self {
+                id,
+                entity_id,
+                key,
+                value,
+            })
+            .boxed()
+    }
+}
+
+impl Arbitrary for Album {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with((): Self::Parameters) -> Self::Strategy {
+        (
+            (id(), option::of(path()), timestamp(), date(), date()),
+            (
+                name(),
+                text(),
+                text(),
+                name(),
+                text(),
+                0..=10_u32,
+                any::<bool>(),
+            ),
+            (mbid(), mbid(), mbid(), text(), text(), text(), text()),
+            (text(), text(), text(), text(), text()),
+            (gain(), peak(), r128_gain()),
+        )
+            .prop_map(
+                |(
+                    (id, artpath, added, (year, month, day), original),
+                    (
+                        albumartist,
+                        albumartist_sort,
+                        albumartist_credit,
+                        album,
+                        genre,
+                        disctotal,
+                        comp,
+                    ),
+                    (
+                        mb_albumid,
+                        mb_albumartistid,
+                        mb_releasegroupid,
+                        albumtype,
+                        label,
+                        asin,
+                        catalognum,
+                    ),
+                    (script, language, country, albumstatus, albumdisambig),
+                    (rg_album_gain, rg_album_peak, r128_album_gain),
+                )| {
+                    let (original_year, original_month, original_day) = original;
+                    Self {
+                        id,
+                        artpath,
+                        added,
+                        albumartist,
+                        albumartist_sort,
+                        albumartist_credit,
+                        album,
+                        genre,
+                        year,
+                        month,
+                        day,
+                        disctotal,
+                        comp,
+                        mb_albumid,
+                        mb_albumartistid,
+                        albumtype,
+                        label,
+                        mb_releasegroupid,
+                        asin,
+                        catalognum,
+                        script,
+                        language,
+                        country,
+                        albumstatus,
+                        albumdisambig,
+                        rg_album_gain,
+                        rg_album_peak,
+                        r128_album_gain,
+                        original_year,
+                        original_month,
+                        original_day,
+                    }
+                },
+            )
+            .boxed()
+    }
+}
+
+impl Arbitrary for Item {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    #[allow(clippy::too_many_lines)]
+    fn arbitrary_with((): Self::Parameters) -> Self::Strategy {
+        (
+            (
+                id(),
+                path(),
+                option::of(id()),
+                name(),
+                name(),
+                text(),
+                text(),
+            ),
+            (text(), text(), text(), text(), text(), text(), text()),
+            (
+                text(),
+                text(),
+                text(),
+                date(),
+                date(),
+                numbering(40),
+                numbering(10),
+            ),
+            (text(), text(), bpm(), any::<bool>(), mbid(), mbid(), mbid()),
+            (mbid(), mbid(), text(), text(), text(), mbid(), mbid()),
+            (text(), text(), text(), text(), text(), text(), text()),
+            (text(), text(), text(), gain(), peak(), gain(), peak()),
+            (
+                r128_gain(),
+                r128_gain(),
+                option::of("[A-G][#b]?m?"),
+                0.0..3600.0,
+                0..=9216_u32,
+                bitrate_mode(),
+            ),
+            (
+                text(),
+                text(),
+                text(),
+                0..=192_000_u32,
+                0..=32_u32,
+                0..=8_u32,
+                timestamp(),
+                timestamp(),
+            ),
+        )
+            .prop_map(
+                |(
+                    (id, path, album_id, title, artist, artist_sort, artist_credit),
+                    (
+                        album,
+                        albumartist,
+                        albumartist_sort,
+                        albumartist_credit,
+                        genre,
+                        lyricist,
+                        composer,
+                    ),
+                    (
+                        composer_sort,
+                        arranger,
+                        grouping,
+                        (year, month, day),
+                        original,
+                        (track, tracktotal),
+                        (disc, disctotal),
+                    ),
+                    (lyrics, comments, bpm, comp, mb_trackid, mb_albumid, mb_artistid),
+                    (
+                        mb_albumartistid,
+                        mb_releasetrackid,
+                        albumtype,
+                        label,
+                        acoustid_fingerprint,
+                        acoustid_id,
+                        mb_releasegroupid,
+                    ),
+                    (asin, catalognum, script, language, country, albumstatus, media),
+                    (
+                        albumdisambig,
+                        disctitle,
+                        encoder,
+                        rg_track_gain,
+                        rg_track_peak,
+                        rg_album_gain,
+                        rg_album_peak,
+                    ),
+                    (r128_track_gain, r128_album_gain, initial_key, length, bitrate, bitrate_mode),
+                    (
+                        encoder_info,
+                        encoder_settings,
+                        format,
+                        samplerate,
+                        bitdepth,
+                        channels,
+                        mtime,
+                        added,
+                    ),
+                )| {
+                    let (original_year, original_month, original_day) = original;
+                    Self {
+                        id,
+                        path,
+                        album_id,
+                        title,
+                        artist,
+                        artist_sort,
+                        artist_credit,
+                        album,
+                        albumartist,
+                        albumartist_sort,
+                        albumartist_credit,
+                        genre,
+                        lyricist,
+                        composer,
+                        composer_sort,
+                        arranger,
+                        grouping,
+                        year,
+                        month,
+                        day,
+                        track,
+                        tracktotal,
+                        disc,
+                        disctotal,
+                        lyrics,
+                        comments,
+                        bpm,
+                        comp,
+                        mb_trackid,
+                        mb_albumid,
+                        mb_artistid,
+                        mb_albumartistid,
+                        mb_releasetrackid,
+                        albumtype,
+                        label,
+                        acoustid_fingerprint,
+                        acoustid_id,
+                        mb_releasegroupid,
+                        asin,
+                        catalognum,
+                        script,
+                        language,
+                        country,
+                        albumstatus,
+                        media,
+                        albumdisambig,
+                        disctitle,
+                        encoder,
+                        rg_track_gain,
+                        rg_track_peak,
+                        rg_album_gain,
+                        rg_album_peak,
+                        r128_track_gain,
+                        r128_album_gain,
+                        original_year,
+                        original_month,
+                        original_day,
+                        initial_key,
+                        length,
+                        bitrate,
+                        bitrate_mode,
+                        encoder_info,
+                        encoder_settings,
+                        format,
+                        samplerate,
+                        bitdepth,
+                        channels,
+                        mtime,
+                        added,
+                    }
+                },
+            )
+            .boxed()
+    }
+}
@@ -0,0 +1,142 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, Int16Array, RecordBatch, StringArray, UInt32Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+
+use crate::R128Gain;
+
+/// A field type that is stored as one value of an Arrow array: whole numbers
+/// as `UInt32`, decimals as `Float64`, text and paths as `Utf8` (paths
+/// converted lossily) and R128 gains, in steps, as `Int16`.
+pub(crate) trait ArrowValue: Sized {
+    fn data_type() -> DataType;
+
+    fn array(values: Vec<Option<&Self>>) -> ArrayRef;
+}
+
+impl ArrowValue for u32 {
+    fn data_type() -> DataType {
+        DataType::UInt32
+    }
+
+    fn array(values: Vec<Option<&Self>>) -> ArrayRef {
+        Arc::new(
+            values
+                .into_iter()
+                .map(Option::<&Self>::copied)
+                .collect::<UInt32Array>(),
+        )
+    }
+}
+
+impl ArrowValue for f64 {
+    fn data_type() -> DataType {
+        DataType::Float64
+    }
+
+    fn array(values: Vec<Option<&Self>>) -> ArrayRef {
+        Arc::new(
+            values
+                .into_iter()
+                .map(Option::<&Self>::copied)
+                .collect::<Float64Array>(),
+        )
+    }
+}
+
+impl ArrowValue for bool {
+    fn data_type() -> DataType {
+        DataType::Boolean
+    }
+
+    fn array(values: Vec<Option<&Self>>) -> ArrayRef {
+        Arc::new(
+            values
+                .into_iter()
+                .map(Option::<&Self>::copied)
+                .collect::<BooleanArray>(),
+        )
+    }
+}
+
+impl ArrowValue for R128Gain {
+    fn data_type() -> DataType {
+        DataType::Int16
+    }
+
+    fn array(values: Vec<Option<&Self>>) -> ArrayRef {
+        let steps = values.into_iter().map(|value| value.map(|gain| gain.q78()));
+        Arc::new(steps.collect::<Int16Array>())
+    }
+}
+
+impl ArrowValue for String {
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+
+    fn array(values: Vec<Option<&Self>>) -> ArrayRef {
+        Arc::new(values.into_iter().collect::<StringArray>())
+    }
+}
+
+impl ArrowValue for PathBuf {
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+
+    fn array(values: Vec<Option<&Self>>) -> ArrayRef {
+        let paths = values
+            .into_iter()
+            .map(|value| value.map(|path| path.to_string_lossy()));
+        Arc::new(paths.collect::<StringArray>())
+    }
+}
+
+/// A field type that is stored as an Arrow array, nullable or not.
+pub(crate) trait ArrowColumn {
+    type Value: ArrowValue;
+
+    const NULLABLE: bool;
+
+    fn value(&self) -> Option<&Self::Value>;
+}
+
+impl<T: ArrowValue> ArrowColumn for T {
+    type Value = T;
+
+    const NULLABLE: bool = false;
+
+    fn value(&self) -> Option<&T> {
+        Some(self)
+    }
+}
+
+impl<T: ArrowValue> ArrowColumn for Option<T> {
+    type Value = T;
+
+    const NULLABLE: bool = true;
+
+    fn value(&self) -> Option<&T> {
+        self.as_ref()
+    }
+}
+
+/// The field `name` holding values of type `T`.
+pub(crate) fn field<T: ArrowColumn>(name: &str) -> Field {
+    Field::new(name, T::Value::data_type(), T::NULLABLE)
+}
+
+/// The array of `values`, one for each row.
+pub(crate) fn array<'a, T: ArrowColumn + 'a>(values: impl Iterator<Item = &'a T>) -> ArrayRef {
+    T::Value::array(values.map(ArrowColumn::value).collect())
+}
+
+/// The batch of `columns`, which were made to fit `schema`.
+pub(crate) fn record_batch(schema: Schema, columns: Vec<ArrayRef>) -> RecordBatch {
+    RecordBatch::try_new(Arc::new(schema), columns)
+        .expect("an array of every field, of its type and as long as the others")
+}
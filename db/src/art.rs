@@ -0,0 +1,146 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use imagesize::{ImageError, ImageType};
+
+use crate::Library;
+
+/// The size and format of an album's cover, read by [`Library::probe_art`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct ArtProbe {
+    pub album_id: u32,
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    /// Such as `JPEG` or `PNG`.
+    pub format: String,
+}
+
+/// A cover worth upgrading, found by [`Library::probe_art`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(tag = "kind", rename_all = "snake_case")
+)]
+pub enum ArtFinding {
+    /// The `artpath` is missing or not an image that can be read.
+    Unreadable {
+        album_id: u32,
+        path: PathBuf,
+        reason: String,
+    },
+    /// A side is shorter than [`ArtThresholds::min_side`].
+    Tiny {
+        album_id: u32,
+        width: u32,
+        height: u32,
+    },
+    /// The sides differ by more than [`ArtThresholds::square_tolerance`].
+    NonSquare {
+        album_id: u32,
+        width: u32,
+        height: u32,
+    },
+}
+
+/// Limits for [`Library::probe_art`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ArtThresholds {
+    /// The shortest acceptable side, in pixels.
+    pub min_side: u32,
+    /// How much the sides may differ, as a fraction of the longer side.
+    pub square_tolerance: f64,
+}
+
+impl Default for ArtThresholds {
+    fn default() -> Self {
+        Self {
+            min_side: 500,
+            square_tolerance: 0.01,
+        }
+    }
+}
+
+/// The covers read by [`Library::probe_art`] and the problems with them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ArtReport {
+    pub probes: Vec<ArtProbe>,
+    pub findings: Vec<ArtFinding>,
+}
+
+fn format_name(image_type: ImageType) -> String {
+    match image_type {
+        ImageType::Heif(_) => "HEIF".to_string(),
+        other => format!("{other:?}").to_uppercase(),
+    }
+}
+
+/// The width, height and format of an image, reading only its header.
+fn probe(path: &Path) -> Result<(u32, u32, String), ImageError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let image_type = imagesize::image_type(reader.fill_buf()?)?;
+    let size = imagesize::reader_size(reader)?;
+    let pixels = |length| u32::try_from(length).map_err(|_| ImageError::CorruptedImage);
+    Ok((
+        pixels(size.width)?,
+        pixels(size.height)?,
+        format_name(image_type),
+    ))
+}
+
+impl Library {
+    /// Read the size and format of every album's `artpath`, in library order,
+    /// flagging covers that are unreadable, tiny or not square.
+    ///
+    /// Only the start of each file is read. Albums without art are skipped.
+    #[must_use]
+    pub fn probe_art(&self, thresholds: &ArtThresholds) -> ArtReport {
+        let mut report = ArtReport::default();
+        for album in &self.albums {
+            let album_id = album.id;
+            let Some(path) = &album.artpath else {
+                continue;
+            };
+            let (width, height, format) = match probe(path) {
+                Ok(probed) => probed,
+                Err(err) => {
+                    report.findings.push(ArtFinding::Unreadable {
+                        album_id,
+                        path: path.clone(),
+                        reason: err.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            if width.min(height) < thresholds.min_side {
+                report.findings.push(ArtFinding::Tiny {
+                    album_id,
+                    width,
+                    height,
+                });
+            }
+            let (long, short) = (width.max(height), width.min(height));
+            if f64::from(long - short) > f64::from(long) * thresholds.square_tolerance {
+                report.findings.push(ArtFinding::NonSquare {
+                    album_id,
+                    width,
+                    height,
+                });
+            }
+            report.probes.push(ArtProbe {
+                album_id,
+                path: path.clone(),
+                width,
+                height,
+                format,
+            });
+        }
+        report
+    }
+}
@@ -0,0 +1,353 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::hash::BuildHasher;
+
+use crate::{Album, Item, Library};
+
+/// The artists named in one artist credit, as split by [`ArtistSplitter`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ArtistCredit<'a> {
+    /// The main artists, in credited order.
+    pub primary: Vec<&'a str>,
+    /// Guest artists, from after a marker such as `feat.`.
+    pub featured: Vec<&'a str>,
+}
+
+impl<'a> ArtistCredit<'a> {
+    /// Every artist, primary first.
+    pub fn all(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.primary.iter().chain(&self.featured).copied()
+    }
+}
+
+/// Splits credits like `A feat. B`, `A & B` or `A; B` into separate artists.
+///
+/// Markers and separators match ignoring ASCII case. Start from
+/// [`ArtistSplitter::new`] for common defaults, or [`ArtistSplitter::empty`]
+/// to configure every rule.
+#[derive(Clone, Debug)]
+pub struct ArtistSplitter {
+    featuring: Vec<String>,
+    separators: Vec<String>,
+}
+
+impl Default for ArtistSplitter {
+    fn default() -> Self {
+        let owned = |words: &[&str]| words.iter().map(|word| (*word).to_string()).collect();
+        Self {
+            featuring: owned(&["feat.", "feat", "ft.", "ft", "featuring"]),
+            separators: owned(&[" & ", "; ", ", ", " / ", " x "]),
+        }
+    }
+}
+
+impl ArtistSplitter {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A splitter without any markers or separators.
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            featuring: vec![],
+            separators: vec![],
+        }
+    }
+
+    /// Treat artists after the word `marker` (e.g. `"with"`) as featured.
+    pub fn featuring(&mut self, marker: impl Into<String>) -> &mut Self {
+        self.featuring
+            .push(marker.into().trim().to_ascii_lowercase());
+        self
+    }
+
+    /// Split artists at `separator`, spaces included (e.g. `" and "`).
+    pub fn separator(&mut self, separator: impl Into<String>) -> &mut Self {
+        self.separators.push(separator.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Split a credit into primary and featured artists.
+    #[must_use]
+    pub fn split<'a>(&self, credit: &'a str) -> ArtistCredit<'a> {
+        // a featured part is often bracketed, as in "A (feat. B)"
+        let (primary, featured) = match find_marker(credit, &self.featuring) {
+            Some((start, end)) => {
+                let primary = credit[..start].trim_end_matches([' ', '(', '[']);
+                let featured = credit[end..].trim_end_matches([' ', ')', ']']);
+                (primary, Some(featured))
+            }
+            None => (credit, None),
+        };
+        ArtistCredit {
+            primary: self.split_list(primary),
+            featured: featured.map_or_else(Vec::new, |featured| self.split_list(featured)),
+        }
+    }
+
+    fn split_list<'a>(&self, mut list: &'a str) -> Vec<&'a str> {
+        let mut artists = vec![];
+        while let Some((start, end)) = find_first(list, &self.separators) {
+            artists.push(list[..start].trim());
+            list = &list[end..];
+        }
+        artists.push(list.trim());
+        artists.retain(|artist| !artist.is_empty());
+        artists
+    }
+}
+
+/// The byte range of the earliest of `patterns` in `text`, ignoring ASCII case.
+fn find_first(text: &str, patterns: &[String]) -> Option<(usize, usize)> {
+    // ASCII lowercasing keeps byte offsets, so they apply to `text` too
+    let lower = text.to_ascii_lowercase();
+    patterns
+        .iter()
+        .filter(|pattern| !pattern.is_empty())
+        .filter_map(|pattern| {
+            lower
+                .find(pattern.as_str())
+                .map(|start| (start, start + pattern.len()))
+        })
+        .min()
+}
+
+/// Like [`find_first`], for whole words that follow a space or an opening
+/// bracket and are followed by a space.
+fn find_marker(text: &str, markers: &[String]) -> Option<(usize, usize)> {
+    let lower = text.to_ascii_lowercase();
+    markers
+        .iter()
+        .filter(|marker| !marker.is_empty())
+        .filter_map(|marker| {
+            lower
+                .match_indices(marker.as_str())
+                .map(|(start, _)| (start, start + marker.len()))
+                .find(|&(start, end)| {
+                    lower[..start].ends_with([' ', '(', '[']) && lower[end..].starts_with(' ')
+                })
+        })
+        .min()
+}
+
+impl Library {
+    /// Every distinct track artist, primary or featured, in sorted order.
+    #[must_use]
+    pub fn credited_artists<'a>(&'a self, splitter: &ArtistSplitter) -> Vec<&'a str> {
+        self.items
+            .iter()
+            .flat_map(|item| splitter.split(&item.artist).all().collect::<Vec<_>>())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Items on which `artist` is credited, either as a primary or featured
+    /// artist, ignoring case.
+    #[must_use]
+    pub fn artist_items(&self, splitter: &ArtistSplitter, artist: &str) -> Vec<&Item> {
+        self.filter_items(|item| {
+            splitter
+                .split(&item.artist)
+                .all()
+                .any(|credited| credited.eq_ignore_ascii_case(artist))
+        })
+    }
+}
+
+/// Totals for one artist, as shown on an artist page.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct ArtistStats {
+    pub artist: String,
+    /// Albums on which the artist is credited as an album artist.
+    pub albums: u32,
+    /// Tracks on which the artist is credited, either as a primary or
+    /// featured artist.
+    pub tracks: u32,
+    /// The length of those tracks, in seconds.
+    pub length: f64,
+    /// The first and last year of those tracks, ignoring unknown years.
+    pub years: Option<(u32, u32)>,
+    /// The most common genre of those tracks, the first alphabetically on a
+    /// tie.
+    pub genre: Option<String>,
+}
+
+/// The stats of `artist` so far, and how many of their tracks have each genre.
+fn tally<'m, 'a>(
+    artists: &'m mut BTreeMap<&'a str, (ArtistStats, BTreeMap<&'a str, u32>)>,
+    artist: &'a str,
+) -> &'m mut (ArtistStats, BTreeMap<&'a str, u32>) {
+    artists.entry(artist).or_insert_with(|| {
+        let stats = ArtistStats {
+            artist: artist.to_string(),
+            ..ArtistStats::default()
+        };
+        (stats, BTreeMap::new())
+    })
+}
+
+impl Library {
+    /// [`ArtistStats`] for every artist credited on an album or track, in
+    /// sorted order.
+    #[must_use]
+    pub fn artist_stats(&self, splitter: &ArtistSplitter) -> Vec<ArtistStats> {
+        let mut artists = BTreeMap::new();
+        for album in &self.albums {
+            for artist in splitter
+                .split(&album.albumartist)
+                .all()
+                .collect::<BTreeSet<_>>()
+            {
+                tally(&mut artists, artist).0.albums += 1;
+            }
+        }
+        for item in &self.items {
+            for artist in splitter.split(&item.artist).all().collect::<BTreeSet<_>>() {
+                let (stats, genres) = tally(&mut artists, artist);
+                stats.tracks += 1;
+                stats.length += item.length;
+                if item.year != 0 {
+                    stats.years =
+                        Some(stats.years.map_or((item.year, item.year), |(first, last)| {
+                            (first.min(item.year), last.max(item.year))
+                        }));
+                }
+                if !item.genre.is_empty() {
+                    *genres.entry(&item.genre).or_default() += 1;
+                }
+            }
+        }
+        artists
+            .into_values()
+            .map(|(mut stats, genres)| {
+                stats.genre = genres
+                    .into_iter()
+                    .max_by_key(|&(genre, count)| (count, Reverse(genre)))
+                    .map(|(genre, _)| genre.to_string());
+                stats
+            })
+            .collect()
+    }
+}
+
+/// Leading articles moved to the end of a sort name, compared ignoring case.
+const ARTICLES: &[&str] = &[
+    "the", "a", "an", "les", "la", "le", "l'", "die", "der", "das", "el", "los", "las", "il",
+];
+
+/// Name suffixes kept after the given names, as in `Davis, Sammy, Jr.`.
+const SUFFIXES: &[&str] = &["jr", "jr.", "sr", "sr.", "ii", "iii", "iv"];
+
+/// Generate a sort name the way MusicBrainz writes them for groups: `The
+/// Beatles` sorts as `Beatles, The`, and other names as they are.
+///
+/// Whether a name is a person's cannot be told from the name alone (`Daft
+/// Punk` is not `Punk, Daft`), so people are left to [`person_sort_name`].
+#[must_use]
+pub fn sort_name(name: &str) -> String {
+    let name = name.trim();
+    let words: Vec<&str> = name.split_whitespace().collect();
+    match words.split_first() {
+        Some((first, rest))
+            if !rest.is_empty() && ARTICLES.contains(&first.to_lowercase().as_str()) =>
+        {
+            format!("{}, {first}", rest.join(" "))
+        }
+        _ => name.to_string(),
+    }
+}
+
+/// Generate a sort name the way MusicBrainz writes them for people, as
+/// `Last, First` (`Ludwig van Beethoven` as `Beethoven, Ludwig van`).
+///
+/// Only use it for names known to be a person's, e.g. an artist MusicBrainz
+/// gives the type `Person`.
+#[must_use]
+pub fn person_sort_name(name: &str) -> String {
+    let name = name.trim();
+    let words: Vec<&str> = name.split_whitespace().collect();
+    let (names, suffix) = match words.split_last() {
+        Some((last, names)) if SUFFIXES.contains(&last.to_lowercase().as_str()) => {
+            (names, Some(*last))
+        }
+        _ => (&words[..], None),
+    };
+    // the surname is the last capitalized word, anything after it is a particle
+    let Some(surname) = names
+        .iter()
+        .rposition(|word| word.chars().next().is_some_and(char::is_uppercase))
+        .filter(|&surname| surname > 0)
+    else {
+        return name.to_string();
+    };
+    let mut sorted = format!(
+        "{}, {}",
+        names[surname..].join(" "),
+        names[..surname].join(" ")
+    );
+    if let Some(suffix) = suffix {
+        sorted.push_str(", ");
+        sorted.push_str(suffix);
+    }
+    sorted
+}
+
+impl Item {
+    /// The `artist_sort` tag, or one generated by [`sort_name`] if it is
+    /// empty.
+    #[must_use]
+    pub fn artist_sort_name(&self) -> Cow<'_, str> {
+        or_sort_name(&self.artist_sort, &self.artist)
+    }
+}
+
+impl Album {
+    /// The `albumartist_sort` tag, or one generated by [`sort_name`] if it is
+    /// empty.
+    #[must_use]
+    pub fn albumartist_sort_name(&self) -> Cow<'_, str> {
+        or_sort_name(&self.albumartist_sort, &self.albumartist)
+    }
+}
+
+fn or_sort_name<'a>(sort: &'a str, name: &str) -> Cow<'a, str> {
+    if sort.trim().is_empty() {
+        Cow::Owned(sort_name(name))
+    } else {
+        Cow::Borrowed(sort)
+    }
+}
+
+impl Library {
+    /// Fill every empty `artist_sort` and `albumartist_sort` with a generated
+    /// sort name, so sorting by them gives a consistent order: a
+    /// [`person_sort_name`] for the artists in `people`, e.g. those
+    /// MusicBrainz gives the type `Person`, and a [`sort_name`] for the rest.
+    pub fn fill_sort_names<S: BuildHasher>(&mut self, people: &HashSet<String, S>) {
+        let generate = |name: &str| {
+            if people.contains(name.trim()) {
+                person_sort_name(name)
+            } else {
+                sort_name(name)
+            }
+        };
+        for album in &mut self.albums {
+            if album.albumartist_sort.trim().is_empty() {
+                album.albumartist_sort = generate(&album.albumartist);
+            }
+        }
+        for item in &mut self.items {
+            if item.artist_sort.trim().is_empty() {
+                item.artist_sort = generate(&item.artist);
+            }
+            if item.albumartist_sort.trim().is_empty() {
+                item.albumartist_sort = generate(&item.albumartist);
+            }
+        }
+    }
+}
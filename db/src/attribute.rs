@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+
+use crate::trace::{self, Hooks};
+use crate::undo::Rows;
+use crate::{Album, Attribute, Database, Error, FieldValue, Item, ReadWrite};
+
+/// An item or album by id, which flexible attributes belong to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize, Serialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Entity {
+    Item(u32),
+    Album(u32),
+}
+
+impl Entity {
+    /// The table holding the attributes of this kind of entity.
+    #[must_use]
+    pub fn attribute_table(self) -> &'static str {
+        match self {
+            Entity::Item(_) => "item_attributes",
+            Entity::Album(_) => "album_attributes",
+        }
+    }
+
+    #[must_use]
+    pub fn id(self) -> u32 {
+        match self {
+            Entity::Item(id) | Entity::Album(id) => id,
+        }
+    }
+}
+
+/// Set the flexible attribute `key` of `entity` to `value`, replacing any
+/// value it had, as `beet modify key=value` does.
+///
+/// Beets reads the fixed fields (`title`, `year`, ...) from their own
+/// columns, so an attribute named like one of them is never seen. Values are
+/// stored as text, as beets stores them, and parsed again by the type a
+/// plugin gives the attribute, so numbers such as a rating can be passed as
+/// they are.
+///
+/// # Errors
+/// Returns an error if the connection is read-only or the table is missing
+pub fn set_attribute<'v>(
+    conn: &Connection,
+    entity: Entity,
+    key: &str,
+    value: impl Into<FieldValue<'v>>,
+) -> Result<(), Error> {
+    let sql = format!(
+        "INSERT OR REPLACE INTO {} (entity_id, key, value) VALUES (?1, ?2, ?3)",
+        entity.attribute_table()
+    );
+    conn.execute(&sql, (entity.id(), key, value.into()))
+        .map_err(Error::write)?;
+    Ok(())
+}
+
+/// Remove the flexible attribute `key` of `entity`, as `beet modify key!`
+/// does, returning whether it was set.
+///
+/// # Errors
+/// Returns an error if the connection is read-only or the table is missing
+pub fn delete_attribute(conn: &Connection, entity: Entity, key: &str) -> Result<bool, Error> {
+    let sql = format!(
+        "DELETE FROM {} WHERE entity_id = ?1 AND key = ?2",
+        entity.attribute_table()
+    );
+    let deleted = conn
+        .execute(&sql, (entity.id(), key))
+        .map_err(Error::write)?;
+    Ok(deleted > 0)
+}
+
+/// [`set_attribute`] through a writable handle, recorded in its journal.
+pub(crate) fn set_recorded<'v>(
+    db: &Database<ReadWrite>,
+    entity: Entity,
+    key: &str,
+    value: impl Into<FieldValue<'v>>,
+) -> Result<(), Error> {
+    db.write(&format!("set {key} of {}", describe(entity)), || {
+        let rows = Rows::EntityKey(entity.id(), key.to_string());
+        db.record(entity.attribute_table(), &rows)?;
+        set_attribute(db.connection(), entity, key, value)
+    })
+}
+
+/// [`delete_attribute`] through a writable handle, recorded in its journal.
+fn delete_recorded(db: &Database<ReadWrite>, entity: Entity, key: &str) -> Result<bool, Error> {
+    db.write(&format!("remove {key} of {}", describe(entity)), || {
+        let rows = Rows::EntityKey(entity.id(), key.to_string());
+        db.record(entity.attribute_table(), &rows)?;
+        delete_attribute(db.connection(), entity, key)
+    })
+}
+
+fn describe(entity: Entity) -> String {
+    match entity {
+        Entity::Item(id) => format!("item {id}"),
+        Entity::Album(id) => format!("album {id}"),
+    }
+}
+
+impl Item {
+    /// Set the flexible attribute `key` of the item. See [`set_attribute`].
+    ///
+    /// # Errors
+    /// Returns an error if the table is missing
+    pub fn set_attribute<'v>(
+        &self,
+        db: &Database<ReadWrite>,
+        key: &str,
+        value: impl Into<FieldValue<'v>>,
+    ) -> Result<(), Error> {
+        set_recorded(db, Entity::Item(self.id), key, value)
+    }
+
+    /// Remove the flexible attribute `key` of the item, returning whether it
+    /// was set.
+    ///
+    /// # Errors
+    /// Returns an error if the table is missing
+    pub fn remove_attribute(&self, db: &Database<ReadWrite>, key: &str) -> Result<bool, Error> {
+        delete_recorded(db, Entity::Item(self.id), key)
+    }
+}
+
+impl Album {
+    /// Set the flexible attribute `key` of the album. See [`set_attribute`].
+    ///
+    /// # Errors
+    /// Returns an error if the table is missing
+    pub fn set_attribute<'v>(
+        &self,
+        db: &Database<ReadWrite>,
+        key: &str,
+        value: impl Into<FieldValue<'v>>,
+    ) -> Result<(), Error> {
+        set_recorded(db, Entity::Album(self.id), key, value)
+    }
+
+    /// Remove the flexible attribute `key` of the album, returning whether it
+    /// was set.
+    ///
+    /// # Errors
+    /// Returns an error if the table is missing
+    pub fn remove_attribute(&self, db: &Database<ReadWrite>, key: &str) -> Result<bool, Error> {
+        delete_recorded(db, Entity::Album(self.id), key)
+    }
+}
+
+/// A record along with its flexible attributes, by key.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct WithAttributes<T> {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub record: T,
+    pub attributes: HashMap<String, String>,
+}
+
+impl<T> WithAttributes<T> {
+    /// Pair each of `records` with its attributes from `map`, as read by
+    /// [`Attribute::read_item_map`] or [`Attribute::read_album_map`].
+    fn attach(
+        records: Vec<T>,
+        id: fn(&T) -> u32,
+        mut map: HashMap<u32, Vec<Attribute>>,
+    ) -> Vec<Self> {
+        records
+            .into_iter()
+            .map(|record| {
+                let attributes = map
+                    .remove(&id(&record))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|attribute| (attribute.key, attribute.value))
+                    .collect();
+                Self { record, attributes }
+            })
+            .collect()
+    }
+}
+
+impl Attribute {
+    fn read_table(conn: &Connection, table: &str, hooks: Hooks) -> Result<Vec<Self>, Error> {
+        // plugins may store typed values, which are read as their text
+        let sql = format!(
+            "SELECT id, entity_id, key, CAST(value AS TEXT) FROM {table} ORDER BY entity_id, id"
+        );
+        trace::query_all(conn, table, &sql, (), Self::from_row, hooks)
+    }
+
+    pub(crate) fn read_map_traced(
+        conn: &Connection,
+        table: &str,
+        hooks: Hooks,
+    ) -> Result<HashMap<u32, Vec<Self>>, Error> {
+        let mut map: HashMap<u32, Vec<Self>> = HashMap::new();
+        for attribute in Self::read_table(conn, table, hooks)? {
+            map.entry(attribute.entity_id).or_default().push(attribute);
+        }
+        Ok(map)
+    }
+
+    /// The flexible attributes of one item or album, in the order they were
+    /// set.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn read_for(conn: &Connection, entity: Entity) -> Result<Vec<Self>, Error> {
+        Self::read_for_traced(conn, entity, Hooks::default())
+    }
+
+    pub(crate) fn read_for_traced(
+        conn: &Connection,
+        entity: Entity,
+        hooks: Hooks,
+    ) -> Result<Vec<Self>, Error> {
+        let table = entity.attribute_table();
+        let sql = format!(
+            "SELECT id, entity_id, key, CAST(value AS TEXT) FROM {table} WHERE entity_id = ?1 ORDER BY id"
+        );
+        trace::query_all(conn, table, &sql, [entity.id()], Self::from_row, hooks)
+    }
+
+    /// Reads every row of `item_attributes`, by item and then in the order
+    /// they were set.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn read_all_for_items(conn: &Connection) -> Result<Vec<Self>, Error> {
+        Self::read_table(conn, "item_attributes", Hooks::default())
+    }
+
+    /// Reads every row of `album_attributes`, by album and then in the order
+    /// they were set.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn read_all_for_albums(conn: &Connection) -> Result<Vec<Self>, Error> {
+        Self::read_table(conn, "album_attributes", Hooks::default())
+    }
+
+    /// Reads the flexible attributes of every item in one query, by item id.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn read_item_map(conn: &Connection) -> Result<HashMap<u32, Vec<Self>>, Error> {
+        Self::read_map_traced(conn, "item_attributes", Hooks::default())
+    }
+
+    /// Reads the flexible attributes of every album in one query, by album id.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn read_album_map(conn: &Connection) -> Result<HashMap<u32, Vec<Self>>, Error> {
+        Self::read_map_traced(conn, "album_attributes", Hooks::default())
+    }
+}
+
+impl Item {
+    /// Like [`Item::read_all`], with the flexible attributes of each item.
+    ///
+    /// # Errors
+    /// Returns an error if an SQL query fails
+    pub fn read_all_with_attributes(conn: &Connection) -> Result<Vec<WithAttributes<Self>>, Error> {
+        let map = Attribute::read_item_map(conn)?;
+        Ok(WithAttributes::attach(
+            Self::read_all(conn)?,
+            |item| item.id,
+            map,
+        ))
+    }
+}
+
+impl Album {
+    /// Like [`Album::read_all`], with the flexible attributes of each album.
+    ///
+    /// # Errors
+    /// Returns an error if an SQL query fails
+    pub fn read_all_with_attributes(conn: &Connection) -> Result<Vec<WithAttributes<Self>>, Error> {
+        let map = Attribute::read_album_map(conn)?;
+        Ok(WithAttributes::attach(
+            Self::read_all(conn)?,
+            |album| album.id,
+            map,
+        ))
+    }
+}
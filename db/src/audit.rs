@@ -0,0 +1,110 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{Item, Library};
+
+const LOSSLESS_FORMATS: &[&str] = &["FLAC", "ALAC", "WAV", "AIFF", "APE", "WavPack"];
+
+impl Item {
+    /// Whether the item is in a lossless format, such as FLAC.
+    #[must_use]
+    pub fn is_lossless(&self) -> bool {
+        LOSSLESS_FORMATS
+            .iter()
+            .any(|format| format.eq_ignore_ascii_case(&self.format))
+    }
+}
+
+/// A problem found by [`Library::quality_audit`], worth a re-rip.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(tag = "kind", rename_all = "snake_case")
+)]
+pub enum QualityFinding {
+    /// A lossy item below [`QualityThresholds::min_bitrate`].
+    LowBitrate { item_id: u32, bitrate: u32 },
+    /// A lossy item on an album that is mostly lossless.
+    LossyInLosslessAlbum {
+        album_id: u32,
+        item_id: u32,
+        format: String,
+    },
+    /// An album whose items have different sample rates, in increasing order.
+    MixedSampleRates {
+        album_id: u32,
+        samplerates: Vec<u32>,
+    },
+    /// A lossless item without a bit depth, e.g. tagged by an old beets.
+    MissingBitDepth { item_id: u32 },
+}
+
+/// Limits for [`Library::quality_audit`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QualityThresholds {
+    /// The lowest acceptable bitrate of a lossy item, in bits per second.
+    pub min_bitrate: u32,
+}
+
+impl Default for QualityThresholds {
+    fn default() -> Self {
+        Self {
+            min_bitrate: 192_000,
+        }
+    }
+}
+
+impl Library {
+    /// Look for items worth replacing: the problems with single items first,
+    /// in library order, then those with whole albums by album id.
+    ///
+    /// Unknown bitrates and sample rates (stored as zero) are not flagged.
+    #[must_use]
+    pub fn quality_audit(&self, thresholds: &QualityThresholds) -> Vec<QualityFinding> {
+        let mut findings = vec![];
+        let mut albums: BTreeMap<u32, Vec<&Item>> = BTreeMap::new();
+        for item in &self.items {
+            if item.is_lossless() {
+                if item.bitdepth == 0 {
+                    findings.push(QualityFinding::MissingBitDepth { item_id: item.id });
+                }
+            } else if item.bitrate != 0 && item.bitrate < thresholds.min_bitrate {
+                findings.push(QualityFinding::LowBitrate {
+                    item_id: item.id,
+                    bitrate: item.bitrate,
+                });
+            }
+            if let Some(album_id) = item.album_id {
+                albums.entry(album_id).or_default().push(item);
+            }
+        }
+
+        for (album_id, items) in albums {
+            let (lossless, lossy): (Vec<&Item>, Vec<&Item>) =
+                items.iter().partition(|item| item.is_lossless());
+            if lossless.len() > lossy.len() {
+                findings.extend(
+                    lossy
+                        .iter()
+                        .map(|item| QualityFinding::LossyInLosslessAlbum {
+                            album_id,
+                            item_id: item.id,
+                            format: item.format.clone(),
+                        }),
+                );
+            }
+            let samplerates: BTreeSet<u32> = items
+                .iter()
+                .map(|item| item.samplerate)
+                .filter(|&rate| rate != 0)
+                .collect();
+            if samplerates.len() > 1 {
+                findings.push(QualityFinding::MixedSampleRates {
+                    album_id,
+                    samplerates: samplerates.into_iter().collect(),
+                });
+            }
+        }
+        findings
+    }
+}
@@ -0,0 +1,203 @@
+//! Compatibility with the JSON written by the beets [`export`] plugin.
+//!
+//! `beet export` writes each field the way `beet ls` would display it: numbers
+//! are zero-padded strings, lengths are `M:SS`, bitrates carry a `kbps` suffix
+//! and so on. Wrapping a record in [`BeetsExport`] (de)serializes it with those
+//! conventions instead of the plain ones derived on the struct.
+//!
+//! [`export`]: https://beets.readthedocs.io/en/stable/plugins/export.html
+
+use serde::de::{DeserializeOwned, Deserializer, Error as _};
+use serde::ser::{Error as _, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{Album, Item};
+
+/// A record (de)serialized with the field formats of `beet export`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BeetsExport<T>(pub T);
+
+/// A record that `beet export` can write.
+pub trait Record: Serialize + DeserializeOwned {
+    /// The name of every field, in schema order.
+    const FIELDS: &'static [&'static str];
+}
+
+impl Record for Album {
+    const FIELDS: &'static [&'static str] = Self::COLUMNS;
+}
+
+impl Record for Item {
+    const FIELDS: &'static [&'static str] = Self::COLUMNS;
+}
+
+/// How beets displays the value of a field.
+#[derive(Clone, Copy, Debug)]
+enum Format {
+    Text,
+    Integer,
+    /// A number that may have a fraction, shown without one when it is whole.
+    Number,
+    ForeignId,
+    Padded(usize),
+    Scaled(i64, &'static str),
+    Float(usize),
+    Boolean,
+    Duration,
+    Path,
+    /// Timestamps are not serialized by this crate, so they are dropped.
+    Date,
+}
+
+fn format_of(field: &str) -> Format {
+    match field {
+        "id" | "bitdepth" | "channels" => Format::Integer,
+        "bpm" => Format::Number,
+        "album_id" => Format::ForeignId,
+        "year" | "original_year" => Format::Padded(4),
+        "month" | "day" | "original_month" | "original_day" | "track" | "tracktotal" | "disc"
+        | "disctotal" => Format::Padded(2),
+        "r128_track_gain" | "r128_album_gain" => Format::Padded(6),
+        "bitrate" => Format::Scaled(1000, "kbps"),
+        "samplerate" => Format::Scaled(1000, "kHz"),
+        "rg_track_gain" | "rg_track_peak" | "rg_album_gain" | "rg_album_peak" => Format::Float(1),
+        "comp" => Format::Boolean,
+        "length" => Format::Duration,
+        "path" | "artpath" => Format::Path,
+        "added" | "mtime" => Format::Date,
+        _ => Format::Text,
+    }
+}
+
+/// Fields that may be absent, as opposed to defaulting to zero.
+fn is_nullable(field: &str) -> bool {
+    matches!(
+        field,
+        "album_id"
+            | "artpath"
+            | "initial_key"
+            | "rg_track_gain"
+            | "rg_track_peak"
+            | "rg_album_gain"
+            | "rg_album_peak"
+            | "r128_track_gain"
+            | "r128_album_gain"
+    )
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn display(field: &str, value: &Value) -> Option<String> {
+    let format = format_of(field);
+    if value.is_null() && is_nullable(field) {
+        return match format {
+            Format::Date => None,
+            _ => Some(String::new()),
+        };
+    }
+
+    let int = || value.as_i64().unwrap_or_default();
+    let float = || value.as_f64().unwrap_or_default();
+    Some(match format {
+        Format::Text | Format::Path => match value {
+            Value::Null => String::new(),
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        },
+        Format::Integer | Format::ForeignId => match value {
+            Value::Null => String::new(),
+            other => other.to_string(),
+        },
+        Format::Number => match value.as_f64() {
+            Some(number) if number.fract() == 0.0 => format!("{}", number as i64),
+            Some(number) => number.to_string(),
+            None => String::new(),
+        },
+        Format::Padded(width) => format!("{:0width$}", int(), width = width),
+        Format::Scaled(unit, suffix) => format!("{}{}", int() / unit, suffix),
+        Format::Float(digits) => format!("{:.digits$}", float(), digits = digits),
+        Format::Boolean => String::from(if value.as_bool().unwrap_or_default() {
+            "True"
+        } else {
+            "False"
+        }),
+        Format::Duration => {
+            let secs = float().max(0.0) as u64;
+            format!("{}:{:02}", secs / 60, secs % 60)
+        }
+        Format::Date => return None,
+    })
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn parse<E: serde::de::Error>(field: &str, format: Format, text: &str) -> Result<Option<Value>, E> {
+    let text = text.trim();
+    let invalid = || E::custom(format_args!("invalid value {text:?} for field {field:?}"));
+    let int = |s: &str| s.trim().parse::<i64>().map_err(|_| invalid());
+    let float = |s: &str| s.trim().parse::<f64>().map_err(|_| invalid());
+
+    if text.is_empty() && (is_nullable(field) || !matches!(format, Format::Text)) {
+        // fall back to the field's default
+        return Ok(None);
+    }
+
+    Ok(Some(match format {
+        Format::Text | Format::Path => Value::from(text),
+        Format::ForeignId => match int(text)? {
+            0 => return Ok(None),
+            id => Value::from(id),
+        },
+        Format::Integer | Format::Padded(_) => Value::from(int(text)?),
+        Format::Scaled(unit, suffix) => {
+            Value::from(int(text.strip_suffix(suffix).unwrap_or(text))? * unit)
+        }
+        Format::Float(_) | Format::Number => Value::from(float(text)?),
+        Format::Boolean => match text {
+            "True" | "true" | "1" => Value::from(true),
+            "False" | "false" | "0" => Value::from(false),
+            _ => return Err(invalid()),
+        },
+        Format::Duration => Value::from(
+            text.split(':')
+                .try_fold(0.0, |total, part| Ok(total * 60.0 + float(part)?))?,
+        ),
+        Format::Date => return Ok(None),
+    }))
+}
+
+impl<T: Record> Serialize for BeetsExport<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Value::Object(fields) = serde_json::to_value(&self.0).map_err(S::Error::custom)? else {
+            return Err(S::Error::custom("record did not serialize as a map"));
+        };
+
+        T::FIELDS
+            .iter()
+            .filter_map(|&field| {
+                let value = fields.get(field).unwrap_or(&Value::Null);
+                display(field, value).map(|text| (field.to_owned(), Value::from(text)))
+            })
+            .collect::<Map<_, _>>()
+            .serialize(serializer)
+    }
+}
+
+impl<'de, T: Record> Deserialize<'de> for BeetsExport<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = Map::new();
+        for (field, value) in Map::<String, Value>::deserialize(deserializer)? {
+            let value = match value {
+                Value::String(text) => parse(&field, format_of(&field), &text)?,
+                // already in this crate's own representation
+                other => Some(other),
+            };
+            if let Some(value) = value {
+                fields.insert(field, value);
+            }
+        }
+
+        serde_json::from_value(Value::Object(fields))
+            .map(BeetsExport)
+            .map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,386 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::{Album, FieldError, FieldValue, Item};
+
+const MAGIC: &[u8] = b"SQLite format 3\0";
+/// Deeper than any real b-tree, so a corrupt one cannot recurse forever.
+const MAX_DEPTH: usize = 64;
+
+const INTERIOR_TABLE: u8 = 0x05;
+const LEAF_TABLE: u8 = 0x0d;
+
+/// Why [`read_all_from_bytes`] could not read a library.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BytesError {
+    /// The bytes are not an SQLite database.
+    NotADatabase,
+    /// The database uses a feature this reader does not support, such as a
+    /// text encoding other than UTF-8.
+    Unsupported(&'static str),
+    /// A page or record points outside the file, or is malformed.
+    Corrupt,
+    /// The database has no table with this name.
+    MissingTable(&'static str),
+    /// A column holds a value its field cannot.
+    Column(FieldError),
+}
+
+impl fmt::Display for BytesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BytesError::NotADatabase => f.write_str("not an SQLite database"),
+            BytesError::Unsupported(feature) => write!(f, "unsupported database: {feature}"),
+            BytesError::Corrupt => f.write_str("the database is corrupt"),
+            BytesError::MissingTable(table) => write!(f, "no table named {table:?}"),
+            BytesError::Column(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BytesError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BytesError::Column(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// What [`File::scan`] calls with the rowid and record of each row.
+type Visit<'v> = dyn FnMut(i64, &[u8]) -> Result<(), BytesError> + 'v;
+
+/// A database file held in memory, read a page at a time as SQLite would.
+struct File<'a> {
+    bytes: &'a [u8],
+    page_size: usize,
+    /// The bytes of each page not reserved for extensions.
+    usable_size: usize,
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Result<usize, BytesError> {
+    let bytes = bytes.get(at..at + 2).ok_or(BytesError::Corrupt)?;
+    Ok(usize::from(u16::from_be_bytes([bytes[0], bytes[1]])))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Result<u32, BytesError> {
+    let bytes = bytes.get(at..at + 4).ok_or(BytesError::Corrupt)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// A variable-length integer at `at`, and how many bytes it took.
+fn read_varint(bytes: &[u8], at: usize) -> Result<(u64, usize), BytesError> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let byte = *bytes.get(at + i).ok_or(BytesError::Corrupt)?;
+        if i == 8 {
+            return Ok(((value << 8) | u64::from(byte), 9));
+        }
+        value = (value << 7) | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    unreachable!("the ninth byte always ends a varint")
+}
+
+fn to_usize(n: u64) -> Result<usize, BytesError> {
+    usize::try_from(n).map_err(|_| BytesError::Corrupt)
+}
+
+impl<'a> File<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self, BytesError> {
+        if bytes.len() < 100 || !bytes.starts_with(MAGIC) {
+            return Err(BytesError::NotADatabase);
+        }
+        let page_size = match read_u16(bytes, 16)? {
+            1 => 65536,
+            size if size >= 512 && size.is_power_of_two() => size,
+            _ => return Err(BytesError::NotADatabase),
+        };
+        if read_u32(bytes, 56)? > 1 {
+            return Err(BytesError::Unsupported("text is not UTF-8"));
+        }
+        let reserved = usize::from(bytes[20]);
+        let usable_size = page_size
+            .checked_sub(reserved)
+            .filter(|size| *size >= 480)
+            .ok_or(BytesError::Corrupt)?;
+        Ok(Self {
+            bytes,
+            page_size,
+            usable_size,
+        })
+    }
+
+    fn page(&self, number: u32) -> Result<&'a [u8], BytesError> {
+        let start = to_usize(u64::from(number).wrapping_sub(1))?
+            .checked_mul(self.page_size)
+            .ok_or(BytesError::Corrupt)?;
+        let end = start
+            .checked_add(self.page_size)
+            .ok_or(BytesError::Corrupt)?;
+        self.bytes.get(start..end).ok_or(BytesError::Corrupt)
+    }
+
+    /// Call `row` with the rowid and record of every row of the table whose
+    /// b-tree starts at page `root`, in order of rowid.
+    fn scan(&self, root: u32, row: &mut Visit) -> Result<(), BytesError> {
+        self.scan_page(root, 0, &mut HashSet::new(), row)
+    }
+
+    /// Scan the b-tree below page `number`. Every page of a real b-tree has
+    /// one parent, so a page seen twice (in `visited`) means the file is
+    /// corrupt, or crafted to make the scan take exponential time.
+    fn scan_page(
+        &self,
+        number: u32,
+        depth: usize,
+        visited: &mut HashSet<u32>,
+        row: &mut Visit,
+    ) -> Result<(), BytesError> {
+        if depth > MAX_DEPTH || !visited.insert(number) {
+            return Err(BytesError::Corrupt);
+        }
+        let page = self.page(number)?;
+        // the first page starts with the file header
+        let header = if number == 1 { 100 } else { 0 };
+        let kind = *page.get(header).ok_or(BytesError::Corrupt)?;
+        let cells = read_u16(page, header + 3)?;
+        let pointers = match kind {
+            INTERIOR_TABLE => header + 12,
+            LEAF_TABLE => header + 8,
+            _ => return Err(BytesError::Corrupt),
+        };
+        for i in 0..cells {
+            let cell = read_u16(page, pointers + 2 * i)?;
+            if kind == INTERIOR_TABLE {
+                self.scan_page(read_u32(page, cell)?, depth + 1, visited, row)?;
+            } else {
+                let (size, n) = read_varint(page, cell)?;
+                let (rowid, m) = read_varint(page, cell + n)?;
+                let payload = self.payload(page, cell + n + m, to_usize(size)?)?;
+                // rowids are stored as the bits of a signed integer
+                #[allow(clippy::cast_possible_wrap)]
+                row(rowid as i64, &payload)?;
+            }
+        }
+        if kind == INTERIOR_TABLE {
+            self.scan_page(read_u32(page, header + 8)?, depth + 1, visited, row)?;
+        }
+        Ok(())
+    }
+
+    /// The `size` bytes of a table leaf cell's payload starting at `at`,
+    /// gathered from overflow pages if it did not fit on its own page.
+    fn payload(&self, page: &'a [u8], at: usize, size: usize) -> Result<Cow<'a, [u8]>, BytesError> {
+        let usable = self.usable_size;
+        let max_local = usable - 35;
+        if size <= max_local {
+            return page
+                .get(at..at + size)
+                .map(Cow::Borrowed)
+                .ok_or(BytesError::Corrupt);
+        }
+        if size > self.bytes.len() {
+            return Err(BytesError::Corrupt);
+        }
+        let min_local = (usable - 12) * 32 / 255 - 23;
+        let local = match min_local + (size - min_local) % (usable - 4) {
+            local if local <= max_local => local,
+            _ => min_local,
+        };
+        let mut payload = Vec::with_capacity(size);
+        payload.extend_from_slice(page.get(at..at + local).ok_or(BytesError::Corrupt)?);
+        let mut next = read_u32(page, at + local)?;
+        // each overflow page is read at most once
+        for _ in 0..self.bytes.len() / self.page_size {
+            if payload.len() >= size {
+                break;
+            }
+            let overflow = self.page(next)?;
+            let take = (size - payload.len()).min(usable - 4);
+            payload.extend_from_slice(overflow.get(4..4 + take).ok_or(BytesError::Corrupt)?);
+            next = read_u32(overflow, 0)?;
+        }
+        if payload.len() < size {
+            return Err(BytesError::Corrupt);
+        }
+        Ok(Cow::Owned(payload))
+    }
+}
+
+/// The values of a record, in column order.
+fn read_record(record: &[u8]) -> Result<Vec<FieldValue<'_>>, BytesError> {
+    let (header_size, mut at) = read_varint(record, 0)?;
+    let header_size = to_usize(header_size)?;
+    let mut body = header_size;
+    let mut values = Vec::new();
+    while at < header_size {
+        let (serial_type, n) = read_varint(record, at)?;
+        at += n;
+        let len = match serial_type {
+            0 | 8 | 9 => 0,
+            1..=4 => to_usize(serial_type)?,
+            5 => 6,
+            6 | 7 => 8,
+            10 | 11 => return Err(BytesError::Corrupt),
+            n => to_usize((n - 12) / 2)?,
+        };
+        let end = body.checked_add(len).ok_or(BytesError::Corrupt)?;
+        let bytes = record.get(body..end).ok_or(BytesError::Corrupt)?;
+        body = end;
+        values.push(match serial_type {
+            0 => FieldValue::Null,
+            8 => FieldValue::Integer(0),
+            9 => FieldValue::Integer(1),
+            1..=6 => {
+                // sign-extended from the first byte
+                let first = i64::from(i8::from_be_bytes([bytes[0]]));
+                FieldValue::Integer(
+                    bytes[1..]
+                        .iter()
+                        .fold(first, |value, byte| (value << 8) | i64::from(*byte)),
+                )
+            }
+            7 => {
+                let mut bits = [0; 8];
+                bits.copy_from_slice(bytes);
+                FieldValue::Real(f64::from_be_bytes(bits))
+            }
+            // paths are BLOBs, read as text the way rows read them too
+            _ => FieldValue::Text(String::from_utf8_lossy(bytes)),
+        });
+    }
+    Ok(values)
+}
+
+/// The column names of a `CREATE TABLE` statement, and which of them is an
+/// alias for the rowid, whose value is not stored in the record.
+fn columns(sql: &str) -> (Vec<String>, Option<usize>) {
+    let Some(definitions) = sql
+        .find('(')
+        .zip(sql.rfind(')'))
+        .and_then(|(start, end)| sql.get(start + 1..end))
+    else {
+        return (vec![], None);
+    };
+    let mut parts = vec![];
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in definitions.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&definitions[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&definitions[start..]);
+
+    let mut names = vec![];
+    let mut rowid = None;
+    for part in parts {
+        let words: Vec<String> = part
+            .split_whitespace()
+            .map(str::to_ascii_uppercase)
+            .collect();
+        let constraint = ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"];
+        let Some(first) = words.first() else {
+            continue;
+        };
+        if constraint.contains(&first.as_str()) {
+            continue;
+        }
+        let name = part.split_whitespace().next().unwrap_or_default();
+        let name = name.trim_matches(|c| matches!(c, '"' | '`' | '[' | ']' | '\''));
+        if words.get(1).is_some_and(|kind| kind == "INTEGER")
+            && words
+                .windows(2)
+                .any(|pair| pair[0] == "PRIMARY" && pair[1] == "KEY")
+        {
+            rowid = Some(names.len());
+        }
+        names.push(name.to_string());
+    }
+    (names, rowid)
+}
+
+/// The root page and columns of `table`, from the schema on the first page.
+fn find_table(
+    file: &File,
+    table: &'static str,
+) -> Result<(u32, Vec<String>, Option<usize>), BytesError> {
+    let mut found = None;
+    file.scan(1, &mut |_, record| {
+        let values = read_record(record)?;
+        match values.as_slice() {
+            [FieldValue::Text(kind), FieldValue::Text(name), _, FieldValue::Integer(root), FieldValue::Text(sql), ..]
+                if kind == "table" && name.eq_ignore_ascii_case(table) =>
+            {
+                let root = u32::try_from(*root).map_err(|_| BytesError::Corrupt)?;
+                let (names, rowid) = columns(sql);
+                found = Some((root, names, rowid));
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+    found.ok_or(BytesError::MissingTable(table))
+}
+
+/// Every row of `name`, with its columns set by name on a default record.
+/// Columns a record has no field for are skipped, and fields without a
+/// column (or with a `NULL` one they cannot hold) keep their default.
+fn read_table<T: Default>(
+    file: &File,
+    name: &'static str,
+    set_field: fn(&mut T, &str, FieldValue) -> Result<(), FieldError>,
+    fields: &[&str],
+) -> Result<Vec<T>, BytesError> {
+    let (root, columns, rowid) = find_table(file, name)?;
+    let mut rows = vec![];
+    file.scan(root, &mut |id, record| {
+        let mut values = read_record(record)?;
+        // columns added since the row was written are not in its record
+        values.resize(columns.len(), FieldValue::Null);
+        if let Some(rowid) = rowid {
+            values[rowid] = FieldValue::Integer(id);
+        }
+        let mut row = T::default();
+        for (column, value) in columns.iter().zip(values) {
+            if !fields.contains(&column.as_str()) {
+                continue;
+            }
+            let is_null = matches!(value, FieldValue::Null);
+            match set_field(&mut row, column, value) {
+                Err(FieldError::WrongType(_)) if is_null => {}
+                result => result.map_err(BytesError::Column)?,
+            }
+        }
+        rows.push(row);
+        Ok(())
+    })?;
+    Ok(rows)
+}
+
+/// Reads all the [`Album`]s and [`Item`]s from the bytes of a library file,
+/// such as one uploaded to a browser, without SQLite. It works on every
+/// target, `wasm32` included.
+///
+/// Only what the file itself holds is read: changes still in a `-wal` file
+/// next to it are not.
+///
+/// # Errors
+/// Returns an error if the bytes are not a beets library, or it is corrupt
+pub fn read_all_from_bytes(bytes: &[u8]) -> Result<(Vec<Album>, Vec<Item>), BytesError> {
+    let file = File::new(bytes)?;
+    Ok((
+        read_table(&file, "albums", Album::set_field, Album::COLUMNS)?,
+        read_table(&file, "items", Item::set_field, Item::COLUMNS)?,
+    ))
+}
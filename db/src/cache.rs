@@ -0,0 +1,142 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::watch::{stamp, with_wal, Stamp};
+use crate::{read_all, Album, Error, Item, Library};
+
+/// What a snapshot was taken of: when any of it differs, the database was
+/// written to since.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct CacheKey {
+    files: [Stamp; 2],
+    /// The file change counter from the database header, which every write
+    /// outside WAL mode bumps, even within the resolution of `mtime`.
+    change_counter: u32,
+}
+
+/// The fields that the library's JSON leaves out, for each entry in order.
+#[derive(Deserialize, Serialize)]
+struct Skipped {
+    album_added: Vec<f64>,
+    item_mtime_added: Vec<(f64, f64)>,
+}
+
+impl Skipped {
+    fn of(library: &Library) -> Self {
+        Self {
+            album_added: library.albums.iter().map(|album| album.added).collect(),
+            item_mtime_added: library
+                .items
+                .iter()
+                .map(|item| (item.mtime, item.added))
+                .collect(),
+        }
+    }
+
+    /// Put the fields back, if there are as many as entries.
+    fn restore(self, library: &mut Library) -> Option<()> {
+        if self.album_added.len() != library.albums.len()
+            || self.item_mtime_added.len() != library.items.len()
+        {
+            return None;
+        }
+        for (album, added) in library.albums.iter_mut().zip(self.album_added) {
+            album.added = added;
+        }
+        for (item, (mtime, added)) in library.items.iter_mut().zip(self.item_mtime_added) {
+            item.mtime = mtime;
+            item.added = added;
+        }
+        Some(())
+    }
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    key: CacheKey,
+    library: Library,
+    skipped: Skipped,
+}
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    key: &'a CacheKey,
+    library: &'a Library,
+    skipped: Skipped,
+}
+
+fn cache_key(db_path: &Path) -> io::Result<CacheKey> {
+    let [db, wal] = with_wal(db_path);
+    let mut header = [0; 28];
+    File::open(&db)?.read_exact(&mut header)?;
+    Ok(CacheKey {
+        files: [stamp(&db)?, stamp(&wal)?],
+        change_counter: u32::from_be_bytes([header[24], header[25], header[26], header[27]]),
+    })
+}
+
+/// Where [`read_all_cached`] keeps the snapshot of the database at `db_path`.
+#[must_use]
+pub fn snapshot_path(db_path: impl AsRef<Path>) -> PathBuf {
+    let mut path = db_path.as_ref().as_os_str().to_owned();
+    path.push(".snapshot.json");
+    path.into()
+}
+
+fn load(path: &Path, key: &CacheKey) -> Option<Library> {
+    let file = BufReader::new(File::open(path).ok()?);
+    let Snapshot {
+        key: snapshot_key,
+        mut library,
+        skipped,
+    } = serde_json::from_reader(file).ok()?;
+    if snapshot_key != *key {
+        return None;
+    }
+    skipped.restore(&mut library)?;
+    Some(library)
+}
+
+fn save(path: &Path, key: &CacheKey, library: &Library) -> io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let mut out = BufWriter::new(File::create(&partial)?);
+    let snapshot = SnapshotRef {
+        key,
+        library,
+        skipped: Skipped::of(library),
+    };
+    serde_json::to_writer(&mut out, &snapshot)?;
+    out.flush()?;
+    drop(out);
+    fs::rename(&partial, path)
+}
+
+/// Like [`read_all`], keeping a snapshot of the library beside the database
+/// to answer from until the database changes.
+///
+/// The snapshot is revalidated against the size and modification time of
+/// the database and its `-wal` file, and the change counter in its header.
+/// If it cannot be written, e.g. in a read-only directory, the database is
+/// simply read every time.
+///
+/// # Errors
+/// Returns an error if the database must be read and that fails
+pub fn read_all_cached(db_path: impl AsRef<Path>) -> Result<(Vec<Album>, Vec<Item>), Error> {
+    let db_path = db_path.as_ref();
+    let snapshot = snapshot_path(db_path);
+    // taken before reading, so a write during the read invalidates the result
+    let key = cache_key(db_path).ok();
+    if let Some(library) = key.as_ref().and_then(|key| load(&snapshot, key)) {
+        return Ok((library.albums, library.items));
+    }
+
+    let (albums, items) = read_all(db_path)?;
+    let library = Library { albums, items };
+    if let Some(key) = &key {
+        // a failed save only costs the next read its speed
+        let _ = save(&snapshot, key, &library);
+    }
+    Ok((library.albums, library.items))
+}
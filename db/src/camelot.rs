@@ -0,0 +1,149 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Item;
+
+/// A musical key as a position on the Camelot wheel, which DJs mix by:
+/// neighbouring keys sound good played one after the other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CamelotKey {
+    /// The hour on the wheel, from 1 to 12, one fifth apart.
+    number: u8,
+    /// Whether the key is minor, `A` on the wheel, rather than major, `B`.
+    minor: bool,
+}
+
+impl CamelotKey {
+    /// The key at `number` on the wheel, from 1 to 12.
+    #[must_use]
+    pub fn new(number: u8, minor: bool) -> Option<Self> {
+        (1..=12).contains(&number).then_some(Self { number, minor })
+    }
+
+    /// The key with the tonic `pitch_class`, counting semitones from C.
+    fn from_pitch_class(pitch_class: u8, minor: bool) -> Self {
+        // a minor key sits with its relative major, three semitones up
+        let major = (pitch_class + if minor { 3 } else { 0 }) % 12;
+        // C major is 8B, and each fifth up is the next hour
+        let number = (major * 7 % 12 + 7) % 12 + 1;
+        Self { number, minor }
+    }
+
+    #[must_use]
+    pub fn number(self) -> u8 {
+        self.number
+    }
+
+    #[must_use]
+    pub fn is_minor(self) -> bool {
+        self.minor
+    }
+
+    /// The key as most DJ software names it, with flats, e.g. `Am`, `Db`
+    /// or `Ebm`.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        const MAJOR: [&str; 12] = [
+            "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B",
+        ];
+        const MINOR: [&str; 12] = [
+            "Cm", "Dbm", "Dm", "Ebm", "Em", "Fm", "Gbm", "Gm", "Abm", "Am", "Bbm", "Bm",
+        ];
+        // the inverse of `from_pitch_class`, as seven fifths up is a semitone
+        let major = usize::from((self.number + 4) * 7 % 12);
+        if self.minor {
+            MINOR[(major + 9) % 12]
+        } else {
+            MAJOR[major]
+        }
+    }
+
+    /// The keys that mix well after this one: the hours on either side, and
+    /// the relative major or minor at the same hour.
+    #[must_use]
+    pub fn neighbors(self) -> [Self; 3] {
+        let hour = |number: u8| Self {
+            number: (number + 11) % 12 + 1,
+            minor: self.minor,
+        };
+        [
+            hour(self.number - 1),
+            hour(self.number + 1),
+            Self {
+                minor: !self.minor,
+                ..self
+            },
+        ]
+    }
+
+    /// Whether `other` is the same key or one of its
+    /// [`neighbors`](Self::neighbors).
+    #[must_use]
+    pub fn is_compatible(self, other: Self) -> bool {
+        self == other || self.neighbors().contains(&other)
+    }
+}
+
+impl fmt::Display for CamelotKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.number, if self.minor { 'A' } else { 'B' })
+    }
+}
+
+impl FromStr for CamelotKey {
+    type Err = String;
+
+    /// Parse a key as beets and taggers write it, e.g. `C#m`, `Bb`, `Ebmin`
+    /// or `A minor`, or in Camelot notation, e.g. `8A`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("unknown key `{s}`, expected e.g. `Am`, `F#` or `8A`");
+        let key = s.trim();
+        if let Some(number) = key
+            .strip_suffix(['A', 'a'])
+            .and_then(|number| number.parse().ok())
+        {
+            return Self::new(number, true).ok_or_else(invalid);
+        }
+        if let Some(number) = key
+            .strip_suffix(['B', 'b'])
+            .and_then(|number| number.parse().ok())
+        {
+            return Self::new(number, false).ok_or_else(invalid);
+        }
+
+        let mut chars = key.chars();
+        let note = match chars.next().map(|c| c.to_ascii_uppercase()) {
+            Some('C') => 0,
+            Some('D') => 2,
+            Some('E') => 4,
+            Some('F') => 5,
+            Some('G') => 7,
+            Some('A') => 9,
+            Some('B') => 11,
+            _ => return Err(invalid()),
+        };
+        let rest = chars.as_str();
+        let (pitch_class, mode) = if let Some(rest) = rest.strip_prefix(['#', '♯']) {
+            (note + 1, rest)
+        } else if let Some(rest) = rest.strip_prefix(['b', '♭']) {
+            (note + 11, rest)
+        } else {
+            (note, rest)
+        };
+        let minor = match mode.trim().to_ascii_lowercase().as_str() {
+            "" | "maj" | "major" => false,
+            "m" | "min" | "minor" => true,
+            _ => return Err(invalid()),
+        };
+        Ok(Self::from_pitch_class(pitch_class % 12, minor))
+    }
+}
+
+impl Item {
+    /// The `initial_key` of the item on the Camelot wheel, if it has one
+    /// that can be read.
+    #[must_use]
+    pub fn camelot_key(&self) -> Option<CamelotKey> {
+        self.initial_key.as_deref()?.parse().ok()
+    }
+}
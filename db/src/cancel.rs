@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use rusqlite::ffi;
+
+use crate::{Error, ErrorKind};
+
+/// A flag shared between a long-running read and whoever may want to stop it,
+/// e.g. a GUI whose window was closed.
+///
+/// Clones share the same flag. Reads given it, or made through a handle it
+/// was set on with [`Database::set_cancellation_token`](crate::Database::set_cancellation_token),
+/// check it between rows and return an error for which
+/// [`Error::is_cancelled`] is true.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every operation using this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fail if the token was cancelled.
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            Err(Error {
+                source: Some(rusqlite::Error::SqliteFailure(
+                    ffi::Error::new(ffi::SQLITE_INTERRUPT),
+                    Some("cancelled".to_string()),
+                )),
+                kind: ErrorKind::Cancelled,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Error {
+    /// Whether the operation was stopped by a [`CancellationToken`].
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self.kind, ErrorKind::Cancelled)
+    }
+}
@@ -0,0 +1,306 @@
+use std::borrow::Cow;
+
+use rusqlite::types::Value;
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::undo::{attach_sidecar, now};
+use crate::write::atomically;
+use crate::{Database, Error, FieldValue, ReadWrite};
+
+/// The tables of the library whose changes are logged.
+const TABLES: &[&str] = &["items", "albums", "item_attributes", "album_attributes"];
+
+/// The log, in the sidecar beside the library like the undo journal. Beside
+/// it are copies of the tables as they were last seen, as
+/// `changelog_items` and so on, whose differences from the library are the
+/// changes made since.
+const CREATE_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS berts_undo.changelog (
+    time REAL NOT NULL,
+    tbl TEXT NOT NULL,
+    entity INTEGER NOT NULL,
+    op TEXT NOT NULL,
+    field TEXT,
+    old,
+    new
+);
+CREATE INDEX IF NOT EXISTS berts_undo.changelog_time ON changelog (time);
+";
+
+/// What a [`ChangelogEntry`] did to its row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl ChangeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Insert => "insert",
+            ChangeKind::Update => "update",
+            ChangeKind::Delete => "delete",
+        }
+    }
+
+    fn parse(op: &str) -> Option<Self> {
+        [ChangeKind::Insert, ChangeKind::Update, ChangeKind::Delete]
+            .iter()
+            .copied()
+            .find(|kind| kind.as_str() == op)
+    }
+}
+
+/// One change to the library, by this crate or any other program, logged
+/// once [`Database::enable_changelog`] was called.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangelogEntry {
+    /// When the change was found, as a Unix timestamp like those beets
+    /// stores: the first time the changelog was read after it was made.
+    pub time: f64,
+    /// The table changed, e.g. `items` or `item_attributes`.
+    pub table: String,
+    /// The id of the item or album, also for its flexible attributes.
+    pub id: u32,
+    pub kind: ChangeKind,
+    /// The column or flexible attribute changed, or `None` for an item or
+    /// album added or removed as a whole.
+    pub field: Option<String>,
+    /// The value before the change, `Null` for one added.
+    pub old: FieldValue<'static>,
+    /// The value after the change, `Null` for one removed.
+    pub new: FieldValue<'static>,
+}
+
+fn field_value(value: Value) -> FieldValue<'static> {
+    match value {
+        Value::Null => FieldValue::Null,
+        Value::Integer(i) => FieldValue::Integer(i),
+        Value::Real(r) => FieldValue::Real(r),
+        Value::Text(t) => FieldValue::Text(Cow::Owned(t)),
+        // paths, as beets stores them
+        Value::Blob(bytes) => {
+            FieldValue::Text(Cow::Owned(String::from_utf8_lossy(&bytes).into_owned()))
+        }
+    }
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_text(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+fn has_table(conn: &Connection, schema: &str, table: &str) -> Result<bool, Error> {
+    let sql = format!("SELECT 1 FROM {schema}.sqlite_master WHERE type = 'table' AND name = ?1");
+    conn.query_row(&sql, [table], |_| Ok(()))
+        .optional()
+        .map(|found| found.is_some())
+        .map_err(Error::query)
+}
+
+/// The columns of `table` in `schema`, in order.
+fn columns(conn: &Connection, schema: &str, table: &str) -> Result<Vec<String>, Error> {
+    let mut stmt = conn
+        .prepare("SELECT name FROM pragma_table_info(?1, ?2) ORDER BY cid")
+        .map_err(Error::query)?;
+    let names = stmt
+        .query_map([table, schema], |row| row.get(0))
+        .map_err(Error::query)?;
+    names.collect::<Result<_, _>>().map_err(Error::query)
+}
+
+/// Replace the copy of `table` with the table as it is now.
+fn snapshot(conn: &Connection, table: &str) -> Result<(), Error> {
+    let key = if table.ends_with("attributes") {
+        "entity_id, key"
+    } else {
+        "id"
+    };
+    conn.execute_batch(&format!(
+        "DROP TABLE IF EXISTS berts_undo.changelog_{table};
+        CREATE TABLE berts_undo.changelog_{table} AS SELECT * FROM main.{table};
+        CREATE INDEX berts_undo.changelog_{table}_key ON changelog_{table} ({key});"
+    ))
+    .map_err(Error::write)
+}
+
+/// Log how `table` differs from its copy as changes made at `time`.
+fn log_changes(conn: &Connection, table: &str, time: f64) -> Result<(), Error> {
+    let log = "INSERT INTO berts_undo.changelog (time, tbl, entity, op, field, old, new)";
+    let name = quote_text(table);
+    let copy = format!("berts_undo.changelog_{table}");
+    let mut sql = vec![];
+    if table.ends_with("attributes") {
+        sql.push(format!(
+            "{log} SELECT ?1, {name}, t.entity_id, 'insert', t.key, NULL, t.value
+            FROM main.{table} t WHERE NOT EXISTS (SELECT 1 FROM {copy} c
+                WHERE c.entity_id = t.entity_id AND c.key = t.key)
+            ORDER BY t.rowid"
+        ));
+        sql.push(format!(
+            "{log} SELECT ?1, {name}, c.entity_id, 'update', c.key, c.value, t.value
+            FROM {copy} c JOIN main.{table} t ON t.entity_id = c.entity_id AND t.key = c.key
+            WHERE t.value IS NOT c.value ORDER BY t.rowid"
+        ));
+        sql.push(format!(
+            "{log} SELECT ?1, {name}, c.entity_id, 'delete', c.key, c.value, NULL
+            FROM {copy} c WHERE NOT EXISTS (SELECT 1 FROM main.{table} t
+                WHERE t.entity_id = c.entity_id AND t.key = c.key)
+            ORDER BY c.rowid"
+        ));
+    } else {
+        sql.push(format!(
+            "{log} SELECT ?1, {name}, id, 'insert', NULL, NULL, NULL FROM main.{table}
+            WHERE id NOT IN (SELECT id FROM {copy}) ORDER BY id"
+        ));
+        // only the columns both have, as beets may have added some since
+        let copied = columns(conn, "berts_undo", &format!("changelog_{table}"))?;
+        let shared: Vec<_> = columns(conn, "main", table)?
+            .into_iter()
+            .filter(|column| copied.contains(column))
+            .map(|column| quote_ident(&column))
+            .collect();
+        // the rows changed at all, found in one pass rather than one per column
+        let list = shared.join(", ");
+        sql.push(format!(
+            "DROP TABLE IF EXISTS temp.berts_changed;
+            CREATE TEMP TABLE berts_changed AS SELECT id FROM (
+                SELECT {list} FROM main.{table} EXCEPT SELECT {list} FROM {copy})"
+        ));
+        // CROSS JOIN keeps SQLite from scanning the copy first, as the
+        // tables are never analyzed
+        for ident in shared.iter().filter(|ident| *ident != "\"id\"") {
+            sql.push(format!(
+                "{log} SELECT ?1, {name}, t.id, 'update', {}, c.{ident}, t.{ident}
+                FROM temp.berts_changed d CROSS JOIN {copy} c ON c.id = d.id
+                CROSS JOIN main.{table} t ON t.id = d.id
+                WHERE t.{ident} IS NOT c.{ident} ORDER BY t.id",
+                quote_text(ident.trim_matches('"'))
+            ));
+        }
+        sql.push(format!(
+            "{log} SELECT ?1, {name}, id, 'delete', NULL, NULL, NULL FROM {copy}
+            WHERE id NOT IN (SELECT id FROM main.{table}) ORDER BY id"
+        ));
+    }
+    for sql in sql {
+        if sql.contains("?1") {
+            conn.execute(&sql, [time]).map_err(Error::write)?;
+        } else {
+            conn.execute_batch(&sql).map_err(Error::write)?;
+        }
+    }
+    conn.execute_batch("DROP TABLE IF EXISTS temp.berts_changed")
+        .map_err(Error::write)
+}
+
+/// Whether changes are being logged, so the copies exist.
+fn enabled(conn: &Connection) -> Result<bool, Error> {
+    has_table(conn, "berts_undo", "changelog_items")
+}
+
+impl Database<ReadWrite> {
+    /// Log every change to the items, albums and their flexible attributes
+    /// from now on, whichever program makes it, for
+    /// [`changelog`](Self::changelog) to list.
+    ///
+    /// Nothing is added to the library itself: the log and a copy of the
+    /// tables it compares them to are kept in the sidecar beside it,
+    /// `library.db.undo`, created if need be. Changes are found by that
+    /// comparison whenever the changelog is read, so several changes to a
+    /// field in between are logged as one, at the time they were found.
+    ///
+    /// # Errors
+    /// Returns an error if the sidecar cannot be opened or written
+    pub fn enable_changelog(&self) -> Result<(), Error> {
+        let conn = self.connection();
+        attach_sidecar(conn)?;
+        atomically(conn, || {
+            self.collect_changes()?;
+            conn.execute_batch(CREATE_TABLE).map_err(Error::write)?;
+            TABLES.iter().try_for_each(|table| snapshot(conn, table))
+        })
+    }
+
+    /// Stop logging changes, logging those made until now first. The log
+    /// is kept.
+    ///
+    /// # Errors
+    /// Returns an error if the sidecar cannot be opened or written
+    pub fn disable_changelog(&self) -> Result<(), Error> {
+        let conn = self.connection();
+        attach_sidecar(conn)?;
+        atomically(conn, || {
+            self.collect_changes()?;
+            for table in TABLES {
+                conn.execute_batch(&format!(
+                    "DROP TABLE IF EXISTS berts_undo.changelog_{table}"
+                ))
+                .map_err(Error::write)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Log the changes made since the copies were taken, and take them
+    /// again.
+    fn collect_changes(&self) -> Result<(), Error> {
+        let conn = self.connection();
+        if !enabled(conn)? {
+            return Ok(());
+        }
+        let time = now();
+        atomically(conn, || {
+            for table in TABLES {
+                log_changes(conn, table, time)?;
+                snapshot(conn, table)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// The changes logged since [`enable_changelog`](Self::enable_changelog)
+    /// made at or after `since`, a Unix timestamp, oldest first.
+    ///
+    /// The changes made since it was last read are logged first.
+    ///
+    /// # Errors
+    /// Returns an error if the sidecar cannot be opened, or the changes
+    /// cannot be read or logged
+    pub fn changelog(&self, since: f64) -> Result<Vec<ChangelogEntry>, Error> {
+        let conn = self.connection();
+        attach_sidecar(conn)?;
+        self.collect_changes()?;
+        if !has_table(conn, "berts_undo", "changelog")? {
+            return Ok(vec![]);
+        }
+        let mut stmt = conn
+            .prepare(
+                "SELECT time, tbl, entity, op, field, old, new FROM berts_undo.changelog
+                WHERE time >= ?1 ORDER BY time, rowid",
+            )
+            .map_err(Error::query)?;
+        let mut rows = stmt.query([since]).map_err(Error::query)?;
+        let mut changes = vec![];
+        while let Some(row) = rows.next().map_err(Error::query)? {
+            let op: String = row.get(3).map_err(Error::query)?;
+            let Some(kind) = ChangeKind::parse(&op) else {
+                continue;
+            };
+            changes.push(ChangelogEntry {
+                time: row.get(0).map_err(Error::query)?,
+                table: row.get(1).map_err(Error::query)?,
+                id: row.get(2).map_err(Error::query)?,
+                kind,
+                field: row.get(4).map_err(Error::query)?,
+                old: field_value(row.get(5).map_err(Error::query)?),
+                new: field_value(row.get(6).map_err(Error::query)?),
+            });
+        }
+        Ok(changes)
+    }
+}
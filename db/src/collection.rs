@@ -0,0 +1,237 @@
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::trace::{self, Hooks};
+use crate::{Entity, Error, Item, Library, PlayQueue, Queue};
+
+/// The sidecar tables holding collections, left alone by beets like any
+/// table it does not know.
+const CREATE_TABLES: &str = "
+CREATE TABLE IF NOT EXISTS berts_collections (
+    name TEXT PRIMARY KEY
+);
+CREATE TABLE IF NOT EXISTS berts_collection_members (
+    collection TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('item', 'album')),
+    entity_id INTEGER NOT NULL,
+    UNIQUE (collection, kind, entity_id)
+);";
+
+/// A named list of items and albums, such as favorites, kept in sidecar
+/// `berts_collections` tables of the library and made on the first write.
+///
+/// Members are kept by id, in the order they were added, and each is in a
+/// collection at most once.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Collection {
+    pub name: String,
+    pub members: Vec<Entity>,
+}
+
+fn has_tables(conn: &Connection) -> Result<bool, Error> {
+    let sql = "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'berts_collections'";
+    let found = conn.query_row(sql, (), |_| Ok(())).optional()?;
+    Ok(found.is_some())
+}
+
+fn kind(entity: Entity) -> &'static str {
+    match entity {
+        Entity::Item(_) => "item",
+        Entity::Album(_) => "album",
+    }
+}
+
+impl Collection {
+    /// Make an empty collection called `name`, returning whether it is new.
+    ///
+    /// # Errors
+    /// Returns an error if the connection is read-only
+    pub fn create(conn: &Connection, name: &str) -> Result<bool, Error> {
+        conn.execute_batch(CREATE_TABLES).map_err(Error::write)?;
+        let created = conn
+            .execute(
+                "INSERT OR IGNORE INTO berts_collections (name) VALUES (?1)",
+                [name],
+            )
+            .map_err(Error::write)?;
+        Ok(created > 0)
+    }
+
+    /// The names of every collection, in sorted order.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn list(conn: &Connection) -> Result<Vec<String>, Error> {
+        if !has_tables(conn)? {
+            return Ok(vec![]);
+        }
+        trace::query_all(
+            conn,
+            "berts_collections",
+            "SELECT name FROM berts_collections ORDER BY name",
+            (),
+            |row| Ok(row.get(0)?),
+            Hooks::default(),
+        )
+    }
+
+    /// The collection called `name`, if there is one.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails, or a member is of an unknown
+    /// kind
+    pub fn load(conn: &Connection, name: &str) -> Result<Option<Self>, Error> {
+        if !has_tables(conn)? {
+            return Ok(None);
+        }
+        let sql = "SELECT 1 FROM berts_collections WHERE name = ?1";
+        let found = conn.query_row(sql, [name], |_| Ok(())).optional()?;
+        if found.is_none() {
+            return Ok(None);
+        }
+        let members = trace::query_all(
+            conn,
+            "berts_collection_members",
+            "SELECT kind, entity_id FROM berts_collection_members
+            WHERE collection = ?1 ORDER BY position",
+            [name],
+            |row| {
+                let kind: String = row.get(0)?;
+                let entity_id = row.get(1)?;
+                match kind.as_str() {
+                    "item" => Ok(Entity::Item(entity_id)),
+                    "album" => Ok(Entity::Album(entity_id)),
+                    _ => Err(rusqlite::Error::InvalidColumnType(
+                        0,
+                        kind,
+                        rusqlite::types::Type::Text,
+                    )
+                    .into()),
+                }
+            },
+            Hooks::default(),
+        )?;
+        Ok(Some(Self {
+            name: name.to_string(),
+            members,
+        }))
+    }
+
+    /// Add `entity` to the end of the collection called `name`, making the
+    /// collection if need be. Returns whether it was not a member already.
+    ///
+    /// # Errors
+    /// Returns an error if the connection is read-only
+    pub fn add(conn: &Connection, name: &str, entity: Entity) -> Result<bool, Error> {
+        Self::create(conn, name)?;
+        let added = conn
+            .execute(
+                "INSERT OR IGNORE INTO berts_collection_members
+                    (collection, position, kind, entity_id)
+                SELECT ?1, COALESCE(MAX(position) + 1, 0), ?2, ?3
+                FROM berts_collection_members WHERE collection = ?1",
+                (name, kind(entity), entity.id()),
+            )
+            .map_err(Error::write)?;
+        Ok(added > 0)
+    }
+
+    /// Take `entity` out of the collection called `name`, returning whether
+    /// it was a member.
+    ///
+    /// # Errors
+    /// Returns an error if the connection is read-only
+    pub fn remove(conn: &Connection, name: &str, entity: Entity) -> Result<bool, Error> {
+        if !has_tables(conn)? {
+            return Ok(false);
+        }
+        let removed = conn
+            .execute(
+                "DELETE FROM berts_collection_members
+                WHERE collection = ?1 AND kind = ?2 AND entity_id = ?3",
+                (name, kind(entity), entity.id()),
+            )
+            .map_err(Error::write)?;
+        Ok(removed > 0)
+    }
+
+    /// Rename the collection `from` to `to`, returning whether `from` existed.
+    ///
+    /// # Errors
+    /// Returns an error if the connection is read-only, or a collection called
+    /// `to` exists already
+    pub fn rename(conn: &Connection, from: &str, to: &str) -> Result<bool, Error> {
+        if !has_tables(conn)? {
+            return Ok(false);
+        }
+        let tx = conn.unchecked_transaction().map_err(Error::write)?;
+        let renamed = tx
+            .execute(
+                "UPDATE berts_collections SET name = ?2 WHERE name = ?1",
+                [from, to],
+            )
+            .map_err(Error::write)?;
+        tx.execute(
+            "UPDATE berts_collection_members SET collection = ?2 WHERE collection = ?1",
+            [from, to],
+        )
+        .map_err(Error::write)?;
+        tx.commit().map_err(Error::write)?;
+        Ok(renamed > 0)
+    }
+
+    /// Delete the collection called `name` and its members, returning whether
+    /// it existed.
+    ///
+    /// # Errors
+    /// Returns an error if the connection is read-only
+    pub fn delete(conn: &Connection, name: &str) -> Result<bool, Error> {
+        if !has_tables(conn)? {
+            return Ok(false);
+        }
+        let tx = conn.unchecked_transaction().map_err(Error::write)?;
+        tx.execute(
+            "DELETE FROM berts_collection_members WHERE collection = ?1",
+            [name],
+        )
+        .map_err(Error::write)?;
+        let deleted = tx
+            .execute("DELETE FROM berts_collections WHERE name = ?1", [name])
+            .map_err(Error::write)?;
+        tx.commit().map_err(Error::write)?;
+        Ok(deleted > 0)
+    }
+
+    /// The items of the collection, with each album's in disc and track
+    /// order. Members no longer in `library` are left out.
+    #[must_use]
+    pub fn items<'a>(&self, library: &'a Library) -> Vec<&'a Item> {
+        let mut items = vec![];
+        for member in &self.members {
+            match *member {
+                Entity::Item(id) => items.extend(library.item(id)),
+                Entity::Album(id) => {
+                    let mut tracks: Vec<_> = library.album_items(id).collect();
+                    tracks.sort_by_key(|item| (item.disc, item.track, item.id));
+                    items.extend(tracks);
+                }
+            }
+        }
+        items
+    }
+
+    /// A play queue of the collection's [`items`](Self::items), with the
+    /// default gain and no crossfade.
+    #[must_use]
+    pub fn to_queue(&self, library: &Library) -> PlayQueue {
+        let mut queue = Queue::new(library);
+        for member in &self.members {
+            match *member {
+                Entity::Item(id) => queue.item(id),
+                Entity::Album(id) => queue.album(id),
+            };
+        }
+        queue.build()
+    }
+}
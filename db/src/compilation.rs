@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+
+use crate::{Album, Item, Library};
+
+/// The MusicBrainz id of the special "Various Artists" artist.
+pub const VARIOUS_ARTISTS_MBID: &str = "89ad4ac3-39f7-470e-963a-56509c546377";
+
+/// Album artist names that stand for "Various Artists", compared ignoring case.
+const VARIOUS_ARTISTS_NAMES: &[&str] = &[
+    "various artists",
+    "various",
+    "va",
+    "v.a.",
+    "v/a",
+    "multiple artists",
+    "verschiedene interpreten",
+    "artistes divers",
+];
+
+/// Why an album was taken for a compilation by [`Library::compilation_reason`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum CompilationReason {
+    /// The `comp` flag is set.
+    Flagged,
+    /// The album artist is the MusicBrainz "Various Artists".
+    VariousArtistsId,
+    /// The album artist is a name like "Various Artists" or "VA".
+    VariousArtistsName,
+    /// The tracks are credited to many different artists.
+    ManyArtists,
+}
+
+/// Thresholds for treating an album with many track artists as a compilation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompilationHeuristics {
+    /// The fewest distinct track artists.
+    pub min_artists: usize,
+    /// The smallest share of tracks with distinct artists, from `0.0` to
+    /// `1.0`.
+    pub min_artist_ratio: f64,
+}
+
+impl Default for CompilationHeuristics {
+    fn default() -> Self {
+        Self {
+            min_artists: 4,
+            min_artist_ratio: 0.5,
+        }
+    }
+}
+
+impl Library {
+    /// Why the album behaves like a compilation, even if `comp` is not set, or
+    /// `None` for a regular album.
+    #[must_use]
+    pub fn compilation_reason(
+        &self,
+        album: &Album,
+        heuristics: &CompilationHeuristics,
+    ) -> Option<CompilationReason> {
+        compilation_reason(album, self.album_items(album.id), heuristics)
+    }
+
+    /// Albums that behave like compilations, as judged by
+    /// [`compilation_reason`](Self::compilation_reason).
+    #[must_use]
+    pub fn compilations(&self, heuristics: &CompilationHeuristics) -> Vec<&Album> {
+        let items = self.items_by_album();
+        self.filter_albums(|album| {
+            let album_items = items.get(&album.id).into_iter().flatten().copied();
+            compilation_reason(album, album_items, heuristics).is_some()
+        })
+    }
+}
+
+/// [`Library::compilation_reason`], given the album's `items`.
+fn compilation_reason<'a>(
+    album: &Album,
+    items: impl Iterator<Item = &'a Item>,
+    heuristics: &CompilationHeuristics,
+) -> Option<CompilationReason> {
+    if album.comp {
+        return Some(CompilationReason::Flagged);
+    }
+    if album.mb_albumartistid == VARIOUS_ARTISTS_MBID {
+        return Some(CompilationReason::VariousArtistsId);
+    }
+    let albumartist = album.albumartist.trim();
+    if VARIOUS_ARTISTS_NAMES
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(albumartist))
+    {
+        return Some(CompilationReason::VariousArtistsName);
+    }
+
+    let mut tracks = 0_u32;
+    let mut artists = HashSet::new();
+    for item in items {
+        tracks += 1;
+        artists.insert(item.artist.to_lowercase());
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let ratio = artists.len() as f64 / f64::from(tracks.max(1));
+    (artists.len() >= heuristics.min_artists && ratio >= heuristics.min_artist_ratio)
+        .then_some(CompilationReason::ManyArtists)
+}
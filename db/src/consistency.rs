@@ -0,0 +1,118 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+
+use crate::Library;
+
+/// Disagreement between an album and its tracks, found by
+/// [`Library::consistency_check`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize),
+    serde(tag = "kind", rename_all = "snake_case")
+)]
+pub enum ConsistencyFinding {
+    /// The album and its tracks name different album artists, in sorted order.
+    AlbumArtist {
+        album_id: u32,
+        albumartists: Vec<String>,
+    },
+    /// The album and its tracks give different years, in increasing order.
+    /// Unknown years (zero) are left out.
+    Year { album_id: u32, years: Vec<u32> },
+    /// Track numbers missing from a disc, counting up to the disc's
+    /// `tracktotal` or else its highest track number, but no further than the
+    /// number of tracks the album has, so a garbage `tracktotal` is ignored.
+    TrackGaps {
+        album_id: u32,
+        disc: u32,
+        missing: Vec<u32>,
+    },
+    /// The album and its tracks give different disc counts (in increasing
+    /// order), or tracks are on a disc past the count.
+    Disctotal {
+        album_id: u32,
+        disctotals: Vec<u32>,
+        /// The highest disc number of a track.
+        last_disc: u32,
+    },
+}
+
+impl Library {
+    /// Check every album against its tracks, in library order.
+    #[must_use]
+    pub fn consistency_check(&self) -> Vec<ConsistencyFinding> {
+        let mut findings = vec![];
+        let album_items = self.items_by_album();
+        for album in &self.albums {
+            let album_id = album.id;
+            let Some(items) = album_items.get(&album_id) else {
+                continue;
+            };
+            let item_count = u32::try_from(items.len()).unwrap_or(u32::MAX);
+
+            let albumartists: BTreeSet<&str> = items
+                .iter()
+                .map(|item| item.albumartist.as_str())
+                .chain(Some(album.albumartist.as_str()))
+                .collect();
+            if albumartists.len() > 1 {
+                findings.push(ConsistencyFinding::AlbumArtist {
+                    album_id,
+                    albumartists: albumartists.into_iter().map(String::from).collect(),
+                });
+            }
+
+            let years: BTreeSet<u32> = items
+                .iter()
+                .map(|item| item.year)
+                .chain(Some(album.year))
+                .filter(|&year| year != 0)
+                .collect();
+            if years.len() > 1 {
+                findings.push(ConsistencyFinding::Year {
+                    album_id,
+                    years: years.into_iter().collect(),
+                });
+            }
+
+            // the track numbers and largest `tracktotal` of each disc
+            let mut discs: BTreeMap<u32, (BTreeSet<u32>, u32)> = BTreeMap::new();
+            for item in items.iter().filter(|item| item.track != 0) {
+                let (tracks, tracktotal) = discs.entry(item.disc.max(1)).or_default();
+                tracks.insert(item.track);
+                *tracktotal = (*tracktotal).max(item.tracktotal);
+            }
+            for (&disc, (tracks, tracktotal)) in &discs {
+                let last = tracks.iter().next_back().copied().unwrap_or(0);
+                let missing: Vec<u32> = (1..=last.max(*tracktotal).min(item_count))
+                    .filter(|track| !tracks.contains(track))
+                    .collect();
+                if !missing.is_empty() {
+                    findings.push(ConsistencyFinding::TrackGaps {
+                        album_id,
+                        disc,
+                        missing,
+                    });
+                }
+            }
+
+            let disctotals: BTreeSet<u32> = items
+                .iter()
+                .map(|item| item.disctotal)
+                .chain(Some(album.disctotal))
+                .filter(|&total| total != 0)
+                .collect();
+            let last_disc = discs.keys().next_back().copied().unwrap_or(0);
+            let overflows = disctotals.iter().any(|&total| last_disc > total);
+            if disctotals.len() > 1 || overflows {
+                findings.push(ConsistencyFinding::Disctotal {
+                    album_id,
+                    disctotals: disctotals.into_iter().collect(),
+                    last_disc,
+                });
+            }
+        }
+        findings
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::{FieldValue, Item};
+
+/// The path template [`CopyExportOptions`] uses by default, as beets names
+/// album tracks.
+pub const DEFAULT_PATH_TEMPLATE: &str = "$albumartist/$album/$track $title";
+
+/// The most UTF-16 code units FAT32 and exFAT allow in a file name.
+const MAX_NAME_UNITS: usize = 255;
+
+/// Characters FAT32 and exFAT do not allow in a file name.
+const FORBIDDEN: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Names DOS reserves for devices, with or without an extension.
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Field(String),
+}
+
+/// Where [`copy_export`] puts each item, as in beets' `paths` setting:
+/// directories separated by `/`, with `$field` or `${field}` replaced by the
+/// item's field and `$$` by a `$`. `$track` and `$disc` are padded to two
+/// digits. The extension of the file is added to the last name.
+///
+/// ```
+/// # use beet_db::PathTemplate;
+/// let template: PathTemplate = "$genre/${artist} - $title".parse().unwrap();
+/// assert!("$genre/$nonsense".parse::<PathTemplate>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathTemplate {
+    source: String,
+    components: Vec<Vec<Piece>>,
+}
+
+impl FromStr for PathTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut components = vec![];
+        for component in s.split('/').filter(|component| !component.is_empty()) {
+            let mut pieces = vec![];
+            let mut text = String::new();
+            let mut chars = component.chars().peekable();
+            while let Some(c) = chars.next() {
+                if c != '$' {
+                    text.push(c);
+                    continue;
+                }
+                let name: String = match chars.peek() {
+                    Some('$') => {
+                        chars.next();
+                        text.push('$');
+                        continue;
+                    }
+                    Some('{') => {
+                        chars.next();
+                        chars.by_ref().take_while(|&c| c != '}').collect()
+                    }
+                    _ => {
+                        let mut name = String::new();
+                        while let Some(&c) = chars.peek() {
+                            if !(c.is_ascii_alphanumeric() || c == '_') {
+                                break;
+                            }
+                            name.push(c);
+                            chars.next();
+                        }
+                        name
+                    }
+                };
+                if !Item::COLUMNS.contains(&name.as_str()) {
+                    return Err(format!("unknown field `{name}` in path template `{s}`"));
+                }
+                if !text.is_empty() {
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(Piece::Field(name));
+            }
+            if !text.is_empty() {
+                pieces.push(Piece::Text(text));
+            }
+            components.push(pieces);
+        }
+        if components.is_empty() {
+            return Err("empty path template".to_string());
+        }
+        Ok(Self {
+            source: s.to_string(),
+            components,
+        })
+    }
+}
+
+impl fmt::Display for PathTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Default for PathTemplate {
+    fn default() -> Self {
+        DEFAULT_PATH_TEMPLATE
+            .parse()
+            .expect("the default template is valid")
+    }
+}
+
+impl PathTemplate {
+    /// The names of each directory and the file for `item`, before they are
+    /// made safe and the extension is added.
+    fn render(&self, item: &Item) -> Vec<String> {
+        self.components
+            .iter()
+            .map(|pieces| {
+                pieces
+                    .iter()
+                    .map(|piece| match piece {
+                        Piece::Text(text) => text.clone(),
+                        Piece::Field(name) => match (name.as_str(), item.field(name)) {
+                            ("track" | "disc", Some(FieldValue::Integer(n))) => format!("{n:02}"),
+                            (_, value) => value.map(|value| value.to_string()).unwrap_or_default(),
+                        },
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// How [`copy_export`] names the files it copies.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CopyExportOptions {
+    /// Where each item goes under the destination.
+    pub template: PathTemplate,
+    /// Spell names in ASCII alone, e.g. `Sigur Ros` for `Sigur Rós`, for
+    /// car stereos and players that show anything else as boxes.
+    pub ascii: bool,
+}
+
+/// `name` made safe to use on FAT32 and exFAT: forbidden and control
+/// characters replaced by `_`, the trailing dots and spaces those drop
+/// trimmed, reserved device names prefixed with `_`, and cut to
+/// `max_units` UTF-16 code units.
+fn sanitize(name: &str, ascii: bool, max_units: usize) -> String {
+    let name = if ascii {
+        deunicode::deunicode(name)
+    } else {
+        name.to_string()
+    };
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || FORBIDDEN.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let mut units = 0;
+    let mut name: String = name
+        .trim_start()
+        .chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= max_units
+        })
+        .collect();
+    name.truncate(name.trim_end_matches(['.', ' ']).len());
+    let stem = name.split('.').next().unwrap_or_default();
+    if RESERVED
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        name.insert(0, '_');
+    }
+    if name.is_empty() {
+        name.push('_');
+    }
+    name
+}
+
+/// Where each of `items` goes, relative to the destination, in order.
+///
+/// Names that would be the same on a case-insensitive filesystem get a
+/// number, e.g. `01 Intro (2).mp3`, so no item overwrites another.
+#[must_use]
+pub fn export_paths<'a>(
+    items: impl IntoIterator<Item = &'a Item>,
+    options: &CopyExportOptions,
+) -> Vec<(&'a Item, PathBuf)> {
+    let mut taken = HashSet::new();
+    items
+        .into_iter()
+        .map(|item| {
+            let mut names = options.template.render(item);
+            let file = names.pop().unwrap_or_default();
+            let mut path: PathBuf = names
+                .iter()
+                .map(|name| sanitize(name, options.ascii, MAX_NAME_UNITS))
+                .collect();
+            let extension = item
+                .path
+                .extension()
+                .map(|extension| sanitize(&extension.to_string_lossy(), true, 16))
+                .map(|extension| format!(".{extension}"))
+                .unwrap_or_default();
+            let mut number = 1;
+            loop {
+                let suffix = if number == 1 {
+                    extension.clone()
+                } else {
+                    format!(" ({number}){extension}")
+                };
+                let max_units = MAX_NAME_UNITS - suffix.len();
+                let name = sanitize(&file, options.ascii, max_units) + &suffix;
+                let candidate = path.join(name);
+                if taken.insert(candidate.to_string_lossy().to_lowercase()) {
+                    path = candidate;
+                    break;
+                }
+                number += 1;
+            }
+            (item, path)
+        })
+        .collect()
+}
+
+/// Whether `existing` is a copy of the file `source` made by [`copy_export`],
+/// by size and modification time. FAT32 only keeps the time to two seconds,
+/// and a renumbered name may now belong to a different file of the same size.
+pub(crate) fn same_file(source: &fs::Metadata, existing: &fs::Metadata) -> bool {
+    let modified = |metadata: &fs::Metadata| metadata.modified().ok();
+    let close = match (modified(source), modified(existing)) {
+        (Some(source), Some(existing)) => source
+            .duration_since(existing)
+            .or_else(|_| existing.duration_since(source))
+            .is_ok_and(|difference| difference <= Duration::from_secs(2)),
+        _ => false,
+    };
+    source.len() == existing.len() && close
+}
+
+/// Copy `source` to `target`, creating its directory, and give the copy the
+/// modification time of `source` so [`same_file`] recognizes it next time.
+///
+/// # Errors
+/// Returns an error naming `source` if it cannot be copied
+pub(crate) fn copy_file(source: &Path, target: &Path) -> io::Result<()> {
+    let copy = || {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(source, target)?;
+        let modified = fs::metadata(source)?.modified()?;
+        fs::File::options()
+            .write(true)
+            .open(target)?
+            .set_modified(modified)
+    };
+    copy().map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("failed to copy {}: {err}", source.display()),
+        )
+    })
+}
+
+/// What [`copy_export`] did.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CopyReport {
+    /// Files copied.
+    pub copied: usize,
+    /// Files already at the destination with the same size and modification
+    /// time, left alone so an export can be topped up.
+    pub skipped: usize,
+}
+
+/// Copy the files of `items` under `destination`, named by the path
+/// template of `options` and made safe for FAT32 and exFAT, as found on
+/// USB sticks and portable players. See [`export_paths`].
+///
+/// # Errors
+/// Returns an error naming the file if one cannot be copied, after copying
+/// those before it
+pub fn copy_export<'a>(
+    items: impl IntoIterator<Item = &'a Item>,
+    destination: &Path,
+    options: &CopyExportOptions,
+) -> io::Result<CopyReport> {
+    let mut report = CopyReport::default();
+    for (item, path) in export_paths(items, options) {
+        let target = destination.join(path);
+        if let (Ok(source), Ok(existing)) = (fs::metadata(&item.path), fs::metadata(&target)) {
+            if same_file(&source, &existing) {
+                report.skipped += 1;
+                continue;
+            }
+        }
+        copy_file(&item.path, &target)?;
+        report.copied += 1;
+    }
+    Ok(report)
+}
@@ -0,0 +1,128 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{FederatedLibrary, Sourced};
+use crate::{Item, Library};
+
+/// How [`Library::bpm_key_coverage`] groups items.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoverageGroup {
+    /// By `genre`, as a DJ plans a set.
+    Genre,
+    /// By the directory holding each file, as a DJ analyzes a crate of them.
+    Folder,
+}
+
+impl FromStr for CoverageGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "genre" => Ok(Self::Genre),
+            "folder" => Ok(Self::Folder),
+            _ => Err(format!(
+                "unknown grouping `{s}`, expected `genre` or `folder`"
+            )),
+        }
+    }
+}
+
+impl CoverageGroup {
+    fn key(self, item: &Item) -> String {
+        match self {
+            CoverageGroup::Genre => item.genre.clone(),
+            CoverageGroup::Folder => item
+                .path
+                .parent()
+                .map(|dir| dir.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// The items of one genre or folder still needing their BPM or key
+/// analyzed, from [`Library::bpm_key_coverage`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Coverage<'a, T = Item> {
+    /// The genre or folder, empty for items without one.
+    pub group: String,
+    /// How many items are in the group, analyzed or not.
+    pub items: u32,
+    /// How many are missing a `bpm`.
+    pub missing_bpm: u32,
+    /// How many are missing an `initial_key`.
+    pub missing_key: u32,
+    /// Those missing either, in library order.
+    pub missing: Vec<&'a T>,
+}
+
+impl Item {
+    /// Whether the item has no `bpm`, which beets stores as 0.
+    #[must_use]
+    pub fn missing_bpm(&self) -> bool {
+        self.bpm <= 0.0
+    }
+
+    /// Whether the item has no `initial_key`.
+    #[must_use]
+    pub fn missing_key(&self) -> bool {
+        self.initial_key
+            .as_deref()
+            .is_none_or(|key| key.trim().is_empty())
+    }
+}
+
+/// The groups of `entries` with items missing a BPM or key, those missing
+/// the most first.
+fn coverage<'a, T>(
+    entries: impl IntoIterator<Item = &'a T>,
+    item: impl Fn(&T) -> &Item,
+    by: CoverageGroup,
+) -> Vec<Coverage<'a, T>> {
+    let mut groups: BTreeMap<String, Coverage<'a, T>> = BTreeMap::new();
+    for entry in entries {
+        let key = by.key(item(entry));
+        let group = groups.entry(key.clone()).or_insert_with(|| Coverage {
+            group: key,
+            items: 0,
+            missing_bpm: 0,
+            missing_key: 0,
+            missing: vec![],
+        });
+        let (bpm, key) = (item(entry).missing_bpm(), item(entry).missing_key());
+        group.items += 1;
+        group.missing_bpm += u32::from(bpm);
+        group.missing_key += u32::from(key);
+        if bpm || key {
+            group.missing.push(entry);
+        }
+    }
+    let mut groups: Vec<_> = groups
+        .into_values()
+        .filter(|group| !group.missing.is_empty())
+        .collect();
+    // stable, so ties stay in order of the group
+    groups.sort_by_key(|group| Reverse(group.missing.len()));
+    groups
+}
+
+impl Library {
+    /// The genres or folders with items still missing a `bpm` or
+    /// `initial_key`, for a DJ to analyze before playing them out, those
+    /// missing the most first.
+    #[must_use]
+    pub fn bpm_key_coverage(&self, by: CoverageGroup) -> Vec<Coverage<'_>> {
+        coverage(&self.items, |item| item, by)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FederatedLibrary {
+    /// Like [`Library::bpm_key_coverage`], across every library.
+    #[must_use]
+    pub fn bpm_key_coverage(&self, by: CoverageGroup) -> Vec<Coverage<'_, Sourced<Item>>> {
+        coverage(&self.items, |item| &item.entry, by)
+    }
+}
@@ -0,0 +1,423 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use rusqlite::{Connection, ErrorCode};
+
+use crate::safety::Safety;
+use crate::trace::Hooks;
+use crate::undo::Journal;
+use crate::{
+    explain, schema, Album, Attribute, CancellationToken, Entity, Error, ErrorKind, Item, Library,
+    LibraryTree, OpenOptions, ProgressSink, Query, QueryPlan, QueryTrace, TraceHook,
+};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// What a [`Database`] handle was opened to do: [`ReadOnly`] or
+/// [`ReadWrite`].
+pub trait Access: sealed::Sealed {}
+
+/// A handle that can only read, as libraries are opened by default.
+#[derive(Debug)]
+pub enum ReadOnly {}
+
+/// A handle opened for writing as well, the only kind the write methods such
+/// as [`Item::insert`] and [`Item::set_attribute`] take.
+#[derive(Debug)]
+pub enum ReadWrite {}
+
+impl sealed::Sealed for ReadOnly {}
+impl sealed::Sealed for ReadWrite {}
+impl Access for ReadOnly {}
+impl Access for ReadWrite {}
+
+/// A handle to a beets library database.
+///
+/// Whether it may write is part of its type, so a tool pointed at a live
+/// library with [`Database::open`] cannot write to it by mistake:
+///
+/// ```compile_fail
+/// # use beet_db::{Database, Item};
+/// # fn main() -> Result<(), beet_db::Error> {
+/// let db = Database::open("library.db")?;
+/// Item::default().insert(&db)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Database<A: Access = ReadOnly> {
+    conn: Connection,
+    trace: Option<Box<TraceHook>>,
+    progress: Option<Box<dyn ProgressSink>>,
+    cancel: Option<CancellationToken>,
+    timeout: Option<Duration>,
+    pub(crate) safety: Safety,
+    pub(crate) journal: Journal,
+    /// The tool named in the provenance of fields this handle updates.
+    pub(crate) provenance: Option<String>,
+    // a `fn` pointer adds no auto-trait bounds of its own, so the handle is
+    // `Send` whatever the marker, but never `Sync`, like its `Connection`
+    access: PhantomData<fn() -> A>,
+}
+
+impl<A: Access> fmt::Debug for Database<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Database")
+            .field("conn", &self.conn)
+            .field("trace", &self.trace.is_some())
+            .field("progress", &self.progress.is_some())
+            .field("cancel", &self.cancel)
+            .field("timeout", &self.timeout)
+            .field("safety", &self.safety)
+            .field("journal", &self.journal)
+            .field("provenance", &self.provenance)
+            .finish()
+    }
+}
+
+impl Database {
+    /// Open the library at `path` read-only, as
+    /// [`OpenOptions::new().open(path)`](OpenOptions::open) does.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be opened
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        OpenOptions::new().open(path)
+    }
+}
+
+impl Database<ReadWrite> {
+    /// Create an empty library that lives only in memory.
+    ///
+    /// The beets tables are created up front, so everything that reads a
+    /// library on disk works the same way against this one. So is the
+    /// [undo journal](Self::undo_journal), in memory as well.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be created
+    pub fn open_in_memory() -> Result<Self, Error> {
+        let conn = Connection::open_in_memory().map_err(|source| Error {
+            source: Some(source),
+            kind: ErrorKind::Open,
+        })?;
+        conn.execute_batch(schema::CREATE_TABLES)
+            .map_err(|source| Error {
+                source: Some(source),
+                kind: ErrorKind::Schema,
+            })?;
+        let mut db = Self::from_connection(conn);
+        db.journal = Journal::attach(&db.conn)?;
+        Ok(db)
+    }
+
+    /// Open the library at `path` for writing as well, e.g. to store
+    /// attributes with [`Item::set_attribute`], as
+    /// [`OpenOptions::new().open_writable(path)`](OpenOptions::open_writable)
+    /// does.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be opened for writing
+    pub fn open_writable(path: impl AsRef<Path>) -> Result<Self, Error> {
+        OpenOptions::new().open_writable(path)
+    }
+
+    /// Run `f` against the library in a transaction, keeping its writes
+    /// only if it succeeds. Transactions nest, as savepoints. The library is
+    /// checked for [`write_hazards`](Self::write_hazards) once, up front,
+    /// and the writes made in `f` are undone together by
+    /// [`undo_last`](Self::undo_last).
+    ///
+    /// # Errors
+    /// Returns the error from `f`, an error if the transaction cannot be
+    /// started or committed, or one for which [`Error::write_hazard`] is
+    /// `Some` if writing is not safe
+    pub fn transaction<T>(&self, f: impl FnOnce(&Self) -> Result<T, Error>) -> Result<T, Error> {
+        self.write("transaction", || f(self))
+    }
+
+    /// Create indexes that speed up lookups on large libraries.
+    ///
+    /// See [`create_indexes`](crate::create_indexes()).
+    ///
+    /// # Errors
+    /// Returns an error if the indexes cannot be created
+    pub fn create_indexes(&self) -> Result<(), Error> {
+        self.checked(|| crate::create_indexes(&self.conn))
+    }
+}
+
+/// Give up writing, e.g. to hand the library to code that only reads.
+impl From<Database<ReadWrite>> for Database {
+    fn from(db: Database<ReadWrite>) -> Self {
+        Self {
+            conn: db.conn,
+            trace: db.trace,
+            progress: db.progress,
+            cancel: db.cancel,
+            timeout: db.timeout,
+            safety: db.safety,
+            journal: db.journal,
+            provenance: db.provenance,
+            access: PhantomData,
+        }
+    }
+}
+
+impl<A: Access> Database<A> {
+    pub(crate) fn from_connection(conn: Connection) -> Self {
+        Self {
+            conn,
+            trace: None,
+            progress: None,
+            cancel: None,
+            timeout: None,
+            safety: Safety::default(),
+            journal: Journal::default(),
+            provenance: None,
+            access: PhantomData,
+        }
+    }
+
+    /// The underlying `rusqlite` connection.
+    #[must_use]
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Call `hook` after every query made through this handle.
+    pub fn set_trace_hook(&mut self, hook: impl Fn(&QueryTrace) + Send + Sync + 'static) {
+        self.trace = Some(Box::new(hook));
+    }
+
+    /// Call `hook` after every query through this handle that takes at least
+    /// `threshold` to complete.
+    pub fn set_slow_query_hook(
+        &mut self,
+        threshold: Duration,
+        hook: impl Fn(&QueryTrace) + Send + Sync + 'static,
+    ) {
+        self.set_trace_hook(move |trace| {
+            if trace.elapsed >= threshold {
+                hook(trace);
+            }
+        });
+    }
+
+    /// Stop reporting queries.
+    pub fn clear_trace_hook(&mut self) {
+        self.trace = None;
+    }
+
+    /// Report [`Progress`](crate::Progress) of long reads such as
+    /// [`read_all`](Self::read_all) to `sink`.
+    ///
+    /// Each table is counted before it is read, to give the expected total.
+    /// Progress is reported by the reads of whole tables through this
+    /// handle: [`read_all`](Self::read_all), [`albums`](Self::albums),
+    /// [`items`](Self::items), the flexible attributes and the NDJSON
+    /// exports. Scans and exports of a [`Library`] already read, such as
+    /// [`missing_items`](Library::missing_items) or
+    /// [`copy_export`](crate::copy_export), report nothing.
+    pub fn set_progress_sink(&mut self, sink: impl ProgressSink + 'static) {
+        self.progress = Some(Box::new(sink));
+    }
+
+    /// Stop reporting progress.
+    pub fn clear_progress_sink(&mut self) {
+        self.progress = None;
+    }
+
+    /// Stop the reads of whole tables through this handle once `cancel` is
+    /// cancelled, with an error for which [`Error::is_cancelled`] is true.
+    ///
+    /// These are the reads that report progress, see
+    /// [`set_progress_sink`](Self::set_progress_sink). Row streams such as
+    /// [`stream_items`](Self::stream_items) stop once dropped instead.
+    pub fn set_cancellation_token(&mut self, cancel: CancellationToken) {
+        self.cancel = Some(cancel);
+    }
+
+    /// Let reads through this handle run to completion again.
+    pub fn clear_cancellation_token(&mut self) {
+        self.cancel = None;
+    }
+
+    /// Interrupt any operation through this handle that runs longer than
+    /// `timeout`, or let operations run to completion with `None`.
+    ///
+    /// Interrupted operations return an error for which
+    /// [`Error::is_timed_out`] is true.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Run `operation`, interrupting its statements once the timeout passes.
+    fn with_timeout<T>(&self, operation: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        let Some(timeout) = self.timeout else {
+            return operation();
+        };
+        let interrupt = self.conn.get_interrupt_handle();
+        let (done, finished) = mpsc::channel::<()>();
+        let watchdog = thread::spawn(move || {
+            let expired = finished.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout);
+            if expired {
+                interrupt.interrupt();
+            }
+            expired
+        });
+
+        let result = operation();
+        drop(done);
+        let expired = watchdog.join().unwrap_or(false);
+        result.map_err(|err| match err {
+            // only the errors of statements the interrupt stopped
+            Error {
+                source: source @ Some(rusqlite::Error::SqliteFailure(code, _)),
+                kind,
+            } if expired
+                && !matches!(kind, ErrorKind::Cancelled)
+                && matches!(
+                    code.code,
+                    ErrorCode::OperationInterrupted | ErrorCode::DatabaseBusy
+                ) =>
+            {
+                Error {
+                    source,
+                    kind: ErrorKind::TimedOut,
+                }
+            }
+            err => err,
+        })
+    }
+
+    pub(crate) fn hooks(&self) -> Hooks<'_> {
+        Hooks {
+            trace: self.trace.as_deref(),
+            progress: self.progress.as_deref(),
+            cancel: self.cancel.as_ref(),
+        }
+    }
+
+    /// Ask SQLite how it would run a query against this library.
+    ///
+    /// See [`explain`](crate::explain()).
+    ///
+    /// # Errors
+    /// Returns an error if the query cannot be prepared
+    pub fn explain(&self, sql: &str) -> Result<QueryPlan, Error> {
+        explain(&self.conn, sql)
+    }
+
+    fn read_all_with(&self, hooks: Hooks) -> Result<(Vec<Album>, Vec<Item>), Error> {
+        self.with_timeout(|| {
+            Ok((
+                Album::read_all_traced(&self.conn, hooks)?,
+                Item::read_all_traced(&self.conn, hooks)?,
+            ))
+        })
+    }
+
+    /// Reads every [`Album`] in this library.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails or the timeout passes
+    pub fn albums(&self) -> Result<Vec<Album>, Error> {
+        self.with_timeout(|| Album::read_all_traced(&self.conn, self.hooks()))
+    }
+
+    /// Reads every [`Item`] in this library.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails or the timeout passes
+    pub fn items(&self) -> Result<Vec<Item>, Error> {
+        self.with_timeout(|| Item::read_all_traced(&self.conn, self.hooks()))
+    }
+
+    /// Runs `query`, with this handle's hooks, cancellation token and
+    /// timeout, unlike [`Query::run`].
+    ///
+    /// # Errors
+    /// Returns an error if a column is not one of the table's fields, the SQL
+    /// query fails or the timeout passes
+    pub fn query<T>(&self, query: &Query<T>) -> Result<Vec<T>, Error> {
+        self.with_timeout(|| query.run_traced(&self.conn, self.hooks()))
+    }
+
+    /// Reads the flexible attributes of one item or album.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails or the timeout passes
+    pub fn attributes(&self, entity: Entity) -> Result<Vec<Attribute>, Error> {
+        self.with_timeout(|| Attribute::read_for_traced(&self.conn, entity, self.hooks()))
+    }
+
+    /// Reads the flexible attributes of every item, by item id.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails or the timeout passes
+    pub fn item_attributes(&self) -> Result<HashMap<u32, Vec<Attribute>>, Error> {
+        self.with_timeout(|| {
+            Attribute::read_map_traced(&self.conn, "item_attributes", self.hooks())
+        })
+    }
+
+    /// Reads the flexible attributes of every album, by album id.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails or the timeout passes
+    pub fn album_attributes(&self) -> Result<HashMap<u32, Vec<Attribute>>, Error> {
+        self.with_timeout(|| {
+            Attribute::read_map_traced(&self.conn, "album_attributes", self.hooks())
+        })
+    }
+
+    /// Reads all the [`Album`]s and [`Item`]s in this library.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails or the timeout passes
+    pub fn read_all(&self) -> Result<(Vec<Album>, Vec<Item>), Error> {
+        self.read_all_with(self.hooks())
+    }
+
+    /// Reads the library as a [`LibraryTree`], with the items of each album
+    /// nested under it.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails or the timeout passes
+    pub fn read_tree(&self) -> Result<LibraryTree, Error> {
+        let (albums, items) = self.read_all()?;
+        Ok(Library { albums, items }.into())
+    }
+
+    /// Like [`read_all`](Self::read_all), stopping early once `cancel` is
+    /// cancelled, whatever token
+    /// [`set_cancellation_token`](Self::set_cancellation_token) set.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails or the timeout passes, or one
+    /// for which [`Error::is_cancelled`] is true if the read was cancelled
+    pub fn read_all_cancellable(
+        &self,
+        cancel: &CancellationToken,
+    ) -> Result<(Vec<Album>, Vec<Item>), Error> {
+        self.read_all_with(Hooks {
+            cancel: Some(cancel),
+            ..self.hooks()
+        })
+    }
+}
+
+impl Error {
+    /// Whether the operation was interrupted by the timeout set with
+    /// [`Database::set_timeout`].
+    #[must_use]
+    pub fn is_timed_out(&self) -> bool {
+        matches!(self.kind, ErrorKind::TimedOut)
+    }
+}
@@ -0,0 +1,68 @@
+//! [`miette`] integration, so command-line tools can render actionable errors.
+
+use std::fmt::Display;
+
+use rusqlite::ffi::ErrorCode;
+
+use crate::{Error, ErrorKind};
+
+impl Error {
+    fn code_name(&self) -> &'static str {
+        match self.kind {
+            ErrorKind::Row(_) => "beet_db::row",
+            ErrorKind::Open => "beet_db::open",
+            ErrorKind::Query => "beet_db::query",
+            ErrorKind::Schema => "beet_db::schema",
+            ErrorKind::Write => "beet_db::write",
+            ErrorKind::Cancelled => "beet_db::cancelled",
+            ErrorKind::TimedOut => "beet_db::timeout",
+            ErrorKind::Hazard(_) => "beet_db::hazard",
+            ErrorKind::Changeset(_) => "beet_db::changeset",
+            ErrorKind::UnknownTransparent => "beet_db::sqlite",
+        }
+    }
+
+    fn help_text(&self) -> Option<&'static str> {
+        if let ErrorKind::Hazard(_) = self.kind {
+            return Some("wait for beets to finish, or turn the checks off with `Database::set_safety_checks` if you are sure");
+        }
+        match self.source.as_ref()? {
+            rusqlite::Error::SqliteFailure(_, Some(message)) if message.contains("no such column") => {
+                Some("a column is missing - your beets version may be older than the schema this crate reads")
+            }
+            rusqlite::Error::SqliteFailure(_, Some(message)) if message.contains("no such table") => {
+                Some("this does not look like a beets library - check the `library` option in your beets config")
+            }
+            rusqlite::Error::SqliteFailure(e, _) if e.code == ErrorCode::NotADatabase => {
+                Some("the file is not an SQLite database - check the `library` option in your beets config")
+            }
+            rusqlite::Error::SqliteFailure(e, _) if e.code == ErrorCode::DatabaseBusy => {
+                Some("beets may be writing to the library right now - try again once it finishes")
+            }
+            rusqlite::Error::InvalidColumnType(..) | rusqlite::Error::FromSqlConversionFailure(..) => {
+                Some("a column holds an unexpected type - your beets version may be newer than this crate supports")
+            }
+            rusqlite::Error::SqliteFailure(e, _) if e.code == ErrorCode::ReadOnly => {
+                Some("the library was opened read-only - reopen it for writing first")
+            }
+            _ if matches!(self.kind, ErrorKind::TimedOut) => {
+                Some("the library may be very large or busy - raise the timeout, or create indexes for faster lookups")
+            }
+            _ if matches!(self.kind, ErrorKind::Open) => {
+                Some("check that the path points to an existing beets library")
+            }
+            _ => None,
+        }
+    }
+}
+
+impl miette::Diagnostic for Error {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new(self.code_name()))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        self.help_text()
+            .map(|help| Box::new(help) as Box<dyn Display>)
+    }
+}
@@ -0,0 +1,404 @@
+//! Differences between two [`Library`] snapshots, as a replayable changeset.
+//!
+//! Serialized with the `serde` feature, a [`Changeset`] is a stable JSON
+//! document:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "changes": [
+//!     {"op": "delete", "table": "items", "id": 7},
+//!     {"op": "update", "table": "albums", "id": 1, "fields": {"genre": "Jazz"}},
+//!     {"op": "insert", "table": "items", "id": 12, "fields": {"title": "...", ...}}
+//!   ]
+//! }
+//! ```
+//!
+//! Changes are ordered by table (albums first), then by id, and field names
+//! are sorted, so diffing the same two libraries always gives the same bytes.
+//!
+//! A changeset replays onto a library read into memory with
+//! [`apply_changes`], or onto one on disk with
+//! [`Database::apply_changes`](crate::Database::apply_changes). Only the
+//! columns of items and albums are diffed, not their flexible attributes.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[cfg(not(target_arch = "wasm32"))]
+use rusqlite::Connection;
+
+use crate::{Album, FieldError, FieldValue, Item, Library};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{Database, Error, ErrorKind, ReadWrite};
+
+/// The version of the changeset format written by [`diff`].
+pub const CHANGESET_VERSION: u32 = 1;
+
+/// The table a [`Change`] applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize, Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Table {
+    Albums,
+    Items,
+}
+
+/// Field values of a single record, keyed by column name.
+pub type Fields = BTreeMap<String, FieldValue<'static>>;
+
+/// A single change to one record.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize, Serialize),
+    serde(tag = "op", rename_all = "lowercase")
+)]
+pub enum Change {
+    /// A new record, with every field.
+    Insert {
+        table: Table,
+        id: u32,
+        fields: Fields,
+    },
+    /// An existing record, with only the fields that changed.
+    Update {
+        table: Table,
+        id: u32,
+        fields: Fields,
+    },
+    /// A removed record.
+    Delete { table: Table, id: u32 },
+}
+
+/// The changes that turn one library into another, made by [`diff`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Changeset {
+    pub version: u32,
+    pub changes: Vec<Change>,
+}
+
+impl Default for Changeset {
+    fn default() -> Self {
+        Self {
+            version: CHANGESET_VERSION,
+            changes: vec![],
+        }
+    }
+}
+
+impl Changeset {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// The reason a [`Changeset`] could not be applied.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApplyError {
+    /// The changeset was written in a format this version cannot read.
+    Version(u32),
+    /// An inserted record already exists.
+    Exists(Table, u32),
+    /// An updated or deleted record does not exist.
+    Missing(Table, u32),
+    /// A field could not be set.
+    Field(Table, u32, FieldError),
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplyError::Version(version) => {
+                write!(f, "unsupported changeset version {version}")
+            }
+            ApplyError::Exists(table, id) => write!(f, "{table:?} record {id} already exists"),
+            ApplyError::Missing(table, id) => write!(f, "{table:?} record {id} does not exist"),
+            ApplyError::Field(table, id, err) => write!(f, "{table:?} record {id}: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ApplyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ApplyError::Field(.., err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+trait Entry: Default {
+    const TABLE: Table;
+    const COLUMNS: &'static [&'static str];
+    fn id(&self) -> u32;
+    fn field(&self, name: &str) -> Option<FieldValue<'_>>;
+    fn set_field(&mut self, name: &str, value: FieldValue) -> Result<(), FieldError>;
+}
+
+macro_rules! impl_entry {
+    ($($ty:ident => $table:ident),*) => {
+        $(impl Entry for $ty {
+            const TABLE: Table = Table::$table;
+            const COLUMNS: &'static [&'static str] = $ty::COLUMNS;
+            fn id(&self) -> u32 {
+                self.id
+            }
+            fn field(&self, name: &str) -> Option<FieldValue<'_>> {
+                $ty::field(self, name)
+            }
+            fn set_field(&mut self, name: &str, value: FieldValue) -> Result<(), FieldError> {
+                $ty::set_field(self, name, value)
+            }
+        })*
+    };
+}
+impl_entry!(Album => Albums, Item => Items);
+
+fn all_fields<T: Entry>(entry: &T) -> Fields {
+    T::COLUMNS
+        .iter()
+        .filter(|&&column| column != "id")
+        .filter_map(|&column| Some((column.to_string(), entry.field(column)?.into_owned())))
+        .collect()
+}
+
+fn changed_fields<T: Entry>(old: &T, new: &T) -> Fields {
+    T::COLUMNS
+        .iter()
+        .filter_map(|&column| {
+            let value = new.field(column)?;
+            (old.field(column).as_ref() != Some(&value))
+                .then(|| (column.to_string(), value.into_owned()))
+        })
+        .collect()
+}
+
+fn diff_table<T: Entry>(old: &[T], new: &[T], changes: &mut Vec<Change>) {
+    let table = T::TABLE;
+    let old: BTreeMap<u32, &T> = old.iter().map(|entry| (entry.id(), entry)).collect();
+    let new: BTreeMap<u32, &T> = new.iter().map(|entry| (entry.id(), entry)).collect();
+
+    let mut ids: Vec<u32> = old.keys().chain(new.keys()).copied().collect();
+    ids.sort_unstable();
+    ids.dedup();
+
+    for id in ids {
+        let change = match (old.get(&id), new.get(&id)) {
+            (Some(_), None) => Change::Delete { table, id },
+            (None, Some(new)) => Change::Insert {
+                table,
+                id,
+                fields: all_fields(*new),
+            },
+            (Some(old), Some(new)) => {
+                let fields = changed_fields(*old, *new);
+                if fields.is_empty() {
+                    continue;
+                }
+                Change::Update { table, id, fields }
+            }
+            (None, None) => unreachable!("id {} came from one of the tables", id),
+        };
+        changes.push(change);
+    }
+}
+
+/// The changes that turn `old` into `new`, matching albums and items by id.
+#[must_use]
+pub fn diff(old: &Library, new: &Library) -> Changeset {
+    let mut changeset = Changeset::default();
+    diff_table(&old.albums, &new.albums, &mut changeset.changes);
+    diff_table(&old.items, &new.items, &mut changeset.changes);
+    changeset
+}
+
+fn set_fields<T: Entry>(entry: &mut T, id: u32, fields: &Fields) -> Result<(), ApplyError> {
+    for (name, value) in fields {
+        entry
+            .set_field(name, value.clone())
+            .map_err(|err| ApplyError::Field(T::TABLE, id, err))?;
+    }
+    Ok(())
+}
+
+fn apply_to<T: Entry>(entries: &mut Vec<T>, change: &Change) -> Result<(), ApplyError> {
+    let table = T::TABLE;
+    let position = |entries: &[T], id| entries.iter().position(|entry| entry.id() == id);
+    match change {
+        Change::Insert { id, fields, .. } => {
+            if position(entries, *id).is_some() {
+                return Err(ApplyError::Exists(table, *id));
+            }
+            let mut entry = T::default();
+            entry
+                .set_field("id", FieldValue::Integer((*id).into()))
+                .map_err(|err| ApplyError::Field(table, *id, err))?;
+            set_fields(&mut entry, *id, fields)?;
+            entries.push(entry);
+        }
+        Change::Update { id, fields, .. } => {
+            let index = position(entries, *id).ok_or(ApplyError::Missing(table, *id))?;
+            set_fields(&mut entries[index], *id, fields)?;
+        }
+        Change::Delete { id, .. } => {
+            let index = position(entries, *id).ok_or(ApplyError::Missing(table, *id))?;
+            entries.remove(index);
+        }
+    }
+    Ok(())
+}
+
+/// Replay a [`Changeset`] onto a library in memory, e.g. one deserialized
+/// from a copy on another machine, to bring it up to date. See
+/// [`Database::apply_changes`](crate::Database::apply_changes) to replay it
+/// onto a library on disk.
+///
+/// Changes are applied in order; on error, those before the failing change
+/// remain applied.
+///
+/// # Errors
+/// Returns an error if the changeset version is unsupported, or a change does
+/// not fit the library (such as updating a record that does not exist)
+pub fn apply_changes(library: &mut Library, changeset: &Changeset) -> Result<(), ApplyError> {
+    if changeset.version != CHANGESET_VERSION {
+        return Err(ApplyError::Version(changeset.version));
+    }
+    for change in &changeset.changes {
+        match change.table() {
+            Table::Albums => apply_to(&mut library.albums, change)?,
+            Table::Items => apply_to(&mut library.items, change)?,
+        }
+    }
+    Ok(())
+}
+
+impl Change {
+    fn table(&self) -> Table {
+        match self {
+            Change::Insert { table, .. }
+            | Change::Update { table, .. }
+            | Change::Delete { table, .. } => *table,
+        }
+    }
+}
+
+/// An [`Entry`] stored in a library on disk.
+#[cfg(not(target_arch = "wasm32"))]
+trait Stored: Entry {
+    fn read(conn: &Connection, id: u32) -> Result<Option<Self>, Error>;
+    fn insert(&self, db: &Database<ReadWrite>) -> Result<u32, Error>;
+    fn update(&self, db: &Database<ReadWrite>) -> Result<bool, Error>;
+    fn delete(&self, db: &Database<ReadWrite>) -> Result<bool, Error>;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+macro_rules! impl_stored {
+    ($($ty:ident => $delete:ident),*) => {
+        $(impl Stored for $ty {
+            fn read(conn: &Connection, id: u32) -> Result<Option<Self>, Error> {
+                Ok($ty::read_id_range(conn, id, id)?.pop())
+            }
+            fn insert(&self, db: &Database<ReadWrite>) -> Result<u32, Error> {
+                $ty::insert(self, db)
+            }
+            fn update(&self, db: &Database<ReadWrite>) -> Result<bool, Error> {
+                $ty::update(self, db)
+            }
+            fn delete(&self, db: &Database<ReadWrite>) -> Result<bool, Error> {
+                $ty::$delete(self, db)
+            }
+        })*
+    };
+}
+// an album removed in a changeset leaves its items, whose removal are
+// changes of their own
+#[cfg(not(target_arch = "wasm32"))]
+impl_stored!(Album => delete_alone, Item => delete);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Error {
+    fn changeset(err: ApplyError) -> Self {
+        Self {
+            source: None,
+            kind: ErrorKind::Changeset(err),
+        }
+    }
+
+    /// Why a changeset could not be replayed by
+    /// [`Database::apply_changes`], if that is what failed.
+    #[must_use]
+    pub fn apply_error(&self) -> Option<&ApplyError> {
+        match &self.kind {
+            ErrorKind::Changeset(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn apply_to_db<T: Stored>(db: &Database<ReadWrite>, change: &Change) -> Result<(), Error> {
+    let table = T::TABLE;
+    let conn = db.connection();
+    match change {
+        Change::Insert { id, fields, .. } => {
+            if T::read(conn, *id)?.is_some() {
+                return Err(Error::changeset(ApplyError::Exists(table, *id)));
+            }
+            let mut entry = T::default();
+            entry
+                .set_field("id", FieldValue::Integer((*id).into()))
+                .map_err(|err| Error::changeset(ApplyError::Field(table, *id, err)))?;
+            set_fields(&mut entry, *id, fields).map_err(Error::changeset)?;
+            entry.insert(db)?;
+        }
+        Change::Update { id, fields, .. } => {
+            let mut entry = T::read(conn, *id)?
+                .ok_or_else(|| Error::changeset(ApplyError::Missing(table, *id)))?;
+            set_fields(&mut entry, *id, fields).map_err(Error::changeset)?;
+            entry.update(db)?;
+        }
+        Change::Delete { id, .. } => {
+            let entry = T::read(conn, *id)?
+                .ok_or_else(|| Error::changeset(ApplyError::Missing(table, *id)))?;
+            entry.delete(db)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Database<ReadWrite> {
+    /// Replay a [`Changeset`] onto the library, as [`apply_changes`] does
+    /// onto one in memory, e.g. to bring a copy on another machine up to
+    /// date.
+    ///
+    /// The changes are written all together or not at all, checked for
+    /// [`write_hazards`](Self::write_hazards) like any other write and taken
+    /// back together by [`undo_last`](Self::undo_last). Removing an album
+    /// leaves its items, as in [`apply_changes`].
+    ///
+    /// # Errors
+    /// Returns an error for which [`Error::apply_error`] is `Some` if the
+    /// changeset version is unsupported or a change does not fit the
+    /// library, or any other error writing it
+    pub fn apply_changes(&self, changeset: &Changeset) -> Result<(), Error> {
+        if changeset.version != CHANGESET_VERSION {
+            return Err(Error::changeset(ApplyError::Version(changeset.version)));
+        }
+        self.write("apply changeset", || {
+            for change in &changeset.changes {
+                match change.table() {
+                    Table::Albums => apply_to_db::<Album>(self, change)?,
+                    Table::Items => apply_to_db::<Item>(self, change)?,
+                }
+            }
+            Ok(())
+        })
+    }
+}
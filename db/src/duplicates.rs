@@ -0,0 +1,321 @@
+use std::collections::{BTreeMap, HashSet};
+
+use std::convert::TryInto;
+
+use crate::{FederatedLibrary, FieldValue, GlobalId, Item, Sourced};
+
+/// How the copies in a [`DuplicateGroup`] were found to be the same recording.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum MatchKind {
+    /// The same MusicBrainz recording id.
+    MusicBrainz,
+    /// The same AcoustID.
+    AcoustId,
+    /// Matching artist and title (ignoring case and punctuation), and lengths
+    /// within [`FUZZY_LENGTH_TOLERANCE`] seconds.
+    Fuzzy,
+}
+
+/// The largest difference in length, in seconds, for a fuzzy match.
+pub const FUZZY_LENGTH_TOLERANCE: f64 = 2.0;
+
+/// The audio quality of an item, ordered from worst to best.
+///
+/// Lossless formats beat lossy ones, then bit depth, sample rate and bitrate
+/// are compared in turn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Quality {
+    pub lossless: bool,
+    pub bitdepth: u32,
+    pub samplerate: u32,
+    pub bitrate: u32,
+}
+
+impl Quality {
+    #[must_use]
+    pub fn of(item: &Item) -> Self {
+        Self {
+            lossless: item.is_lossless(),
+            bitdepth: item.bitdepth,
+            samplerate: item.samplerate,
+            bitrate: item.bitrate,
+        }
+    }
+}
+
+/// The tags counted by [`Item::tag_completeness`], those that make a copy
+/// easy to find, sort and match again.
+pub const COMPLETENESS_TAGS: &[&str] = &[
+    "artist",
+    "albumartist",
+    "album",
+    "title",
+    "genre",
+    "year",
+    "track",
+    "tracktotal",
+    "disc",
+    "label",
+    "mb_trackid",
+    "mb_albumid",
+    "mb_artistid",
+    "acoustid_id",
+];
+
+impl Item {
+    /// How many of the [`COMPLETENESS_TAGS`] are set on the item, counting
+    /// zero and blank text as unset.
+    #[must_use]
+    pub fn tag_completeness(&self) -> u32 {
+        let set = |value: FieldValue<'_>| match value {
+            FieldValue::Null => false,
+            FieldValue::Bool(_) => true,
+            FieldValue::Integer(i) => i != 0,
+            FieldValue::Real(r) => r != 0.0,
+            FieldValue::Text(text) => !text.trim().is_empty(),
+        };
+        COMPLETENESS_TAGS
+            .iter()
+            .filter(|tag| self.field(tag).is_some_and(set))
+            .count()
+            .try_into()
+            .unwrap_or(u32::MAX)
+    }
+}
+
+/// How good a copy of a recording is to keep, ordered from worst to best:
+/// its [`Quality`] first, then how complete its tags are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct CopyScore {
+    pub quality: Quality,
+    /// See [`Item::tag_completeness`].
+    pub tags: u32,
+}
+
+impl CopyScore {
+    #[must_use]
+    pub fn of(item: &Item) -> Self {
+        Self {
+            quality: Quality::of(item),
+            tags: item.tag_completeness(),
+        }
+    }
+}
+
+/// Why a [`DuplicatePlan`] keeps one copy rather than another: the first
+/// way in which its [`CopyScore`] is better.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum KeepReason {
+    /// The kept copy is lossless, the other lossy.
+    Lossless,
+    /// The kept copy has the greater bit depth.
+    Bitdepth,
+    /// The kept copy has the higher sample rate.
+    Samplerate,
+    /// The kept copy has the higher bitrate.
+    Bitrate,
+    /// More of the [`COMPLETENESS_TAGS`] are set on the kept copy.
+    Tags,
+    /// The copies score the same, so the first found is kept.
+    Tie,
+}
+
+impl KeepReason {
+    fn between(kept: CopyScore, other: CopyScore) -> Self {
+        let (kept_quality, other_quality) = (kept.quality, other.quality);
+        if kept_quality.lossless != other_quality.lossless {
+            KeepReason::Lossless
+        } else if kept_quality.bitdepth != other_quality.bitdepth {
+            KeepReason::Bitdepth
+        } else if kept_quality.samplerate != other_quality.samplerate {
+            KeepReason::Samplerate
+        } else if kept_quality.bitrate != other_quality.bitrate {
+            KeepReason::Bitrate
+        } else if kept.tags != other.tags {
+            KeepReason::Tags
+        } else {
+            KeepReason::Tie
+        }
+    }
+}
+
+/// A copy that a [`DuplicatePlan`] removes.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Removal<'a> {
+    pub copy: &'a Sourced<Item>,
+    pub reason: KeepReason,
+}
+
+/// Which copy of a recording to keep and which to remove, from
+/// [`DuplicateGroup::plan`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DuplicatePlan<'a> {
+    pub matched_by: MatchKind,
+    pub keep: &'a Sourced<Item>,
+    /// The other copies, best first, leaving out any that are the kept file
+    /// itself as another library sees it.
+    pub remove: Vec<Removal<'a>>,
+}
+
+/// Copies of one recording, found by [`FederatedLibrary::duplicates`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DuplicateGroup<'a> {
+    pub matched_by: MatchKind,
+    /// Every copy, best [`CopyScore`] first.
+    pub copies: Vec<&'a Sourced<Item>>,
+}
+
+impl<'a> DuplicateGroup<'a> {
+    /// The copy with the best score.
+    #[must_use]
+    pub fn best(&self) -> &'a Sourced<Item> {
+        self.copies[0]
+    }
+
+    /// Keep the [`best`](Self::best) copy and remove the others.
+    #[must_use]
+    pub fn plan(&self) -> DuplicatePlan<'a> {
+        let keep = self.best();
+        let score = CopyScore::of(&keep.entry);
+        let remove = self.copies[1..]
+            .iter()
+            .copied()
+            .filter(|copy| copy.entry.path != keep.entry.path)
+            .map(|copy| Removal {
+                copy,
+                reason: KeepReason::between(score, CopyScore::of(&copy.entry)),
+            })
+            .collect();
+        DuplicatePlan {
+            matched_by: self.matched_by,
+            keep,
+            remove,
+        }
+    }
+
+    /// The ids of every copy, best first.
+    pub fn ids(&self) -> impl Iterator<Item = GlobalId> + '_ {
+        self.copies.iter().map(|copy| copy.global_id())
+    }
+}
+
+/// Lower-case letters and digits only, so "Don't Stop" matches "dont stop".
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+struct Grouper<'a> {
+    items: &'a [Sourced<Item>],
+    /// Whether to keep groups held by only one library.
+    within_libraries: bool,
+    grouped: HashSet<usize>,
+    groups: Vec<DuplicateGroup<'a>>,
+}
+
+impl<'a> Grouper<'a> {
+    fn ungrouped(&self) -> impl Iterator<Item = (usize, &'a Sourced<Item>)> + '_ {
+        let items = self.items;
+        items
+            .iter()
+            .enumerate()
+            .filter(move |(index, _)| !self.grouped.contains(index))
+    }
+
+    fn add(&mut self, matched_by: MatchKind, indices: &[usize]) {
+        let spans_libraries = indices
+            .iter()
+            .any(|&index| self.items[index].source != self.items[indices[0]].source);
+        let keep = if self.within_libraries {
+            indices.len() > 1
+        } else {
+            spans_libraries
+        };
+        if !keep {
+            return;
+        }
+        self.grouped.extend(indices);
+        let mut copies: Vec<_> = indices.iter().map(|&index| &self.items[index]).collect();
+        copies.sort_by_key(|copy| std::cmp::Reverse(CopyScore::of(&copy.entry)));
+        self.groups.push(DuplicateGroup { matched_by, copies });
+    }
+
+    fn group_by_key(&mut self, matched_by: MatchKind, key: impl Fn(&Item) -> &str) {
+        let mut buckets: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (index, item) in self.ungrouped() {
+            let key = key(&item.entry);
+            if !key.is_empty() {
+                buckets.entry(key).or_default().push(index);
+            }
+        }
+        for indices in buckets.values() {
+            self.add(matched_by, indices);
+        }
+    }
+
+    fn group_fuzzy(&mut self) {
+        let mut buckets: BTreeMap<(String, String), Vec<usize>> = BTreeMap::new();
+        for (index, item) in self.ungrouped() {
+            let key = (normalize(&item.entry.artist), normalize(&item.entry.title));
+            if !key.1.is_empty() {
+                buckets.entry(key).or_default().push(index);
+            }
+        }
+        let items = self.items;
+        let length = |index: &usize| items[*index].entry.length;
+        for mut indices in buckets.into_values() {
+            indices.sort_by(|a, b| length(a).total_cmp(&length(b)));
+            // split wherever neighbouring lengths are too far apart
+            let mut start = 0;
+            for end in 1..=indices.len() {
+                let split = end == indices.len()
+                    || length(&indices[end]) - length(&indices[end - 1]) > FUZZY_LENGTH_TOLERANCE;
+                if split {
+                    self.add(MatchKind::Fuzzy, &indices[start..end]);
+                    start = end;
+                }
+            }
+        }
+    }
+}
+
+impl FederatedLibrary {
+    /// Find recordings present in more than one library.
+    ///
+    /// Items are matched by MusicBrainz recording id first, then by AcoustID,
+    /// and finally by a fuzzy match on artist, title and length. Each item is
+    /// in at most one group, and groups held by only one library are left out.
+    #[must_use]
+    pub fn duplicates(&self) -> Vec<DuplicateGroup<'_>> {
+        self.find_duplicates(false)
+    }
+
+    /// Like [`duplicates`](Self::duplicates), also finding copies held twice
+    /// by the same library.
+    #[must_use]
+    pub fn all_duplicates(&self) -> Vec<DuplicateGroup<'_>> {
+        self.find_duplicates(true)
+    }
+
+    fn find_duplicates(&self, within_libraries: bool) -> Vec<DuplicateGroup<'_>> {
+        let mut grouper = Grouper {
+            items: &self.items,
+            within_libraries,
+            grouped: HashSet::new(),
+            groups: vec![],
+        };
+        grouper.group_by_key(MatchKind::MusicBrainz, |item| &item.mb_trackid);
+        grouper.group_by_key(MatchKind::AcoustId, |item| &item.acoustid_id);
+        grouper.group_fuzzy();
+        grouper.groups
+    }
+}
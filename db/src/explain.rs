@@ -0,0 +1,76 @@
+use std::fmt;
+
+use rusqlite::{params_from_iter, types::Null, Connection};
+
+use crate::Error;
+
+/// One node of SQLite's `EXPLAIN QUERY PLAN` output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlanStep {
+    pub id: i64,
+    /// The id of the enclosing step, or `0` at the top level.
+    pub parent: i64,
+    /// A description such as `SCAN items` or
+    /// `SEARCH items USING INDEX items_by_album (album_id=?)`.
+    pub detail: String,
+}
+
+/// The plan SQLite chose for a query, as returned by [`explain`].
+///
+/// Displays as an indented tree, like the `sqlite3` shell does.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueryPlan {
+    pub steps: Vec<PlanStep>,
+}
+
+impl QueryPlan {
+    /// Steps that read a whole table, which an index might avoid.
+    pub fn full_scans(&self) -> impl Iterator<Item = &PlanStep> {
+        self.steps
+            .iter()
+            .filter(|step| step.detail.starts_with("SCAN ") && !step.detail.contains(" USING "))
+    }
+
+    fn depth(&self, step: &PlanStep) -> usize {
+        let mut depth = 0;
+        let mut parent = step.parent;
+        while let Some(next) = self.steps.iter().find(|s| s.id == parent) {
+            depth += 1;
+            parent = next.parent;
+        }
+        depth
+    }
+}
+
+impl fmt::Display for QueryPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "QUERY PLAN")?;
+        for step in &self.steps {
+            writeln!(f, "{}`--{}", "   ".repeat(self.depth(step)), step.detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Ask SQLite how it would run `sql`, such as [`Item::SQL_QUERY`](crate::Item::SQL_QUERY).
+///
+/// Any parameters in the query are bound to `NULL`, which does not affect the
+/// plan.
+///
+/// # Errors
+/// Returns an error if the query cannot be prepared
+pub fn explain(conn: &Connection, sql: &str) -> Result<QueryPlan, Error> {
+    let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {sql}"))?;
+    let nulls = params_from_iter(std::iter::repeat_n(Null, stmt.parameter_count()));
+    let steps = stmt
+        .query_map(nulls, |row| {
+            Ok(PlanStep {
+                id: row.get(0)?,
+                parent: row.get(1)?,
+                detail: row.get(3)?,
+            })
+        })
+        .and_then(Iterator::collect)
+        .map_err(Error::query)?;
+    Ok(QueryPlan { steps })
+}
@@ -0,0 +1,263 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{Album, Database, Error, FieldValue, Item, OpenOptions};
+
+/// The name a library goes by within a [`Federation`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize), serde(transparent))]
+pub struct LibraryTag(String);
+
+impl LibraryTag {
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for LibraryTag {
+    fn from(name: &str) -> Self {
+        Self(name.to_string())
+    }
+}
+
+impl From<String> for LibraryTag {
+    fn from(name: String) -> Self {
+        Self(name)
+    }
+}
+
+impl fmt::Display for LibraryTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// An album or item id that is unique across a [`Federation`], written as
+/// `library:id` (e.g. `alice:42`).
+///
+/// Row ids are only unique within one library, so anything referring to
+/// entries of several libraries should hold these instead.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct GlobalId {
+    pub library: LibraryTag,
+    pub id: u32,
+}
+
+impl GlobalId {
+    #[must_use]
+    pub fn new(library: impl Into<LibraryTag>, id: u32) -> Self {
+        Self {
+            library: library.into(),
+            id,
+        }
+    }
+}
+
+impl fmt::Display for GlobalId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.library, self.id)
+    }
+}
+
+/// The reason a string is not a valid [`GlobalId`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseGlobalIdError(String);
+
+impl fmt::Display for ParseGlobalIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected `library:id`, found {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseGlobalIdError {}
+
+impl FromStr for GlobalId {
+    type Err = ParseGlobalIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // tags may contain ':' themselves, ids never do
+        let (library, id) = s
+            .rsplit_once(':')
+            .filter(|(library, _)| !library.is_empty())
+            .ok_or_else(|| ParseGlobalIdError(s.to_string()))?;
+        let id = id.parse().map_err(|_| ParseGlobalIdError(s.to_string()))?;
+        Ok(Self::new(library, id))
+    }
+}
+
+/// An entry along with the library it was read from.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Sourced<T> {
+    pub source: LibraryTag,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub entry: T,
+}
+
+impl Sourced<Album> {
+    /// The id of this album across every library.
+    #[must_use]
+    pub fn global_id(&self) -> GlobalId {
+        GlobalId::new(self.source.clone(), self.entry.id)
+    }
+}
+
+impl Sourced<Item> {
+    /// The id of this item across every library.
+    #[must_use]
+    pub fn global_id(&self) -> GlobalId {
+        GlobalId::new(self.source.clone(), self.entry.id)
+    }
+
+    /// The album this item belongs to, if any.
+    #[must_use]
+    pub fn album_global_id(&self) -> Option<GlobalId> {
+        self.entry
+            .album_id
+            .map(|id| GlobalId::new(self.source.clone(), id))
+    }
+}
+
+/// Several beets libraries read together, e.g. one per person or per storage
+/// volume.
+#[derive(Debug, Default)]
+pub struct Federation {
+    members: Vec<(LibraryTag, Database)>,
+}
+
+impl Federation {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open each `(tag, path)` pair read-only.
+    ///
+    /// # Errors
+    /// Returns an error if any of the libraries cannot be opened
+    ///
+    /// # Panics
+    /// Panics if two libraries share a tag
+    pub fn open<T, P>(libraries: impl IntoIterator<Item = (T, P)>) -> Result<Self, Error>
+    where
+        T: Into<LibraryTag>,
+        P: AsRef<Path>,
+    {
+        let mut federation = Self::new();
+        for (tag, path) in libraries {
+            federation.add(tag, OpenOptions::new().open(path)?);
+        }
+        Ok(federation)
+    }
+
+    /// Add an already-open library, which is only read from then on.
+    ///
+    /// # Panics
+    /// Panics if another library already uses the tag
+    pub fn add(&mut self, tag: impl Into<LibraryTag>, db: impl Into<Database>) -> &mut Self {
+        let tag = tag.into();
+        let db = db.into();
+        assert!(
+            self.database(&tag).is_none(),
+            "library tag {:?} is already in use",
+            tag.as_str()
+        );
+        self.members.push((tag, db));
+        self
+    }
+
+    /// The tags of every library, in the order they were added.
+    pub fn tags(&self) -> impl Iterator<Item = &LibraryTag> {
+        self.members.iter().map(|(tag, _)| tag)
+    }
+
+    /// The library with the given tag.
+    #[must_use]
+    pub fn database(&self, tag: &LibraryTag) -> Option<&Database> {
+        self.members
+            .iter()
+            .find(|(member, _)| member == tag)
+            .map(|(_, db)| db)
+    }
+
+    /// Read every library into one merged view.
+    ///
+    /// # Errors
+    /// Returns an error if reading any of the libraries fails
+    pub fn read_all(&self) -> Result<FederatedLibrary, Error> {
+        let mut merged = FederatedLibrary::default();
+        for (tag, db) in &self.members {
+            let (albums, items) = db.read_all()?;
+            merged
+                .albums
+                .extend(albums.into_iter().map(|entry| Sourced {
+                    source: tag.clone(),
+                    entry,
+                }));
+            merged.items.extend(items.into_iter().map(|entry| Sourced {
+                source: tag.clone(),
+                entry,
+            }));
+        }
+        Ok(merged)
+    }
+}
+
+/// The merged contents of a [`Federation`].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct FederatedLibrary {
+    pub albums: Vec<Sourced<Album>>,
+    pub items: Vec<Sourced<Item>>,
+}
+
+impl FederatedLibrary {
+    /// The album with the given id in its library.
+    #[must_use]
+    pub fn album(&self, id: &GlobalId) -> Option<&Sourced<Album>> {
+        self.albums
+            .iter()
+            .find(|album| album.source == id.library && album.entry.id == id.id)
+    }
+
+    /// The item with the given id in its library.
+    #[must_use]
+    pub fn item(&self, id: &GlobalId) -> Option<&Sourced<Item>> {
+        self.items
+            .iter()
+            .find(|item| item.source == id.library && item.entry.id == id.id)
+    }
+
+    /// Like [`Library::distinct_album_values`](crate::Library::distinct_album_values), across every library.
+    #[must_use]
+    pub fn distinct_album_values(&self, field: &str) -> Option<Vec<FieldValue<'_>>> {
+        Album::COLUMNS.contains(&field).then(|| {
+            crate::field::distinct(
+                self.albums
+                    .iter()
+                    .filter_map(|album| album.entry.field(field)),
+            )
+        })
+    }
+
+    /// Like [`Library::distinct_item_values`](crate::Library::distinct_item_values), across every library.
+    #[must_use]
+    pub fn distinct_item_values(&self, field: &str) -> Option<Vec<FieldValue<'_>>> {
+        Item::COLUMNS.contains(&field).then(|| {
+            crate::field::distinct(self.items.iter().filter_map(|item| item.entry.field(field)))
+        })
+    }
+
+    /// The items of an album, which always share its library.
+    pub fn album_items<'a>(
+        &'a self,
+        album: &'a Sourced<Album>,
+    ) -> impl Iterator<Item = &'a Sourced<Item>> + 'a {
+        self.items.iter().filter(move |item| {
+            item.source == album.source && item.entry.album_id == Some(album.entry.id)
+        })
+    }
+}
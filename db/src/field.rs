@@ -0,0 +1,320 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::R128Gain;
+
+/// The value of a single field, looked up by name.
+///
+/// Values are totally ordered so they can be used as sort keys: `Null` sorts
+/// first, then booleans, numbers (integers and reals compared numerically)
+/// and finally text.
+#[derive(Clone, Debug)]
+pub enum FieldValue<'a> {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Real(f64),
+    Text(Cow<'a, str>),
+}
+
+impl FieldValue<'_> {
+    fn rank(&self) -> u8 {
+        match self {
+            FieldValue::Null => 0,
+            FieldValue::Bool(_) => 1,
+            FieldValue::Integer(_) | FieldValue::Real(_) => 2,
+            FieldValue::Text(_) => 3,
+        }
+    }
+}
+
+impl Ord for FieldValue<'_> {
+    #[allow(clippy::cast_precision_loss)]
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (FieldValue::Bool(a), FieldValue::Bool(b)) => a.cmp(b),
+            (FieldValue::Integer(a), FieldValue::Integer(b)) => a.cmp(b),
+            (FieldValue::Real(a), FieldValue::Real(b)) => a.total_cmp(b),
+            (FieldValue::Integer(a), FieldValue::Real(b)) => (*a as f64).total_cmp(b),
+            (FieldValue::Real(a), FieldValue::Integer(b)) => a.total_cmp(&(*b as f64)),
+            (FieldValue::Text(a), FieldValue::Text(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+impl PartialOrd for FieldValue<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for FieldValue<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for FieldValue<'_> {}
+
+impl fmt::Display for FieldValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Null => Ok(()),
+            FieldValue::Bool(b) => write!(f, "{b}"),
+            FieldValue::Integer(i) => write!(f, "{i}"),
+            FieldValue::Real(r) => write!(f, "{r}"),
+            FieldValue::Text(t) => write!(f, "{t}"),
+        }
+    }
+}
+
+/// Every distinct value among `values`, in sorted order.
+pub(crate) fn distinct<'a>(values: impl Iterator<Item = FieldValue<'a>>) -> Vec<FieldValue<'a>> {
+    values.collect::<BTreeSet<_>>().into_iter().collect()
+}
+
+/// Conversion of a struct field into a [`FieldValue`].
+pub(crate) trait ToFieldValue {
+    fn to_field_value(&self) -> FieldValue<'_>;
+}
+
+impl ToFieldValue for bool {
+    fn to_field_value(&self) -> FieldValue<'_> {
+        FieldValue::Bool(*self)
+    }
+}
+impl ToFieldValue for u32 {
+    fn to_field_value(&self) -> FieldValue<'_> {
+        FieldValue::Integer((*self).into())
+    }
+}
+impl ToFieldValue for i32 {
+    fn to_field_value(&self) -> FieldValue<'_> {
+        FieldValue::Integer((*self).into())
+    }
+}
+impl ToFieldValue for f64 {
+    fn to_field_value(&self) -> FieldValue<'_> {
+        FieldValue::Real(*self)
+    }
+}
+impl ToFieldValue for String {
+    fn to_field_value(&self) -> FieldValue<'_> {
+        FieldValue::Text(Cow::Borrowed(self))
+    }
+}
+impl ToFieldValue for PathBuf {
+    fn to_field_value(&self) -> FieldValue<'_> {
+        FieldValue::Text(self.to_string_lossy())
+    }
+}
+impl ToFieldValue for R128Gain {
+    fn to_field_value(&self) -> FieldValue<'_> {
+        FieldValue::Integer(self.q78().into())
+    }
+}
+impl<T: ToFieldValue> ToFieldValue for Option<T> {
+    fn to_field_value(&self) -> FieldValue<'_> {
+        self.as_ref()
+            .map_or(FieldValue::Null, ToFieldValue::to_field_value)
+    }
+}
+
+impl FieldValue<'_> {
+    /// Detach the value from the record it was read from.
+    #[must_use]
+    pub fn into_owned(self) -> FieldValue<'static> {
+        match self {
+            FieldValue::Null => FieldValue::Null,
+            FieldValue::Bool(b) => FieldValue::Bool(b),
+            FieldValue::Integer(i) => FieldValue::Integer(i),
+            FieldValue::Real(r) => FieldValue::Real(r),
+            FieldValue::Text(t) => FieldValue::Text(Cow::Owned(t.into_owned())),
+        }
+    }
+}
+
+impl From<bool> for FieldValue<'_> {
+    fn from(b: bool) -> Self {
+        FieldValue::Bool(b)
+    }
+}
+impl From<u32> for FieldValue<'_> {
+    fn from(i: u32) -> Self {
+        FieldValue::Integer(i.into())
+    }
+}
+impl From<i32> for FieldValue<'_> {
+    fn from(i: i32) -> Self {
+        FieldValue::Integer(i.into())
+    }
+}
+impl From<i64> for FieldValue<'_> {
+    fn from(i: i64) -> Self {
+        FieldValue::Integer(i)
+    }
+}
+impl From<f64> for FieldValue<'_> {
+    fn from(r: f64) -> Self {
+        FieldValue::Real(r)
+    }
+}
+impl From<String> for FieldValue<'_> {
+    fn from(t: String) -> Self {
+        FieldValue::Text(Cow::Owned(t))
+    }
+}
+impl<'a> From<&'a str> for FieldValue<'a> {
+    fn from(t: &'a str) -> Self {
+        FieldValue::Text(Cow::Borrowed(t))
+    }
+}
+
+/// The reason a field could not be set by name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldError {
+    /// The record has no field with this name.
+    Unknown(String),
+    /// The value cannot be stored in this field.
+    WrongType(&'static str),
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldError::Unknown(field) => write!(f, "no field named {field:?}"),
+            FieldError::WrongType(field) => write!(f, "wrong type of value for field {field:?}"),
+        }
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+/// Conversion of a [`FieldValue`] back into a struct field.
+pub(crate) trait FromFieldValue: Sized {
+    fn from_field_value(value: FieldValue) -> Option<Self>;
+}
+
+impl FromFieldValue for bool {
+    fn from_field_value(value: FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Bool(b) => Some(b),
+            FieldValue::Integer(i) => Some(i != 0),
+            _ => None,
+        }
+    }
+}
+impl FromFieldValue for u32 {
+    fn from_field_value(value: FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Integer(i) => i.try_into().ok(),
+            _ => None,
+        }
+    }
+}
+impl FromFieldValue for i32 {
+    fn from_field_value(value: FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Integer(i) => i.try_into().ok(),
+            _ => None,
+        }
+    }
+}
+impl FromFieldValue for f64 {
+    #[allow(clippy::cast_precision_loss)]
+    fn from_field_value(value: FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Real(r) => Some(r),
+            FieldValue::Integer(i) => Some(i as f64),
+            _ => None,
+        }
+    }
+}
+impl FromFieldValue for String {
+    fn from_field_value(value: FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Text(t) => Some(t.into_owned()),
+            _ => None,
+        }
+    }
+}
+impl FromFieldValue for PathBuf {
+    fn from_field_value(value: FieldValue) -> Option<Self> {
+        String::from_field_value(value).map(PathBuf::from)
+    }
+}
+impl FromFieldValue for R128Gain {
+    fn from_field_value(value: FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Integer(i) => Self::try_from(i).ok(),
+            _ => None,
+        }
+    }
+}
+impl<T: FromFieldValue> FromFieldValue for Option<T> {
+    fn from_field_value(value: FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Null => Some(None),
+            value => T::from_field_value(value).map(Some),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for FieldValue<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            FieldValue::Null => serializer.serialize_none(),
+            FieldValue::Bool(b) => serializer.serialize_bool(*b),
+            FieldValue::Integer(i) => serializer.serialize_i64(*i),
+            FieldValue::Real(r) => serializer.serialize_f64(*r),
+            FieldValue::Text(t) => serializer.serialize_str(t),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for FieldValue<'static> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = FieldValue<'static>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("null, a boolean, a number or a string")
+            }
+
+            fn visit_unit<E>(self) -> Result<Self::Value, E> {
+                Ok(FieldValue::Null)
+            }
+            fn visit_none<E>(self) -> Result<Self::Value, E> {
+                Ok(FieldValue::Null)
+            }
+            fn visit_bool<E>(self, b: bool) -> Result<Self::Value, E> {
+                Ok(FieldValue::Bool(b))
+            }
+            fn visit_i64<E>(self, i: i64) -> Result<Self::Value, E> {
+                Ok(FieldValue::Integer(i))
+            }
+            #[allow(clippy::cast_precision_loss)]
+            fn visit_u64<E>(self, u: u64) -> Result<Self::Value, E> {
+                Ok(u.try_into()
+                    .map_or(FieldValue::Real(u as f64), FieldValue::Integer))
+            }
+            fn visit_f64<E>(self, r: f64) -> Result<Self::Value, E> {
+                Ok(FieldValue::Real(r))
+            }
+            fn visit_str<E>(self, s: &str) -> Result<Self::Value, E> {
+                Ok(FieldValue::Text(Cow::Owned(s.to_string())))
+            }
+            fn visit_string<E>(self, s: String) -> Result<Self::Value, E> {
+                Ok(FieldValue::Text(Cow::Owned(s)))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
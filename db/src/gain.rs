@@ -0,0 +1,82 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+/// An EBU R128 gain adjustment, in the Q7.8 fixed-point format of the Opus
+/// `R128_TRACK_GAIN` and `R128_ALBUM_GAIN` tags: a signed 16-bit count of
+/// 1/256 dB steps, relative to -23 LUFS.
+///
+/// This is how beets stores the `r128_*_gain` columns. A missing gain is a
+/// `None` in the surrounding `Option`, which stays distinct from a gain of
+/// zero (also when serialized).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize), serde(transparent))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct R128Gain(i16);
+
+impl R128Gain {
+    /// The number of steps per dB.
+    pub const STEPS_PER_DB: f64 = 256.0;
+
+    #[must_use]
+    pub const fn from_q78(steps: i16) -> Self {
+        Self(steps)
+    }
+
+    /// The gain in dB, rounded to the nearest step, or `None` if it is out of
+    /// the Q7.8 range of about ±128 dB.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn from_db(db: f64) -> Option<Self> {
+        let steps = (db * Self::STEPS_PER_DB).round();
+        (f64::from(i16::MIN)..=f64::from(i16::MAX))
+            .contains(&steps)
+            .then_some(Self(steps as i16))
+    }
+
+    /// The raw Q7.8 value.
+    #[must_use]
+    pub const fn q78(self) -> i16 {
+        self.0
+    }
+
+    #[must_use]
+    pub fn db(self) -> f64 {
+        f64::from(self.0) / Self::STEPS_PER_DB
+    }
+}
+
+impl fmt::Display for R128Gain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2} dB", self.db())
+    }
+}
+
+impl TryFrom<i64> for R128Gain {
+    type Error = std::num::TryFromIntError;
+
+    fn try_from(steps: i64) -> Result<Self, Self::Error> {
+        i16::try_from(steps).map(Self)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl rusqlite::types::FromSql for R128Gain {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        use rusqlite::types::{FromSqlError, ValueRef};
+        #[allow(clippy::cast_possible_truncation)]
+        let steps = match value {
+            ValueRef::Integer(steps) => steps,
+            // the column has INTEGER affinity, so only fractional steps end up as REAL
+            ValueRef::Real(steps) => steps.round() as i64,
+            _ => return Err(FromSqlError::InvalidType),
+        };
+        Self::try_from(steps).map_err(|_| FromSqlError::OutOfRange(steps))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl rusqlite::types::ToSql for R128Gain {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(i64::from(self.0).into())
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::{Album, Item, Library};
+
+/// A small tree of common genres, in the format of the lastgenre plugin.
+const BUILTIN_TREE: &str = "\
+- blues:
+    - chicago blues
+    - delta blues
+    - electric blues
+- classical:
+    - baroque
+    - opera
+    - romantic
+- country:
+    - bluegrass
+    - outlaw country
+- electronic:
+    - ambient:
+        - dark ambient
+        - drone
+    - drum and bass:
+        - jungle
+        - liquid funk
+    - house:
+        - deep house
+        - tech house
+    - idm
+    - techno:
+        - detroit techno
+        - minimal techno
+    - trance
+    - downtempo:
+        - trip hop
+- folk:
+    - folk rock
+    - singer-songwriter
+- hip hop:
+    - boom bap
+    - trap
+- jazz:
+    - bebop
+    - cool jazz
+    - free jazz
+    - fusion
+    - swing
+- metal:
+    - black metal
+    - death metal
+    - doom metal
+    - heavy metal
+- pop:
+    - dream pop
+    - synthpop
+- r&b:
+    - funk
+    - soul:
+        - neo soul
+- reggae:
+    - dub
+    - ska
+- rock:
+    - alternative rock:
+        - grunge
+        - indie rock
+    - hard rock
+    - post-rock
+    - progressive rock
+    - psychedelic rock
+    - punk:
+        - hardcore punk
+        - post-punk
+";
+
+/// The characters that separate several genres within one tag.
+const SEPARATORS: &[char] = &[',', ';', '/'];
+
+/// A line of a genre tree that could not be understood.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenreTreeError {
+    /// The 1-based line number.
+    pub line: usize,
+}
+
+impl fmt::Display for GenreTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected `- genre` or `- genre:` on line {}", self.line)
+    }
+}
+
+impl std::error::Error for GenreTreeError {}
+
+#[derive(Clone, Debug)]
+struct Node {
+    name: String,
+    parent: Option<usize>,
+}
+
+/// A hierarchy of genres, so that e.g. "techno" is known to be "electronic".
+///
+/// Genres are matched ignoring case, and tags holding several genres (such as
+/// `Techno, House` as written by lastgenre) match if any of them does.
+#[derive(Clone, Debug)]
+pub struct GenreTree {
+    nodes: Vec<Node>,
+    index: HashMap<String, usize>,
+}
+
+impl Default for GenreTree {
+    /// A built-in tree of common genres.
+    fn default() -> Self {
+        Self::from_yaml(BUILTIN_TREE).expect("built-in genre tree is valid")
+    }
+}
+
+impl GenreTree {
+    /// Read a tree in the format of the lastgenre plugin's `genres-tree.yaml`,
+    /// where each genre is a list entry and parents end with `:`.
+    ///
+    /// A genre listed more than once keeps its first position.
+    ///
+    /// # Errors
+    /// Returns an error for any line that is not a list entry
+    pub fn from_yaml(text: &str) -> Result<Self, GenreTreeError> {
+        let mut nodes: Vec<Node> = vec![];
+        let mut index = HashMap::new();
+        // (indent, node) of each open parent
+        let mut parents: Vec<(usize, usize)> = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let content = line.trim_start();
+            if content.is_empty() || content.starts_with('#') || content == "---" {
+                continue;
+            }
+            let indent = line.len() - content.len();
+            let entry = content
+                .strip_prefix('-')
+                .ok_or(GenreTreeError { line: number + 1 })?
+                .trim();
+            let (name, is_parent) = match entry.strip_suffix(':') {
+                Some(name) => (name.trim_end(), true),
+                None => (entry, false),
+            };
+            let name = name.trim_matches(|c| c == '\'' || c == '"');
+            if name.is_empty() {
+                return Err(GenreTreeError { line: number + 1 });
+            }
+
+            while parents.last().is_some_and(|&(open, _)| open >= indent) {
+                parents.pop();
+            }
+            let parent = parents.last().map(|&(_, node)| node);
+            let node = *index.entry(name.to_lowercase()).or_insert_with(|| {
+                nodes.push(Node {
+                    name: name.to_string(),
+                    parent,
+                });
+                nodes.len() - 1
+            });
+            if is_parent {
+                parents.push((indent, node));
+            }
+        }
+        Ok(Self { nodes, index })
+    }
+
+    fn node(&self, genre: &str) -> Option<usize> {
+        self.index.get(&genre.trim().to_lowercase()).copied()
+    }
+
+    #[must_use]
+    pub fn contains(&self, genre: &str) -> bool {
+        self.node(genre).is_some()
+    }
+
+    /// The genre directly above `genre`.
+    #[must_use]
+    pub fn parent(&self, genre: &str) -> Option<&str> {
+        let parent = self.nodes[self.node(genre)?].parent?;
+        Some(&self.nodes[parent].name)
+    }
+
+    /// Every genre above `genre`, nearest first.
+    pub fn ancestors<'a>(&'a self, genre: &str) -> impl Iterator<Item = &'a str> + 'a {
+        let start = self.node(genre).and_then(|node| self.nodes[node].parent);
+        std::iter::successors(start, move |&node| self.nodes[node].parent)
+            .map(move |node| self.nodes[node].name.as_str())
+    }
+
+    /// Every genre below `genre`, at any depth, in tree order.
+    #[must_use]
+    pub fn descendants(&self, genre: &str) -> Vec<&str> {
+        let Some(root) = self.node(genre) else {
+            return vec![];
+        };
+        // parents always come before their children
+        let mut below = vec![false; self.nodes.len()];
+        below[root] = true;
+        let mut descendants = vec![];
+        for (index, node) in self.nodes.iter().enumerate().skip(root + 1) {
+            if node.parent.is_some_and(|parent| below[parent]) {
+                below[index] = true;
+                descendants.push(node.name.as_str());
+            }
+        }
+        descendants
+    }
+
+    /// Whether `genre` is `ancestor` or anywhere below it.
+    #[must_use]
+    pub fn is_under(&self, genre: &str, ancestor: &str) -> bool {
+        let (Some(mut node), Some(ancestor)) = (self.node(genre), self.node(ancestor)) else {
+            return false;
+        };
+        loop {
+            if node == ancestor {
+                return true;
+            }
+            match self.nodes[node].parent {
+                Some(parent) => node = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Whether any of the genres in the tag `genres` is under `ancestor`.
+    #[must_use]
+    pub fn matches(&self, genres: &str, ancestor: &str) -> bool {
+        genres
+            .split(SEPARATORS)
+            .any(|genre| self.is_under(genre, ancestor))
+    }
+}
+
+impl Library {
+    /// Albums whose genre is `genre` or anywhere below it in `tree`.
+    #[must_use]
+    pub fn albums_in_genre(&self, tree: &GenreTree, genre: &str) -> Vec<&Album> {
+        self.filter_albums(|album| tree.matches(&album.genre, genre))
+    }
+
+    /// Items whose genre is `genre` or anywhere below it in `tree`.
+    #[must_use]
+    pub fn items_in_genre(&self, tree: &GenreTree, genre: &str) -> Vec<&Item> {
+        self.filter_items(|item| tree.matches(&item.genre, genre))
+    }
+}
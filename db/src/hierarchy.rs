@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use crate::{Album, Item, Library};
+
+/// An album and its items, in disc and track order.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct AlbumNode {
+    pub album: Album,
+    pub items: Vec<Item>,
+}
+
+/// A library with the items of each album nested under it, as beets shows
+/// it with `beet ls -a`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct LibraryTree {
+    /// In the order of the library's albums.
+    pub albums: Vec<AlbumNode>,
+    /// Items without an album, in library order.
+    pub singletons: Vec<Item>,
+    /// Items whose `album_id` names an album that does not exist, which
+    /// beets leaves behind when an album row is removed by hand.
+    pub orphans: Vec<Item>,
+}
+
+impl From<Library> for LibraryTree {
+    fn from(library: Library) -> Self {
+        let mut tree = Self {
+            albums: Vec::with_capacity(library.albums.len()),
+            ..Self::default()
+        };
+        let mut index = HashMap::with_capacity(library.albums.len());
+        for album in library.albums {
+            index.insert(album.id, tree.albums.len());
+            tree.albums.push(AlbumNode {
+                album,
+                items: vec![],
+            });
+        }
+        for item in library.items {
+            match item.album_id {
+                None => tree.singletons.push(item),
+                Some(album_id) => match index.get(&album_id) {
+                    Some(&i) => tree.albums[i].items.push(item),
+                    None => tree.orphans.push(item),
+                },
+            }
+        }
+        for node in &mut tree.albums {
+            node.items
+                .sort_by_key(|item| (item.disc, item.track, item.id));
+        }
+        tree
+    }
+}
+
+impl From<LibraryTree> for Library {
+    /// Flatten the tree again, with each album's items together.
+    fn from(tree: LibraryTree) -> Self {
+        let mut library = Library::default();
+        for node in tree.albums {
+            library.albums.push(node.album);
+            library.items.extend(node.items);
+        }
+        library.items.extend(tree.singletons);
+        library.items.extend(tree.orphans);
+        library
+    }
+}
+
+impl LibraryTree {
+    /// The album with `id` and its items.
+    #[must_use]
+    pub fn album(&self, id: u32) -> Option<&AlbumNode> {
+        self.albums.iter().find(|node| node.album.id == id)
+    }
+
+    /// Every item, album by album, then the singletons and orphans.
+    pub fn items(&self) -> impl Iterator<Item = &Item> + '_ {
+        self.albums
+            .iter()
+            .flat_map(|node| &node.items)
+            .chain(&self.singletons)
+            .chain(&self.orphans)
+    }
+}
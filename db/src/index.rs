@@ -0,0 +1,60 @@
+use rusqlite::Connection;
+
+use crate::Error;
+
+/// The indexes made by [`create_indexes`], as `(name, table, column)`.
+///
+/// The attribute table indexes use the names beets gives them, so creating
+/// them is a no-op on any library made by a recent beets version.
+pub const INDEXES: &[(&str, &str, &str)] = &[
+    ("berts_items_album_id", "items", "album_id"),
+    ("berts_items_path", "items", "path"),
+    ("berts_items_mb_trackid", "items", "mb_trackid"),
+    ("item_attributes_by_entity", "item_attributes", "entity_id"),
+    (
+        "album_attributes_by_entity",
+        "album_attributes",
+        "entity_id",
+    ),
+];
+
+/// Create indexes that speed up per-album, per-path and attribute lookups on
+/// large libraries.
+///
+/// This needs a connection that can write to the library. SQLite only allows
+/// an index in the same database file as its table, so to keep the library
+/// untouched, index a copy of it instead (see [`create_indexes_in`]).
+///
+/// # Errors
+/// Returns an error if the connection is read-only or the tables are missing
+pub fn create_indexes(conn: &Connection) -> Result<(), Error> {
+    create_indexes_in(conn, "main")
+}
+
+/// Like [`create_indexes`], for a library attached to `conn` as `schema`.
+///
+/// # Errors
+/// Returns an error if the connection is read-only or the tables are missing
+pub fn create_indexes_in(conn: &Connection, schema: &str) -> Result<(), Error> {
+    for (name, table, column) in INDEXES {
+        let sql = format!("CREATE INDEX IF NOT EXISTS \"{schema}\".{name} ON {table} ({column})");
+        conn.execute(&sql, ()).map_err(Error::write)?;
+    }
+    Ok(())
+}
+
+/// Remove the indexes added by [`create_indexes`], leaving those beets itself
+/// creates.
+///
+/// # Errors
+/// Returns an error if the connection is read-only
+pub fn drop_indexes(conn: &Connection) -> Result<(), Error> {
+    for (name, ..) in INDEXES
+        .iter()
+        .filter(|(name, ..)| name.starts_with("berts_"))
+    {
+        conn.execute(&format!("DROP INDEX IF EXISTS {name}"), ())
+            .map_err(Error::write)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::review::civil_date;
+use crate::Item;
+
+/// `text` escaped for XML, without the control characters XML 1.0 cannot
+/// hold at all.
+pub(crate) fn escape_xml(text: &str) -> String {
+    text.chars()
+        .filter(|&c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+        .collect::<String>()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// `path` as the `file://` URL iTunes gives as the `Location` of a track,
+/// with every byte but unreserved ones, separators and the colon of a drive
+/// percent-encoded.
+pub(crate) fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut url = "file://localhost".to_string();
+    // e.g. `C:/Music`, which iTunes writes as `file://localhost/C:/Music`
+    if !path.starts_with('/') {
+        url.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                url.push(char::from(byte));
+            }
+            _ => {
+                let _ = write!(url, "%{byte:02X}");
+            }
+        }
+    }
+    url
+}
+
+/// A Unix timestamp as the UTC date iTunes writes, e.g.
+/// `2021-03-04T05:06:07Z`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn plist_date(timestamp: f64) -> String {
+    let (year, month, day) = civil_date(timestamp);
+    let secs = timestamp.rem_euclid(86_400.0) as u32;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// What iTunes calls a file of `format`, as beets names it.
+fn kind(format: &str) -> String {
+    match format {
+        "MP3" => "MPEG audio file".to_string(),
+        "AAC" => "AAC audio file".to_string(),
+        "ALAC" => "Apple Lossless audio file".to_string(),
+        "AIFF" => "AIFF audio file".to_string(),
+        "WAVE" => "WAV audio file".to_string(),
+        "" => "Audio file".to_string(),
+        format => format!("{format} audio file"),
+    }
+}
+
+/// The keys of one track, each written only where the item has a value, as
+/// iTunes leaves out those it does not know.
+struct Track<'a> {
+    out: &'a mut dyn Write,
+}
+
+impl Track<'_> {
+    fn string(&mut self, key: &str, value: &str) -> io::Result<()> {
+        if value.is_empty() {
+            return Ok(());
+        }
+        writeln!(
+            self.out,
+            "\t\t\t<key>{key}</key><string>{}</string>",
+            escape_xml(value)
+        )
+    }
+
+    fn integer(&mut self, key: &str, value: u64) -> io::Result<()> {
+        if value == 0 {
+            return Ok(());
+        }
+        writeln!(self.out, "\t\t\t<key>{key}</key><integer>{value}</integer>")
+    }
+
+    fn date(&mut self, key: &str, timestamp: f64) -> io::Result<()> {
+        if timestamp <= 0.0 {
+            return Ok(());
+        }
+        let date = plist_date(timestamp);
+        writeln!(self.out, "\t\t\t<key>{key}</key><date>{date}</date>")
+    }
+}
+
+/// The persistent id of the track of `item`, the same for every export of
+/// the library.
+fn persistent_id(item: &Item) -> String {
+    format!("BE375{:011X}", item.id)
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn write_track(out: &mut dyn Write, item: &Item) -> io::Result<()> {
+    writeln!(out, "\t\t<key>{}</key>", item.id)?;
+    writeln!(out, "\t\t<dict>")?;
+    let mut track = Track { out };
+    track.integer("Track ID", item.id.into())?;
+    track.string("Name", &item.title)?;
+    track.string("Artist", &item.artist)?;
+    track.string("Album Artist", &item.albumartist)?;
+    track.string("Composer", &item.composer)?;
+    track.string("Album", &item.album)?;
+    track.string("Grouping", &item.grouping)?;
+    track.string("Genre", &item.genre)?;
+    track.string("Kind", &kind(&item.format))?;
+    track.integer("Total Time", (item.length * 1000.0).round() as u64)?;
+    track.integer("Disc Number", item.disc.into())?;
+    track.integer("Disc Count", item.disctotal.into())?;
+    track.integer("Track Number", item.track.into())?;
+    track.integer("Track Count", item.tracktotal.into())?;
+    track.integer("Year", item.year.into())?;
+    track.integer("BPM", item.bpm_rounded().into())?;
+    track.date("Date Modified", item.mtime)?;
+    track.date("Date Added", item.added)?;
+    track.integer("Bit Rate", (item.bitrate / 1000).into())?;
+    track.integer("Sample Rate", item.samplerate.into())?;
+    track.string("Comments", &item.comments)?;
+    if item.comp {
+        writeln!(track.out, "\t\t\t<key>Compilation</key><true/>")?;
+    }
+    track.string("Sort Album Artist", &item.albumartist_sort)?;
+    track.string("Sort Artist", &item.artist_sort)?;
+    track.string("Sort Composer", &item.composer_sort)?;
+    track.string("Persistent ID", &persistent_id(item))?;
+    track.string("Track Type", "File")?;
+    track.string("Location", &file_url(&item.path))?;
+    writeln!(out, "\t\t</dict>")
+}
+
+/// Write `items` as an iTunes `Library.xml`, for DJ software and players
+/// that can import a library from iTunes but not from beets.
+///
+/// Every item is a track, with the beets id as its `Track ID`, and is listed
+/// in the `Library` playlist, in order. Items from several libraries must
+/// not share an id.
+///
+/// # Errors
+/// Returns an error if writing to `out` fails
+pub fn write_itunes_xml<'a, W: Write>(
+    out: &mut W,
+    items: impl IntoIterator<Item = &'a Item>,
+) -> io::Result<()> {
+    let items: Vec<&Item> = items.into_iter().collect();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<!DOCTYPE plist PUBLIC "-//Apple Computer//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">"#
+    )?;
+    writeln!(out, r#"<plist version="1.0">"#)?;
+    writeln!(out, "<dict>")?;
+    writeln!(out, "\t<key>Major Version</key><integer>1</integer>")?;
+    writeln!(out, "\t<key>Minor Version</key><integer>1</integer>")?;
+    writeln!(
+        out,
+        "\t<key>Application Version</key><string>beet_db {}</string>",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(out, "\t<key>Tracks</key>")?;
+    writeln!(out, "\t<dict>")?;
+    for item in &items {
+        write_track(out, item)?;
+    }
+    writeln!(out, "\t</dict>")?;
+    writeln!(out, "\t<key>Playlists</key>")?;
+    writeln!(out, "\t<array>")?;
+    writeln!(out, "\t\t<dict>")?;
+    writeln!(out, "\t\t\t<key>Name</key><string>Library</string>")?;
+    writeln!(out, "\t\t\t<key>Master</key><true/>")?;
+    writeln!(out, "\t\t\t<key>Playlist ID</key><integer>1</integer>")?;
+    writeln!(out, "\t\t\t<key>All Items</key><true/>")?;
+    writeln!(out, "\t\t\t<key>Playlist Items</key>")?;
+    writeln!(out, "\t\t\t<array>")?;
+    for item in &items {
+        writeln!(
+            out,
+            "\t\t\t\t<dict><key>Track ID</key><integer>{}</integer></dict>",
+            item.id
+        )?;
+    }
+    writeln!(out, "\t\t\t</array>")?;
+    writeln!(out, "\t\t</dict>")?;
+    writeln!(out, "\t</array>")?;
+    writeln!(out, "</dict>")?;
+    writeln!(out, "</plist>")
+}
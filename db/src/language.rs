@@ -0,0 +1,83 @@
+use crate::attribute::set_recorded;
+use crate::{Attribute, Database, Entity, Error, Item, ReadWrite};
+
+/// The flexible attribute holding the language detected in the lyrics of an
+/// item, as an ISO 639-3 code like the `language` beets gives albums.
+pub const LYRICS_LANGUAGE: &str = "lyrics_language";
+/// The flexible attribute holding the language detected in the title of an
+/// item. See [`LYRICS_LANGUAGE`].
+pub const TITLE_LANGUAGE: &str = "title_language";
+
+/// Detects the language of the lyrics and titles of items, for
+/// [`Database::tag_languages`] to save.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LanguageAnalyzer {
+    /// How sure the detection must be to be kept, from `0.0` to `1.0`.
+    /// Titles are short, so fewer of them pass a high bar.
+    pub min_confidence: f64,
+}
+
+impl Default for LanguageAnalyzer {
+    fn default() -> Self {
+        Self {
+            min_confidence: 0.5,
+        }
+    }
+}
+
+impl LanguageAnalyzer {
+    /// The ISO 639-3 code of the language `text` is in, if it is detected
+    /// with enough confidence.
+    #[must_use]
+    pub fn detect(&self, text: &str) -> Option<&'static str> {
+        let info = whatlang::detect(text)?;
+        (info.confidence() >= self.min_confidence).then(|| info.lang().code())
+    }
+
+    /// The flexible attributes to tag `item` with, for its lyrics and title
+    /// in turn, leaving out those whose language is not detected.
+    #[must_use]
+    pub fn analyze(&self, item: &Item) -> Vec<(&'static str, &'static str)> {
+        [
+            (LYRICS_LANGUAGE, &item.lyrics),
+            (TITLE_LANGUAGE, &item.title),
+        ]
+        .iter()
+        .filter_map(|&(key, text)| Some((key, self.detect(text)?)))
+        .collect()
+    }
+}
+
+impl Database<ReadWrite> {
+    /// Tag every item with the languages `analyzer` detects in its lyrics
+    /// and title, as [`LYRICS_LANGUAGE`] and [`TITLE_LANGUAGE`], for queries
+    /// such as `lyrics_language:jpn` to browse by. Returns how many
+    /// attributes were set or changed.
+    ///
+    /// Attributes are left alone where nothing is detected, so values set by
+    /// hand are kept. All of them are undone together.
+    ///
+    /// # Errors
+    /// Returns an error if the items cannot be read or tagged, or one for
+    /// which [`Error::write_hazard`] is `Some` if writing is not safe
+    pub fn tag_languages(&self, analyzer: &LanguageAnalyzer) -> Result<usize, Error> {
+        let conn = self.connection();
+        self.write("tag languages", || {
+            let mut attributes = Attribute::read_item_map(conn)?;
+            let mut tagged = 0;
+            for item in self.items()? {
+                let current = attributes.remove(&item.id).unwrap_or_default();
+                for (key, language) in analyzer.analyze(&item) {
+                    let unchanged = current
+                        .iter()
+                        .any(|attribute| attribute.key == key && attribute.value == language);
+                    if !unchanged {
+                        set_recorded(self, Entity::Item(item.id), key, language)?;
+                        tagged += 1;
+                    }
+                }
+            }
+            Ok(tagged)
+        })
+    }
+}
@@ -2,15 +2,23 @@
 
 #![deny(clippy::pedantic)]
 
+#[cfg(feature = "serde")]
 #[macro_use]
 extern crate serde_derive;
 
+#[cfg(all(
+    feature = "bundled",
+    any(feature = "sqlcipher", feature = "bundled-sqlcipher")
+))]
+compile_error!("`bundled` compiles plain SQLite; use `bundled-sqlcipher` to compile SQLCipher");
+
 use std::path::PathBuf;
 
 #[cfg(not(target_arch = "wasm32"))]
 #[derive(Debug)]
 pub struct Error {
-    source: rusqlite::Error,
+    /// `None` for errors of this crate's own, such as a refused write.
+    source: Option<rusqlite::Error>,
     kind: ErrorKind,
 }
 #[cfg(not(target_arch = "wasm32"))]
@@ -19,20 +27,51 @@ enum ErrorKind {
     Row(TableColumn),
     Open,
     Query,
+    Schema,
+    Write,
+    Cancelled,
+    TimedOut,
+    Hazard(WriteHazard),
+    Changeset(ApplyError),
     UnknownTransparent,
 }
 #[cfg(not(target_arch = "wasm32"))]
 impl From<rusqlite::Error> for Error {
     fn from(source: rusqlite::Error) -> Self {
         Self {
-            source,
+            source: Some(source),
             kind: ErrorKind::UnknownTransparent,
         }
     }
 }
+#[cfg(not(target_arch = "wasm32"))]
+impl Error {
+    /// An error writing to the database.
+    pub(crate) fn write(source: rusqlite::Error) -> Self {
+        Self {
+            source: Some(source),
+            kind: ErrorKind::Write,
+        }
+    }
+
+    /// An error querying the database.
+    pub(crate) fn query(source: rusqlite::Error) -> Self {
+        Self {
+            source: Some(source),
+            kind: ErrorKind::Query,
+        }
+    }
+}
 impl From<Error> for rusqlite::Error {
     fn from(value: Error) -> Self {
-        value.source
+        match value.source {
+            Some(source) => source,
+            // the one variant always built that carries any error
+            None => rusqlite::Error::ToSqlConversionFailure(Box::new(Error {
+                source: None,
+                kind: value.kind,
+            })),
+        }
     }
 }
 
@@ -45,14 +84,24 @@ struct TableColumn {
 
 #[cfg(not(target_arch = "wasm32"))]
 use rusqlite::{Connection, OpenFlags};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
 
 #[cfg(not(target_arch = "wasm32"))]
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match &self.kind {
-            ErrorKind::Row(_) | ErrorKind::Open | ErrorKind::Query => Some(&self.source),
+            ErrorKind::Row(_)
+            | ErrorKind::Open
+            | ErrorKind::Query
+            | ErrorKind::Schema
+            | ErrorKind::Write
+            | ErrorKind::Cancelled
+            | ErrorKind::TimedOut => self.source.as_ref().map(|source| source as _),
+            ErrorKind::Hazard(hazard) => Some(hazard),
+            ErrorKind::Changeset(err) => Some(err),
             // Unknown is transparent
-            ErrorKind::UnknownTransparent => self.source.source(),
+            ErrorKind::UnknownTransparent => self.source.as_ref()?.source(),
         }
     }
 }
@@ -65,21 +114,343 @@ impl std::fmt::Display for Error {
             }
             ErrorKind::Open => write!(f, "failed to open database"),
             ErrorKind::Query => write!(f, "failed to query database"),
+            ErrorKind::Schema => write!(f, "failed to create database schema"),
+            ErrorKind::Write => write!(f, "failed to write to database"),
+            ErrorKind::Cancelled => write!(f, "operation was cancelled"),
+            ErrorKind::TimedOut => write!(f, "operation timed out"),
+            ErrorKind::Hazard(hazard) => write!(f, "refused to write: {hazard}"),
+            ErrorKind::Changeset(err) => write!(f, "failed to apply changeset: {err}"),
             // Unknown is transparent
-            ErrorKind::UnknownTransparent => write!(f, "{}", self.source),
+            ErrorKind::UnknownTransparent => match &self.source {
+                Some(source) => write!(f, "{source}"),
+                None => f.write_str("unknown error"),
+            },
         }
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+mod aggregate;
+#[cfg(feature = "proptest")]
+mod arbitrary;
+#[cfg(feature = "arrow")]
+mod arrow_export;
+#[cfg(feature = "art")]
+mod art;
+mod artist;
+#[cfg(not(target_arch = "wasm32"))]
+mod attribute;
+mod audit;
+#[cfg(feature = "serde")]
+pub mod beets_export;
+mod bytes;
+#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+mod cache;
+mod camelot;
+#[cfg(not(target_arch = "wasm32"))]
+mod cancel;
+#[cfg(not(target_arch = "wasm32"))]
+mod changelog;
+#[cfg(not(target_arch = "wasm32"))]
+mod collection;
+mod compilation;
+mod consistency;
+#[cfg(not(target_arch = "wasm32"))]
+mod copy_export;
+mod coverage;
+#[cfg(not(target_arch = "wasm32"))]
+mod database;
+#[cfg(all(feature = "miette", not(target_arch = "wasm32")))]
+mod diagnostic;
+mod diff;
+#[cfg(not(target_arch = "wasm32"))]
+mod duplicates;
+#[cfg(not(target_arch = "wasm32"))]
+mod explain;
+#[cfg(not(target_arch = "wasm32"))]
+mod federation;
+mod field;
+mod gain;
+mod genre;
+mod hierarchy;
+#[cfg(not(target_arch = "wasm32"))]
+mod index;
+mod itunes;
+#[cfg(all(feature = "language", not(target_arch = "wasm32")))]
+mod language;
+mod library;
+mod lyrics;
+mod mbid;
+mod merge;
+#[cfg(not(target_arch = "wasm32"))]
+mod missing;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+mod ndjson;
+#[cfg(not(target_arch = "wasm32"))]
+mod open;
+#[cfg(not(target_arch = "wasm32"))]
+mod parallel;
+#[cfg(all(feature = "parquet", not(target_arch = "wasm32")))]
+mod parquet_export;
+#[cfg(not(target_arch = "wasm32"))]
+mod path_case;
+mod playlist;
+mod playlist_preset;
+#[cfg(not(target_arch = "wasm32"))]
+mod progress;
+#[cfg(not(target_arch = "wasm32"))]
+mod provenance;
+#[cfg(not(target_arch = "wasm32"))]
+mod query;
+mod queue;
+mod redact;
+mod rekordbox;
+#[cfg(all(feature = "remote", not(target_arch = "wasm32")))]
+mod remote;
+mod review;
+#[cfg(not(target_arch = "wasm32"))]
+mod safety;
+#[cfg(not(target_arch = "wasm32"))]
+mod schema;
+#[cfg(not(target_arch = "wasm32"))]
+mod session;
+mod smart_crate;
+mod stats;
+#[cfg(not(target_arch = "wasm32"))]
+mod stream;
+#[cfg(not(target_arch = "wasm32"))]
+mod sync;
 mod tests;
+mod timestamp;
+#[cfg(not(target_arch = "wasm32"))]
+mod trace;
+#[cfg(not(target_arch = "wasm32"))]
+mod trash;
+#[cfg(feature = "typescript")]
+mod typescript;
+#[cfg(not(target_arch = "wasm32"))]
+mod undo;
+#[cfg(feature = "wasm-bindings")]
+mod wasm;
+#[cfg(not(target_arch = "wasm32"))]
+mod watch;
+#[cfg(not(target_arch = "wasm32"))]
+mod write;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use aggregate::AlbumTotals;
+#[cfg(feature = "art")]
+pub use art::{ArtFinding, ArtProbe, ArtReport, ArtThresholds};
+pub use artist::{person_sort_name, sort_name, ArtistCredit, ArtistSplitter, ArtistStats};
+#[cfg(not(target_arch = "wasm32"))]
+pub use attribute::{delete_attribute, set_attribute, Entity, WithAttributes};
+pub use audit::{QualityFinding, QualityThresholds};
+pub use bytes::{read_all_from_bytes, BytesError};
+#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+pub use cache::{read_all_cached, snapshot_path};
+pub use camelot::CamelotKey;
+#[cfg(not(target_arch = "wasm32"))]
+pub use cancel::CancellationToken;
+#[cfg(not(target_arch = "wasm32"))]
+pub use changelog::{ChangeKind, ChangelogEntry};
+#[cfg(not(target_arch = "wasm32"))]
+pub use collection::Collection;
+pub use compilation::{CompilationHeuristics, CompilationReason, VARIOUS_ARTISTS_MBID};
+pub use consistency::ConsistencyFinding;
+#[cfg(not(target_arch = "wasm32"))]
+pub use copy_export::{
+    copy_export, export_paths, CopyExportOptions, CopyReport, PathTemplate, DEFAULT_PATH_TEMPLATE,
+};
+pub use coverage::{Coverage, CoverageGroup};
+#[cfg(not(target_arch = "wasm32"))]
+pub use database::{Access, Database, ReadOnly, ReadWrite};
+pub use diff::{
+    apply_changes, diff, ApplyError, Change, Changeset, Fields, Table, CHANGESET_VERSION,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use duplicates::{
+    CopyScore, DuplicateGroup, DuplicatePlan, KeepReason, MatchKind, Quality, Removal,
+    COMPLETENESS_TAGS, FUZZY_LENGTH_TOLERANCE,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use explain::{explain, PlanStep, QueryPlan};
+#[cfg(not(target_arch = "wasm32"))]
+pub use federation::{
+    FederatedLibrary, Federation, GlobalId, LibraryTag, ParseGlobalIdError, Sourced,
+};
+pub use field::{FieldError, FieldValue};
+pub use gain::R128Gain;
+pub use genre::{GenreTree, GenreTreeError};
+pub use hierarchy::{AlbumNode, LibraryTree};
+#[cfg(not(target_arch = "wasm32"))]
+pub use index::{create_indexes, create_indexes_in, drop_indexes, INDEXES};
+pub use itunes::write_itunes_xml;
+#[cfg(all(feature = "language", not(target_arch = "wasm32")))]
+pub use language::{LanguageAnalyzer, LYRICS_LANGUAGE, TITLE_LANGUAGE};
+pub use library::{Library, SortKey};
+pub use lyrics::{LyricsFiles, LyricsFilled, LyricsProvider};
+pub use mbid::{MbidResolver, MbidSuggestion, MbidWork};
+pub use merge::SplitAlbum;
+#[cfg(feature = "msgpack")]
+pub use msgpack::{from_msgpack, to_msgpack, MsgpackDecodeError, MsgpackEncodeError};
+#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+pub use ndjson::{write_ndjson, NdjsonError};
+#[cfg(not(target_arch = "wasm32"))]
+pub use open::{OpenOptions, Preset};
+#[cfg(all(feature = "parquet", not(target_arch = "wasm32")))]
+pub use parquet::errors::ParquetError;
+#[cfg(all(feature = "parquet", not(target_arch = "wasm32")))]
+pub use parquet_export::{write_parquet, Parquet, PARQUET_ROW_GROUP_SIZE};
+#[cfg(not(target_arch = "wasm32"))]
+pub use path_case::CaseMismatch;
+pub use playlist::{write_m3u, PlaylistOptions};
+pub use playlist_preset::{PlaylistPreset, FORGOTTEN_AGE, FORGOTTEN_PLAYS};
+#[cfg(not(target_arch = "wasm32"))]
+pub use progress::{Progress, ProgressSink, PROGRESS_INTERVAL};
+#[cfg(not(target_arch = "wasm32"))]
+pub use provenance::{Provenance, PROVENANCE_PREFIX};
+#[cfg(not(target_arch = "wasm32"))]
+pub use query::{Filter, Query};
+pub use queue::{GainMode, PlayQueue, Queue, QueueEntry, R128_TO_REPLAYGAIN_DB};
+pub use redact::Redaction;
+pub use rekordbox::write_rekordbox_xml;
+#[cfg(all(feature = "remote", not(target_arch = "wasm32")))]
+pub use remote::{fetch_url, remote_cache_dir, RemoteError};
+pub use review::{play_counts, RankedArtist, YearInReview, TOP_ARTISTS};
+#[cfg(not(target_arch = "wasm32"))]
+pub use safety::WriteHazard;
+#[cfg(not(target_arch = "wasm32"))]
+pub use session::{Session, HISTORY_LIMIT};
+pub use smart_crate::CrateBuilder;
+pub use stats::{LibraryStats, RECENTLY_ADDED};
+#[cfg(not(target_arch = "wasm32"))]
+pub use stream::{RowIter, RowStream};
+#[cfg(not(target_arch = "wasm32"))]
+pub use sync::{SyncExclusion, SyncPlan, SyncPriority, SyncProfile, SYNC_MANIFEST};
+#[cfg(all(feature = "chrono", feature = "serde"))]
+pub use timestamp::Timestamped;
+#[cfg(not(target_arch = "wasm32"))]
+pub use trace::{QueryTrace, TraceHook};
+#[cfg(feature = "typescript")]
+pub use typescript::{typescript_declarations, TypeScript};
+#[cfg(not(target_arch = "wasm32"))]
+pub use undo::UndoEntry;
+#[cfg(feature = "wasm-bindings")]
+pub use wasm::{parse_library, JsAlbum, JsItem, JsLibrary};
+#[cfg(not(target_arch = "wasm32"))]
+pub use watch::{Watcher, WATCH_INTERVAL};
 
 macro_rules! def_sqlite_struct {
-    ( $(#[$outer:meta])* $name:ident [ $( $(#[$inner:meta])* $field:ident: $typ:ty $(; $func:ident)?, )* ]
+    ( $(#[$outer:meta])* $name:ident [ $(
+        $(#[doc = $doc:expr])* $(#[serde($($serde:tt)*)])* $field:ident: $typ:ty $(; $func:ident)?,
+    )* ]
     ) => {
         $(#[$outer])*
-        #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+        #[derive(Clone, Debug, Default, PartialEq)]
+        #[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+        #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
         pub struct $name {
-            $( $(#[$inner])* pub $field: $typ ),*
+            $(
+                $(#[doc = $doc])*
+                $(#[cfg_attr(feature = "serde", serde($($serde)*))])*
+                pub $field: $typ
+            ),*
+        }
+
+        impl $name {
+            /// Look up the value of a field by its column name.
+            #[must_use]
+            pub fn field(&self, name: &str) -> Option<$crate::FieldValue<'_>> {
+                use $crate::field::ToFieldValue;
+                match name {
+                    $( stringify!($field) => Some(self.$field.to_field_value()), )*
+                    _ => None,
+                }
+            }
+
+            /// Set the value of a field by its column name.
+            ///
+            /// # Errors
+            /// Returns an error if there is no such field, or it cannot hold the value
+            pub fn set_field(
+                &mut self,
+                name: &str,
+                value: $crate::FieldValue,
+            ) -> Result<(), $crate::FieldError> {
+                use $crate::field::FromFieldValue;
+                match name {
+                    $( stringify!($field) => {
+                        self.$field = FromFieldValue::from_field_value(value)
+                            .ok_or($crate::FieldError::WrongType(stringify!($field)))?;
+                    } )*
+                    _ => return Err($crate::FieldError::Unknown(name.to_string())),
+                }
+                Ok(())
+            }
+        }
+
+        #[cfg(all(feature = "parquet", not(target_arch = "wasm32")))]
+        impl $crate::Parquet for $name {
+            fn parquet_schema() -> ::parquet::schema::types::Type {
+                $crate::parquet_export::message(
+                    stringify!($name),
+                    vec![ $( $crate::parquet_export::column::<$typ>(stringify!($field)) ),* ],
+                )
+            }
+
+            fn write_row_group<W: ::std::io::Write + Send>(
+                row_group: &mut ::parquet::file::writer::SerializedRowGroupWriter<'_, W>,
+                rows: &[Self],
+            ) -> Result<(), ::parquet::errors::ParquetError> {
+                $(
+                    $crate::parquet_export::write_column::<$typ, W>(
+                        row_group,
+                        rows.iter().map(|row| &row.$field),
+                    )?;
+                )*
+                Ok(())
+            }
+        }
+
+        #[cfg(feature = "arrow")]
+        impl $name {
+            /// The Arrow schema of [`to_record_batch`](Self::to_record_batch),
+            /// with a field for every column, nullable if it may be missing.
+            #[must_use]
+            pub fn arrow_schema() -> ::arrow_schema::Schema {
+                ::arrow_schema::Schema::new(vec![
+                    $( $crate::arrow_export::field::<$typ>(stringify!($field)) ),*
+                ])
+            }
+
+            /// `rows` as an Arrow record batch, with an array of every
+            /// column, to hand to Arrow-based tools.
+            #[must_use]
+            pub fn to_record_batch(rows: &[Self]) -> ::arrow_array::RecordBatch {
+                $crate::arrow_export::record_batch(
+                    Self::arrow_schema(),
+                    vec![ $( $crate::arrow_export::array::<$typ>(rows.iter().map(|row| &row.$field)) ),* ],
+                )
+            }
+        }
+
+        #[cfg(feature = "typescript")]
+        impl $crate::TypeScript for $name {
+            fn declaration() -> String {
+                let mut declaration =
+                    concat!("export interface ", stringify!($name), " {\n").to_string();
+                $(
+                    declaration.push_str(&$crate::typescript::property::<$typ>(
+                        stringify!($field),
+                        &[$($doc),*],
+                        &[$(stringify!($($serde)*)),*],
+                    ));
+                )*
+                declaration.push_str("}\n");
+                declaration
+            }
         }
 
         #[cfg(not(target_arch = "wasm32"))]
@@ -120,6 +491,10 @@ macro_rules! def_sqlite_struct {
     };
 
     ( $name:ident $table:expr => [ $( $(#[$_inner:meta])* $field:ident : $_typ:ty $(; $_func:ident)?, )* ] ) => {
+        impl $name {
+            pub const COLUMNS: &[&str] = &[ $(stringify!($field)),* ];
+        }
+
         #[cfg(not(target_arch = "wasm32"))]
         impl $name {
             #[doc = "Query string for all fieldname columns for the `"]
@@ -127,7 +502,12 @@ macro_rules! def_sqlite_struct {
             #[doc = "` table."]
             pub const SQL_QUERY: &str = concat!("SELECT ", $(stringify!($field), ",",)* "id FROM ", $table);
 
-            pub const COLUMNS: &[&str] = &[ $(stringify!($field)),* ];
+            /// [`Self::SQL_QUERY`], adapted to the columns of an older schema.
+            pub(crate) fn select_sql(
+                c: &::rusqlite::Connection,
+            ) -> ::std::borrow::Cow<'static, str> {
+                $crate::schema::select_sql(c, $table, Self::COLUMNS, Self::SQL_QUERY)
+            }
 
             #[doc = "Bind each of the entries in the `"]
             #[doc = $table]
@@ -138,16 +518,49 @@ macro_rules! def_sqlite_struct {
             pub fn read_all(c: &::rusqlite::Connection) ->
                 ::std::result::Result<::std::vec::Vec<Self>, $crate::Error>
             {
-                let mut stmt = c.prepare(Self::SQL_QUERY)?;
-                let rows = stmt.query_and_then((), Self::from_row)
-                    .map_err(|source| Error { source, kind: ErrorKind::Query })?;
+                Self::read_all_traced(c, $crate::trace::Hooks::default())
+            }
 
-                let mut v = ::std::vec::Vec::new();
-                for row in rows {
-                    v.push(row?);
-                }
+            pub(crate) fn read_all_traced(
+                c: &::rusqlite::Connection,
+                hooks: $crate::trace::Hooks,
+            ) -> ::std::result::Result<::std::vec::Vec<Self>, $crate::Error> {
+                $crate::trace::query_all(c, $table, &Self::select_sql(c), (), Self::from_row, hooks)
+            }
 
-                Ok(v)
+            #[doc = "Lazily bind the entries in the `"]
+            #[doc = $table]
+            #[doc = "` table in order of id, without reading the whole table"]
+            #[doc = "up front. See [`RowIter`](crate::RowIter)."]
+            #[must_use]
+            pub fn iter(c: &::rusqlite::Connection) -> $crate::RowIter<'_, Self> {
+                $crate::stream::RowIter::new(c, $table, &Self::select_sql(c), Self::from_row, |row| row.id)
+            }
+
+            #[doc = "Start a [`Query`](crate::Query) of the `"]
+            #[doc = $table]
+            #[doc = "` table, to filter rows in SQLite."]
+            #[must_use]
+            pub fn query() -> $crate::Query<Self> {
+                $crate::Query::new($table, Self::COLUMNS, Self::select_sql, Self::from_row)
+            }
+
+            /// Like [`Self::read_all`], for ids from `first` to `last` inclusive.
+            #[allow(dead_code)] // only some tables are read in chunks
+            pub(crate) fn read_id_range(
+                c: &::rusqlite::Connection,
+                first: u32,
+                last: u32,
+            ) -> ::std::result::Result<::std::vec::Vec<Self>, $crate::Error> {
+                let sql = format!("{} WHERE id BETWEEN ?1 AND ?2", Self::select_sql(c));
+                $crate::trace::query_all(
+                    c,
+                    $table,
+                    &sql,
+                    (first, last),
+                    Self::from_row,
+                    $crate::trace::Hooks::default(),
+                )
             }
         }
     };
@@ -171,7 +584,7 @@ impl LocalRow<'_, '_> {
     {
         let Self(row, table_column) = self;
         row.get(idx).map_err(|source| Error {
-            source,
+            source: Some(source),
             kind: ErrorKind::Row(table_column),
         })
     }
@@ -202,10 +615,44 @@ fn optional_blob_to_path(
     Ok(value.map(blob_to_path))
 }
 
+// columns added by newer beets versions read as NULL from older libraries
+fn text_or_empty(row: LocalRow, idx: impl rusqlite::RowIndex) -> Result<String, Error> {
+    let value: Option<String> = row.get(idx)?;
+    Ok(value.unwrap_or_default())
+}
+
+#[cfg(feature = "serde")]
 fn is_num_zero<T: Default + PartialEq>(n: &T) -> bool {
     n == &T::default()
 }
 
+/// What the `#[serde]` attributes of a record field ask of it, read from the
+/// attributes as written in [`def_sqlite_struct!`].
+#[cfg(feature = "typescript")]
+#[derive(Clone, Copy, Debug, Default)]
+struct SerdeField {
+    /// Never serialized.
+    skip: bool,
+    /// Left out when empty.
+    skip_if: bool,
+    /// Filled in when missing.
+    default: bool,
+}
+
+#[cfg(feature = "typescript")]
+impl SerdeField {
+    fn parse(attrs: &[&str]) -> Self {
+        let options = attrs.iter().flat_map(|attr| attr.split(',')).map(str::trim);
+        let mut field = Self::default();
+        for option in options {
+            field.skip |= option == "skip" || option == "skip_serializing";
+            field.skip_if |= option.starts_with("skip_serializing_if");
+            field.default |= option == "default";
+        }
+        field
+    }
+}
+
 def_sqlite_struct! {
     /// All of the fields present on an "attribute" in the beets schema.
     Attribute [
@@ -270,8 +717,8 @@ def_sqlite_struct! {
         rg_album_gain: Option<f64>,
         #[serde(skip_serializing_if = "Option::is_none", default)]
         rg_album_peak: Option<f64>,
-        #[serde(skip_serializing_if = "is_num_zero", default)]
-        r128_album_gain: Option<i32>,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        r128_album_gain: Option<R128Gain>,
         #[serde(skip_serializing_if = "is_num_zero", default)]
         original_year: u32,
         #[serde(skip_serializing_if = "is_num_zero", default)]
@@ -335,7 +782,7 @@ def_sqlite_struct! {
         #[serde(skip_serializing_if = "String::is_empty", default)]
         comments: String,
         #[serde(skip_serializing_if = "is_num_zero", default)]
-        bpm: u32,
+        bpm: f64,
         comp: bool,
         #[serde(skip_serializing_if = "String::is_empty", default)]
         mb_trackid: String,
@@ -385,10 +832,10 @@ def_sqlite_struct! {
         rg_album_gain: Option<f64>,
         #[serde(skip_serializing_if = "Option::is_none", default)]
         rg_album_peak: Option<f64>,
-        #[serde(skip_serializing_if = "is_num_zero", default)]
-        r128_track_gain: Option<f64>,
-        #[serde(skip_serializing_if = "is_num_zero", default)]
-        r128_album_gain: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        r128_track_gain: Option<R128Gain>,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        r128_album_gain: Option<R128Gain>,
         #[serde(skip_serializing_if = "is_num_zero", default)]
         original_year: u32,
         #[serde(skip_serializing_if = "is_num_zero", default)]
@@ -400,6 +847,14 @@ def_sqlite_struct! {
         length: f64,
         #[serde(skip_serializing_if = "is_num_zero", default)]
         bitrate: u32,
+        /// `CBR`, `VBR` or `ABR`, empty if unknown or for libraries from before
+        /// beets 2.0.
+        #[serde(skip_serializing_if = "String::is_empty", default)]
+        bitrate_mode: String; text_or_empty,
+        #[serde(skip_serializing_if = "String::is_empty", default)]
+        encoder_info: String; text_or_empty,
+        #[serde(skip_serializing_if = "String::is_empty", default)]
+        encoder_settings: String; text_or_empty,
         #[serde(skip_serializing_if = "String::is_empty", default)]
         format: String,
         #[serde(skip_serializing_if = "is_num_zero", default)]
@@ -415,16 +870,98 @@ def_sqlite_struct! {
     ]
 }
 
+impl Item {
+    /// The `bpm` rounded to a whole number, as older beets versions stored it.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn bpm_rounded(&self) -> u32 {
+        // saturates on out-of-range values
+        self.bpm.round() as u32
+    }
+
+    /// The MIME type to serve the file as, from the `format` beets names,
+    /// falling back to `application/octet-stream`.
+    #[must_use]
+    pub fn mime_type(&self) -> &'static str {
+        match self.format.as_str() {
+            "MP3" => "audio/mpeg",
+            "AAC" | "ALAC" => "audio/mp4",
+            "FLAC" => "audio/flac",
+            "OGG" => "audio/ogg",
+            "Opus" => "audio/ogg; codecs=opus",
+            "WAV" => "audio/wav",
+            "AIFF" => "audio/aiff",
+            "Windows Media" => "audio/x-ms-wma",
+            "APE" => "audio/x-ape",
+            "WavPack" => "audio/x-wavpack",
+            "Musepack" => "audio/x-musepack",
+            "DSD Stream File" => "audio/x-dsf",
+            _ => "application/octet-stream",
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Item {
+    /// Reads the items added or modified after `since`, a Unix timestamp like
+    /// those beets stores, e.g. the time a sync last ran.
+    ///
+    /// Beets updates `mtime` when it reads or writes the tags of a file, so
+    /// changes made only to the database (`beet modify` without writing tags)
+    /// are not seen.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn read_changed_since(c: &Connection, since: f64) -> Result<Vec<Self>, Error> {
+        let sql = format!(
+            "{} WHERE added > ?1 OR mtime > ?1 ORDER BY id",
+            Self::select_sql(c)
+        );
+        trace::query_all(
+            c,
+            "items",
+            &sql,
+            [since],
+            Self::from_row,
+            trace::Hooks::default(),
+        )
+    }
+}
+
 /// Reads all the [`Album`]s and [`Item`]s in the specified database
 ///
 /// # Errors
 /// Returns an error if the SQL query fails
 #[cfg(not(target_arch = "wasm32"))]
-pub fn read_all(db_path: PathBuf) -> Result<(Vec<Album>, Vec<Item>), Error> {
-    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY) //rustfmt-hint
+pub fn read_all(db_path: impl AsRef<Path>) -> Result<(Vec<Album>, Vec<Item>), Error> {
+    read_all_with_flags(db_path.as_ref(), OpenFlags::SQLITE_OPEN_READ_ONLY)
+}
+
+/// Like [`read_all`], opening the database with `flags` rather than read-only,
+/// e.g. to add `SQLITE_OPEN_NO_MUTEX` or a `file:` URI.
+///
+/// # Errors
+/// Returns an error if the database cannot be opened with `flags`, or the SQL
+/// query fails
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_all_with_flags(
+    db_path: &Path,
+    flags: OpenFlags,
+) -> Result<(Vec<Album>, Vec<Item>), Error> {
+    let conn = Connection::open_with_flags(db_path, flags) //rustfmt-hint
         .map_err(|source| Error {
-            source,
+            source: Some(source),
             kind: ErrorKind::Open,
         })?;
-    Ok((Album::read_all(&conn)?, Item::read_all(&conn)?))
+    read_all_from(&conn)
+}
+
+/// Like [`read_all`], reading from a connection the caller opened already,
+/// e.g. to an in-memory or attached database, or with its own pragmas.
+///
+/// # Errors
+/// Returns an error if the SQL query fails
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_all_from(conn: &Connection) -> Result<(Vec<Album>, Vec<Item>), Error> {
+    Ok((Album::read_all(conn)?, Item::read_all(conn)?))
 }
@@ -0,0 +1,244 @@
+use std::collections::{BTreeSet, HashMap};
+#[cfg(feature = "serde")]
+use std::io::Read;
+
+#[cfg(feature = "serde")]
+use serde_json::Value;
+
+#[cfg(feature = "serde")]
+use crate::beets_export::BeetsExport;
+use crate::{Album, FieldValue, Item};
+
+/// Every [`Album`] and [`Item`] of a beets library, held in memory.
+///
+/// All lookups, searches and sorts work on the loaded data alone, so a library
+/// can be used anywhere the structs can - including on `wasm32`, where it is
+/// usually loaded with [`Library::from_json_bytes`].
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Library {
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub albums: Vec<Album>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub items: Vec<Item>,
+}
+
+/// A field to order records by, as used by [`Library::sort_albums`] and
+/// [`Library::sort_items`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SortKey<'a> {
+    pub field: &'a str,
+    pub ascending: bool,
+}
+
+/// Fields matched by [`Library::search_albums`].
+const ALBUM_SEARCH_FIELDS: &[&str] = &[
+    "album",
+    "albumartist",
+    "albumartist_sort",
+    "albumartist_credit",
+    "genre",
+];
+
+/// Fields matched by [`Library::search_items`].
+const ITEM_SEARCH_FIELDS: &[&str] = &[
+    "title",
+    "album",
+    "artist",
+    "artist_sort",
+    "artist_credit",
+    "albumartist",
+    "albumartist_sort",
+    "albumartist_credit",
+    "genre",
+    "comments",
+];
+
+impl Library {
+    #[cfg(feature = "serde")]
+    /// Parse a library serialized as `{"albums": [...], "items": [...]}`.
+    ///
+    /// # Errors
+    /// Returns an error if the bytes are not a valid serialized library
+    pub fn from_json_bytes(bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(bytes)
+    }
+
+    #[cfg(feature = "serde")]
+    /// Parse the output of the beets `export` plugin.
+    ///
+    /// Both the `json` (one array) and `jsonlines` (one object per line)
+    /// formats are accepted. Records with a `path` are read as items, and all
+    /// others as albums (from `beet export --album`), so the output of several
+    /// exports may be concatenated.
+    ///
+    /// # Errors
+    /// Returns an error if the input is not valid JSON or a record does not
+    /// match the [`BeetsExport`] conventions
+    pub fn from_beets_export(reader: impl Read) -> Result<Self, serde_json::Error> {
+        let mut library = Self::default();
+        for value in serde_json::Deserializer::from_reader(reader).into_iter::<Value>() {
+            match value? {
+                Value::Array(records) => {
+                    for record in records {
+                        library.push_export_record(record)?;
+                    }
+                }
+                record => library.push_export_record(record)?,
+            }
+        }
+        Ok(library)
+    }
+
+    #[cfg(feature = "serde")]
+    fn push_export_record(&mut self, record: Value) -> Result<(), serde_json::Error> {
+        if record.get("path").is_some() {
+            let BeetsExport(item) = serde_json::from_value(record)?;
+            self.items.push(item);
+        } else {
+            let BeetsExport(album) = serde_json::from_value(record)?;
+            self.albums.push(album);
+        }
+        Ok(())
+    }
+
+    /// The album with the given id.
+    #[must_use]
+    pub fn album(&self, id: u32) -> Option<&Album> {
+        self.albums.iter().find(|album| album.id == id)
+    }
+
+    /// The item with the given id.
+    #[must_use]
+    pub fn item(&self, id: u32) -> Option<&Item> {
+        self.items.iter().find(|item| item.id == id)
+    }
+
+    /// The items belonging to the album with the given id.
+    pub fn album_items(&self, album_id: u32) -> impl Iterator<Item = &Item> + '_ {
+        self.items
+            .iter()
+            .filter(move |item| item.album_id == Some(album_id))
+    }
+
+    /// The items of every album, keyed by album id, in library order, to look
+    /// up many albums' items without scanning all items for each.
+    pub(crate) fn items_by_album(&self) -> HashMap<u32, Vec<&Item>> {
+        let mut albums: HashMap<_, Vec<_>> = HashMap::new();
+        for item in &self.items {
+            if let Some(album_id) = item.album_id {
+                albums.entry(album_id).or_default().push(item);
+            }
+        }
+        albums
+    }
+
+    /// Every distinct album artist, in sorted order.
+    #[must_use]
+    pub fn album_artists(&self) -> Vec<&str> {
+        self.albums
+            .iter()
+            .map(|album| album.albumartist.as_str())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Every distinct value of an album field, in sorted order, or `None` if
+    /// there is no such field. Useful e.g. to complete queries.
+    #[must_use]
+    pub fn distinct_album_values(&self, field: &str) -> Option<Vec<FieldValue<'_>>> {
+        Album::COLUMNS.contains(&field).then(|| {
+            crate::field::distinct(self.albums.iter().filter_map(|album| album.field(field)))
+        })
+    }
+
+    /// Every distinct value of an item field, in sorted order, or `None` if
+    /// there is no such field.
+    #[must_use]
+    pub fn distinct_item_values(&self, field: &str) -> Option<Vec<FieldValue<'_>>> {
+        Item::COLUMNS
+            .contains(&field)
+            .then(|| crate::field::distinct(self.items.iter().filter_map(|item| item.field(field))))
+    }
+
+    /// The albums credited to the given album artist.
+    pub fn artist_albums<'a>(&'a self, artist: &'a str) -> impl Iterator<Item = &'a Album> + 'a {
+        self.albums
+            .iter()
+            .filter(move |album| album.albumartist == artist)
+    }
+
+    /// Albums whose name, artist or genre contains the text, ignoring case.
+    #[must_use]
+    pub fn search_albums(&self, text: &str) -> Vec<&Album> {
+        let text = text.to_lowercase();
+        self.filter_albums(|album| matches_any(ALBUM_SEARCH_FIELDS, &text, |f| album.field(f)))
+    }
+
+    /// Items whose title, album, artist, genre or comments contain the text,
+    /// ignoring case.
+    #[must_use]
+    pub fn search_items(&self, text: &str) -> Vec<&Item> {
+        let text = text.to_lowercase();
+        self.filter_items(|item| matches_any(ITEM_SEARCH_FIELDS, &text, |f| item.field(f)))
+    }
+
+    /// Albums matching an arbitrary predicate, such as a parsed query.
+    pub fn filter_albums(&self, predicate: impl Fn(&Album) -> bool) -> Vec<&Album> {
+        self.albums
+            .iter()
+            .filter(|album| predicate(album))
+            .collect()
+    }
+
+    /// Items matching an arbitrary predicate, such as a parsed query.
+    pub fn filter_items(&self, predicate: impl Fn(&Item) -> bool) -> Vec<&Item> {
+        self.items.iter().filter(|item| predicate(item)).collect()
+    }
+
+    /// Sort the albums by each key in turn.
+    ///
+    /// Unknown field names leave the order unchanged.
+    pub fn sort_albums(&mut self, keys: &[SortKey]) {
+        self.albums
+            .sort_by(|a, b| compare_by(keys, |f| a.field(f), |f| b.field(f)));
+    }
+
+    /// Sort the items by each key in turn.
+    ///
+    /// Unknown field names leave the order unchanged.
+    pub fn sort_items(&mut self, keys: &[SortKey]) {
+        self.items
+            .sort_by(|a, b| compare_by(keys, |f| a.field(f), |f| b.field(f)));
+    }
+}
+
+fn matches_any<'a>(
+    fields: &[&str],
+    lowercase_text: &str,
+    field: impl Fn(&str) -> Option<FieldValue<'a>>,
+) -> bool {
+    fields.iter().any(|&name| match field(name) {
+        Some(FieldValue::Text(value)) => value.to_lowercase().contains(lowercase_text),
+        _ => false,
+    })
+}
+
+fn compare_by<'a, 'b>(
+    keys: &[SortKey],
+    a: impl Fn(&str) -> Option<FieldValue<'a>>,
+    b: impl Fn(&str) -> Option<FieldValue<'b>>,
+) -> std::cmp::Ordering {
+    keys.iter()
+        .map(|key| {
+            let order = a(key.field).cmp(&b(key.field));
+            if key.ascending {
+                order
+            } else {
+                order.reverse()
+            }
+        })
+        .find(|order| order.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+}
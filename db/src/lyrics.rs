@@ -0,0 +1,130 @@
+use std::future::Future;
+use std::io;
+use std::path::Path;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{Database, Error, FederatedLibrary, ReadWrite, Sourced};
+use crate::{Item, Library};
+
+/// Looks up the lyrics of items that have none, for
+/// [`Database::fill_lyrics`] to save, e.g. from a lyrics website or files
+/// beside the music as [`LyricsFiles`] does.
+pub trait LyricsProvider {
+    /// Why lyrics could not be looked up, as opposed to there being none.
+    type Error;
+
+    /// The lyrics of `item`, or `None` if there are none to be found.
+    fn lyrics(&self, item: &Item) -> impl Future<Output = Result<Option<String>, Self::Error>>;
+}
+
+/// A [`LyricsProvider`] reading the lyrics of each item from a file beside
+/// it with the same name, ending in `.lrc` (as many players and taggers
+/// save them, timestamps and all) or else `.txt`.
+///
+/// The files are read as soon as they are asked for, so this provider
+/// never leaves the caller waiting for other tasks.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LyricsFiles;
+
+impl LyricsFiles {
+    const EXTENSIONS: &'static [&'static str] = &["lrc", "txt"];
+
+    fn read(path: &Path) -> io::Result<Option<String>> {
+        for extension in Self::EXTENSIONS {
+            match std::fs::read_to_string(path.with_extension(extension)) {
+                Ok(lyrics) if lyrics.trim().is_empty() => {}
+                Ok(lyrics) => return Ok(Some(lyrics)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl LyricsProvider for LyricsFiles {
+    type Error = io::Error;
+
+    fn lyrics(&self, item: &Item) -> impl Future<Output = Result<Option<String>, Self::Error>> {
+        std::future::ready(Self::read(&item.path))
+    }
+}
+
+/// What [`Database::fill_lyrics`] did with each item without lyrics.
+#[derive(Debug)]
+pub struct LyricsFilled<E> {
+    /// The ids of the items whose lyrics were saved.
+    pub filled: Vec<u32>,
+    /// The ids of the items the provider found nothing for.
+    pub not_found: Vec<u32>,
+    /// The items the provider failed to look up, with why.
+    pub failed: Vec<(u32, E)>,
+}
+
+impl Library {
+    /// The items with no lyrics, to fill in with
+    /// [`Database::fill_lyrics`].
+    #[must_use]
+    pub fn items_without_lyrics(&self) -> Vec<&Item> {
+        self.items
+            .iter()
+            .filter(|item| item.lyrics.trim().is_empty())
+            .collect()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FederatedLibrary {
+    /// Like [`Library::items_without_lyrics`], across every library.
+    #[must_use]
+    pub fn items_without_lyrics(&self) -> Vec<&Sourced<Item>> {
+        self.items
+            .iter()
+            .filter(|item| item.entry.lyrics.trim().is_empty())
+            .collect()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Database<ReadWrite> {
+    /// Ask `provider` for the lyrics of every item without any, one at a
+    /// time, and save those it finds, as the beets lyrics plugin would
+    /// without looking again at the items that have them.
+    ///
+    /// An item the provider fails to look up is left as it is, and the rest
+    /// are still asked for.
+    ///
+    /// # Errors
+    /// Returns an error if the items cannot be read or the lyrics saved, or
+    /// one for which [`Error::write_hazard`] is `Some` if writing is not safe
+    pub async fn fill_lyrics<P: LyricsProvider>(
+        &self,
+        provider: &P,
+    ) -> Result<LyricsFilled<P::Error>, Error> {
+        let mut filled = LyricsFilled {
+            filled: vec![],
+            not_found: vec![],
+            failed: vec![],
+        };
+        let items = self.items()?;
+        for item in items
+            .into_iter()
+            .filter(|item| item.lyrics.trim().is_empty())
+        {
+            match provider.lyrics(&item).await {
+                Ok(Some(lyrics)) if !lyrics.trim().is_empty() => {
+                    // beets may have changed the item while waiting
+                    let conn = self.connection();
+                    let Some(current) = Item::read_id_range(conn, item.id, item.id)?.pop() else {
+                        continue;
+                    };
+                    Item { lyrics, ..current }.update(self)?;
+                    filled.filled.push(item.id);
+                }
+                Ok(_) => filled.not_found.push(item.id),
+                Err(err) => filled.failed.push((item.id, err)),
+            }
+        }
+        Ok(filled)
+    }
+}
@@ -0,0 +1,108 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+use crate::{Album, Item, Library};
+
+/// A release or recording that an album or item without a MusicBrainz id
+/// might be, as found by an [`MbidResolver`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct MbidSuggestion {
+    pub mbid: String,
+    pub title: String,
+    pub artist: String,
+    /// How well it matches, from 0 to 100, as MusicBrainz scores the
+    /// results of a search.
+    pub score: u8,
+}
+
+/// Looks up what albums and items without MusicBrainz ids might be, e.g.
+/// with a fuzzy search of the MusicBrainz web service.
+pub trait MbidResolver {
+    /// The releases `album` might be, best first.
+    fn releases(&self, album: &Album) -> Vec<MbidSuggestion>;
+
+    /// The recordings `item`, which is in no album, might be, best first.
+    fn recordings(&self, item: &Item) -> Vec<MbidSuggestion>;
+}
+
+/// An album to re-tag, or an item in no album, from
+/// [`Library::mbid_worklist`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MbidWork<'a> {
+    /// The album, or `None` for an item in no album.
+    pub album: Option<&'a Album>,
+    /// The items without an `mb_trackid`, in library order.
+    pub items: Vec<&'a Item>,
+    /// What the album or item might be, once [`suggest`](Self::suggest) has
+    /// asked for it.
+    pub suggestions: Vec<MbidSuggestion>,
+}
+
+impl MbidWork<'_> {
+    /// How many ids are missing, counting the album's own.
+    #[must_use]
+    pub fn missing(&self) -> usize {
+        let album = self.album.is_some_and(|album| album.mb_albumid.is_empty());
+        self.items.len() + usize::from(album)
+    }
+
+    /// Ask `resolver` what the album, or else the item, might be.
+    pub fn suggest(&mut self, resolver: &impl MbidResolver) {
+        self.suggestions = match self.album {
+            Some(album) => resolver.releases(album),
+            None => self
+                .items
+                .iter()
+                .flat_map(|item| resolver.recordings(item))
+                .collect(),
+        };
+    }
+}
+
+impl Library {
+    /// The albums and items missing MusicBrainz ids, to work through when
+    /// re-tagging. Each album missing its `mb_albumid` or the `mb_trackid`
+    /// of any item comes first, those missing the most ids before the rest,
+    /// then each item in no album without an `mb_trackid`.
+    #[must_use]
+    pub fn mbid_worklist(&self) -> Vec<MbidWork<'_>> {
+        let untagged = |item: &&Item| item.mb_trackid.is_empty();
+        let album_items = self.items_by_album();
+        let mut albums: Vec<_> = self
+            .albums
+            .iter()
+            .map(|album| MbidWork {
+                album: Some(album),
+                items: album_items
+                    .get(&album.id)
+                    .into_iter()
+                    .flatten()
+                    .copied()
+                    .filter(untagged)
+                    .collect(),
+                suggestions: vec![],
+            })
+            .filter(|work| work.missing() > 0)
+            .collect();
+        // stable, so ties stay in library order
+        albums.sort_by_key(|work| Reverse(work.missing()));
+
+        let album_ids: HashSet<u32> = self.albums.iter().map(|album| album.id).collect();
+        let singletons = self
+            .items
+            .iter()
+            .filter(|item| match item.album_id {
+                Some(id) => !album_ids.contains(&id),
+                None => true,
+            })
+            .filter(untagged)
+            .map(|item| MbidWork {
+                album: None,
+                items: vec![item],
+                suggestions: vec![],
+            });
+        albums.extend(singletons);
+        albums
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::undo::Rows;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::write::album_item_ids;
+use crate::{Album, Library};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{Database, Error, ReadWrite};
+
+/// An album that beets imported in parts, each with its own album id, found
+/// by [`Library::split_albums`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SplitAlbum<'a> {
+    /// The part to merge the others into: the one with the most items, the
+    /// first imported on a tie.
+    pub keep: &'a Album,
+    /// The other parts, in order of id.
+    pub parts: Vec<&'a Album>,
+}
+
+impl SplitAlbum<'_> {
+    /// The ids of the [`parts`](Self::parts), for
+    /// [`Database::merge_albums`].
+    #[must_use]
+    pub fn part_ids(&self) -> Vec<u32> {
+        self.parts.iter().map(|album| album.id).collect()
+    }
+}
+
+impl Library {
+    /// Albums with the same album artist, title and year (ignoring case)
+    /// that were imported as separate albums, e.g. a release whose discs
+    /// were imported one at a time.
+    ///
+    /// Parts with different MusicBrainz release ids are different releases,
+    /// such as a deluxe edition, and parts with an item at the same disc and
+    /// track are copies rather than halves, so neither is reported.
+    #[must_use]
+    pub fn split_albums(&self) -> Vec<SplitAlbum<'_>> {
+        let mut tracks: HashMap<u32, Vec<(u32, u32)>> = HashMap::new();
+        for item in &self.items {
+            if let Some(album_id) = item.album_id {
+                tracks
+                    .entry(album_id)
+                    .or_default()
+                    .push((item.disc, item.track));
+            }
+        }
+
+        let mut groups: BTreeMap<(String, String, u32), Vec<&Album>> = BTreeMap::new();
+        for album in &self.albums {
+            if album.album.trim().is_empty() {
+                continue;
+            }
+            let key = (
+                album.albumartist.trim().to_lowercase(),
+                album.album.trim().to_lowercase(),
+                album.year,
+            );
+            groups.entry(key).or_default().push(album);
+        }
+
+        let mut split = vec![];
+        for mut albums in groups.into_values().filter(|albums| albums.len() > 1) {
+            let releases: BTreeSet<&str> = albums
+                .iter()
+                .map(|album| album.mb_albumid.as_str())
+                .filter(|id| !id.is_empty())
+                .collect();
+            if releases.len() > 1 {
+                continue;
+            }
+            let mut seen = BTreeSet::new();
+            let overlapping = albums
+                .iter()
+                .flat_map(|album| tracks.get(&album.id).into_iter().flatten())
+                .filter(|&&(_, track)| track > 0)
+                .any(|track| !seen.insert(track));
+            if overlapping {
+                continue;
+            }
+            let size = |album: &Album| tracks.get(&album.id).map_or(0, Vec::len);
+            albums.sort_by_key(|album| album.id);
+            // the first of the largest, as `max_by_key` returns the last
+            let Some(keep) = albums.iter().copied().rev().max_by_key(|album| size(album)) else {
+                continue;
+            };
+            albums.retain(|album| album.id != keep.id);
+            split.push(SplitAlbum {
+                keep,
+                parts: albums,
+            });
+        }
+        split.sort_by_key(|split| split.keep.id);
+        split
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Database<ReadWrite> {
+    /// Move the items of the albums `parts` into the album `into` and delete
+    /// the emptied album rows, returning whether `into` was there to merge
+    /// into; nothing is changed if it was not. See
+    /// [`Library::split_albums`].
+    ///
+    /// The flexible attributes of the parts move along where `into` has none
+    /// of the same name. The merge is undone all together.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL statements fail, or one for which
+    /// [`Error::write_hazard`] is `Some` if writing is not safe
+    pub fn merge_albums(&self, into: u32, parts: &[u32]) -> Result<bool, Error> {
+        let conn = self.connection();
+        self.write(&format!("merge albums into {into}"), || {
+            let exists = conn
+                .query_row("SELECT 1 FROM albums WHERE id = ?1", [into], |_| Ok(()))
+                .is_ok();
+            if !exists {
+                return Ok(false);
+            }
+            for &part in parts.iter().filter(|&&part| part != into) {
+                // by id, as the items no longer have the album id of the
+                // part to find them by when undone
+                for item in album_item_ids(conn, part)? {
+                    self.record("items", &Rows::Id(item))?;
+                }
+                // the attributes of `into` after those of the part, so they
+                // are restored first and give back the ones that moved
+                self.record("album_attributes", &Rows::EntityId(part))?;
+                self.record("album_attributes", &Rows::EntityId(into))?;
+                self.record("albums", &Rows::Id(part))?;
+                conn.execute(
+                    "UPDATE items SET album_id = ?1 WHERE album_id = ?2",
+                    [into, part],
+                )
+                .map_err(Error::write)?;
+                conn.execute(
+                    "UPDATE album_attributes SET entity_id = ?1 WHERE entity_id = ?2
+                    AND key NOT IN (SELECT key FROM album_attributes WHERE entity_id = ?1)",
+                    [into, part],
+                )
+                .map_err(Error::write)?;
+                conn.execute("DELETE FROM album_attributes WHERE entity_id = ?1", [part])
+                    .map_err(Error::write)?;
+                conn.execute("DELETE FROM albums WHERE id = ?1", [part])
+                    .map_err(Error::write)?;
+            }
+            Ok(true)
+        })
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::Path;
+use std::thread;
+
+use crate::{FederatedLibrary, Item, Library, Sourced};
+
+/// The entries whose path does not exist, checked by up to `threads` threads
+/// at once, in their original order.
+///
+/// A path that cannot be checked (e.g. for lack of permission) is not taken
+/// to be missing.
+fn find_missing<T: Sync>(
+    entries: &[T],
+    path: impl Fn(&T) -> &Path + Sync,
+    threads: usize,
+) -> Vec<&T> {
+    if entries.is_empty() {
+        return vec![];
+    }
+    let size = entries.len().div_ceil(threads.max(1));
+    let missing = |entry: &&T| matches!(path(entry).try_exists(), Ok(false));
+    thread::scope(|scope| {
+        let checkers: Vec<_> = entries
+            .chunks(size)
+            .map(|chunk| scope.spawn(move || chunk.iter().filter(missing).collect::<Vec<_>>()))
+            .collect();
+        checkers
+            .into_iter()
+            .flat_map(|checker| {
+                checker
+                    .join()
+                    .unwrap_or_else(|err| std::panic::resume_unwind(err))
+            })
+            .collect()
+    })
+}
+
+impl Library {
+    /// Items whose file no longer exists, checking up to `threads` files at
+    /// once since network storage is often slow to answer.
+    #[must_use]
+    pub fn missing_items(&self, threads: usize) -> Vec<&Item> {
+        find_missing(&self.items, |item| &item.path, threads)
+    }
+}
+
+impl FederatedLibrary {
+    /// Like [`Library::missing_items`], across every library.
+    #[must_use]
+    pub fn missing_items(&self, threads: usize) -> Vec<&Sourced<Item>> {
+        find_missing(&self.items, |item| &item.entry.path, threads)
+    }
+}
@@ -0,0 +1,62 @@
+//! MessagePack snapshots of a library, for shipping one to a client, such as
+//! a web frontend on `wasm32`, in far fewer bytes than JSON.
+//!
+//! Records are written as maps keyed by field name rather than as arrays.
+//! Most fields are left out while empty or zero, so only names tell the
+//! fields that are there apart; positional formats like bincode lose track of
+//! which is which, and are not supported for that reason.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::Library;
+
+pub use rmp_serde::decode::Error as MsgpackDecodeError;
+pub use rmp_serde::encode::Error as MsgpackEncodeError;
+
+/// Serialize `value`, such as an [`Item`](crate::Item) or a whole
+/// [`Library`], as MessagePack. Fields are serialized as for JSON, so any
+/// that are skipped there are skipped here too.
+///
+/// ```
+/// # use beet_db::{from_msgpack, to_msgpack, Item};
+/// let item = Item {
+///     title: "Dayvan Cowboy".to_string(),
+///     ..Item::default()
+/// };
+/// let bytes = to_msgpack(&item).unwrap();
+/// assert_eq!(from_msgpack::<Item>(&bytes).unwrap(), item);
+/// ```
+///
+/// # Errors
+/// Returns an error if `value` cannot be serialized, e.g. a path that is not
+/// valid UTF-8
+pub fn to_msgpack<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, MsgpackEncodeError> {
+    rmp_serde::to_vec_named(value)
+}
+
+/// Deserialize a value serialized with [`to_msgpack`].
+///
+/// # Errors
+/// Returns an error if the bytes are not valid MessagePack of a `T`
+pub fn from_msgpack<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, MsgpackDecodeError> {
+    rmp_serde::from_slice(bytes)
+}
+
+impl Library {
+    /// Serialize the library as MessagePack. See [`to_msgpack`].
+    ///
+    /// # Errors
+    /// Returns an error if a record cannot be serialized
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, MsgpackEncodeError> {
+        to_msgpack(self)
+    }
+
+    /// Parse a library serialized with [`Library::to_msgpack`].
+    ///
+    /// # Errors
+    /// Returns an error if the bytes are not a valid serialized library
+    pub fn from_msgpack_bytes(bytes: &[u8]) -> Result<Self, MsgpackDecodeError> {
+        from_msgpack(bytes)
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt;
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::trace::watch;
+use crate::{Access, Album, Database, Error, Item};
+
+/// Why [`write_ndjson`] stopped.
+#[derive(Debug)]
+pub enum NdjsonError {
+    /// A row could not be read.
+    Read(Error),
+    /// A line could not be written.
+    Write(io::Error),
+}
+
+impl fmt::Display for NdjsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NdjsonError::Read(_) => write!(f, "failed to read a row to export"),
+            NdjsonError::Write(_) => write!(f, "failed to write exported rows"),
+        }
+    }
+}
+
+impl std::error::Error for NdjsonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NdjsonError::Read(err) => Some(err),
+            NdjsonError::Write(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for NdjsonError {
+    fn from(err: io::Error) -> Self {
+        NdjsonError::Write(err)
+    }
+}
+
+/// Write each of `rows` as a line of JSON as soon as it is read, returning
+/// how many were written. Only one row is held at a time, so with rows from
+/// [`Item::iter`] even a huge library is exported in little memory.
+///
+/// # Errors
+/// Returns an error if a row cannot be read or written, after writing the
+/// rows before it
+pub fn write_ndjson<T: Serialize, W: Write>(
+    out: &mut W,
+    rows: impl IntoIterator<Item = Result<T, Error>>,
+) -> Result<usize, NdjsonError> {
+    let mut written = 0;
+    for row in rows {
+        let row = row.map_err(NdjsonError::Read)?;
+        serde_json::to_writer(&mut *out, &row).map_err(io::Error::from)?;
+        writeln!(out)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+impl<A: Access> Database<A> {
+    /// Write every [`Item`] as a line of JSON, reading them a page at a
+    /// time. See [`write_ndjson`]. Progress is reported, and the export
+    /// cancelled, as for [`read_all`](Self::read_all).
+    ///
+    /// # Errors
+    /// Returns an error if an item cannot be read or written, or one for
+    /// which [`Error::is_cancelled`] is true if the export was cancelled
+    pub fn write_items_ndjson(&self, out: &mut impl Write) -> Result<usize, NdjsonError> {
+        let conn = self.connection();
+        let rows =
+            watch(conn, "items", Item::iter(conn), self.hooks()).map_err(NdjsonError::Read)?;
+        write_ndjson(out, rows)
+    }
+
+    /// Write every [`Album`] as a line of JSON, reading them a page at a
+    /// time. See [`write_ndjson`]. Progress is reported, and the export
+    /// cancelled, as for [`read_all`](Self::read_all).
+    ///
+    /// # Errors
+    /// Returns an error if an album cannot be read or written, or one for
+    /// which [`Error::is_cancelled`] is true if the export was cancelled
+    pub fn write_albums_ndjson(&self, out: &mut impl Write) -> Result<usize, NdjsonError> {
+        let conn = self.connection();
+        let rows =
+            watch(conn, "albums", Album::iter(conn), self.hooks()).map_err(NdjsonError::Read)?;
+        write_ndjson(out, rows)
+    }
+}
@@ -0,0 +1,156 @@
+use std::path::Path;
+
+use rusqlite::{Connection, OpenFlags};
+
+use crate::undo::Journal;
+use crate::{Database, Error, ErrorKind, ReadWrite};
+
+/// A bundle of connection settings for a common workload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// Tuned for reading a whole library as quickly as possible:
+    ///
+    /// - `mmap_size = 256 MiB`, so pages are read straight from the OS cache
+    /// - `cache_size = 64 MiB`, enough to hold the tables of most libraries
+    /// - `temp_store = MEMORY`, so sorts and temporary indexes avoid the disk
+    /// - `query_only = ON`, refusing writes even on a writable handle
+    ///
+    /// How much it helps depends on the library and the OS cache; compare it
+    /// against the defaults on your own library with the `read_fast` example.
+    ReadFast,
+}
+
+impl Preset {
+    fn pragmas(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Preset::ReadFast => &[
+                ("mmap_size", "268435456"),
+                ("cache_size", "-65536"),
+                ("temp_store", "MEMORY"),
+                ("query_only", "ON"),
+            ],
+        }
+    }
+}
+
+/// Options for opening a beets library, in the style of
+/// [`std::fs::OpenOptions`].
+///
+/// Libraries are opened read-only unless [`write`](Self::write) is set, or
+/// they are opened with [`open_writable`](Self::open_writable), which gives
+/// the handle the write methods too.
+#[derive(Clone, Debug, Default)]
+pub struct OpenOptions {
+    write: bool,
+    preset: Option<Preset>,
+    pragmas: Vec<(String, String)>,
+    without_journal: bool,
+    undo_limit: Option<usize>,
+    trash: bool,
+}
+
+impl OpenOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open the library for writing as well as reading.
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// Apply the settings of a [`Preset`].
+    pub fn preset(&mut self, preset: Preset) -> &mut Self {
+        self.preset = Some(preset);
+        self
+    }
+
+    /// Set an arbitrary `PRAGMA`, after those of any preset.
+    pub fn pragma(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.pragmas.push((name.into(), value.into()));
+        self
+    }
+
+    /// Record the writes made through a writable handle in an undo journal
+    /// beside the library, as is the default, or not.
+    ///
+    /// See [`Database::undo_journal`].
+    pub fn undo_journal(&mut self, enabled: bool) -> &mut Self {
+        self.without_journal = !enabled;
+        self
+    }
+
+    /// Keep only the latest `writes` in the undo journal, forgetting older
+    /// ones as new ones are made, so it does not grow without end. 100 are
+    /// kept by default.
+    pub fn undo_limit(&mut self, writes: usize) -> &mut Self {
+        self.undo_limit = Some(writes);
+        self
+    }
+
+    /// Move what a writable handle deletes to a trash beside the library,
+    /// in the same file as the undo journal, rather than removing it for
+    /// good. This is off by default.
+    ///
+    /// See [`Database::restore_item`] and [`Database::empty_trash`].
+    pub fn trash(&mut self, enabled: bool) -> &mut Self {
+        self.trash = enabled;
+        self
+    }
+
+    /// Open the library at `path` with these options.
+    ///
+    /// The handle is read-only even if [`write`](Self::write) is set, which
+    /// only lets its [`connection`](Database::connection) write.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be opened or a `PRAGMA` is
+    /// rejected
+    pub fn open(&self, path: impl AsRef<Path>) -> Result<Database, Error> {
+        self.connect(path.as_ref(), self.write)
+            .map(Database::from_connection)
+    }
+
+    /// Open the library at `path` with these options, for writing whether
+    /// or not [`write`](Self::write) is set.
+    ///
+    /// # Errors
+    /// Returns an error if the database, its undo journal or its trash cannot
+    /// be opened for writing, or a `PRAGMA` is rejected
+    pub fn open_writable(&self, path: impl AsRef<Path>) -> Result<Database<ReadWrite>, Error> {
+        let mut db = Database::from_connection(self.connect(path.as_ref(), true)?);
+        if !self.without_journal || self.trash {
+            db.journal = Journal::attach(db.connection())?;
+            db.journal.recording = !self.without_journal;
+            db.journal.trash = self.trash;
+            db.journal.limit = self.undo_limit.unwrap_or(db.journal.limit);
+        }
+        Ok(db)
+    }
+
+    fn connect(&self, path: &Path, write: bool) -> Result<Connection, Error> {
+        let access = if write {
+            OpenFlags::SQLITE_OPEN_READ_WRITE
+        } else {
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+        };
+        let flags = access | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+
+        let open_err = |source| Error {
+            source: Some(source),
+            kind: ErrorKind::Open,
+        };
+        let conn = Connection::open_with_flags(path, flags).map_err(open_err)?;
+
+        let preset = self.preset.map_or(&[][..], Preset::pragmas);
+        for (name, value) in preset {
+            conn.pragma_update(None, name, value).map_err(open_err)?;
+        }
+        for (name, value) in &self.pragmas {
+            conn.pragma_update(None, name, value).map_err(open_err)?;
+        }
+        Ok(conn)
+    }
+}
@@ -0,0 +1,86 @@
+use std::convert::TryFrom;
+use std::path::Path;
+use std::thread;
+
+use rusqlite::OptionalExtension;
+
+use crate::{Album, Error, Item, OpenOptions};
+
+/// Split the ids from `min` to `max` into at most `chunks` inclusive ranges of
+/// near-equal size.
+fn id_ranges(min: u32, max: u32, chunks: usize) -> Vec<(u32, u32)> {
+    let span = u64::from(max - min) + 1;
+    let chunks = u64::try_from(chunks).unwrap_or(u64::MAX).clamp(1, span);
+    let size = span.div_ceil(chunks);
+    (0..chunks)
+        .map(|chunk| chunk * size)
+        .take_while(|&offset| offset < span)
+        .map(|offset| {
+            let first = u64::from(min) + offset;
+            let last = (first + size - 1).min(u64::from(max));
+            let id = |id| u32::try_from(id).expect("within min..=max");
+            (id(first), id(last))
+        })
+        .collect()
+}
+
+impl OpenOptions {
+    /// Read every album and item of the library at `path`, splitting the
+    /// `items` table into `chunks` id ranges read on separate connections at
+    /// once.
+    ///
+    /// This pays off for the initial load of very large libraries on a
+    /// machine with several cores, where decoding rows dominates; see the
+    /// `parallel_scan` example to measure it. Results are in the same order
+    /// as [`Database::read_all`](crate::Database::read_all). Since each chunk
+    /// opens its own connection, `path` must name a file rather than an
+    /// in-memory database.
+    ///
+    /// # Errors
+    /// Returns an error if any connection cannot be opened or query fails
+    #[allow(clippy::missing_panics_doc)] // only re-raises panics of the readers
+    pub fn read_all_parallel(
+        &self,
+        path: impl AsRef<Path>,
+        chunks: usize,
+    ) -> Result<(Vec<Album>, Vec<Item>), Error> {
+        let path = path.as_ref();
+        let db = self.open(path)?;
+        let bounds: Option<(u32, u32)> = db
+            .connection()
+            .query_row(
+                "SELECT min(id), max(id) FROM items WHERE id IS NOT NULL HAVING count(*) > 0",
+                (),
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(Error::query)?;
+        let Some((min, max)) = bounds else {
+            return Ok((Album::read_all(db.connection())?, vec![]));
+        };
+
+        let ranges = id_ranges(min, max, chunks);
+        thread::scope(|scope| {
+            // the first range is read on the calling thread, after the albums
+            let (&(first, last), rest) = ranges.split_first().expect("at least one range");
+            let readers: Vec<_> = rest
+                .iter()
+                .map(|&(first, last)| {
+                    scope.spawn(move || {
+                        Item::read_id_range(self.open(path)?.connection(), first, last)
+                    })
+                })
+                .collect();
+
+            let albums = Album::read_all(db.connection())?;
+            let mut items = Item::read_id_range(db.connection(), first, last)?;
+            for reader in readers {
+                let chunk = reader
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))?;
+                items.extend(chunk);
+            }
+            Ok((albums, items))
+        })
+    }
+}
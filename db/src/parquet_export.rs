@@ -0,0 +1,254 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parquet::basic::{Compression, LogicalType, Repetition, Type as PhysicalType};
+use parquet::data_type::{
+    BoolType, ByteArray, ByteArrayType, DataType, DoubleType, Int32Type, Int64Type,
+};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+use parquet::schema::types::Type;
+
+use crate::{Library, R128Gain};
+
+/// How many rows [`write_parquet`] puts in each row group.
+pub const PARQUET_ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// A record that can be written as a row of a Parquet file, with a column
+/// for every field.
+///
+/// The schema only changes along with the fields: whole numbers are
+/// `INT64`, decimals `DOUBLE`, text and paths UTF-8 `BYTE_ARRAY`s, and
+/// R128 gains, in steps, 16-bit `INT32`s. `added` and `mtime` stay
+/// Unix timestamps, as beets stores them. Fields that may be missing are
+/// optional columns, every other one required.
+pub trait Parquet: Sized {
+    /// The schema of a file of these records.
+    fn parquet_schema() -> Type;
+
+    /// Write a column chunk of every field of `rows`, in order.
+    #[doc(hidden)]
+    fn write_row_group<W: Write + Send>(
+        row_group: &mut SerializedRowGroupWriter<'_, W>,
+        rows: &[Self],
+    ) -> Result<(), ParquetError>;
+}
+
+/// A field type that is stored as one value of a Parquet column.
+pub(crate) trait ParquetValue {
+    type Physical: DataType;
+
+    fn physical_type() -> PhysicalType;
+
+    fn logical_type() -> Option<LogicalType> {
+        None
+    }
+
+    fn to_physical(&self) -> <Self::Physical as DataType>::T;
+}
+
+impl ParquetValue for u32 {
+    type Physical = Int64Type;
+
+    fn physical_type() -> PhysicalType {
+        PhysicalType::INT64
+    }
+
+    fn to_physical(&self) -> i64 {
+        i64::from(*self)
+    }
+}
+
+impl ParquetValue for f64 {
+    type Physical = DoubleType;
+
+    fn physical_type() -> PhysicalType {
+        PhysicalType::DOUBLE
+    }
+
+    fn to_physical(&self) -> f64 {
+        *self
+    }
+}
+
+impl ParquetValue for bool {
+    type Physical = BoolType;
+
+    fn physical_type() -> PhysicalType {
+        PhysicalType::BOOLEAN
+    }
+
+    fn to_physical(&self) -> bool {
+        *self
+    }
+}
+
+impl ParquetValue for R128Gain {
+    type Physical = Int32Type;
+
+    fn physical_type() -> PhysicalType {
+        PhysicalType::INT32
+    }
+
+    fn logical_type() -> Option<LogicalType> {
+        Some(LogicalType::Integer {
+            bit_width: 16,
+            is_signed: true,
+        })
+    }
+
+    fn to_physical(&self) -> i32 {
+        i32::from(self.q78())
+    }
+}
+
+impl ParquetValue for String {
+    type Physical = ByteArrayType;
+
+    fn physical_type() -> PhysicalType {
+        PhysicalType::BYTE_ARRAY
+    }
+
+    fn logical_type() -> Option<LogicalType> {
+        Some(LogicalType::String)
+    }
+
+    fn to_physical(&self) -> ByteArray {
+        self.as_str().into()
+    }
+}
+
+impl ParquetValue for PathBuf {
+    type Physical = ByteArrayType;
+
+    fn physical_type() -> PhysicalType {
+        PhysicalType::BYTE_ARRAY
+    }
+
+    fn logical_type() -> Option<LogicalType> {
+        Some(LogicalType::String)
+    }
+
+    fn to_physical(&self) -> ByteArray {
+        self.to_string_lossy().as_ref().into()
+    }
+}
+
+/// A field type that is stored as a Parquet column, required or optional.
+pub(crate) trait ParquetColumn {
+    type Value: ParquetValue;
+
+    const REPETITION: Repetition;
+
+    fn value(&self) -> Option<&Self::Value>;
+}
+
+impl<T: ParquetValue> ParquetColumn for T {
+    type Value = T;
+
+    const REPETITION: Repetition = Repetition::REQUIRED;
+
+    fn value(&self) -> Option<&T> {
+        Some(self)
+    }
+}
+
+impl<T: ParquetValue> ParquetColumn for Option<T> {
+    type Value = T;
+
+    const REPETITION: Repetition = Repetition::OPTIONAL;
+
+    fn value(&self) -> Option<&T> {
+        self.as_ref()
+    }
+}
+
+/// The schema of a file of records named `name`, with `columns`.
+pub(crate) fn message(name: &str, columns: Vec<Arc<Type>>) -> Type {
+    Type::group_type_builder(name)
+        .with_fields(columns)
+        .build()
+        .expect("a group type without a logical type is valid")
+}
+
+/// The column `name` holding a field of type `T`.
+pub(crate) fn column<T: ParquetColumn>(name: &str) -> Arc<Type> {
+    let column = Type::primitive_type_builder(name, T::Value::physical_type())
+        .with_repetition(T::REPETITION)
+        .with_logical_type(T::Value::logical_type())
+        .build()
+        .expect("the logical type of each field fits its physical type");
+    Arc::new(column)
+}
+
+/// Write the next column chunk of `row_group` from `values`.
+pub(crate) fn write_column<'a, T: ParquetColumn + 'a, W: Write + Send>(
+    row_group: &mut SerializedRowGroupWriter<'_, W>,
+    values: impl Iterator<Item = &'a T>,
+) -> Result<(), ParquetError> {
+    let mut column = row_group
+        .next_column()?
+        .ok_or_else(|| ParquetError::General("more fields than columns".to_string()))?;
+    let mut physical = vec![];
+    let mut definition_levels = vec![];
+    for value in values {
+        let value = value.value();
+        definition_levels.push(i16::from(value.is_some()));
+        physical.extend(value.map(ParquetValue::to_physical));
+    }
+    let levels = match T::REPETITION {
+        Repetition::OPTIONAL => Some(definition_levels.as_slice()),
+        _ => None,
+    };
+    column
+        .typed::<<T::Value as ParquetValue>::Physical>()
+        .write_batch(&physical, levels, None)?;
+    column.close()
+}
+
+/// Write `rows` as a Parquet file, compressed with Snappy, returning `out`
+/// once the file is complete.
+///
+/// ```
+/// # use beet_db::{write_parquet, Item};
+/// let items = vec![Item::default()];
+/// let file = write_parquet(Vec::new(), &items).unwrap();
+/// assert!(file.starts_with(b"PAR1"));
+/// ```
+///
+/// # Errors
+/// Returns an error if writing to `out` fails
+pub fn write_parquet<T: Parquet, W: Write + Send>(out: W, rows: &[T]) -> Result<W, ParquetError> {
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_created_by(concat!("beet_db ", env!("CARGO_PKG_VERSION")).to_string())
+        .build();
+    let mut writer =
+        SerializedFileWriter::new(out, Arc::new(T::parquet_schema()), Arc::new(properties))?;
+    for chunk in rows.chunks(PARQUET_ROW_GROUP_SIZE) {
+        let mut row_group = writer.next_row_group()?;
+        T::write_row_group(&mut row_group, chunk)?;
+        row_group.close()?;
+    }
+    writer.into_inner()
+}
+
+impl Library {
+    /// Write the items and albums as `items.parquet` and `albums.parquet` in
+    /// `dir`, replacing any there, for DuckDB, Spark or pandas to query. See
+    /// [`write_parquet`].
+    ///
+    /// # Errors
+    /// Returns an error if either file cannot be written
+    pub fn write_parquet(&self, dir: &Path) -> Result<(), ParquetError> {
+        let create = |name: &str| -> Result<_, ParquetError> {
+            Ok(BufWriter::new(File::create(dir.join(name))?))
+        };
+        write_parquet(create("items.parquet")?, &self.items)?.flush()?;
+        write_parquet(create("albums.parquet")?, &self.albums)?.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{FederatedLibrary, Item, Library, Sourced};
+
+/// An entry whose path in the library differs in case from the file on disk,
+/// found by [`Library::case_mismatches`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaseMismatch<'a, T> {
+    pub entry: &'a T,
+    /// The path as the filesystem spells it.
+    pub on_disk: PathBuf,
+}
+
+/// A name, folded to compare names regardless of case.
+fn fold(name: &OsStr) -> String {
+    name.to_string_lossy().to_lowercase()
+}
+
+/// The entries whose paths differ only in case, in groups of more than one
+/// in order of path.
+fn find_conflicts<T>(entries: &[T], path: impl Fn(&T) -> &Path) -> Vec<Vec<&T>> {
+    let mut by_folded: BTreeMap<String, Vec<&T>> = BTreeMap::new();
+    for entry in entries {
+        by_folded
+            .entry(fold(path(entry).as_os_str()))
+            .or_default()
+            .push(entry);
+    }
+    by_folded
+        .into_values()
+        .filter(|group| group.iter().any(|entry| path(entry) != path(group[0])))
+        .map(|mut group| {
+            group.sort_by(|a, b| path(a).cmp(path(b)));
+            group
+        })
+        .collect()
+}
+
+/// The names in the directory `dir`, listed once however many paths are in
+/// it; `None` if it cannot be read.
+fn listing<'a>(
+    listings: &'a mut HashMap<PathBuf, Option<Vec<OsString>>>,
+    dir: &Path,
+) -> Option<&'a [OsString]> {
+    listings
+        .entry(dir.to_path_buf())
+        .or_insert_with(|| {
+            let entries = fs::read_dir(dir).ok()?;
+            Some(
+                entries
+                    .filter_map(|entry| Some(entry.ok()?.file_name()))
+                    .collect(),
+            )
+        })
+        .as_deref()
+}
+
+/// `path` as the filesystem spells it, if that differs from `path` in case.
+///
+/// Each name is looked up in the listing of its directory, so this works on
+/// case-sensitive filesystems too, where only the spelling on disk opens. A
+/// path that cannot be found in either spelling is not reported.
+fn disk_case(
+    path: &Path,
+    listings: &mut HashMap<PathBuf, Option<Vec<OsString>>>,
+) -> Option<PathBuf> {
+    let mut on_disk = PathBuf::new();
+    let mut differs = false;
+    for component in path.components() {
+        let Component::Normal(name) = component else {
+            on_disk.push(component);
+            continue;
+        };
+        let dir = if on_disk.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &on_disk
+        };
+        let names = listing(listings, dir)?;
+        if names.iter().any(|found| found == name) {
+            on_disk.push(name);
+        } else {
+            let folded = fold(name);
+            let found = names.iter().find(|found| fold(found) == folded)?;
+            on_disk.push(found);
+            differs = true;
+        }
+    }
+    differs.then_some(on_disk)
+}
+
+fn find_mismatches<T>(entries: &[T], path: impl Fn(&T) -> &Path) -> Vec<CaseMismatch<'_, T>> {
+    let mut listings = HashMap::new();
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let on_disk = disk_case(path(entry), &mut listings)?;
+            Some(CaseMismatch { entry, on_disk })
+        })
+        .collect()
+}
+
+impl Library {
+    /// Items whose paths differ only in case, which would be the same file
+    /// on a case-insensitive filesystem such as the defaults of macOS and
+    /// Windows, in groups in order of path.
+    #[must_use]
+    pub fn case_conflicts(&self) -> Vec<Vec<&Item>> {
+        find_conflicts(&self.items, |item| &item.path)
+    }
+
+    /// Items whose file exists under a path that differs in case from the
+    /// one in the library, such as after renaming a folder on a
+    /// case-insensitive filesystem. Each directory is read once.
+    #[must_use]
+    pub fn case_mismatches(&self) -> Vec<CaseMismatch<'_, Item>> {
+        find_mismatches(&self.items, |item| &item.path)
+    }
+}
+
+impl FederatedLibrary {
+    /// Like [`Library::case_conflicts`], across every library, for libraries
+    /// kept on the same storage.
+    #[must_use]
+    pub fn case_conflicts(&self) -> Vec<Vec<&Sourced<Item>>> {
+        find_conflicts(&self.items, |item| &item.entry.path)
+    }
+
+    /// Like [`Library::case_mismatches`], across every library.
+    #[must_use]
+    pub fn case_mismatches(&self) -> Vec<CaseMismatch<'_, Sourced<Item>>> {
+        find_mismatches(&self.items, |item| &item.entry.path)
+    }
+}
@@ -0,0 +1,116 @@
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::Item;
+
+/// How [`write_m3u`] writes a playlist.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlaylistOptions {
+    /// Write an extended M3U, with an `#EXTINF` line giving the length and
+    /// `artist - title` of each item.
+    pub extended: bool,
+    /// Pairs of directories, each replacing the first in the paths that
+    /// start with it by the second, e.g. where the server keeps the music
+    /// by where a player mounts it. The first pair that applies is used.
+    pub remap: Vec<(PathBuf, PathBuf)>,
+    /// Write each path, once remapped, relative to this directory, as that
+    /// of the playlist for one kept beside the music it lists.
+    pub relative_to: Option<PathBuf>,
+}
+
+impl Default for PlaylistOptions {
+    fn default() -> Self {
+        Self {
+            extended: true,
+            remap: vec![],
+            relative_to: None,
+        }
+    }
+}
+
+impl PlaylistOptions {
+    /// The path written for `path`.
+    fn path(&self, path: &Path) -> PathBuf {
+        let path = self
+            .remap
+            .iter()
+            .find_map(|(from, to)| Some(to.join(path.strip_prefix(from).ok()?)))
+            .unwrap_or_else(|| path.to_path_buf());
+        match &self.relative_to {
+            Some(base) => relative_path(&path, base),
+            None => path,
+        }
+    }
+}
+
+/// The components of `path` with `.` and `..` resolved, without looking at
+/// the file system.
+fn normalize(path: &Path) -> Vec<Component<'_>> {
+    let mut components = vec![];
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match components.last() {
+                Some(Component::Normal(_)) => {
+                    components.pop();
+                }
+                // nothing is above the root
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => components.push(component),
+            },
+            component => components.push(component),
+        }
+    }
+    components
+}
+
+/// `path` as reached from the directory `base`, both absolute.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path = normalize(path);
+    let base = normalize(base);
+    let shared = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    // e.g. on another drive, which only the full path reaches
+    if shared == 0 {
+        return path.iter().collect();
+    }
+    base[shared..]
+        .iter()
+        .map(|_| Component::ParentDir)
+        .chain(path[shared..].iter().copied())
+        .collect()
+}
+
+/// `text` on one line, as each entry of a playlist must be.
+fn single_line(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+/// Write `items` as an M3U playlist, in UTF-8 as the `.m3u8` extension
+/// promises.
+///
+/// # Errors
+/// Returns an error if writing to `out` fails
+pub fn write_m3u<'a, W: Write>(
+    out: &mut W,
+    items: impl IntoIterator<Item = &'a Item>,
+    options: &PlaylistOptions,
+) -> io::Result<()> {
+    if options.extended {
+        writeln!(out, "#EXTM3U")?;
+    }
+    for item in items {
+        if options.extended {
+            writeln!(
+                out,
+                "#EXTINF:{:.0},{}",
+                item.length,
+                single_line(&format!("{} - {}", item.artist, item.title))
+            )?;
+        }
+        let path = options.path(&item.path);
+        writeln!(out, "{}", single_line(&path.to_string_lossy()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::BuildHasher;
+use std::str::FromStr;
+
+use crate::review::year_month;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{FederatedLibrary, GlobalId, Sourced};
+use crate::{Item, Library};
+
+/// How long ago an item must have been added to be a
+/// [forgotten gem](PlaylistPreset::ForgottenGems), in seconds: two years.
+pub const FORGOTTEN_AGE: f64 = 2.0 * 365.25 * 86_400.0;
+
+/// The most plays a [forgotten gem](PlaylistPreset::ForgottenGems) may have had.
+pub const FORGOTTEN_PLAYS: u32 = 1;
+
+/// A smart playlist made from what beets knows of each item, written (and
+/// parsed) as e.g. `1990s`, `added-2021` or `forgotten-gems`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PlaylistPreset {
+    /// Items released in the decade starting with this year, e.g. 1990, by
+    /// their `original_year` if they have one.
+    Decade(u32),
+    /// Items added to the library in this year, in UTC.
+    Added(i32),
+    /// Items added at least [`FORGOTTEN_AGE`] ago and played at most
+    /// [`FORGOTTEN_PLAYS`] times since.
+    ForgottenGems,
+}
+
+impl fmt::Display for PlaylistPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaylistPreset::Decade(decade) => write!(f, "{decade}s"),
+            PlaylistPreset::Added(year) => write!(f, "added-{year}"),
+            PlaylistPreset::ForgottenGems => write!(f, "forgotten-gems"),
+        }
+    }
+}
+
+impl FromStr for PlaylistPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!("unknown preset `{s}`, expected e.g. `1990s`, `added-2021` or `forgotten-gems`")
+        };
+        if s == "forgotten-gems" {
+            return Ok(PlaylistPreset::ForgottenGems);
+        }
+        if let Some(year) = s.strip_prefix("added-") {
+            return year
+                .parse()
+                .map(PlaylistPreset::Added)
+                .map_err(|_| invalid());
+        }
+        match s.strip_suffix('s').map(str::parse) {
+            Some(Ok(decade)) if decade % 10 == 0 => Ok(PlaylistPreset::Decade(decade)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// The year `item` was first released, or 0 if unknown.
+fn release_year(item: &Item) -> u32 {
+    if item.original_year > 0 {
+        item.original_year
+    } else {
+        item.year
+    }
+}
+
+impl PlaylistPreset {
+    /// Whether `item`, played `plays` times, belongs in the playlist at the
+    /// time `now`, as a Unix timestamp.
+    #[must_use]
+    pub fn matches(self, item: &Item, plays: u32, now: f64) -> bool {
+        match self {
+            PlaylistPreset::Decade(decade) => {
+                let year = release_year(item);
+                year > 0 && year / 10 * 10 == decade
+            }
+            PlaylistPreset::Added(year) => year_month(item.added).0 == year,
+            PlaylistPreset::ForgottenGems => {
+                now - item.added >= FORGOTTEN_AGE && plays <= FORGOTTEN_PLAYS
+            }
+        }
+    }
+
+    /// The presets with any of `entries`, and how many each has: the
+    /// decades, then the years added, then the forgotten gems.
+    fn available<T>(
+        entries: &[T],
+        item: impl Fn(&T) -> &Item,
+        plays: impl Fn(&T) -> u32,
+        now: f64,
+    ) -> Vec<(PlaylistPreset, usize)> {
+        let mut counts: BTreeMap<PlaylistPreset, usize> = BTreeMap::new();
+        for entry in entries {
+            let (item, plays) = (item(entry), plays(entry));
+            let year = release_year(item);
+            if year > 0 {
+                *counts
+                    .entry(PlaylistPreset::Decade(year / 10 * 10))
+                    .or_default() += 1;
+            }
+            *counts
+                .entry(PlaylistPreset::Added(year_month(item.added).0))
+                .or_default() += 1;
+            if PlaylistPreset::ForgottenGems.matches(item, plays, now) {
+                *counts.entry(PlaylistPreset::ForgottenGems).or_default() += 1;
+            }
+        }
+        counts.into_iter().collect()
+    }
+}
+
+impl Library {
+    /// Every [`PlaylistPreset`] with items in the library and how many, given the
+    /// `play_counts` of items (see [`play_counts`](crate::play_counts)) and
+    /// the time `now`, as a Unix timestamp.
+    #[must_use]
+    pub fn presets<S: BuildHasher>(
+        &self,
+        play_counts: &HashMap<u32, u32, S>,
+        now: f64,
+    ) -> Vec<(PlaylistPreset, usize)> {
+        let plays = |item: &Item| play_counts.get(&item.id).copied().unwrap_or(0);
+        PlaylistPreset::available(&self.items, |item| item, plays, now)
+    }
+
+    /// The items in the playlist of `preset`, in library order.
+    #[must_use]
+    pub fn preset_items<S: BuildHasher>(
+        &self,
+        preset: PlaylistPreset,
+        play_counts: &HashMap<u32, u32, S>,
+        now: f64,
+    ) -> Vec<&Item> {
+        self.items
+            .iter()
+            .filter(|item| {
+                let plays = play_counts.get(&item.id).copied().unwrap_or(0);
+                preset.matches(item, plays, now)
+            })
+            .collect()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FederatedLibrary {
+    /// Like [`Library::presets`], across every library, with the play
+    /// counts of items by global id.
+    #[must_use]
+    pub fn presets<S: BuildHasher>(
+        &self,
+        play_counts: &HashMap<GlobalId, u32, S>,
+        now: f64,
+    ) -> Vec<(PlaylistPreset, usize)> {
+        let plays = |item: &Sourced<Item>| play_counts.get(&item.global_id()).copied().unwrap_or(0);
+        PlaylistPreset::available(&self.items, |item| &item.entry, plays, now)
+    }
+
+    /// Like [`Library::preset_items`], across every library.
+    #[must_use]
+    pub fn preset_items<S: BuildHasher>(
+        &self,
+        preset: PlaylistPreset,
+        play_counts: &HashMap<GlobalId, u32, S>,
+        now: f64,
+    ) -> Vec<&Sourced<Item>> {
+        self.items
+            .iter()
+            .filter(|item| {
+                let plays = play_counts.get(&item.global_id()).copied().unwrap_or(0);
+                preset.matches(&item.entry, plays, now)
+            })
+            .collect()
+    }
+}
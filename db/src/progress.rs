@@ -0,0 +1,40 @@
+/// How far a long operation has got, as passed to a [`ProgressSink`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress<'a> {
+    /// The table being read, e.g. `"items"`.
+    pub table: &'a str,
+    /// Rows processed so far.
+    pub rows: u64,
+    /// The number of rows expected in total, if known.
+    pub total: Option<u64>,
+}
+
+impl Progress<'_> {
+    /// The fraction of rows processed so far, from `0.0` to `1.0`.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn fraction(&self) -> Option<f64> {
+        self.total
+            .map(|total| (self.rows as f64 / total.max(1) as f64).min(1.0))
+    }
+}
+
+/// A receiver of [`Progress`] updates, e.g. to drive a progress bar.
+///
+/// Updates arrive every [`PROGRESS_INTERVAL`] rows and once more when each
+/// table is finished. Any `Fn(&Progress)` closure is a sink.
+pub trait ProgressSink: Send + Sync {
+    fn progress(&self, progress: &Progress);
+}
+
+impl<F> ProgressSink for F
+where
+    F: Fn(&Progress) + Send + Sync,
+{
+    fn progress(&self, progress: &Progress) {
+        self(progress);
+    }
+}
+
+/// The number of rows between [`Progress`] updates.
+pub const PROGRESS_INTERVAL: u64 = 1000;
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use rusqlite::Connection;
+
+use crate::attribute::set_recorded;
+use crate::undo::now;
+use crate::{Attribute, Database, Entity, Error, FieldValue, ReadWrite};
+
+/// What the flexible attribute holding the provenance of a field is named
+/// after, e.g. `provenance_genre` for `genre`.
+pub const PROVENANCE_PREFIX: &str = "provenance_";
+
+/// Who last changed a field through this crate, when, and what it was
+/// before, as kept in a flexible attribute once
+/// [`Database::set_provenance`] names the tool writing.
+///
+/// The attribute holds the three separated by tabs, so beets shows it as
+/// text and other tools can read it without this crate.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Provenance {
+    pub tool: String,
+    /// When the field was changed, as a Unix timestamp like those beets
+    /// stores.
+    pub time: f64,
+    /// The value before the change, as text, empty if there was none.
+    pub previous: String,
+}
+
+impl Provenance {
+    /// Read the value of a provenance attribute.
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.splitn(3, '\t');
+        let tool = parts.next()?.to_string();
+        let time = parts.next()?.parse().ok()?;
+        let previous = parts.next()?.to_string();
+        Some(Self {
+            tool,
+            time,
+            previous,
+        })
+    }
+
+    /// The provenance of each field of `entity` that has one, by field.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn read_for(conn: &Connection, entity: Entity) -> Result<BTreeMap<String, Self>, Error> {
+        Ok(Attribute::read_for(conn, entity)?
+            .into_iter()
+            .filter_map(|attribute| {
+                let field = attribute.key.strip_prefix(PROVENANCE_PREFIX)?;
+                Some((field.to_string(), Self::parse(&attribute.value)?))
+            })
+            .collect())
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // tabs separate the parts, so none is left in the tool's name
+        write!(
+            f,
+            "{}\t{}\t{}",
+            self.tool.replace('\t', " "),
+            self.time,
+            self.previous
+        )
+    }
+}
+
+impl Database<ReadWrite> {
+    /// Record the provenance of every field this handle changes with
+    /// [`Item::update`](crate::Item::update) or
+    /// [`Album::update`](crate::Album::update), naming `tool` as the writer,
+    /// or stop with `None`, as is the default.
+    ///
+    /// Each changed field gets a flexible attribute named with
+    /// [`PROVENANCE_PREFIX`], replacing the one from the change before. See
+    /// [`Provenance`].
+    pub fn set_provenance(&mut self, tool: Option<String>) {
+        self.provenance = tool;
+    }
+
+    /// Record the provenance of each of `columns` of `entity` that differs
+    /// between `previous` and `next`, if the handle names a tool.
+    pub(crate) fn stamp_provenance<'a, 'b>(
+        &self,
+        entity: Entity,
+        columns: &[&str],
+        previous: impl Fn(&str) -> Option<FieldValue<'a>>,
+        next: impl Fn(&str) -> Option<FieldValue<'b>>,
+    ) -> Result<(), Error> {
+        let Some(tool) = &self.provenance else {
+            return Ok(());
+        };
+        let time = now();
+        for column in columns.iter().filter(|column| **column != "id") {
+            let (before, after) = (previous(column), next(column));
+            if before == after {
+                continue;
+            }
+            let provenance = Provenance {
+                tool: tool.clone(),
+                time,
+                previous: match before {
+                    Some(FieldValue::Null) | None => String::new(),
+                    Some(value) => value.to_string(),
+                },
+            };
+            let key = format!("{PROVENANCE_PREFIX}{column}");
+            set_recorded(self, entity, &key, provenance.to_string())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,368 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::fmt::Write as _;
+
+use rusqlite::types::{ToSqlOutput, Value, ValueRef};
+use rusqlite::{params_from_iter, Connection, Row, ToSql};
+
+use crate::trace::{self, Hooks};
+use crate::{Album, Error, ErrorKind, FieldValue, Item};
+
+impl ToSql for FieldValue<'_> {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self {
+            FieldValue::Null => ToSqlOutput::Owned(Value::Null),
+            FieldValue::Bool(b) => ToSqlOutput::Owned(Value::Integer((*b).into())),
+            FieldValue::Integer(i) => ToSqlOutput::Owned(Value::Integer(*i)),
+            FieldValue::Real(r) => ToSqlOutput::Owned(Value::Real(*r)),
+            FieldValue::Text(t) => ToSqlOutput::Borrowed(ValueRef::Text(t.as_bytes())),
+        })
+    }
+}
+
+/// A condition on the rows of a [`Query`], which may combine others.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    /// The column equals the value.
+    Eq(&'static str, FieldValue<'static>),
+    /// The column contains the text, ignoring ASCII case as SQLite's `LIKE`
+    /// does. `NULL` counts as empty text.
+    Contains(&'static str, String),
+    /// The column is at least `low` and at most `high`, where given.
+    Range {
+        column: &'static str,
+        low: Option<FieldValue<'static>>,
+        high: Option<FieldValue<'static>>,
+    },
+    /// The column is `NULL`.
+    IsNull(&'static str),
+    /// The column holds a number, so neither text nor `NULL`.
+    IsNumber(&'static str),
+    /// The column is the path or lies in the directory of that path, compared
+    /// case-sensitively as [`Path::starts_with`](std::path::Path::starts_with)
+    /// does. `BLOB` paths count as their text.
+    Under(&'static str, String),
+    /// Any of the filters match, so none for an empty list.
+    Any(Vec<Filter>),
+    /// All of the filters match, so every row for an empty list.
+    All(Vec<Filter>),
+    /// The filter does not match. Comparisons with `NULL` never match, so
+    /// neither do their negations.
+    Not(Box<Filter>),
+}
+
+/// A `SELECT` with `WHERE` filters on an items or albums table, built up one
+/// condition at a time and run in SQLite, as started by [`Item::query`] and
+/// [`Album::query`].
+///
+/// Conditions are joined with `AND`. Values are bound as parameters, never
+/// spliced into the SQL. Columns are checked against the table's fields, and
+/// a query naming any other column fails when run.
+#[derive(Clone)]
+pub struct Query<T> {
+    table: &'static str,
+    columns: &'static [&'static str],
+    select_sql: fn(&Connection) -> Cow<'static, str>,
+    from_row: fn(&Row) -> Result<T, Error>,
+    conditions: Vec<String>,
+    params: Vec<FieldValue<'static>>,
+    order_by: Vec<(&'static str, bool)>,
+    limit: Option<u32>,
+    unknown_column: Option<String>,
+}
+
+impl<T> fmt::Debug for Query<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Query")
+            .field("table", &self.table)
+            .field("conditions", &self.where_sql())
+            .field("params", &self.params)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Query<T> {
+    pub(crate) fn new(
+        table: &'static str,
+        columns: &'static [&'static str],
+        select_sql: fn(&Connection) -> Cow<'static, str>,
+        from_row: fn(&Row) -> Result<T, Error>,
+    ) -> Self {
+        Self {
+            table,
+            columns,
+            select_sql,
+            from_row,
+            conditions: vec![],
+            params: vec![],
+            order_by: vec![],
+            limit: None,
+            unknown_column: None,
+        }
+    }
+
+    /// The column, if the table has it; otherwise the query is marked to fail.
+    fn column(&mut self, column: &'static str) -> &'static str {
+        if column != "id" && !self.columns.contains(&column) {
+            self.unknown_column
+                .get_or_insert_with(|| column.to_string());
+        }
+        column
+    }
+
+    /// Bind `value` as the next parameter, returning its placeholder.
+    fn param(&mut self, value: FieldValue<'static>) -> String {
+        self.params.push(value);
+        format!("?{}", self.params.len())
+    }
+
+    fn filter_sql(&mut self, filter: Filter) -> String {
+        match filter {
+            Filter::Eq(column, value) => {
+                format!("{} = {}", self.column(column), self.param(value))
+            }
+            Filter::Contains(column, text) => {
+                let escaped = text
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_");
+                let column = self.column(column);
+                let pattern = self.param(format!("%{escaped}%").into());
+                format!("IFNULL({column}, '') LIKE {pattern} ESCAPE '\\'")
+            }
+            Filter::Range { column, low, high } => {
+                let column = self.column(column);
+                match (low, high) {
+                    (Some(low), Some(high)) => format!(
+                        "{column} BETWEEN {} AND {}",
+                        self.param(low),
+                        self.param(high)
+                    ),
+                    (Some(low), None) => format!("{column} >= {}", self.param(low)),
+                    (None, Some(high)) => format!("{column} <= {}", self.param(high)),
+                    (None, None) => format!("{column} IS NOT NULL"),
+                }
+            }
+            Filter::IsNull(column) => format!("{} IS NULL", self.column(column)),
+            Filter::IsNumber(column) => {
+                format!("typeof({}) IN ('integer', 'real')", self.column(column))
+            }
+            Filter::Under(column, path) => {
+                // `LIKE` would ignore case, so the prefix is compared with `substr`
+                let exact = match path.trim_end_matches('/') {
+                    "" => &path[..path.len().min(1)],
+                    trimmed => trimmed,
+                };
+                let prefix = if exact.is_empty() || exact.ends_with('/') {
+                    exact.to_string()
+                } else {
+                    format!("{exact}/")
+                };
+                let column = format!("CAST({} AS TEXT)", self.column(column));
+                let exact = self.param(exact.to_string().into());
+                let prefix = self.param(prefix.into());
+                format!("{column} = {exact} OR substr({column}, 1, length({prefix})) = {prefix}")
+            }
+            Filter::Any(filters) => self.group_sql(filters, " OR ", "0"),
+            Filter::All(filters) => self.group_sql(filters, " AND ", "1"),
+            Filter::Not(filter) => format!("NOT ({})", self.filter_sql(*filter)),
+        }
+    }
+
+    fn group_sql(&mut self, filters: Vec<Filter>, separator: &str, empty: &str) -> String {
+        if filters.is_empty() {
+            return empty.to_string();
+        }
+        let conditions: Vec<_> = filters
+            .into_iter()
+            .map(|filter| format!("({})", self.filter_sql(filter)))
+            .collect();
+        conditions.join(separator)
+    }
+
+    /// Keep rows matching `filter`.
+    #[must_use]
+    pub fn filter(mut self, filter: Filter) -> Self {
+        let condition = self.filter_sql(filter);
+        self.conditions.push(condition);
+        self
+    }
+
+    /// Keep rows whose `column` equals `value`.
+    #[must_use]
+    pub fn eq(self, column: &'static str, value: impl Into<FieldValue<'static>>) -> Self {
+        self.filter(Filter::Eq(column, value.into()))
+    }
+
+    /// Keep rows whose `column` differs from `value`.
+    #[must_use]
+    pub fn ne(self, column: &'static str, value: impl Into<FieldValue<'static>>) -> Self {
+        self.filter(Filter::Not(Box::new(Filter::Eq(column, value.into()))))
+    }
+
+    /// Keep rows whose `column` is from `low` to `high` inclusive.
+    #[must_use]
+    pub fn between(
+        self,
+        column: &'static str,
+        low: impl Into<FieldValue<'static>>,
+        high: impl Into<FieldValue<'static>>,
+    ) -> Self {
+        self.filter(Filter::Range {
+            column,
+            low: Some(low.into()),
+            high: Some(high.into()),
+        })
+    }
+
+    /// Keep rows whose `column` contains `text`, ignoring ASCII case as
+    /// SQLite's `LIKE` does.
+    #[must_use]
+    pub fn contains(self, column: &'static str, text: &str) -> Self {
+        self.filter(Filter::Contains(column, text.to_string()))
+    }
+
+    /// Keep rows whose `column` is `NULL`.
+    #[must_use]
+    pub fn is_null(self, column: &'static str) -> Self {
+        self.filter(Filter::IsNull(column))
+    }
+
+    /// Sort by `column` after any earlier sort columns.
+    #[must_use]
+    pub fn order_by(mut self, column: &'static str, ascending: bool) -> Self {
+        let column = self.column(column);
+        self.order_by.push((column, ascending));
+        self
+    }
+
+    /// Return at most `limit` rows.
+    #[must_use]
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Keep rows whose album artist is `albumartist`.
+    #[must_use]
+    pub fn albumartist_eq(self, albumartist: &str) -> Self {
+        self.eq("albumartist", albumartist.to_string())
+    }
+
+    /// Keep rows from the album titled `album`.
+    #[must_use]
+    pub fn album_eq(self, album: &str) -> Self {
+        self.eq("album", album.to_string())
+    }
+
+    /// Keep rows whose genre is `genre`.
+    #[must_use]
+    pub fn genre_eq(self, genre: &str) -> Self {
+        self.eq("genre", genre.to_string())
+    }
+
+    /// Keep rows from `first` to `last` inclusive.
+    #[must_use]
+    pub fn year_between(self, first: u32, last: u32) -> Self {
+        self.between("year", first, last)
+    }
+
+    /// The `WHERE`, `ORDER BY` and `LIMIT` clauses, with a leading space.
+    fn where_sql(&self) -> String {
+        let mut sql = String::new();
+        if !self.conditions.is_empty() {
+            let _ = write!(sql, " WHERE {}", self.conditions.join(" AND "));
+        }
+        if !self.order_by.is_empty() {
+            let order: Vec<_> = self
+                .order_by
+                .iter()
+                .map(|&(column, ascending)| {
+                    format!("{column} {}", if ascending { "ASC" } else { "DESC" })
+                })
+                .collect();
+            let _ = write!(sql, " ORDER BY {}", order.join(", "));
+        }
+        if let Some(limit) = self.limit {
+            let _ = write!(sql, " LIMIT {limit}");
+        }
+        sql
+    }
+
+    /// The full statement as it would be run on `conn`, whose schema decides
+    /// which columns are selected.
+    #[must_use]
+    pub fn to_sql(&self, conn: &Connection) -> String {
+        format!("{}{}", (self.select_sql)(conn), self.where_sql())
+    }
+
+    /// The values bound to the statement's parameters, in order.
+    #[must_use]
+    pub fn params(&self) -> &[FieldValue<'static>] {
+        &self.params
+    }
+
+    /// Run the query, binding each row that matches.
+    ///
+    /// This runs on the bare connection, so a [`Database`](crate::Database)'s
+    /// hooks, cancellation token and timeout do not apply; see
+    /// [`Database::query`](crate::Database::query) for those.
+    ///
+    /// # Errors
+    /// Returns an error if a column is not one of the table's fields, or the
+    /// SQL query fails
+    pub fn run(&self, conn: &Connection) -> Result<Vec<T>, Error> {
+        self.run_traced(conn, Hooks::default())
+    }
+
+    pub(crate) fn run_traced(&self, conn: &Connection, hooks: Hooks) -> Result<Vec<T>, Error> {
+        if let Some(column) = &self.unknown_column {
+            return Err(Error {
+                source: Some(rusqlite::Error::InvalidColumnName(column.clone())),
+                kind: ErrorKind::Query,
+            });
+        }
+        trace::query_all(
+            conn,
+            self.table,
+            &self.to_sql(conn),
+            params_from_iter(&self.params),
+            self.from_row,
+            hooks,
+        )
+    }
+}
+
+impl Query<Item> {
+    /// Keep items whose artist is `artist`.
+    #[must_use]
+    pub fn artist_eq(self, artist: &str) -> Self {
+        self.eq("artist", artist.to_string())
+    }
+
+    /// Keep items titled `title`.
+    #[must_use]
+    pub fn title_eq(self, title: &str) -> Self {
+        self.eq("title", title.to_string())
+    }
+
+    /// Keep items on the album with id `album_id`.
+    #[must_use]
+    pub fn album_id_eq(self, album_id: u32) -> Self {
+        self.eq("album_id", album_id)
+    }
+
+    /// Keep items that are not on an album.
+    #[must_use]
+    pub fn singletons(self) -> Self {
+        self.is_null("album_id")
+    }
+}
+
+impl Query<Album> {
+    /// Keep albums that are compilations, or those that are not.
+    #[must_use]
+    pub fn comp(self, comp: bool) -> Self {
+        self.eq("comp", comp)
+    }
+}
@@ -0,0 +1,198 @@
+use std::path::PathBuf;
+
+use crate::{Item, Library, R128Gain};
+
+/// How many dB louder the `ReplayGain` reference level (89 dB SPL, about
+/// -18 LUFS) is than the -23 LUFS that R128 gains aim for.
+pub const R128_TO_REPLAYGAIN_DB: f64 = 5.0;
+
+/// Which gain a [`Queue`] prefers, as in a player's `ReplayGain` setting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum GainMode {
+    /// Level every track the same, for shuffled or mixed queues.
+    Track,
+    /// Keep the loudness differences within an album.
+    #[default]
+    Album,
+    /// Play tracks as they are.
+    Off,
+}
+
+/// One track of a [`PlayQueue`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct QueueEntry {
+    pub item_id: u32,
+    pub path: PathBuf,
+    /// In seconds.
+    pub length: f64,
+    /// The adjustment to play the track at, in dB relative to the
+    /// `ReplayGain` reference level, if the track has a gain to use.
+    pub gain: Option<f64>,
+    /// How many seconds of the end of this track to overlap with the next.
+    /// Zero between consecutive tracks of an album, which play gaplessly.
+    pub crossfade: f64,
+}
+
+/// An ordered queue of tracks, as built by [`Queue`], ready to hand to a
+/// player (also as JSON with the `serde` feature).
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct PlayQueue {
+    pub entries: Vec<QueueEntry>,
+}
+
+impl PlayQueue {
+    /// The total playing time in seconds, less the crossfades.
+    #[must_use]
+    pub fn length(&self) -> f64 {
+        self.entries
+            .iter()
+            .map(|entry| entry.length - entry.crossfade)
+            .sum()
+    }
+}
+
+/// Builds a [`PlayQueue`] from albums and matching items of a [`Library`].
+#[derive(Clone, Debug)]
+pub struct Queue<'a> {
+    library: &'a Library,
+    items: Vec<&'a Item>,
+    mode: GainMode,
+    preamp: f64,
+    prevent_clipping: bool,
+    crossfade: f64,
+}
+
+impl<'a> Queue<'a> {
+    #[must_use]
+    pub fn new(library: &'a Library) -> Self {
+        Self {
+            library,
+            items: vec![],
+            mode: GainMode::default(),
+            preamp: 0.0,
+            prevent_clipping: true,
+            crossfade: 0.0,
+        }
+    }
+
+    /// Add the items of an album, in disc and track order.
+    pub fn album(&mut self, album_id: u32) -> &mut Self {
+        let mut items: Vec<_> = self.library.album_items(album_id).collect();
+        items.sort_by_key(|item| (item.disc, item.track, item.id));
+        self.items.extend(items);
+        self
+    }
+
+    /// Add one item, if the library has it.
+    pub fn item(&mut self, item_id: u32) -> &mut Self {
+        self.items.extend(self.library.item(item_id));
+        self
+    }
+
+    /// Add every item matching `predicate` (such as a parsed query's
+    /// `match_item`), in library order.
+    pub fn items(&mut self, predicate: impl Fn(&Item) -> bool) -> &mut Self {
+        let library = self.library;
+        self.items
+            .extend(library.items.iter().filter(|item| predicate(item)));
+        self
+    }
+
+    /// Choose between track and album gain; albums by default.
+    pub fn gain_mode(&mut self, mode: GainMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Add `db` to every gain, as a player's pre-amp does.
+    pub fn preamp(&mut self, db: f64) -> &mut Self {
+        self.preamp = db;
+        self
+    }
+
+    /// Lower gains that would push a track's known peak past full scale; on
+    /// by default.
+    pub fn prevent_clipping(&mut self, prevent: bool) -> &mut Self {
+        self.prevent_clipping = prevent;
+        self
+    }
+
+    /// Overlap tracks by up to `seconds`, except those that play gaplessly.
+    /// No track overlaps more than a quarter of its own length, or of the
+    /// next track's.
+    pub fn crossfade(&mut self, seconds: f64) -> &mut Self {
+        self.crossfade = seconds.max(0.0);
+        self
+    }
+
+    /// The gain of `item` in dB, and the peak (as a fraction of full scale)
+    /// that goes with it.
+    fn gain(&self, item: &Item) -> Option<(f64, Option<f64>)> {
+        let track = gain_of(item.r128_track_gain, item.rg_track_gain, item.rg_track_peak);
+        let album = gain_of(item.r128_album_gain, item.rg_album_gain, item.rg_album_peak);
+        match self.mode {
+            GainMode::Track => track.or(album),
+            GainMode::Album => album.or(track),
+            GainMode::Off => None,
+        }
+    }
+
+    /// The queue of everything added so far, in the order it was added.
+    #[must_use]
+    pub fn build(&self) -> PlayQueue {
+        let mut entries: Vec<QueueEntry> = self
+            .items
+            .iter()
+            .map(|item| {
+                let gain = self.gain(item).map(|(gain, peak)| {
+                    let gain = gain + self.preamp;
+                    match peak.filter(|&peak| self.prevent_clipping && peak > 0.0) {
+                        Some(peak) => gain.min(-20.0 * peak.log10()),
+                        None => gain,
+                    }
+                });
+                QueueEntry {
+                    item_id: item.id,
+                    path: item.path.clone(),
+                    length: item.length,
+                    gain,
+                    crossfade: 0.0,
+                }
+            })
+            .collect();
+
+        for (i, pair) in self.items.windows(2).enumerate() {
+            if !plays_gaplessly(pair[0], pair[1]) {
+                let limit = pair[0].length.min(pair[1].length) / 4.0;
+                entries[i].crossfade = self.crossfade.min(limit.max(0.0));
+            }
+        }
+        PlayQueue { entries }
+    }
+}
+
+/// A gain at the `ReplayGain` reference level, preferring R128 when both are
+/// tagged. R128 gains come without a peak.
+fn gain_of(
+    r128: Option<R128Gain>,
+    replaygain: Option<f64>,
+    peak: Option<f64>,
+) -> Option<(f64, Option<f64>)> {
+    match (r128, replaygain) {
+        (Some(r128), _) => Some((r128.db() + R128_TO_REPLAYGAIN_DB, None)),
+        (None, Some(gain)) => Some((gain, peak)),
+        (None, None) => None,
+    }
+}
+
+/// Whether `next` follows straight on from `item` on the same album.
+fn plays_gaplessly(item: &Item, next: &Item) -> bool {
+    item.album_id.is_some()
+        && item.album_id == next.album_id
+        && ((next.disc == item.disc && next.track.checked_sub(1) == Some(item.track))
+            || (next.disc.checked_sub(1) == Some(item.disc) && next.track == 1))
+}
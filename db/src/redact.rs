@@ -0,0 +1,125 @@
+use std::path::Path;
+
+use crate::{Album, FieldError, FieldValue, Item, Library};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Rule {
+    /// Reset to the default value, which serializes as nothing.
+    Strip,
+    /// Keep only the last component of a path.
+    FileName,
+}
+
+/// Fields to hide from records before they are serialized or served, e.g.
+/// so a public catalog does not give away the layout of the music folder.
+///
+/// Each rule applies to whichever of [`Album`] and [`Item`] has the field.
+/// Redacting never touches the library it reads from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Redaction {
+    rules: Vec<(String, Rule)>,
+}
+
+fn check_field(field: &str) -> Result<(), FieldError> {
+    if Album::COLUMNS.contains(&field) || Item::COLUMNS.contains(&field) {
+        Ok(())
+    } else {
+        Err(FieldError::Unknown(field.to_string()))
+    }
+}
+
+/// Apply `rules` to a record through its by-name field accessors.
+fn redact<T: Default>(
+    record: &mut T,
+    rules: &[(String, Rule)],
+    field: for<'r> fn(&'r T, &str) -> Option<FieldValue<'r>>,
+    set_field: fn(&mut T, &str, FieldValue) -> Result<(), FieldError>,
+) {
+    let defaults = T::default();
+    for (name, rule) in rules {
+        let value = match rule {
+            Rule::Strip => field(&defaults, name),
+            Rule::FileName => match field(record, name) {
+                Some(FieldValue::Text(text)) => Path::new(text.as_ref())
+                    .file_name()
+                    .map(|name| FieldValue::Text(name.to_string_lossy().into_owned().into())),
+                _ => None,
+            },
+        };
+        if let Some(value) = value.map(FieldValue::into_owned) {
+            // the value comes from a field of the same type
+            let _ = set_field(record, name, value);
+        }
+    }
+}
+
+impl Redaction {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Paths cut down to file names, and no lyrics or comments: enough to
+    /// browse a catalog without learning anything about the machine it is
+    /// on.
+    #[must_use]
+    pub fn public() -> Self {
+        let mut redaction = Self::new();
+        for field in ["path", "artpath"] {
+            redaction.rules.push((field.to_string(), Rule::FileName));
+        }
+        for field in ["lyrics", "comments"] {
+            redaction.rules.push((field.to_string(), Rule::Strip));
+        }
+        redaction
+    }
+
+    /// Empty `field`, leaving it out when serialized.
+    ///
+    /// # Errors
+    /// Returns an error if neither albums nor items have the field
+    pub fn strip(&mut self, field: &str) -> Result<&mut Self, FieldError> {
+        check_field(field)?;
+        self.rules.push((field.to_string(), Rule::Strip));
+        Ok(self)
+    }
+
+    /// Cut the path in `field` down to its file name.
+    ///
+    /// # Errors
+    /// Returns an error if neither albums nor items have the field
+    pub fn file_name_only(&mut self, field: &str) -> Result<&mut Self, FieldError> {
+        check_field(field)?;
+        self.rules.push((field.to_string(), Rule::FileName));
+        Ok(self)
+    }
+
+    /// A redacted copy of `album`.
+    #[must_use]
+    pub fn album(&self, album: &Album) -> Album {
+        let mut album = album.clone();
+        redact(&mut album, &self.rules, Album::field, Album::set_field);
+        album
+    }
+
+    /// A redacted copy of `item`.
+    #[must_use]
+    pub fn item(&self, item: &Item) -> Item {
+        let mut item = item.clone();
+        redact(&mut item, &self.rules, Item::field, Item::set_field);
+        item
+    }
+
+    /// A redacted copy of every album and item of `library`.
+    #[must_use]
+    pub fn library(&self, library: &Library) -> Library {
+        Library {
+            albums: library
+                .albums
+                .iter()
+                .map(|album| self.album(album))
+                .collect(),
+            items: library.items.iter().map(|item| self.item(item)).collect(),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::itunes::{escape_xml, file_url};
+use crate::review::civil_date;
+use crate::{CamelotKey, Item};
+
+/// What Rekordbox calls a file of `format`, as beets names it.
+fn kind(format: &str) -> String {
+    match format {
+        "AAC" | "ALAC" => "M4A File".to_string(),
+        "WAVE" => "WAV File".to_string(),
+        "" => "Audio File".to_string(),
+        format => format!("{format} File"),
+    }
+}
+
+/// The attributes of one `TRACK`, each written only where the item has a
+/// value, as Rekordbox fills in those left out when it analyzes the file.
+struct Track {
+    attributes: String,
+}
+
+impl Track {
+    fn string(&mut self, name: &str, value: &str) {
+        if !value.is_empty() {
+            let value = escape_xml(value).replace('"', "&quot;");
+            let _ = write!(self.attributes, " {name}=\"{value}\"");
+        }
+    }
+
+    fn integer(&mut self, name: &str, value: u64) {
+        if value != 0 {
+            let _ = write!(self.attributes, " {name}=\"{value}\"");
+        }
+    }
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn write_track(out: &mut dyn Write, item: &Item) -> io::Result<()> {
+    let mut track = Track {
+        attributes: format!("TrackID=\"{}\"", item.id),
+    };
+    track.string("Name", &item.title);
+    track.string("Artist", &item.artist);
+    track.string("Composer", &item.composer);
+    track.string("Album", &item.album);
+    track.string("Grouping", &item.grouping);
+    track.string("Genre", &item.genre);
+    track.string("Kind", &kind(&item.format));
+    track.integer("TotalTime", item.length.round() as u64);
+    track.integer("DiscNumber", item.disc.into());
+    track.integer("TrackNumber", item.track.into());
+    track.integer("Year", item.year.into());
+    if item.bpm > 0.0 {
+        track.string("AverageBpm", &format!("{:.2}", item.bpm));
+    }
+    if item.added > 0.0 {
+        let (year, month, day) = civil_date(item.added);
+        track.string("DateAdded", &format!("{year:04}-{month:02}-{day:02}"));
+    }
+    track.integer("BitRate", (item.bitrate / 1000).into());
+    track.integer("SampleRate", item.samplerate.into());
+    track.string("Comments", &item.comments);
+    track.string("Label", &item.label);
+    // Rekordbox reads keys in its own notation, but not the Camelot one
+    let key = item.camelot_key().map(CamelotKey::name);
+    track.string(
+        "Tonality",
+        key.or(item.initial_key.as_deref()).unwrap_or_default(),
+    );
+    track.string("Location", &file_url(&item.path));
+    writeln!(out, "    <TRACK {}/>", track.attributes)
+}
+
+/// Write `items` as a Rekordbox XML collection, which Rekordbox imports
+/// under `rekordbox xml` in its tree view, so the BPM and key beets has
+/// are kept rather than analyzed again.
+///
+/// Every item is a track, with the beets id as its `TrackID`, and is listed
+/// in a `beets` playlist, in order. Items from several libraries must not
+/// share an id.
+///
+/// # Errors
+/// Returns an error if writing to `out` fails
+pub fn write_rekordbox_xml<'a, W: Write>(
+    out: &mut W,
+    items: impl IntoIterator<Item = &'a Item>,
+) -> io::Result<()> {
+    let items: Vec<&Item> = items.into_iter().collect();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<DJ_PLAYLISTS Version="1.0.0">"#)?;
+    writeln!(
+        out,
+        r#"  <PRODUCT Name="beet_db" Version="{}" Company="berts"/>"#,
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(out, r#"  <COLLECTION Entries="{}">"#, items.len())?;
+    for item in &items {
+        write_track(out, item)?;
+    }
+    writeln!(out, "  </COLLECTION>")?;
+    writeln!(out, "  <PLAYLISTS>")?;
+    writeln!(out, r#"    <NODE Type="0" Name="ROOT" Count="1">"#)?;
+    writeln!(
+        out,
+        r#"      <NODE Name="beets" Type="1" KeyType="0" Entries="{}">"#,
+        items.len()
+    )?;
+    for item in &items {
+        writeln!(out, r#"        <TRACK Key="{}"/>"#, item.id)?;
+    }
+    writeln!(out, "      </NODE>")?;
+    writeln!(out, "    </NODE>")?;
+    writeln!(out, "  </PLAYLISTS>")?;
+    writeln!(out, "</DJ_PLAYLISTS>")
+}
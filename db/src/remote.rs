@@ -0,0 +1,195 @@
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{Database, Error, OpenOptions};
+
+/// The reason a library could not be fetched from a URL.
+#[derive(Debug)]
+pub enum RemoteError {
+    /// The URL is not `http://`, `https://` or `s3://`.
+    Url(String),
+    /// The request failed, or the server answered with an error.
+    Http(Box<ureq::Error>),
+    /// The download could not be saved in the cache.
+    Io(io::Error),
+    /// The downloaded file could not be opened as a library.
+    Open(Error),
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoteError::Url(url) => write!(f, "unsupported library URL {url:?}"),
+            RemoteError::Http(_) => write!(f, "failed to download library"),
+            RemoteError::Io(_) => write!(f, "failed to cache downloaded library"),
+            RemoteError::Open(_) => write!(f, "failed to open downloaded library"),
+        }
+    }
+}
+
+impl std::error::Error for RemoteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RemoteError::Url(_) => None,
+            RemoteError::Http(err) => Some(err),
+            RemoteError::Io(err) => Some(err),
+            RemoteError::Open(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for RemoteError {
+    fn from(err: io::Error) -> Self {
+        RemoteError::Io(err)
+    }
+}
+
+/// The HTTP(S) address to download `url` from.
+///
+/// `s3://bucket/key` is fetched from the bucket's public endpoint, so only
+/// public objects can be read that way; use a presigned `https://` URL for
+/// private ones.
+fn http_url(url: &str) -> Result<String, RemoteError> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        return Ok(url.to_string());
+    }
+    match url
+        .strip_prefix("s3://")
+        .and_then(|path| path.split_once('/'))
+    {
+        Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => {
+            Ok(format!("https://{bucket}.s3.amazonaws.com/{key}"))
+        }
+        _ => Err(RemoteError::Url(url.to_string())),
+    }
+}
+
+/// A file name for `url` that stays the same across runs and Rust versions,
+/// unlike `DefaultHasher`.
+fn cache_name(url: &str) -> String {
+    // 64-bit FNV-1a
+    let hash = url.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// The directory [`Database::open_url`] keeps downloaded libraries in, in the
+/// user's own cache directory, or `None` if there is none.
+#[must_use]
+pub fn remote_cache_dir() -> Option<PathBuf> {
+    let cache = if cfg!(windows) {
+        PathBuf::from(env::var_os("LOCALAPPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        Path::new(&env::var_os("HOME")?).join("Library/Caches")
+    } else if let Some(dir) = env::var_os("XDG_CACHE_HOME") {
+        PathBuf::from(dir)
+    } else {
+        Path::new(&env::var_os("HOME")?).join(".cache")
+    };
+    Some(cache.join("beet_db").join("remote"))
+}
+
+/// Create `dir`, readable only by the user where permissions allow.
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+/// Replace `path` with what `write` writes, through a new file beside it
+/// that is renamed into place, so readers never see half a file and a link
+/// planted at either path is never followed.
+fn replace_file(path: &Path, write: impl FnOnce(&mut File) -> io::Result<()>) -> io::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(format!(".{}.part", std::process::id()));
+    let partial = PathBuf::from(partial);
+    let create = || File::options().write(true).create_new(true).open(&partial);
+    let mut file = match create() {
+        // left behind by a crashed run with the same process id
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            fs::remove_file(&partial)?;
+            create()?
+        }
+        result => result?,
+    };
+    let written = write(&mut file).and_then(|()| file.sync_all());
+    drop(file);
+    match written.and_then(|()| fs::rename(&partial, path)) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let _ = fs::remove_file(&partial);
+            Err(err)
+        }
+    }
+}
+
+/// Download the library at `url` into `cache_dir`, returning its path there.
+///
+/// `url` may be `http://`, `https://`, or `s3://bucket/key` for a public S3
+/// object. Requests are not signed, so private objects need a presigned
+/// `https://` URL instead.
+///
+/// The `ETag` of each download is kept next to it, and an unchanged library
+/// is not downloaded again.
+///
+/// # Errors
+/// Returns an error if the URL is unsupported, the download fails, or it
+/// cannot be saved
+pub fn fetch_url(url: &str, cache_dir: &Path) -> Result<PathBuf, RemoteError> {
+    let name = cache_name(url);
+    let path = cache_dir.join(format!("{name}.db"));
+    let etag_path = cache_dir.join(format!("{name}.etag"));
+
+    let mut request = ureq::get(&http_url(url)?);
+    if path.exists() {
+        if let Ok(etag) = fs::read_to_string(&etag_path) {
+            request = request.set("If-None-Match", etag.trim());
+        }
+    }
+    let response = request
+        .call()
+        .map_err(|err| RemoteError::Http(Box::new(err)))?;
+    if response.status() == 304 {
+        return Ok(path);
+    }
+
+    create_private_dir(cache_dir)?;
+    let etag = response.header("ETag").map(String::from);
+    replace_file(&path, |file| {
+        io::copy(&mut response.into_reader(), file).map(drop)
+    })?;
+    match etag {
+        Some(etag) => replace_file(&etag_path, |file| file.write_all(etag.as_bytes()))?,
+        None => match fs::remove_file(&etag_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        },
+    }
+    Ok(path)
+}
+
+impl Database {
+    /// Download a library over HTTP(S) and open it read-only, so it can be
+    /// read without mounting the machine that holds it.
+    ///
+    /// Downloads are cached in [`remote_cache_dir`] and revalidated by
+    /// `ETag`. See [`fetch_url`] for the URLs accepted; `s3://` URLs only
+    /// reach public objects.
+    ///
+    /// # Errors
+    /// Returns an error if the user has no cache directory, or the library
+    /// cannot be downloaded or opened
+    pub fn open_url(url: &str) -> Result<Self, RemoteError> {
+        let cache_dir = remote_cache_dir().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no cache directory for the user")
+        })?;
+        let path = fetch_url(url, &cache_dir)?;
+        OpenOptions::new().open(path).map_err(RemoteError::Open)
+    }
+}
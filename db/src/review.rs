@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::fmt::Write as _;
+
+use crate::{Attribute, Library};
+
+/// How many artists [`YearInReview`] ranks.
+pub const TOP_ARTISTS: usize = 10;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// An artist and how many items (or plays) they had.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct RankedArtist {
+    pub artist: String,
+    pub count: u32,
+}
+
+/// What joined a library in one year, for a "year in review" page.
+///
+/// Years and months are those of the `added` timestamps in UTC.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct YearInReview {
+    pub year: i32,
+    pub albums_added: u32,
+    pub items_added: u32,
+    /// The length of the items added, in seconds.
+    pub length_added: f64,
+    /// Items added in each month, January first.
+    pub items_by_month: [u32; 12],
+    /// The month (1 for January) in which the most items were added, the
+    /// earliest on a tie.
+    pub biggest_month: Option<u32>,
+    /// The genre of the most items added, the first alphabetically on a tie.
+    pub top_genre: Option<String>,
+    /// The artists with the most items added, most first.
+    pub top_artists: Vec<RankedArtist>,
+    /// The artists whose items added this year were played the most, most
+    /// first. Beets keeps a total `play_count` rather than a history, so
+    /// these include plays from later years.
+    pub top_played_artists: Vec<RankedArtist>,
+}
+
+/// The year and month (1 for January) of a Unix timestamp, in UTC.
+pub(crate) fn year_month(timestamp: f64) -> (i32, u32) {
+    let (year, month, _) = civil_date(timestamp);
+    (year, month)
+}
+
+/// The year, month (1 for January) and day of a Unix timestamp, in UTC. A
+/// timestamp that is not a number or out of range is taken as the epoch.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub(crate) fn civil_date(timestamp: f64) -> (i32, u32, u32) {
+    // days since 1970-01-01 to a civil date, after Howard Hinnant's
+    // `civil_from_days`
+    let days = (timestamp / 86_400.0).floor();
+    // a billion years either way, beyond which it is taken as the epoch
+    let days = if days.abs() < 365e9 { days as i64 } else { 0 } + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year as i32, month as u32, day as u32)
+}
+
+/// The `play_count` attribute of each item, as kept by the `mpdstats` and
+/// `lastimport` plugins, from a map like [`Attribute::read_item_map`] gives.
+#[must_use]
+pub fn play_counts<S: std::hash::BuildHasher>(
+    item_attributes: &HashMap<u32, Vec<Attribute>, S>,
+) -> HashMap<u32, u32> {
+    item_attributes
+        .iter()
+        .filter_map(|(id, attributes)| {
+            let plays = attributes
+                .iter()
+                .find(|attribute| attribute.key == "play_count")?;
+            Some((*id, plays.value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// The `count` highest of `counts`, ties in alphabetical order.
+fn rank(counts: BTreeMap<&str, u32>, count: usize) -> Vec<RankedArtist> {
+    let mut ranked: Vec<_> = counts.into_iter().filter(|&(_, n)| n > 0).collect();
+    // stable, so ties stay alphabetical
+    ranked.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+    ranked
+        .into_iter()
+        .take(count)
+        .map(|(artist, count)| RankedArtist {
+            artist: artist.to_string(),
+            count,
+        })
+        .collect()
+}
+
+impl Library {
+    /// Sum up the albums and items added in `year`, ranking their artists by
+    /// the `play_counts` of items (see [`play_counts`]) as well.
+    #[must_use]
+    pub fn year_in_review<S: std::hash::BuildHasher>(
+        &self,
+        year: i32,
+        play_counts: &HashMap<u32, u32, S>,
+    ) -> YearInReview {
+        let mut review = YearInReview {
+            year,
+            ..YearInReview::default()
+        };
+        review.albums_added = self
+            .albums
+            .iter()
+            .filter(|album| year_month(album.added).0 == year)
+            .count()
+            .try_into()
+            .unwrap_or(u32::MAX);
+
+        let mut genres: BTreeMap<&str, u32> = BTreeMap::new();
+        let mut artists: BTreeMap<&str, u32> = BTreeMap::new();
+        let mut plays: BTreeMap<&str, u32> = BTreeMap::new();
+        for item in &self.items {
+            let (added_year, month) = year_month(item.added);
+            if added_year != year {
+                continue;
+            }
+            review.items_added += 1;
+            review.length_added += item.length;
+            review.items_by_month[month as usize - 1] += 1;
+            if !item.genre.is_empty() {
+                *genres.entry(&item.genre).or_default() += 1;
+            }
+            *artists.entry(&item.artist).or_default() += 1;
+            let item_plays = play_counts.get(&item.id).copied().unwrap_or(0);
+            *plays.entry(&item.artist).or_default() += item_plays;
+        }
+
+        review.biggest_month = (1..=12)
+            .zip(review.items_by_month)
+            .filter(|&(_, count)| count > 0)
+            .min_by_key(|&(_, count)| std::cmp::Reverse(count))
+            .map(|(month, _)| month);
+        review.top_genre = genres
+            .into_iter()
+            .max_by_key(|&(genre, count)| (count, std::cmp::Reverse(genre)))
+            .map(|(genre, _)| genre.to_string());
+        review.top_artists = rank(artists, TOP_ARTISTS);
+        review.top_played_artists = rank(plays, TOP_ARTISTS);
+        review
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl YearInReview {
+    /// A standalone HTML page of the report.
+    #[must_use]
+    pub fn to_html(&self) -> String {
+        let year = self.year;
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{year} in review</title>\n</head>\n<body>\n<h1>{year} in review</h1>\n"
+        );
+        let minutes = (self.length_added / 60.0).round();
+        let _ = writeln!(
+            html,
+            "<p>{} albums and {} items added, {minutes} minutes of music.</p>",
+            self.albums_added, self.items_added
+        );
+        if let Some(genre) = &self.top_genre {
+            let _ = writeln!(html, "<p>Most added genre: {}</p>", escape_html(genre));
+        }
+        if let Some(month) = self.biggest_month {
+            let _ = writeln!(html, "<p>Biggest month: {}</p>", MONTHS[month as usize - 1]);
+        }
+        for (heading, ranked, unit) in [
+            ("Top artists", &self.top_artists, "items"),
+            ("Most played", &self.top_played_artists, "plays"),
+        ] {
+            if ranked.is_empty() {
+                continue;
+            }
+            let _ = writeln!(html, "<h2>{heading}</h2>\n<ol>");
+            for RankedArtist { artist, count } in ranked {
+                let _ = writeln!(html, "<li>{} ({count} {unit})</li>", escape_html(artist));
+            }
+            html.push_str("</ol>\n");
+        }
+        html.push_str("<h2>By month</h2>\n<table>\n");
+        for (month, count) in MONTHS.iter().zip(self.items_by_month) {
+            let _ = writeln!(html, "<tr><td>{month}</td><td>{count}</td></tr>");
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+}
@@ -0,0 +1,309 @@
+use std::cell::Cell;
+#[cfg(target_os = "linux")]
+use std::cell::RefCell;
+use std::fmt;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::time::{Duration, Instant};
+
+use rusqlite::{Connection, ErrorCode};
+
+use crate::{schema, Album, Database, Error, ErrorKind, Item, ReadWrite};
+
+/// The attribute tables, which every beets version lays out the same way.
+const ATTRIBUTE_COLUMNS: &[&str] = &["id", "entity_id", "key", "value"];
+
+/// How long a scan for beets processes is trusted, since it reads the open
+/// files of every process and writes may come in quick succession.
+#[cfg(target_os = "linux")]
+const PROCESS_SCAN_TTL: Duration = Duration::from_secs(2);
+
+/// A reason writing to a library now could corrupt it, found by
+/// [`Database::write_hazards`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WriteHazard {
+    /// The table lacks columns this crate writes, so the library is from an
+    /// older beets than this crate expects, or not from beets at all.
+    MissingColumns {
+        table: &'static str,
+        columns: Vec<String>,
+    },
+    /// The table has columns this crate does not know, left empty in the
+    /// rows it inserts, so the library is from a newer beets.
+    UnknownColumns {
+        table: &'static str,
+        columns: Vec<String>,
+    },
+    /// Another connection is in the middle of writing.
+    Locked,
+    /// A beets process has the library open, e.g. during `beet import`.
+    BeetsRunning { pid: u32 },
+}
+
+impl WriteHazard {
+    /// Whether writes are refused over this hazard, rather than only
+    /// reported to the hook set with [`Database::set_hazard_hook`].
+    #[must_use]
+    pub fn is_blocking(&self) -> bool {
+        !matches!(self, WriteHazard::UnknownColumns { .. })
+    }
+}
+
+impl std::error::Error for WriteHazard {}
+
+impl fmt::Display for WriteHazard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteHazard::MissingColumns { table, columns } => {
+                write!(f, "{table} is missing columns {}", columns.join(", "))
+            }
+            WriteHazard::UnknownColumns { table, columns } => {
+                write!(f, "{table} has unknown columns {}", columns.join(", "))
+            }
+            WriteHazard::Locked => f.write_str("another connection is writing"),
+            WriteHazard::BeetsRunning { pid } => {
+                write!(f, "beets is running with the library open (pid {pid})")
+            }
+        }
+    }
+}
+
+type HazardHook = dyn Fn(&WriteHazard) + Send + Sync;
+
+/// The state of the checks made before writes.
+pub(crate) struct Safety {
+    enabled: bool,
+    hook: Option<Box<HazardHook>>,
+    /// Set during a write that was checked already, so the writes it is
+    /// made of are not checked again.
+    checked: Cell<bool>,
+    /// The last scan for beets processes, and when it was made.
+    #[cfg(target_os = "linux")]
+    processes: RefCell<Option<(Instant, Vec<u32>)>>,
+}
+
+impl Default for Safety {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            hook: None,
+            checked: Cell::new(false),
+            #[cfg(target_os = "linux")]
+            processes: RefCell::new(None),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Safety {
+    /// [`beets_processes`] with `path` open, scanning again only once the
+    /// last scan is [`PROCESS_SCAN_TTL`] old.
+    fn beets_processes(&self, path: &Path) -> Vec<u32> {
+        let mut processes = self.processes.borrow_mut();
+        match &*processes {
+            Some((scanned, pids)) if scanned.elapsed() < PROCESS_SCAN_TTL => pids.clone(),
+            _ => {
+                let pids = beets_processes(path);
+                *processes = Some((Instant::now(), pids.clone()));
+                pids
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Safety {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Safety");
+        debug
+            .field("enabled", &self.enabled)
+            .field("hook", &self.hook.is_some())
+            .field("checked", &self.checked);
+        #[cfg(target_os = "linux")]
+        debug.field("processes", &self.processes);
+        debug.finish()
+    }
+}
+
+/// Clears [`Safety::checked`] once the checked write ends, even by panic.
+struct Checked<'a>(&'a Cell<bool>);
+
+impl Drop for Checked<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+fn hazard_err(hazard: WriteHazard) -> Error {
+    Error {
+        source: None,
+        kind: ErrorKind::Hazard(hazard),
+    }
+}
+
+fn schema_hazards(
+    conn: &Connection,
+    table: &'static str,
+    columns: &[&str],
+    hazards: &mut Vec<WriteHazard>,
+) -> Result<(), Error> {
+    let present = schema::table_columns(conn, table).map_err(|source| Error {
+        source: Some(source),
+        kind: ErrorKind::Schema,
+    })?;
+    let mut missing: Vec<String> = columns
+        .iter()
+        .filter(|column| !present.contains(**column) && !schema::is_optional(table, column))
+        .map(|column| (*column).to_string())
+        .collect();
+    let mut unknown: Vec<String> = present
+        .into_iter()
+        .filter(|column| !columns.contains(&column.as_str()))
+        .collect();
+    missing.sort();
+    unknown.sort();
+    if !missing.is_empty() {
+        hazards.push(WriteHazard::MissingColumns {
+            table,
+            columns: missing,
+        });
+    }
+    if !unknown.is_empty() {
+        hazards.push(WriteHazard::UnknownColumns {
+            table,
+            columns: unknown,
+        });
+    }
+    Ok(())
+}
+
+/// Whether another connection holds a write lock, found by briefly taking
+/// one. Only possible outside a transaction.
+fn locked(conn: &Connection) -> Result<bool, Error> {
+    if !conn.is_autocommit() {
+        return Ok(false);
+    }
+    match conn.execute_batch("BEGIN IMMEDIATE; ROLLBACK") {
+        Ok(()) => Ok(false),
+        Err(rusqlite::Error::SqliteFailure(err, _))
+            if matches!(
+                err.code,
+                ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked
+            ) =>
+        {
+            Ok(true)
+        }
+        Err(source) => Err(Error::write(source)),
+    }
+}
+
+/// The ids of beets processes with `path` open, found by their open files.
+#[cfg(target_os = "linux")]
+fn beets_processes(path: &Path) -> Vec<u32> {
+    let Ok(path) = path.canonicalize() else {
+        return vec![];
+    };
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return vec![];
+    };
+    let own = std::process::id();
+    processes
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| *pid != own)
+        .filter(|pid| {
+            // beets runs as `beet`, or as a script passed to `python`
+            std::fs::read(format!("/proc/{pid}/cmdline")).is_ok_and(|cmdline| {
+                cmdline.split(|byte| *byte == 0).take(2).any(|arg| {
+                    let arg = String::from_utf8_lossy(arg);
+                    let name = arg.rsplit('/').next().unwrap_or_default();
+                    name == "beet" || name == "beets"
+                })
+            })
+        })
+        .filter(|pid| {
+            std::fs::read_dir(format!("/proc/{pid}/fd")).is_ok_and(|files| {
+                files
+                    .filter_map(|file| std::fs::read_link(file.ok()?.path()).ok())
+                    .any(|file| file == path)
+            })
+        })
+        .collect()
+}
+
+impl Database<ReadWrite> {
+    /// Look for reasons writing to the library now could corrupt it: a
+    /// schema from another beets version, another connection writing, or
+    /// (on Linux) beets itself running with the library open. Processes are
+    /// looked for at most every couple of seconds, and the last answer used
+    /// in between.
+    ///
+    /// Every write through this handle checks these first, refusing with an
+    /// error for which [`Error::write_hazard`] is `Some` if any is
+    /// [blocking](WriteHazard::is_blocking). The free functions that write
+    /// through a [`Connection`], such as [`set_attribute`](crate::set_attribute()),
+    /// do not.
+    ///
+    /// # Errors
+    /// Returns an error if the schema cannot be inspected
+    pub fn write_hazards(&self) -> Result<Vec<WriteHazard>, Error> {
+        let conn = self.connection();
+        let mut hazards = vec![];
+        schema_hazards(conn, "items", Item::COLUMNS, &mut hazards)?;
+        schema_hazards(conn, "albums", Album::COLUMNS, &mut hazards)?;
+        schema_hazards(conn, "item_attributes", ATTRIBUTE_COLUMNS, &mut hazards)?;
+        schema_hazards(conn, "album_attributes", ATTRIBUTE_COLUMNS, &mut hazards)?;
+        if locked(conn)? {
+            hazards.push(WriteHazard::Locked);
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(path) = conn.path().filter(|path| !path.is_empty()) {
+            hazards.extend(
+                self.safety
+                    .beets_processes(Path::new(path))
+                    .into_iter()
+                    .map(|pid| WriteHazard::BeetsRunning { pid }),
+            );
+        }
+        Ok(hazards)
+    }
+
+    /// Check for [`write_hazards`](Self::write_hazards) before every write,
+    /// as is the default, or write regardless.
+    pub fn set_safety_checks(&mut self, enabled: bool) {
+        self.safety.enabled = enabled;
+    }
+
+    /// Call `hook` with each hazard found before a write that is not
+    /// blocking, such as columns added by a newer beets.
+    pub fn set_hazard_hook(&mut self, hook: impl Fn(&WriteHazard) + Send + Sync + 'static) {
+        self.safety.hook = Some(Box::new(hook));
+    }
+
+    /// Run the write `f` once the library is found safe to write to.
+    pub(crate) fn checked<T>(&self, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        if !self.safety.enabled || self.safety.checked.get() {
+            return f();
+        }
+        let hazards = self.write_hazards()?;
+        if let Some(blocking) = hazards.iter().find(|hazard| hazard.is_blocking()) {
+            return Err(hazard_err(blocking.clone()));
+        }
+        if let Some(hook) = &self.safety.hook {
+            hazards.iter().for_each(hook);
+        }
+        self.safety.checked.set(true);
+        let _checked = Checked(&self.safety.checked);
+        f()
+    }
+}
+
+impl Error {
+    /// Why a write through a [`Database`] was refused, if it was.
+    #[must_use]
+    pub fn write_hazard(&self) -> Option<&WriteHazard> {
+        match &self.kind {
+            ErrorKind::Hazard(hazard) => Some(hazard),
+            _ => None,
+        }
+    }
+}
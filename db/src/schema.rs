@@ -0,0 +1,211 @@
+//! The table layout of a beets library, for bootstrapping new databases.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use rusqlite::Connection;
+
+/// Statements creating every table read by this crate.
+///
+/// Column names and types follow what beets itself creates. Unlike beets, each
+/// column carries a default value so that partially-specified rows still bind.
+pub(crate) const CREATE_TABLES: &str = "
+CREATE TABLE IF NOT EXISTS items (
+    id INTEGER PRIMARY KEY,
+    path BLOB NOT NULL DEFAULT '',
+    album_id INTEGER,
+    title TEXT NOT NULL DEFAULT '',
+    artist TEXT NOT NULL DEFAULT '',
+    artist_sort TEXT NOT NULL DEFAULT '',
+    artist_credit TEXT NOT NULL DEFAULT '',
+    album TEXT NOT NULL DEFAULT '',
+    albumartist TEXT NOT NULL DEFAULT '',
+    albumartist_sort TEXT NOT NULL DEFAULT '',
+    albumartist_credit TEXT NOT NULL DEFAULT '',
+    genre TEXT NOT NULL DEFAULT '',
+    lyricist TEXT NOT NULL DEFAULT '',
+    composer TEXT NOT NULL DEFAULT '',
+    composer_sort TEXT NOT NULL DEFAULT '',
+    arranger TEXT NOT NULL DEFAULT '',
+    grouping TEXT NOT NULL DEFAULT '',
+    year INTEGER NOT NULL DEFAULT 0,
+    month INTEGER NOT NULL DEFAULT 0,
+    day INTEGER NOT NULL DEFAULT 0,
+    track INTEGER NOT NULL DEFAULT 0,
+    tracktotal INTEGER NOT NULL DEFAULT 0,
+    disc INTEGER NOT NULL DEFAULT 0,
+    disctotal INTEGER NOT NULL DEFAULT 0,
+    lyrics TEXT NOT NULL DEFAULT '',
+    comments TEXT NOT NULL DEFAULT '',
+    bpm INTEGER NOT NULL DEFAULT 0,
+    comp INTEGER NOT NULL DEFAULT 0,
+    mb_trackid TEXT NOT NULL DEFAULT '',
+    mb_albumid TEXT NOT NULL DEFAULT '',
+    mb_artistid TEXT NOT NULL DEFAULT '',
+    mb_albumartistid TEXT NOT NULL DEFAULT '',
+    mb_releasetrackid TEXT NOT NULL DEFAULT '',
+    albumtype TEXT NOT NULL DEFAULT '',
+    label TEXT NOT NULL DEFAULT '',
+    acoustid_fingerprint TEXT NOT NULL DEFAULT '',
+    acoustid_id TEXT NOT NULL DEFAULT '',
+    mb_releasegroupid TEXT NOT NULL DEFAULT '',
+    asin TEXT NOT NULL DEFAULT '',
+    catalognum TEXT NOT NULL DEFAULT '',
+    script TEXT NOT NULL DEFAULT '',
+    language TEXT NOT NULL DEFAULT '',
+    country TEXT NOT NULL DEFAULT '',
+    albumstatus TEXT NOT NULL DEFAULT '',
+    media TEXT NOT NULL DEFAULT '',
+    albumdisambig TEXT NOT NULL DEFAULT '',
+    disctitle TEXT NOT NULL DEFAULT '',
+    encoder TEXT NOT NULL DEFAULT '',
+    rg_track_gain REAL,
+    rg_track_peak REAL,
+    rg_album_gain REAL,
+    rg_album_peak REAL,
+    r128_track_gain INTEGER,
+    r128_album_gain INTEGER,
+    original_year INTEGER NOT NULL DEFAULT 0,
+    original_month INTEGER NOT NULL DEFAULT 0,
+    original_day INTEGER NOT NULL DEFAULT 0,
+    initial_key TEXT,
+    length REAL NOT NULL DEFAULT 0,
+    bitrate INTEGER NOT NULL DEFAULT 0,
+    bitrate_mode TEXT NOT NULL DEFAULT '',
+    encoder_info TEXT NOT NULL DEFAULT '',
+    encoder_settings TEXT NOT NULL DEFAULT '',
+    format TEXT NOT NULL DEFAULT '',
+    samplerate INTEGER NOT NULL DEFAULT 0,
+    bitdepth INTEGER NOT NULL DEFAULT 0,
+    channels INTEGER NOT NULL DEFAULT 0,
+    mtime REAL NOT NULL DEFAULT 0,
+    added REAL NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS albums (
+    id INTEGER PRIMARY KEY,
+    artpath BLOB,
+    added REAL NOT NULL DEFAULT 0,
+    albumartist TEXT NOT NULL DEFAULT '',
+    albumartist_sort TEXT NOT NULL DEFAULT '',
+    albumartist_credit TEXT NOT NULL DEFAULT '',
+    album TEXT NOT NULL DEFAULT '',
+    genre TEXT NOT NULL DEFAULT '',
+    year INTEGER NOT NULL DEFAULT 0,
+    month INTEGER NOT NULL DEFAULT 0,
+    day INTEGER NOT NULL DEFAULT 0,
+    disctotal INTEGER NOT NULL DEFAULT 0,
+    comp INTEGER NOT NULL DEFAULT 0,
+    mb_albumid TEXT NOT NULL DEFAULT '',
+    mb_albumartistid TEXT NOT NULL DEFAULT '',
+    albumtype TEXT NOT NULL DEFAULT '',
+    label TEXT NOT NULL DEFAULT '',
+    mb_releasegroupid TEXT NOT NULL DEFAULT '',
+    asin TEXT NOT NULL DEFAULT '',
+    catalognum TEXT NOT NULL DEFAULT '',
+    script TEXT NOT NULL DEFAULT '',
+    language TEXT NOT NULL DEFAULT '',
+    country TEXT NOT NULL DEFAULT '',
+    albumstatus TEXT NOT NULL DEFAULT '',
+    albumdisambig TEXT NOT NULL DEFAULT '',
+    rg_album_gain REAL,
+    rg_album_peak REAL,
+    r128_album_gain INTEGER,
+    original_year INTEGER NOT NULL DEFAULT 0,
+    original_month INTEGER NOT NULL DEFAULT 0,
+    original_day INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS item_attributes (
+    id INTEGER PRIMARY KEY,
+    entity_id INTEGER,
+    key TEXT,
+    value TEXT,
+    UNIQUE(entity_id, key) ON CONFLICT REPLACE
+);
+CREATE INDEX IF NOT EXISTS item_attributes_by_entity ON item_attributes (entity_id);
+CREATE TABLE IF NOT EXISTS album_attributes (
+    id INTEGER PRIMARY KEY,
+    entity_id INTEGER,
+    key TEXT,
+    value TEXT,
+    UNIQUE(entity_id, key) ON CONFLICT REPLACE
+);
+CREATE INDEX IF NOT EXISTS album_attributes_by_entity ON album_attributes (entity_id);
+";
+
+/// Columns added by newer beets versions, as `(table, column)`. Libraries from
+/// before these existed read `NULL` in their place.
+const OPTIONAL_COLUMNS: &[(&str, &str)] = &[
+    ("items", "bitrate_mode"),
+    ("items", "encoder_info"),
+    ("items", "encoder_settings"),
+];
+
+/// Whether `column` of `table` is one of the [`OPTIONAL_COLUMNS`].
+pub(crate) fn is_optional(table: &str, column: &str) -> bool {
+    OPTIONAL_COLUMNS.contains(&(table, column))
+}
+
+/// The query for `columns` of `table`, which is `full` unless some of the
+/// [`OPTIONAL_COLUMNS`] are missing from the database.
+///
+/// If the schema cannot be inspected, `full` is returned so that running it
+/// reports the actual problem.
+pub(crate) fn select_sql(
+    conn: &Connection,
+    table: &str,
+    columns: &[&str],
+    full: &'static str,
+) -> Cow<'static, str> {
+    let optional: Vec<&str> = OPTIONAL_COLUMNS
+        .iter()
+        .filter(|(optional_table, _)| *optional_table == table)
+        .map(|(_, column)| *column)
+        .collect();
+    if optional.is_empty() {
+        return Cow::Borrowed(full);
+    }
+    let Ok(present) = table_columns(conn, table) else {
+        return Cow::Borrowed(full);
+    };
+    let missing = |column: &str| optional.contains(&column) && !present.contains(column);
+    if !columns.iter().any(|column| missing(column)) {
+        return Cow::Borrowed(full);
+    }
+
+    let select: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            if missing(column) {
+                format!("NULL AS {column}")
+            } else {
+                (*column).to_string()
+            }
+        })
+        .collect();
+    Cow::Owned(format!("SELECT {},id FROM {table}", select.join(",")))
+}
+
+/// The `columns` of `table` that can be written, leaving out any of the
+/// [`OPTIONAL_COLUMNS`] missing from the database.
+pub(crate) fn writable_columns(
+    conn: &Connection,
+    table: &str,
+    columns: &[&'static str],
+) -> rusqlite::Result<Vec<&'static str>> {
+    let optional = |column: &str| is_optional(table, column);
+    if !columns.iter().any(|column| optional(column)) {
+        return Ok(columns.to_vec());
+    }
+    let present = table_columns(conn, table)?;
+    Ok(columns
+        .iter()
+        .copied()
+        .filter(|column| !optional(column) || present.contains(*column))
+        .collect())
+}
+
+pub(crate) fn table_columns(conn: &Connection, table: &str) -> rusqlite::Result<HashSet<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1)")?;
+    let names = stmt.query_map([table], |row| row.get(0))?;
+    names.collect()
+}
@@ -0,0 +1,183 @@
+use std::convert::TryFrom;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{Connection, OptionalExtension, Row};
+
+use crate::trace::{self, Hooks};
+use crate::{Error, PlayQueue};
+
+/// How many played items a [`Session`] remembers.
+pub const HISTORY_LIMIT: usize = 500;
+
+/// The sidecar table holding sessions. Beets leaves tables it does not know
+/// alone, so the library stays usable by `beet` itself.
+const CREATE_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS berts_sessions (
+    name TEXT PRIMARY KEY,
+    queue TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    elapsed REAL NOT NULL,
+    history TEXT NOT NULL,
+    updated REAL NOT NULL
+)";
+
+/// A named play queue and how far through it a player got, so a player can
+/// resume where it left off.
+///
+/// Sessions are kept in a `berts_sessions` table of the library, made on the
+/// first [`save`](Self::save). Items are kept by id.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Session {
+    pub name: String,
+    pub queue: Vec<u32>,
+    /// The index in `queue` of the current item, which is past the end once
+    /// the queue has finished.
+    pub position: usize,
+    /// How many seconds of the current item have been played.
+    pub elapsed: f64,
+    /// The items played, most recent last, up to [`HISTORY_LIMIT`] of them.
+    pub history: Vec<u32>,
+}
+
+fn join_ids(ids: &[u32]) -> String {
+    ids.iter().map(u32::to_string).collect::<Vec<_>>().join(" ")
+}
+
+fn split_ids(text: &str, column: usize) -> rusqlite::Result<Vec<u32>> {
+    text.split_whitespace()
+        .map(|id| {
+            id.parse().map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    column,
+                    rusqlite::types::Type::Text,
+                    Box::new(err),
+                )
+            })
+        })
+        .collect()
+}
+
+fn has_table(conn: &Connection) -> Result<bool, Error> {
+    let sql = "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'berts_sessions'";
+    let found = conn.query_row(sql, (), |_| Ok(())).optional()?;
+    Ok(found.is_some())
+}
+
+impl Session {
+    /// A session at the start of `queue`.
+    #[must_use]
+    pub fn new(name: impl Into<String>, queue: &PlayQueue) -> Self {
+        Self {
+            name: name.into(),
+            queue: queue.entries.iter().map(|entry| entry.item_id).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// The id of the item to play now, unless the queue has finished.
+    #[must_use]
+    pub fn current(&self) -> Option<u32> {
+        self.queue.get(self.position).copied()
+    }
+
+    /// Move on to the next item, adding the current one to the history.
+    pub fn advance(&mut self) {
+        if let Some(item_id) = self.current() {
+            self.history.push(item_id);
+            let excess = self.history.len().saturating_sub(HISTORY_LIMIT);
+            self.history.drain(..excess);
+            self.position += 1;
+        }
+        self.elapsed = 0.0;
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let position: i64 = row.get(2)?;
+        Ok(Self {
+            name: row.get(0)?,
+            queue: split_ids(&row.get::<_, String>(1)?, 1)?,
+            position: usize::try_from(position).unwrap_or(0),
+            elapsed: row.get(3)?,
+            history: split_ids(&row.get::<_, String>(4)?, 4)?,
+        })
+    }
+
+    /// Store the session, replacing any other of the same name.
+    ///
+    /// # Errors
+    /// Returns an error if the connection is read-only
+    pub fn save(&self, conn: &Connection) -> Result<(), Error> {
+        let updated = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |since| since.as_secs_f64());
+        let position = i64::try_from(self.position).unwrap_or(i64::MAX);
+        conn.execute(CREATE_TABLE, ()).map_err(Error::write)?;
+        conn.execute(
+            "INSERT OR REPLACE INTO berts_sessions
+                (name, queue, position, elapsed, history, updated)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (
+                &self.name,
+                join_ids(&self.queue),
+                position,
+                self.elapsed,
+                join_ids(&self.history),
+                updated,
+            ),
+        )
+        .map_err(Error::write)?;
+        Ok(())
+    }
+
+    /// The session called `name`, if one was saved.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn load(conn: &Connection, name: &str) -> Result<Option<Self>, Error> {
+        if !has_table(conn)? {
+            return Ok(None);
+        }
+        let sessions = trace::query_all(
+            conn,
+            "berts_sessions",
+            "SELECT name, queue, position, elapsed, history FROM berts_sessions WHERE name = ?1",
+            [name],
+            |row| Ok(Self::from_row(row)?),
+            Hooks::default(),
+        )?;
+        Ok(sessions.into_iter().next())
+    }
+
+    /// The names of the saved sessions, most recently saved first.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn list(conn: &Connection) -> Result<Vec<String>, Error> {
+        if !has_table(conn)? {
+            return Ok(vec![]);
+        }
+        trace::query_all(
+            conn,
+            "berts_sessions",
+            "SELECT name FROM berts_sessions ORDER BY updated DESC, name",
+            (),
+            |row| Ok(row.get(0)?),
+            Hooks::default(),
+        )
+    }
+
+    /// Forget the session called `name`, returning whether it was saved.
+    ///
+    /// # Errors
+    /// Returns an error if the connection is read-only
+    pub fn delete(conn: &Connection, name: &str) -> Result<bool, Error> {
+        if !has_table(conn)? {
+            return Ok(false);
+        }
+        let deleted = conn
+            .execute("DELETE FROM berts_sessions WHERE name = ?1", [name])
+            .map_err(Error::write)?;
+        Ok(deleted > 0)
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{CamelotKey, Item};
+
+/// Assembles a crate of items to play one after the other, within a tempo
+/// range, mixing harmonically and lasting about as long as a set:
+///
+/// ```
+/// # use beet_db::{CrateBuilder, Library};
+/// # let library = Library::default();
+/// let set = CrateBuilder::new()
+///     .bpm(120.0, 128.0)
+///     .harmonic(true)
+///     .duration(60.0 * 60.0)
+///     .build(&library.items);
+/// ```
+///
+/// Items are played from the slowest up, each the slowest of those left that
+/// fit the constraints after the one before, so the tempo builds through the
+/// crate.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CrateBuilder {
+    bpm: Option<(f64, f64)>,
+    key: Option<CamelotKey>,
+    harmonic: bool,
+    duration: Option<f64>,
+}
+
+impl CrateBuilder {
+    /// A builder taking every item, in order of tempo.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only take items with a `bpm` from `min` to `max`, inclusive.
+    pub fn bpm(&mut self, min: f64, max: f64) -> &mut Self {
+        self.bpm = Some((min, max));
+        self
+    }
+
+    /// Only take items in `key` or a key compatible with it, such as the key
+    /// of the track playing before the crate.
+    pub fn key(&mut self, key: CamelotKey) -> &mut Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Only follow each item with one in a key compatible with its own, so
+    /// the whole crate mixes harmonically.
+    pub fn harmonic(&mut self, harmonic: bool) -> &mut Self {
+        self.harmonic = harmonic;
+        self
+    }
+
+    /// Take items until the crate lasts this many seconds, skipping those
+    /// that would run over.
+    pub fn duration(&mut self, seconds: f64) -> &mut Self {
+        self.duration = Some(seconds);
+        self
+    }
+
+    /// Whether `item` may be in the crate at all.
+    fn admits(&self, item: &Item) -> bool {
+        if let Some((min, max)) = self.bpm {
+            if item.missing_bpm() || item.bpm < min || item.bpm > max {
+                return false;
+            }
+        }
+        match (self.key, item.camelot_key()) {
+            (Some(key), Some(own)) => key.is_compatible(own),
+            (Some(_), None) => false,
+            // an item without a key cannot be mixed harmonically
+            (None, own) => !self.harmonic || own.is_some(),
+        }
+    }
+
+    /// The crate, in the order to play it.
+    #[must_use]
+    pub fn build<'a>(&self, items: impl IntoIterator<Item = &'a Item>) -> Vec<&'a Item> {
+        let mut candidates: Vec<&Item> =
+            items.into_iter().filter(|item| self.admits(item)).collect();
+        candidates.sort_by(|a, b| a.bpm.total_cmp(&b.bpm));
+
+        let budget = self.duration.unwrap_or(f64::INFINITY);
+        let mut length = 0.0;
+        let mut crate_items: Vec<&Item> = vec![];
+        while let Some(next) = candidates.iter().position(|item| {
+            let follows = match crate_items.last() {
+                Some(last) if self.harmonic => {
+                    matches!((last.camelot_key(), item.camelot_key()),
+                        (Some(last), Some(key)) if last.is_compatible(key))
+                }
+                _ => true,
+            };
+            follows && length + item.length <= budget
+        }) {
+            let item = candidates.remove(next);
+            length += item.length;
+            crate_items.push(item);
+        }
+        crate_items
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::FederatedLibrary;
+use crate::{Album, Item, Library};
+
+/// How many albums [`LibraryStats::recently_added`] lists.
+pub const RECENTLY_ADDED: usize = 10;
+
+/// Totals for a whole library, as printed by `berts stats`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct LibraryStats {
+    pub items: u32,
+    pub albums: u32,
+    /// The length of every item, in seconds.
+    pub length: f64,
+    /// How many items have each genre, leaving out those with none.
+    pub genres: BTreeMap<String, u32>,
+    /// How many items are in each format, e.g. `FLAC`.
+    pub formats: BTreeMap<String, u32>,
+    /// How many items are from each year, leaving out unknown years.
+    pub years: BTreeMap<u32, u32>,
+    /// The albums added last, newest first, up to [`RECENTLY_ADDED`].
+    pub recently_added: Vec<Album>,
+}
+
+fn count<K: Ord>(counts: &mut BTreeMap<K, u32>, key: K) {
+    *counts.entry(key).or_default() += 1;
+}
+
+impl LibraryStats {
+    /// Sum up `albums` and `items`, which may come from several libraries.
+    #[must_use]
+    pub fn new<'a>(
+        albums: impl IntoIterator<Item = &'a Album>,
+        items: impl IntoIterator<Item = &'a Item>,
+    ) -> Self {
+        let mut stats = Self::default();
+        let mut albums: Vec<&Album> = albums.into_iter().collect();
+        stats.albums = albums.len().try_into().unwrap_or(u32::MAX);
+        albums.sort_by(|a, b| b.added.total_cmp(&a.added));
+        stats.recently_added = albums.into_iter().take(RECENTLY_ADDED).cloned().collect();
+
+        for item in items {
+            stats.items += 1;
+            stats.length += item.length;
+            if !item.genre.is_empty() {
+                count(&mut stats.genres, item.genre.clone());
+            }
+            if !item.format.is_empty() {
+                count(&mut stats.formats, item.format.clone());
+            }
+            if item.year != 0 {
+                count(&mut stats.years, item.year);
+            }
+        }
+        stats
+    }
+
+    /// The entries of `counts` with the most items first, the first in
+    /// order on a tie.
+    #[must_use]
+    pub fn ranked<K: Clone + Ord>(counts: &BTreeMap<K, u32>) -> Vec<(K, u32)> {
+        let mut ranked: Vec<_> = counts
+            .iter()
+            .map(|(key, count)| (key.clone(), *count))
+            .collect();
+        // stable, so ties stay in order
+        ranked.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        ranked
+    }
+}
+
+impl Library {
+    /// [`LibraryStats`] for the whole library.
+    #[must_use]
+    pub fn stats(&self) -> LibraryStats {
+        LibraryStats::new(&self.albums, &self.items)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FederatedLibrary {
+    /// [`LibraryStats`] for every library together.
+    #[must_use]
+    pub fn stats(&self) -> LibraryStats {
+        LibraryStats::new(
+            self.albums.iter().map(|album| &album.entry),
+            self.items.iter().map(|item| &item.entry),
+        )
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use rusqlite::{Connection, Row};
+
+use crate::trace::{self, Hooks};
+use crate::{Access, Album, Database, Error, Item};
+
+/// How many rows [`RowIter`] reads per query.
+const ITER_PAGE: u32 = 512;
+
+/// Rows read on a dedicated thread and handed over through a bounded channel.
+///
+/// The reader stays at most `capacity` rows ahead of the consumer, so rows
+/// can be processed while SQLite is still reading without buffering the
+/// whole table. Dropping the stream stops the reader at the next row.
+#[derive(Debug)]
+pub struct RowStream<T> {
+    rows: Receiver<Result<T, Error>>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl<T> Iterator for RowStream<T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.rows.recv().ok();
+        if row.is_none() {
+            // the reader is done, pass on its panic if it had one
+            if let Some(Err(panic)) = self.reader.take().map(JoinHandle::join) {
+                std::panic::resume_unwind(panic);
+            }
+        }
+        row
+    }
+}
+
+fn send_rows<T, A: Access>(
+    db: &Database<A>,
+    sql: &str,
+    from_row: fn(&Row) -> Result<T, Error>,
+    sender: &SyncSender<Result<T, Error>>,
+) -> Result<(), Error> {
+    let mut stmt = db.connection().prepare(sql)?;
+    let rows = stmt.query_and_then((), from_row).map_err(Error::query)?;
+    for row in rows {
+        if sender.send(row).is_err() {
+            // the stream was dropped
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn spawn<T: Send + 'static, A: Access + 'static>(
+    db: Database<A>,
+    sql: String,
+    from_row: fn(&Row) -> Result<T, Error>,
+    capacity: usize,
+) -> RowStream<T> {
+    let (sender, rows) = mpsc::sync_channel(capacity);
+    let reader = thread::spawn(move || {
+        if let Err(err) = send_rows(&db, &sql, from_row, &sender) {
+            // nobody may be listening anymore, which is fine
+            let _ = sender.send(Err(err));
+        }
+    });
+    RowStream {
+        rows,
+        reader: Some(reader),
+    }
+}
+
+/// Rows read lazily from a borrowed connection, in order of id, as given by
+/// [`Item::iter`] and [`Album::iter`].
+///
+/// Rows are read a page at a time, each page starting after the last id
+/// seen, so only one page is held in memory and stopping early skips the
+/// rest of the table. Iteration ends after the first error.
+pub struct RowIter<'conn, T> {
+    conn: &'conn Connection,
+    table: &'static str,
+    sql: String,
+    from_row: fn(&Row) -> Result<T, Error>,
+    id: fn(&T) -> u32,
+    page: VecDeque<T>,
+    /// The last id read, `None` before the first page.
+    last_id: Option<u32>,
+    done: bool,
+}
+
+impl<'conn, T> RowIter<'conn, T> {
+    pub(crate) fn new(
+        conn: &'conn Connection,
+        table: &'static str,
+        select_sql: &str,
+        from_row: fn(&Row) -> Result<T, Error>,
+        id: fn(&T) -> u32,
+    ) -> Self {
+        Self {
+            conn,
+            table,
+            sql: format!("{select_sql} WHERE id > ?1 ORDER BY id LIMIT ?2"),
+            from_row,
+            id,
+            page: VecDeque::new(),
+            last_id: None,
+            done: false,
+        }
+    }
+
+    fn read_page(&mut self) -> Result<(), Error> {
+        // -1 comes before every id
+        let after = self.last_id.map_or(-1, i64::from);
+        let page = trace::query_all(
+            self.conn,
+            self.table,
+            &self.sql,
+            (after, ITER_PAGE),
+            self.from_row,
+            Hooks::default(),
+        )?;
+        if page.len() < ITER_PAGE as usize {
+            self.done = true;
+        }
+        self.last_id = page.last().map(self.id).or(self.last_id);
+        self.page = page.into();
+        Ok(())
+    }
+}
+
+impl<T> Iterator for RowIter<'_, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.page.is_empty() && !self.done {
+            if let Err(err) = self.read_page() {
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+        self.page.pop_front().map(Ok)
+    }
+}
+
+impl<T> std::fmt::Debug for RowIter<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RowIter")
+            .field("table", &self.table)
+            .field("buffered", &self.page.len())
+            .field("last_id", &self.last_id)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+impl<A: Access + 'static> Database<A> {
+    /// Read every [`Item`] on a dedicated thread, at most `capacity` rows
+    /// ahead of the returned stream.
+    #[must_use]
+    pub fn stream_items(self, capacity: usize) -> RowStream<Item> {
+        let sql = Item::select_sql(self.connection()).into_owned();
+        spawn(self, sql, Item::from_row, capacity)
+    }
+
+    /// Like [`stream_items`](Self::stream_items), ordered by album, disc and
+    /// track so each album's items arrive together, in playing order.
+    ///
+    /// Items without an album come last. The order is applied by SQLite, so
+    /// nothing is sorted in memory; [`create_indexes`](Self::create_indexes)
+    /// speeds this up on large libraries.
+    #[must_use]
+    pub fn stream_items_by_album(self, capacity: usize) -> RowStream<Item> {
+        let sql = format!(
+            "{} ORDER BY album_id IS NULL, album_id, disc, track, id",
+            Item::select_sql(self.connection())
+        );
+        spawn(self, sql, Item::from_row, capacity)
+    }
+
+    /// Read every [`Album`] on a dedicated thread, at most `capacity` rows
+    /// ahead of the returned stream.
+    #[must_use]
+    pub fn stream_albums(self, capacity: usize) -> RowStream<Album> {
+        let sql = Album::select_sql(self.connection()).into_owned();
+        spawn(self, sql, Album::from_row, capacity)
+    }
+}
@@ -0,0 +1,584 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind as IoErrorKind};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use rusqlite::{Connection, OptionalExtension, Row};
+
+use crate::copy_export::{copy_file, same_file};
+use crate::trace::{self, Hooks};
+use crate::undo::{attach_existing_sidecar, attach_sidecar};
+use crate::write::atomically;
+use crate::{
+    export_paths, Access, Attribute, CopyExportOptions, Database, Error, Item, PathTemplate,
+    ReadWrite,
+};
+
+/// The file in the destination of a [`SyncProfile`] listing the files it
+/// copied there, so those of items no longer synced can be deleted and
+/// anything else left alone.
+pub const SYNC_MANIFEST: &str = ".berts-sync";
+
+/// The table holding sync profiles, in the sidecar beside the library like
+/// the undo journal, so saving one never writes to the library itself.
+const CREATE_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS berts_undo.sync_profiles (
+    name TEXT PRIMARY KEY,
+    query TEXT NOT NULL,
+    destination TEXT NOT NULL,
+    formats TEXT NOT NULL,
+    size_cap INTEGER,
+    template TEXT NOT NULL,
+    ascii INTEGER NOT NULL,
+    priority TEXT NOT NULL DEFAULT 'order'
+)";
+
+/// A named set of items to keep on a portable player or USB stick, synced
+/// with [`SyncProfile::plan`] and [`SyncPlan::run`].
+///
+/// Profiles are kept in the sidecar beside the library, `library.db.undo`,
+/// made on the first [`save`](Self::save).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyncProfile {
+    pub name: String,
+    /// The beets query choosing the items to sync, empty for every item.
+    /// This crate stores it as it is, for the caller to run.
+    pub query: String,
+    /// Where the player or stick is mounted.
+    pub destination: PathBuf,
+    /// The formats the player can play, as beets names them, e.g. `MP3` and
+    /// `AAC`. Items in others are left out; empty for any format.
+    pub formats: Vec<String>,
+    /// The most bytes to keep at the destination. Items are taken by
+    /// [`priority`](Self::priority), leaving out any that would not fit.
+    pub size_cap: Option<u64>,
+    /// Which items to keep first when they do not all fit under the size cap.
+    pub priority: SyncPriority,
+    /// How the files are named. See [`copy_export`](crate::copy_export).
+    pub template: PathTemplate,
+    /// Spell names in ASCII alone.
+    pub ascii: bool,
+}
+
+/// Why an item chosen by a [`SyncProfile`] is not synced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncExclusion {
+    /// Its format is not one of the profile's.
+    Format,
+    /// Its file cannot be read.
+    Missing,
+    /// Its file, of `size` bytes, did not fit in the `free` bytes left under
+    /// the size cap by the items ranked above it.
+    SizeCap { size: u64, free: u64 },
+}
+
+impl fmt::Display for SyncExclusion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Format => "format",
+            Self::Missing => "missing",
+            Self::SizeCap { .. } => "size-cap",
+        })
+    }
+}
+
+/// Which items a [`SyncProfile`] keeps first when they do not all fit under
+/// its size cap. Ties, and every item for [`Order`](Self::Order), are kept in
+/// the order of the query.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncPriority {
+    /// The order of the query.
+    #[default]
+    Order,
+    /// The most recently added.
+    Recent,
+    /// The highest `rating` attribute, as kept by the `mpdstats` plugin.
+    Rating,
+    /// The highest `play_count` attribute, as kept by the `mpdstats` and
+    /// `lastimport` plugins.
+    Plays,
+}
+
+impl SyncPriority {
+    /// How highly this ranks `item`, with its flexible `attributes`. Higher
+    /// scores are kept first.
+    #[must_use]
+    pub fn score(self, item: &Item, attributes: &[Attribute]) -> f64 {
+        let attribute = |key: &str| {
+            attributes
+                .iter()
+                .find(|attribute| attribute.key == key)
+                .and_then(|attribute| attribute.value.trim().parse().ok())
+                .unwrap_or(0.0)
+        };
+        match self {
+            Self::Order => 0.0,
+            Self::Recent => item.added,
+            Self::Rating => attribute("rating"),
+            Self::Plays => attribute("play_count"),
+        }
+    }
+
+    /// Whether ranking by this reads the flexible attributes of items.
+    #[must_use]
+    pub fn needs_attributes(self) -> bool {
+        matches!(self, Self::Rating | Self::Plays)
+    }
+}
+
+impl fmt::Display for SyncPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Order => "order",
+            Self::Recent => "recent",
+            Self::Rating => "rating",
+            Self::Plays => "plays",
+        })
+    }
+}
+
+impl FromStr for SyncPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "order" => Ok(Self::Order),
+            "recent" => Ok(Self::Recent),
+            "rating" => Ok(Self::Rating),
+            "plays" => Ok(Self::Plays),
+            _ => Err(format!("unknown sync priority `{s}`")),
+        }
+    }
+}
+
+/// What [`SyncProfile::plan`] would change at the destination.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyncPlan<'a> {
+    pub destination: PathBuf,
+    /// Items to copy, with where they go under the destination.
+    pub copy: Vec<(&'a Item, PathBuf)>,
+    /// Items already at the destination, with the same size.
+    pub keep: Vec<(&'a Item, PathBuf)>,
+    /// Files synced before whose items are no longer chosen, under the
+    /// destination.
+    pub delete: Vec<PathBuf>,
+    /// Items chosen but left out, and why.
+    pub excluded: Vec<(&'a Item, SyncExclusion)>,
+    /// How many bytes the items copied and kept take up.
+    pub bytes: u64,
+}
+
+fn has_table(conn: &Connection, schema: &str, table: &str) -> Result<bool, Error> {
+    let sql = format!("SELECT 1 FROM {schema}.sqlite_master WHERE type = 'table' AND name = ?1");
+    let found = conn.query_row(&sql, [table], |_| Ok(())).optional()?;
+    Ok(found.is_some())
+}
+
+/// How to select the saved profiles, or `None` if none were ever saved.
+///
+/// Earlier versions kept them in a `berts_sync_profiles` table of the
+/// library itself, which is read until a profile is saved or deleted and
+/// copies them to the sidecar. Profiles saved before they had a priority
+/// rank in the order of the query.
+fn profiles_select(conn: &Connection) -> Result<Option<String>, Error> {
+    let (schema, table) =
+        if attach_existing_sidecar(conn)? && has_table(conn, "berts_undo", "sync_profiles")? {
+            ("berts_undo", "sync_profiles")
+        } else if has_table(conn, "main", "berts_sync_profiles")? {
+            ("main", "berts_sync_profiles")
+        } else {
+            return Ok(None);
+        };
+    let priority = conn
+        .query_row(
+            "SELECT 1 FROM pragma_table_info(?1, ?2) WHERE name = 'priority'",
+            [table, schema],
+            |_| Ok(()),
+        )
+        .optional()?
+        .map_or("'order'", |()| "priority");
+    Ok(Some(format!(
+        "SELECT name, query, destination, formats, size_cap, template, ascii, {priority}
+        FROM {schema}.{table}"
+    )))
+}
+
+/// Make the sidecar table for writing profiles, copying in those saved in
+/// the library by earlier versions the first time.
+fn create_table(conn: &Connection) -> Result<(), Error> {
+    attach_sidecar(conn)?;
+    if has_table(conn, "berts_undo", "sync_profiles")? {
+        return Ok(());
+    }
+    let legacy = profiles_select(conn)?;
+    conn.execute(CREATE_TABLE, ()).map_err(Error::write)?;
+    if let Some(select) = legacy {
+        conn.execute(
+            &format!("INSERT INTO berts_undo.sync_profiles {select}"),
+            (),
+        )
+        .map_err(Error::write)?;
+    }
+    Ok(())
+}
+
+/// The paths listed in the manifest of `destination`, none if there is
+/// none yet. Any that would reach outside it are dropped, so a tampered
+/// manifest cannot delete other files.
+fn read_manifest(destination: &Path) -> io::Result<Vec<PathBuf>> {
+    match fs::read_to_string(destination.join(SYNC_MANIFEST)) {
+        Ok(manifest) => Ok(manifest
+            .lines()
+            .map(PathBuf::from)
+            .filter(|path| {
+                path.components().next().is_some()
+                    && path
+                        .components()
+                        .all(|component| matches!(component, Component::Normal(_)))
+            })
+            .collect()),
+        Err(err) if err.kind() == IoErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(err),
+    }
+}
+
+/// Remove the directories from `path`'s parent up to `root` that are left
+/// empty.
+fn remove_empty_parents(path: &Path, root: &Path) {
+    for dir in path.ancestors().skip(1) {
+        if dir == root || !dir.starts_with(root) || fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+impl SyncProfile {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let formats: String = row.get(3)?;
+        let size_cap: Option<i64> = row.get(4)?;
+        let template: String = row.get(5)?;
+        Ok(Self {
+            name: row.get(0)?,
+            query: row.get(1)?,
+            destination: PathBuf::from(row.get::<_, String>(2)?),
+            formats: formats.split_whitespace().map(String::from).collect(),
+            size_cap: size_cap.and_then(|cap| u64::try_from(cap).ok()),
+            template: template.parse().map_err(|err: String| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    5,
+                    rusqlite::types::Type::Text,
+                    err.into(),
+                )
+            })?,
+            ascii: row.get(6)?,
+            priority: row.get::<_, String>(7)?.parse().map_err(|err: String| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    7,
+                    rusqlite::types::Type::Text,
+                    err.into(),
+                )
+            })?,
+        })
+    }
+
+    /// Store the profile, replacing any other of the same name.
+    ///
+    /// # Errors
+    /// Returns an error if the sidecar cannot be opened or written
+    pub fn save(&self, db: &Database<ReadWrite>) -> Result<(), Error> {
+        let conn = db.connection();
+        let size_cap = self
+            .size_cap
+            .map(|cap| i64::try_from(cap).unwrap_or(i64::MAX));
+        atomically(conn, || {
+            create_table(conn)?;
+            conn.execute(
+                "INSERT OR REPLACE INTO berts_undo.sync_profiles
+                    (name, query, destination, formats, size_cap, template, ascii, priority)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                (
+                    &self.name,
+                    &self.query,
+                    self.destination.to_string_lossy(),
+                    self.formats.join(" "),
+                    size_cap,
+                    self.template.to_string(),
+                    self.ascii,
+                    self.priority.to_string(),
+                ),
+            )
+            .map_err(Error::write)?;
+            Ok(())
+        })
+    }
+
+    /// The profile called `name`, if one was saved.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn load<A: Access>(db: &Database<A>, name: &str) -> Result<Option<Self>, Error> {
+        let conn = db.connection();
+        let Some(select) = profiles_select(conn)? else {
+            return Ok(None);
+        };
+        let profiles = trace::query_all(
+            conn,
+            "sync_profiles",
+            &format!("{select} WHERE name = ?1"),
+            [name],
+            |row| Ok(Self::from_row(row)?),
+            Hooks::default(),
+        )?;
+        Ok(profiles.into_iter().next())
+    }
+
+    /// Every saved profile, in order of name.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL query fails
+    pub fn list<A: Access>(db: &Database<A>) -> Result<Vec<Self>, Error> {
+        let conn = db.connection();
+        let Some(select) = profiles_select(conn)? else {
+            return Ok(vec![]);
+        };
+        trace::query_all(
+            conn,
+            "sync_profiles",
+            &format!("{select} ORDER BY name"),
+            (),
+            |row| Ok(Self::from_row(row)?),
+            Hooks::default(),
+        )
+    }
+
+    /// Forget the profile called `name`, returning whether it was saved. The
+    /// files it synced are left where they are.
+    ///
+    /// # Errors
+    /// Returns an error if the sidecar cannot be opened or written
+    pub fn delete(db: &Database<ReadWrite>, name: &str) -> Result<bool, Error> {
+        let conn = db.connection();
+        if profiles_select(conn)?.is_none() {
+            return Ok(false);
+        }
+        atomically(conn, || {
+            create_table(conn)?;
+            let deleted = conn
+                .execute(
+                    "DELETE FROM berts_undo.sync_profiles WHERE name = ?1",
+                    [name],
+                )
+                .map_err(Error::write)?;
+            Ok(deleted > 0)
+        })
+    }
+
+    /// Make the destination, which must be a directory, one to sync to by
+    /// writing an empty manifest there unless it has one. Planning a sync
+    /// to a destination without one fails, so an empty mount point of a
+    /// player that is not mounted is not filled instead.
+    ///
+    /// # Errors
+    /// Returns an error if the destination is not a directory, or the
+    /// manifest cannot be written
+    pub fn init(&self) -> io::Result<()> {
+        if !self.destination.is_dir() {
+            return Err(io::Error::new(
+                IoErrorKind::NotFound,
+                format!(
+                    "sync destination {} is not a directory",
+                    self.destination.display()
+                ),
+            ));
+        }
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(self.destination.join(SYNC_MANIFEST))
+        {
+            Err(err) if err.kind() != IoErrorKind::AlreadyExists => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn options(&self) -> CopyExportOptions {
+        CopyExportOptions {
+            template: self.template.clone(),
+            ascii: self.ascii,
+        }
+    }
+
+    /// What syncing `items`, those matching the [`query`](Self::query), would
+    /// copy, keep and delete, reading the sizes of their files and the
+    /// destination's manifest. Items are ranked for the size cap without
+    /// their flexible attributes; see
+    /// [`plan_with_attributes`](Self::plan_with_attributes).
+    ///
+    /// Only files listed in the manifest, which [`SyncPlan::run`] writes, are
+    /// ever deleted, so the destination can hold other files too.
+    ///
+    /// # Errors
+    /// Returns an error if the destination has no manifest, e.g. when the
+    /// player is not mounted, until it is [initialized](Self::init), or the
+    /// manifest cannot be read
+    pub fn plan<'a>(&self, items: impl IntoIterator<Item = &'a Item>) -> io::Result<SyncPlan<'a>> {
+        self.plan_with_attributes(items.into_iter().map(|item| (item, &[][..])))
+    }
+
+    /// Like [`plan`](Self::plan), with the flexible attributes of each item
+    /// to rank it by for the size cap, e.g. its `rating`.
+    ///
+    /// The items that fit are found greedily: from the highest
+    /// [`priority`](Self::priority) down, each is kept if it fits in what is
+    /// left, so smaller items further down fill the space a large one could
+    /// not. The items kept are synced in the order given.
+    ///
+    /// # Errors
+    /// Returns an error if the destination has no manifest, e.g. when the
+    /// player is not mounted, until it is [initialized](Self::init), or the
+    /// manifest cannot be read
+    pub fn plan_with_attributes<'a, 'b>(
+        &self,
+        items: impl IntoIterator<Item = (&'a Item, &'b [Attribute])>,
+    ) -> io::Result<SyncPlan<'a>> {
+        // the mount point of a player that is not mounted is a directory
+        // too, but without the manifest of an earlier sync
+        if !self.destination.join(SYNC_MANIFEST).is_file() {
+            return Err(io::Error::new(
+                IoErrorKind::NotFound,
+                format!(
+                    "sync destination {} has no {SYNC_MANIFEST}, is the player mounted? \
+                    Initialize it to sync there for the first time",
+                    self.destination.display()
+                ),
+            ));
+        }
+        let mut plan = SyncPlan {
+            destination: self.destination.clone(),
+            copy: vec![],
+            keep: vec![],
+            delete: vec![],
+            excluded: vec![],
+            bytes: 0,
+        };
+        // each item with the size of its file and its score, or why it is
+        // left out before any are ranked
+        let mut candidates = vec![];
+        for (item, attributes) in items {
+            if !self.formats.is_empty()
+                && !self
+                    .formats
+                    .iter()
+                    .any(|format| format.eq_ignore_ascii_case(&item.format))
+            {
+                candidates.push((item, Err(SyncExclusion::Format)));
+                continue;
+            }
+            let Ok(size) = fs::metadata(&item.path).map(|metadata| metadata.len()) else {
+                candidates.push((item, Err(SyncExclusion::Missing)));
+                continue;
+            };
+            candidates.push((item, Ok((size, self.priority.score(item, attributes)))));
+        }
+        if let Some(cap) = self.size_cap {
+            let mut ranked: Vec<(usize, u64, f64)> = candidates
+                .iter()
+                .enumerate()
+                .filter_map(|(index, (_, candidate))| {
+                    let (size, score) = candidate.ok()?;
+                    Some((index, size, score))
+                })
+                .collect();
+            // stable, so ties stay in the order given
+            ranked.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+            let mut free = cap;
+            for (index, size, _) in ranked {
+                if size <= free {
+                    free -= size;
+                } else {
+                    candidates[index].1 = Err(SyncExclusion::SizeCap { size, free });
+                }
+            }
+        }
+        let mut chosen = vec![];
+        for (item, candidate) in candidates {
+            match candidate {
+                Ok((size, _)) => {
+                    plan.bytes += size;
+                    chosen.push((item, size));
+                }
+                Err(why) => plan.excluded.push((item, why)),
+            }
+        }
+
+        let paths = export_paths(chosen.into_iter().map(|(item, _)| item), &self.options());
+        // the names of most players' filesystems ignore case
+        let synced: HashSet<String> = paths
+            .iter()
+            .map(|(_, path)| path.to_string_lossy().to_lowercase())
+            .collect();
+        for (item, path) in paths {
+            let target = self.destination.join(&path);
+            let up_to_date = match (fs::metadata(&item.path), fs::metadata(target)) {
+                (Ok(source), Ok(existing)) => same_file(&source, &existing),
+                _ => false,
+            };
+            if up_to_date {
+                plan.keep.push((item, path));
+            } else {
+                plan.copy.push((item, path));
+            }
+        }
+        plan.delete = read_manifest(&self.destination)?
+            .into_iter()
+            .filter(|path| !synced.contains(&path.to_string_lossy().to_lowercase()))
+            .collect();
+        Ok(plan)
+    }
+}
+
+impl SyncPlan<'_> {
+    /// Copy and delete the files, prune the directories left empty and
+    /// write the new manifest.
+    ///
+    /// # Errors
+    /// Returns an error if a file cannot be copied or deleted, after the
+    /// changes before it. The manifest is written anyway, listing the files
+    /// copied so far and those not yet deleted, so running the profile again
+    /// picks up where this left off.
+    pub fn run(&self) -> io::Result<()> {
+        let mut deleted = 0;
+        let mut copied = 0;
+        let result = (|| {
+            for path in &self.delete {
+                let target = self.destination.join(path);
+                match fs::remove_file(&target) {
+                    Err(err) if err.kind() != IoErrorKind::NotFound => return Err(err),
+                    _ => remove_empty_parents(&target, &self.destination),
+                }
+                deleted += 1;
+            }
+            for (item, path) in &self.copy {
+                // counted first, as a failed copy may leave part of a file
+                copied += 1;
+                copy_file(&item.path, &self.destination.join(path))?;
+            }
+            Ok(())
+        })();
+        let mut manifest = String::new();
+        let paths = self
+            .keep
+            .iter()
+            .chain(&self.copy[..copied])
+            .map(|(_, path)| path);
+        for path in paths.chain(&self.delete[deleted..]) {
+            manifest.push_str(&path.to_string_lossy());
+            manifest.push('\n');
+        }
+        let written = fs::write(self.destination.join(SYNC_MANIFEST), manifest);
+        result.and(written)
+    }
+}
@@ -17,3 +17,3631 @@ fn read_all_tracks() -> Result<(), Error> {
     Item::read_all(&conn)?;
     Ok(())
 }
+
+#[test]
+fn read_all_in_memory() -> Result<(), Error> {
+    let db = Database::open_in_memory()?;
+    assert_eq!(db.read_all()?, (vec![], vec![]));
+
+    db.connection().execute(
+        "INSERT INTO albums (id, album, albumartist) VALUES (1, 'Geogaddi', 'Boards of Canada')",
+        (),
+    )?;
+    db.connection().execute(
+        "INSERT INTO items (id, path, album_id, title) VALUES (1, '/music/a.flac', 1, 'Music Is Math')",
+        (),
+    )?;
+
+    let (albums, items) = db.read_all()?;
+    assert_eq!(albums.len(), 1);
+    assert_eq!(albums[0].album, "Geogaddi");
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].album_id, Some(1));
+    assert_eq!(items[0].path, PathBuf::from("/music/a.flac"));
+    Ok(())
+}
+
+#[test]
+fn read_all_from_existing_connection() -> Result<(), Error> {
+    let conn = Connection::open_in_memory()?;
+    conn.execute("ATTACH DATABASE 'tests/test.db' AS beets", ())?;
+    conn.execute_batch(
+        "CREATE TEMP VIEW albums AS SELECT * FROM beets.albums;
+        CREATE TEMP VIEW items AS SELECT * FROM beets.items;",
+    )?;
+    assert_eq!(read_all_from(&conn)?, read_all("tests/test.db")?);
+    Ok(())
+}
+
+#[cfg(feature = "proptest")]
+proptest::proptest! {
+    #[test]
+    fn arbitrary_item_invariants(item: Item) {
+        proptest::prop_assert!(item.id > 0);
+        proptest::prop_assert!(item.track <= item.tracktotal);
+        proptest::prop_assert!(item.disc <= item.disctotal);
+        proptest::prop_assert!(item.month <= 12 && (item.month > 0 || item.day == 0));
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn beets_export_item() -> Result<(), serde_json::Error> {
+    use beets_export::BeetsExport;
+
+    let json = r#"{
+        "id": "12", "path": "/music/a.flac", "album_id": "3",
+        "title": "Music Is Math", "artist": "Boards of Canada",
+        "year": "2002", "track": "07", "tracktotal": "23",
+        "length": "5:21", "bitrate": "1010kbps", "samplerate": "44kHz",
+        "comp": "False", "bpm": "", "rg_track_gain": "-7.5",
+        "added": "2019-01-01 12:00:00"
+    }"#;
+    let BeetsExport(item) = serde_json::from_str::<BeetsExport<Item>>(json)?;
+    assert_eq!(item.id, 12);
+    assert_eq!(item.album_id, Some(3));
+    assert_eq!((item.year, item.track, item.tracktotal), (2002, 7, 23));
+    assert!((item.length - 321.0).abs() < f64::EPSILON);
+    assert_eq!((item.bitrate, item.samplerate), (1_010_000, 44_000));
+    assert_eq!(item.rg_track_gain, Some(-7.5));
+
+    let exported = serde_json::to_value(BeetsExport(item.clone()))?;
+    assert_eq!(exported["track"], "07");
+    assert_eq!(exported["length"], "5:21");
+    assert_eq!(exported["comp"], "False");
+    assert_eq!(
+        serde_json::from_value::<BeetsExport<Item>>(exported)?,
+        BeetsExport(item)
+    );
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn library_from_beets_export() -> Result<(), serde_json::Error> {
+    let albums = r#"[{"id": "1", "album": "Geogaddi", "albumartist": "Boards of Canada", "artpath": "", "comp": "False"}]"#;
+    let items = r#"
+        {"id": "1", "path": "/music/a.flac", "album_id": "1", "title": "Ready Lets Go", "artist": "Boards of Canada", "length": "0:59", "comp": "False"}
+        {"id": "2", "path": "/music/b.flac", "album_id": "1", "title": "Music Is Math", "artist": "Boards of Canada", "length": "5:21", "comp": "False"}
+    "#;
+
+    let library = Library::from_beets_export(format!("{albums}\n{items}").as_bytes())?;
+    assert_eq!(library.albums.len(), 1);
+    assert_eq!(library.albums[0].artpath, None);
+    assert_eq!(library.items.len(), 2);
+    assert_eq!(library.items[1].title, "Music Is Math");
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn library_search_and_sort() -> Result<(), serde_json::Error> {
+    let mut library = Library::from_json_bytes(
+        br#"{
+            "albums": [
+                {"id": 1, "album": "Geogaddi", "albumartist": "Boards of Canada", "comp": false, "year": 2002},
+                {"id": 2, "album": "Selected Ambient Works 85-92", "albumartist": "Aphex Twin", "comp": false, "year": 1992}
+            ],
+            "items": [
+                {"id": 1, "path": "/a.flac", "album_id": 1, "title": "Music Is Math", "artist": "Boards of Canada", "comp": false, "length": 321.0}
+            ]
+        }"#,
+    )?;
+
+    assert_eq!(library.album_artists(), ["Aphex Twin", "Boards of Canada"]);
+    assert_eq!(library.album_items(1).count(), 1);
+    assert_eq!(library.search_items("math").len(), 1);
+    assert_eq!(library.search_albums("APHEX")[0].id, 2);
+
+    library.sort_albums(&[SortKey {
+        field: "year",
+        ascending: true,
+    }]);
+    assert_eq!(library.albums[0].id, 2);
+    Ok(())
+}
+
+#[cfg(feature = "miette")]
+#[test]
+fn diagnostic_help_for_missing_column() -> Result<(), Error> {
+    use miette::Diagnostic;
+
+    let db = Database::open_in_memory()?;
+    db.connection()
+        .execute_batch("DROP TABLE albums; CREATE TABLE albums (id INTEGER PRIMARY KEY)")?;
+    let err = db.read_all().unwrap_err();
+    assert!(err
+        .help()
+        .is_some_and(|help| help.to_string().contains("column is missing")));
+    Ok(())
+}
+
+#[test]
+fn trace_hook_reports_queries() -> Result<(), Error> {
+    use std::sync::{Arc, Mutex};
+
+    let traces = Arc::new(Mutex::new(Vec::new()));
+    let mut db = Database::open_in_memory()?;
+    db.connection().execute(
+        "INSERT INTO items (id, title) VALUES (1, 'a'), (2, 'b')",
+        (),
+    )?;
+
+    let sink = Arc::clone(&traces);
+    db.set_trace_hook(move |trace| {
+        sink.lock()
+            .unwrap()
+            .push((trace.sql.to_string(), trace.rows));
+    });
+    db.read_all()?;
+
+    let traces = traces.lock().unwrap();
+    assert_eq!(
+        *traces,
+        [
+            (Album::SQL_QUERY.to_string(), 0),
+            (Item::SQL_QUERY.to_string(), 2)
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn explain_full_scan() -> Result<(), Error> {
+    let db = Database::open_in_memory()?;
+    let plan = db.explain(Item::SQL_QUERY)?;
+    assert_eq!(plan.full_scans().count(), 1);
+    assert!(plan.to_string().contains("SCAN items"));
+
+    let plan = db.explain("SELECT value FROM item_attributes WHERE entity_id = ?")?;
+    assert_eq!(plan.full_scans().count(), 0);
+    Ok(())
+}
+
+#[test]
+fn indexes_avoid_full_scans() -> Result<(), Error> {
+    let db = Database::open_in_memory()?;
+    let by_album = "SELECT id FROM items WHERE album_id = ?";
+    assert_eq!(db.explain(by_album)?.full_scans().count(), 1);
+
+    db.create_indexes()?;
+    assert_eq!(db.explain(by_album)?.full_scans().count(), 0);
+
+    drop_indexes(db.connection())?;
+    assert_eq!(db.explain(by_album)?.full_scans().count(), 1);
+    Ok(())
+}
+
+#[test]
+fn open_read_fast_preset() -> Result<(), Error> {
+    let db = OpenOptions::new()
+        .preset(Preset::ReadFast)
+        .open("tests/test.db")?;
+    let temp_store: i64 = db
+        .connection()
+        .pragma_query_value(None, "temp_store", |row| row.get(0))?;
+    assert_eq!(temp_store, 2);
+    assert!(!db.read_all()?.1.is_empty());
+    Ok(())
+}
+
+#[test]
+fn federation_tags_entries() -> Result<(), Error> {
+    let federation = Federation::open([("alice", "tests/test.db"), ("bob", "tests/test.db")])?;
+    let merged = federation.read_all()?;
+    let (_, items) = read_all("tests/test.db")?;
+    assert_eq!(merged.items.len(), 2 * items.len());
+
+    let id: GlobalId = format!("bob:{}", items[0].id).parse().expect("valid id");
+    let item = merged.item(&id).expect("item in second library");
+    assert_eq!(item.entry, items[0]);
+    assert_eq!(item.global_id(), id);
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn diff_round_trips_through_json() -> Result<(), Box<dyn std::error::Error>> {
+    let (albums, items) = OpenOptions::new().open("tests/test.db")?.read_all()?;
+    let old = Library { albums, items };
+
+    let mut new = old.clone();
+    new.albums[0].genre = "Jazz".to_string();
+    new.items.remove(1);
+    let mut added = new.items[0].clone();
+    added.id = u32::MAX;
+    added.added = 1_700_000_000.5;
+    new.items.push(added);
+
+    let changeset = diff(&old, &new);
+    assert_eq!(changeset.changes.len(), 3);
+
+    let json = serde_json::to_string(&changeset)?;
+    assert_eq!(serde_json::from_str::<Changeset>(&json)?, changeset);
+
+    let mut replayed = old;
+    apply_changes(&mut replayed, &serde_json::from_str(&json)?)?;
+    assert!(diff(&replayed, &new).is_empty());
+    Ok(())
+}
+
+#[test]
+fn diff_replays_onto_a_database() -> Result<(), Box<dyn std::error::Error>> {
+    let db = Database::open_in_memory()?;
+    Album {
+        id: 1,
+        album: "Geogaddi".to_string(),
+        ..Album::default()
+    }
+    .insert(&db)?;
+    for (id, title) in [(1, "Ready Lets Go"), (2, "Music Is Math")] {
+        Item {
+            id,
+            album_id: Some(1),
+            title: title.to_string(),
+            ..Item::default()
+        }
+        .insert(&db)?;
+    }
+    let (albums, items) = db.read_all()?;
+    let old = Library { albums, items };
+
+    let mut new = old.clone();
+    new.albums.clear();
+    new.items[0].genre = "IDM".to_string();
+    new.items[0].album_id = None;
+    new.items.remove(1);
+    let changeset = diff(&old, &new);
+    db.apply_changes(&changeset)?;
+    let (albums, items) = db.read_all()?;
+    assert!(diff(&Library { albums, items }, &new).is_empty());
+
+    // a change that does not fit leaves the library as it was
+    let err = db
+        .apply_changes(&changeset)
+        .expect_err("the album is gone already");
+    assert_eq!(
+        err.apply_error(),
+        Some(&ApplyError::Missing(Table::Albums, 1))
+    );
+    assert_eq!(db.undo_last(1)?, 1);
+    let (albums, items) = db.read_all()?;
+    assert!(diff(&Library { albums, items }, &old).is_empty());
+    Ok(())
+}
+
+#[test]
+fn duplicates_across_libraries() {
+    let item = |source: &str, id, title: &str, format: &str, bitrate| Sourced {
+        source: LibraryTag::from(source),
+        entry: Item {
+            id,
+            title: title.to_string(),
+            artist: "Boards of Canada".to_string(),
+            format: format.to_string(),
+            bitrate,
+            length: 321.0,
+            ..Item::default()
+        },
+    };
+    let library = FederatedLibrary {
+        albums: vec![],
+        items: vec![
+            item("laptop", 1, "Music Is Math", "MP3", 320_000),
+            item("nas", 1, "Music is math!", "FLAC", 900_000),
+            item("nas", 2, "Gyroscope", "FLAC", 900_000),
+            item("nas", 3, "Gyroscope", "MP3", 128_000),
+        ],
+    };
+
+    let groups = library.duplicates();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].matched_by, MatchKind::Fuzzy);
+    assert_eq!(groups[0].best().source.as_str(), "nas");
+    assert_eq!(groups[0].copies.len(), 2);
+
+    let groups = library.all_duplicates();
+    assert_eq!(groups.len(), 2);
+    let ids: Vec<_> = groups[0].ids().map(|id| id.to_string()).collect();
+    assert_eq!(ids, ["nas:2", "nas:3"]);
+}
+
+#[test]
+fn duplicate_plan_keeps_the_best_copy() {
+    let item = |source: &str, id, path: &str, format: &str, bitrate| Sourced {
+        source: LibraryTag::from(source),
+        entry: Item {
+            id,
+            path: path.into(),
+            title: "Roygbiv".to_string(),
+            artist: "Boards of Canada".to_string(),
+            format: format.to_string(),
+            bitrate,
+            length: 151.0,
+            ..Item::default()
+        },
+    };
+    let mut tagged = item("nas", 2, "/mnt/b.flac", "FLAC", 900_000);
+    tagged.entry.album = "Music Has the Right to Children".to_string();
+    tagged.entry.track = 5;
+    let library = FederatedLibrary {
+        albums: vec![],
+        items: vec![
+            item("nas", 1, "/mnt/a.flac", "FLAC", 900_000),
+            tagged,
+            item("laptop", 1, "/music/a.mp3", "MP3", 320_000),
+            item("laptop", 2, "/mnt/b.flac", "FLAC", 900_000),
+        ],
+    };
+    assert_eq!(library.items[1].entry.tag_completeness(), 4);
+
+    let groups = library.all_duplicates();
+    assert_eq!(groups.len(), 1);
+    let plan = groups[0].plan();
+    assert_eq!(plan.keep.global_id().to_string(), "nas:2");
+    let removed: Vec<_> = plan
+        .remove
+        .iter()
+        .map(|removal| (removal.copy.global_id().to_string(), removal.reason))
+        .collect();
+    // laptop:2 is the kept file itself, as the laptop sees it
+    assert_eq!(
+        removed,
+        [
+            ("nas:1".to_string(), KeepReason::Tags),
+            ("laptop:1".to_string(), KeepReason::Lossless),
+        ]
+    );
+}
+
+#[test]
+fn read_all_parallel_matches_sequential() -> Result<(), Error> {
+    let expected = read_all("tests/test.db")?;
+    for chunks in [1, 3, 8] {
+        let parallel = OpenOptions::new().read_all_parallel("tests/test.db", chunks)?;
+        assert_eq!(parallel, expected, "with {chunks} chunks");
+    }
+    Ok(())
+}
+
+#[test]
+fn progress_sink_counts_rows() -> Result<(), Error> {
+    use std::sync::{Arc, Mutex};
+
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let mut db = OpenOptions::new().open("tests/test.db")?;
+    let sink = Arc::clone(&updates);
+    db.set_progress_sink(move |progress: &Progress| {
+        sink.lock()
+            .unwrap()
+            .push((progress.table.to_string(), progress.rows, progress.total));
+    });
+    let (albums, items) = db.read_all()?;
+
+    let updates = updates.lock().unwrap();
+    let items_total = Some(items.len() as u64);
+    assert!(updates.contains(&("items".to_string(), 1000, items_total)));
+    assert_eq!(
+        updates.last(),
+        Some(&("items".to_string(), items.len() as u64, items_total))
+    );
+    assert!(updates.contains(&(
+        "albums".to_string(),
+        albums.len() as u64,
+        Some(albums.len() as u64)
+    )));
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn progress_sink_counts_exported_rows() -> Result<(), Box<dyn std::error::Error>> {
+    use std::sync::{Arc, Mutex};
+
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let mut db = OpenOptions::new().open("tests/test.db")?;
+    let sink = Arc::clone(&updates);
+    db.set_progress_sink(move |progress: &Progress| {
+        sink.lock()
+            .unwrap()
+            .push((progress.table.to_string(), progress.rows, progress.total));
+    });
+    let items = db.write_items_ndjson(&mut std::io::sink())? as u64;
+    let albums = db.write_albums_ndjson(&mut std::io::sink())? as u64;
+
+    let updates = updates.lock().unwrap();
+    assert!(updates.contains(&("items".to_string(), 1000, Some(items))));
+    assert!(updates.contains(&("items".to_string(), items, Some(items))));
+    assert_eq!(
+        updates.last(),
+        Some(&("albums".to_string(), albums, Some(albums)))
+    );
+    Ok(())
+}
+
+#[test]
+fn cancellation_stops_read() -> Result<(), Error> {
+    let cancel = CancellationToken::new();
+    let mut db = OpenOptions::new().open("tests/test.db")?;
+    let trigger = cancel.clone();
+    db.set_progress_sink(move |progress: &Progress| {
+        if progress.table == "items" {
+            trigger.cancel();
+        }
+    });
+
+    let err = db.read_all_cancellable(&cancel).unwrap_err();
+    assert!(err.is_cancelled());
+    assert!(db.read_all_cancellable(&CancellationToken::new()).is_ok());
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn cancellation_stops_export() -> Result<(), Box<dyn std::error::Error>> {
+    let cancel = CancellationToken::new();
+    let mut db = OpenOptions::new().open("tests/test.db")?;
+    db.set_cancellation_token(cancel.clone());
+    db.set_progress_sink(move |progress: &Progress| {
+        if progress.rows == PROGRESS_INTERVAL {
+            cancel.cancel();
+        }
+    });
+
+    let mut out = vec![];
+    match db.write_items_ndjson(&mut out) {
+        Err(NdjsonError::Read(err)) => assert!(err.is_cancelled()),
+        other => panic!("export was not cancelled: {:?}", other),
+    }
+    assert_eq!(String::from_utf8(out)?.lines().count(), 1000);
+    assert!(db.items().unwrap_err().is_cancelled());
+    db.clear_cancellation_token();
+    assert!(db.items().is_ok());
+    Ok(())
+}
+
+#[test]
+fn timeout_interrupts_read() -> Result<(), Error> {
+    use std::time::Duration;
+
+    let mut db = OpenOptions::new().open("tests/test.db")?;
+    db.set_progress_sink(|_: &Progress| std::thread::sleep(Duration::from_millis(50)));
+    db.set_timeout(Some(Duration::from_millis(10)));
+    assert!(db.read_all().unwrap_err().is_timed_out());
+
+    db.set_timeout(Some(Duration::from_secs(30)));
+    assert!(db.read_all().is_ok());
+    Ok(())
+}
+
+#[test]
+fn stream_items_matches_read_all() -> Result<(), Error> {
+    let (_, expected) = read_all("tests/test.db")?;
+    let streamed = OpenOptions::new()
+        .open("tests/test.db")?
+        .stream_items(16)
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(streamed, expected);
+
+    // stopping early leaves the reader to wind down on its own
+    let first = OpenOptions::new()
+        .open("tests/test.db")?
+        .stream_items(1)
+        .next();
+    assert_eq!(first.transpose()?.as_ref(), expected.first());
+    Ok(())
+}
+
+#[test]
+fn iter_matches_read_all() -> Result<(), Error> {
+    let conn = rusqlite::Connection::open("tests/test.db")?;
+    let items = Item::read_all(&conn)?;
+    assert!(items.len() > 512, "spans several pages");
+    assert_eq!(Item::iter(&conn).collect::<Result<Vec<_>, _>>()?, items);
+    assert_eq!(
+        Album::iter(&conn).collect::<Result<Vec<_>, _>>()?,
+        Album::read_all(&conn)?
+    );
+
+    let first = Item::iter(&conn).take(3).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(first, items[..3]);
+    Ok(())
+}
+
+#[test]
+fn stream_items_by_album_is_contiguous() -> Result<(), Error> {
+    let items = OpenOptions::new()
+        .open("tests/test.db")?
+        .stream_items_by_album(64)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let key = |item: &Item| {
+        (
+            item.album_id.is_none(),
+            item.album_id,
+            item.disc,
+            item.track,
+        )
+    };
+    assert!(items.windows(2).all(|pair| key(&pair[0]) <= key(&pair[1])));
+    Ok(())
+}
+
+#[test]
+fn genre_tree_queries() {
+    let tree = GenreTree::from_yaml(
+        "- electronic:\n    - techno:\n        - detroit techno\n    - ambient\n- rock:\n    - post-rock\n",
+    )
+    .expect("valid tree");
+    assert_eq!(tree.parent("Detroit Techno"), Some("techno"));
+    assert_eq!(
+        tree.ancestors("detroit techno").collect::<Vec<_>>(),
+        ["techno", "electronic"]
+    );
+    assert_eq!(
+        tree.descendants("electronic"),
+        ["techno", "detroit techno", "ambient"]
+    );
+    assert!(tree.matches("Post-Rock; Detroit Techno", "Electronic"));
+    assert!(!tree.matches("Post-Rock", "electronic"));
+    assert_eq!(GenreTree::from_yaml("rock").unwrap_err().line, 1);
+
+    let library = Library {
+        albums: vec![],
+        items: vec![
+            Item {
+                genre: "Techno".to_string(),
+                ..Item::default()
+            },
+            Item {
+                genre: "Jazz".to_string(),
+                ..Item::default()
+            },
+        ],
+    };
+    assert_eq!(
+        library
+            .items_in_genre(&GenreTree::default(), "electronic")
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn artist_splitter_featuring() {
+    let splitter = ArtistSplitter::new();
+    let credit = splitter.split("Daft Punk feat. Pharrell Williams & Nile Rodgers");
+    assert_eq!(credit.primary, ["Daft Punk"]);
+    assert_eq!(credit.featured, ["Pharrell Williams", "Nile Rodgers"]);
+    assert_eq!(
+        splitter.split("Jay-Z (Ft. Kanye West)").featured,
+        ["Kanye West"]
+    );
+    assert_eq!(splitter.split("Aphex Twin; Squarepusher").primary.len(), 2);
+    assert_eq!(splitter.split("Feathers").primary, ["Feathers"]);
+
+    let mut splitter = ArtistSplitter::empty();
+    splitter.featuring("with").separator(" and ");
+    let credit = splitter.split("Simon & Garfunkel with Bob and Ray");
+    assert_eq!(credit.primary, ["Simon & Garfunkel"]);
+    assert_eq!(credit.featured, ["Bob", "Ray"]);
+}
+
+#[test]
+fn compilation_heuristics() {
+    let album = |id, albumartist: &str| Album {
+        id,
+        albumartist: albumartist.to_string(),
+        ..Album::default()
+    };
+    let item = |album_id, artist: &str| Item {
+        album_id: Some(album_id),
+        artist: artist.to_string(),
+        ..Item::default()
+    };
+    let library = Library {
+        albums: vec![album(1, "VA"), album(2, "DJ Mix"), album(3, "Autechre")],
+        items: ["A", "B", "C", "D", "E"]
+            .iter()
+            .map(|artist| item(2, artist))
+            .chain([item(3, "Autechre"), item(3, "Autechre")])
+            .collect(),
+    };
+
+    let heuristics = CompilationHeuristics::default();
+    let reason = |id| library.compilation_reason(&library.albums[id], &heuristics);
+    assert_eq!(reason(0), Some(CompilationReason::VariousArtistsName));
+    assert_eq!(reason(1), Some(CompilationReason::ManyArtists));
+    assert_eq!(reason(2), None);
+    assert_eq!(library.compilations(&heuristics).len(), 2);
+}
+
+#[test]
+fn sort_name_rules() {
+    assert_eq!(sort_name("The Beatles"), "Beatles, The");
+    assert_eq!(sort_name("Ludwig van Beethoven"), "Ludwig van Beethoven");
+    assert_eq!(sort_name("Daft Punk"), "Daft Punk");
+    assert_eq!(
+        person_sort_name("Ludwig van Beethoven"),
+        "Beethoven, Ludwig van"
+    );
+    assert_eq!(person_sort_name("Sammy Davis Jr."), "Davis, Sammy, Jr.");
+    assert_eq!(person_sort_name("Björk"), "Björk");
+    assert_eq!(sort_name("Boards of Canada"), "Boards of Canada");
+    assert_eq!(
+        sort_name("Penguin Cafe Orchestra"),
+        "Penguin Cafe Orchestra"
+    );
+    assert_eq!(sort_name("Björk"), "Björk");
+
+    let album = Album {
+        albumartist: "The Orb".to_string(),
+        ..Album::default()
+    };
+    assert_eq!(album.albumartist_sort_name(), "Orb, The");
+
+    let artist = |artist: &str| Item {
+        artist: artist.to_string(),
+        ..Item::default()
+    };
+    let mut library = Library {
+        albums: vec![],
+        items: vec![artist("Daft Punk"), artist("Nina Simone")],
+    };
+    let people: std::collections::HashSet<_> = std::iter::once("Nina Simone".to_string()).collect();
+    library.fill_sort_names(&people);
+    assert_eq!(library.items[0].artist_sort, "Daft Punk");
+    assert_eq!(library.items[1].artist_sort, "Simone, Nina");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn r128_gain_zero_is_not_absent() -> Result<(), serde_json::Error> {
+    let item = Item {
+        r128_track_gain: Some(R128Gain::from_q78(0)),
+        r128_album_gain: R128Gain::from_db(-1.5),
+        ..Item::default()
+    };
+    let json = serde_json::to_value(&item)?;
+    assert_eq!(json["r128_track_gain"], 0);
+    assert_eq!(json["r128_album_gain"], -384);
+    assert!(json.get("rg_track_gain").is_none());
+    assert_eq!(serde_json::from_value::<Item>(json)?, item);
+    Ok(())
+}
+
+#[test]
+fn read_real_bpm() -> Result<(), Error> {
+    let db = Database::open_in_memory()?;
+    db.connection().execute(
+        "INSERT INTO items (id, title, bpm) VALUES (1, 'int', 120), (2, 'real', 127.6)",
+        (),
+    )?;
+    let (_, items) = db.read_all()?;
+    assert_eq!(items[0].bpm.to_string(), "120");
+    assert_eq!(items[1].bpm.to_string(), "127.6");
+    assert_eq!(items[1].bpm_rounded(), 128);
+    Ok(())
+}
+
+#[test]
+fn encoder_columns_optional() -> Result<(), Error> {
+    let db = Database::open_in_memory()?;
+    db.connection().execute(
+        "INSERT INTO items (id, title, bitrate_mode, encoder_info) VALUES (1, 'a', 'VBR', 'LAME 3.100')",
+        (),
+    )?;
+    let (_, items) = db.read_all()?;
+    assert_eq!(items[0].bitrate_mode, "VBR");
+    assert_eq!(items[0].encoder_info, "LAME 3.100");
+
+    // as in a library from before beets 2.0
+    for column in ["bitrate_mode", "encoder_info", "encoder_settings"] {
+        db.connection()
+            .execute(&format!("ALTER TABLE items DROP COLUMN {column}"), ())?;
+    }
+    let (_, items) = db.read_all()?;
+    assert_eq!(items[0].title, "a");
+    assert_eq!(items[0].bitrate_mode, "");
+    let streamed = db.stream_items(1).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(streamed, items);
+    Ok(())
+}
+
+#[test]
+fn missing_items_in_order() {
+    let item = |id, path: &str| Item {
+        id,
+        path: path.into(),
+        ..Item::default()
+    };
+    let library = Library {
+        albums: vec![],
+        items: vec![
+            item(1, "tests/test.db"),
+            item(2, "tests/gone.flac"),
+            item(3, "Cargo.toml"),
+            item(4, "tests/also gone.mp3"),
+        ],
+    };
+    for threads in [0, 1, 3, 8] {
+        let missing: Vec<_> = library
+            .missing_items(threads)
+            .iter()
+            .map(|item| item.id)
+            .collect();
+        assert_eq!(missing, [2, 4], "with {threads} threads");
+    }
+}
+
+#[test]
+fn paths_differing_in_case() {
+    let item = |id, path: &str| Item {
+        id,
+        path: path.into(),
+        ..Item::default()
+    };
+    let library = Library {
+        albums: vec![],
+        items: vec![
+            item(1, "tests/test.db"),
+            item(2, "Tests/Test.DB"),
+            item(3, "cargo.TOML"),
+            item(4, "tests/gone.flac"),
+            item(5, "tests/test.db"),
+        ],
+    };
+    let conflicts: Vec<Vec<u32>> = library
+        .case_conflicts()
+        .iter()
+        .map(|group| group.iter().map(|item| item.id).collect())
+        .collect();
+    assert_eq!(conflicts, [[2, 1, 5]]);
+
+    let mismatches: Vec<_> = library
+        .case_mismatches()
+        .into_iter()
+        .map(|mismatch| (mismatch.entry.id, mismatch.on_disk))
+        .collect();
+    assert_eq!(
+        mismatches,
+        [
+            (2, PathBuf::from("tests/test.db")),
+            (3, PathBuf::from("Cargo.toml")),
+        ]
+    );
+}
+
+#[test]
+fn copy_export_names_files_safely() -> Result<(), Box<dyn std::error::Error>> {
+    let item = |path: &str, albumartist: &str, album: &str, track, title: &str| Item {
+        path: path.into(),
+        albumartist: albumartist.to_string(),
+        album: album.to_string(),
+        track,
+        title: title.to_string(),
+        ..Item::default()
+    };
+    let items = vec![
+        item(
+            "tests/test.db",
+            "AC/DC",
+            "Back in Black",
+            1,
+            "Hells Bells...",
+        ),
+        item(
+            "Cargo.toml",
+            "Sigur Rós",
+            "Ágætis byrjun",
+            3,
+            "Svefn-g-englar",
+        ),
+        item(
+            "Cargo.toml",
+            "Sigur Rós",
+            "ágætis byrjun",
+            3,
+            "svefn-g-englar",
+        ),
+        item("Cargo.toml", "", "con", 12, &"long ".repeat(60)),
+    ];
+    let paths = |options: &CopyExportOptions| -> Vec<PathBuf> {
+        export_paths(&items, options)
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect()
+    };
+    let options = CopyExportOptions::default();
+    let named = paths(&options);
+    assert_eq!(named[0], Path::new("AC_DC/Back in Black/01 Hells Bells.db"));
+    assert_eq!(
+        named[1],
+        Path::new("Sigur Rós/Ágætis byrjun/03 Svefn-g-englar.toml")
+    );
+    // the same name but for case gets a number on a case-insensitive stick
+    assert_eq!(
+        named[2],
+        Path::new("Sigur Rós/ágætis byrjun/03 svefn-g-englar (2).toml")
+    );
+    let name = named[3].file_name().unwrap().to_str().unwrap();
+    assert!(named[3].starts_with("_/_con"));
+    assert_eq!(name.encode_utf16().count(), 255);
+    assert!(name.ends_with(" lo.toml"));
+
+    let ascii = CopyExportOptions {
+        ascii: true,
+        ..CopyExportOptions::default()
+    };
+    assert_eq!(
+        paths(&ascii)[1],
+        Path::new("Sigur Ros/Agaetis byrjun/03 Svefn-g-englar.toml")
+    );
+    let flat = CopyExportOptions {
+        template: "$$ ${artist}$title".parse()?,
+        ..CopyExportOptions::default()
+    };
+    assert_eq!(paths(&flat)[0], Path::new("$ Hells Bells.db"));
+    assert_eq!(flat.template.to_string(), "$$ ${artist}$title");
+    assert!("$albumartist/$nope".parse::<PathTemplate>().is_err());
+
+    let dir = std::env::temp_dir().join(format!("beet_db-copy-{}", std::process::id()));
+    let report = copy_export(&items[..2], &dir, &options)?;
+    assert_eq!(
+        report,
+        CopyReport {
+            copied: 2,
+            skipped: 0
+        }
+    );
+    assert_eq!(
+        std::fs::read(dir.join(&named[1]))?,
+        std::fs::read("Cargo.toml")?
+    );
+    // copying again tops up what is not there yet
+    let report = copy_export(&items, &dir, &options)?;
+    assert_eq!(
+        report,
+        CopyReport {
+            copied: 2,
+            skipped: 2
+        }
+    );
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn copy_export_replaces_files_of_the_same_size() -> Result<(), Box<dyn std::error::Error>> {
+    let items = [Item {
+        path: "Cargo.toml".into(),
+        title: "Roygbiv".to_string(),
+        ..Item::default()
+    }];
+    let options = CopyExportOptions::default();
+    let dir = std::env::temp_dir().join(format!("beet_db-copy-stale-{}", std::process::id()));
+    assert_eq!(copy_export(&items, &dir, &options)?.copied, 1);
+    assert_eq!(copy_export(&items, &dir, &options)?.skipped, 1);
+
+    // a different file of the same size, e.g. left by a renumbered name
+    let (_, path) = export_paths(&items, &options).remove(0);
+    let stale = dir.join(path);
+    std::fs::write(&stale, vec![b'x'; std::fs::read("Cargo.toml")?.len()])?;
+    std::fs::File::options()
+        .write(true)
+        .open(&stale)?
+        .set_modified(std::time::UNIX_EPOCH)?;
+    assert_eq!(copy_export(&items, &dir, &options)?.copied, 1);
+    assert_eq!(std::fs::read(&stale)?, std::fs::read("Cargo.toml")?);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn sync_profiles_copy_new_and_delete_removed() -> Result<(), Box<dyn std::error::Error>> {
+    let db = Database::open_in_memory()?;
+    assert_eq!(SyncProfile::load(&db, "car")?, None);
+
+    let item = |id, path: &str, format: &str, title: &str| Item {
+        id,
+        path: path.into(),
+        format: format.to_string(),
+        albumartist: "Artist".to_string(),
+        album: "Album".to_string(),
+        track: id,
+        title: title.to_string(),
+        ..Item::default()
+    };
+    let items = vec![
+        item(1, "Cargo.toml", "MP3", "One"),
+        item(2, "tests/test.db", "MP3", "Two"),
+        item(3, "Cargo.toml", "FLAC", "Three"),
+        item(4, "nowhere.mp3", "MP3", "Four"),
+    ];
+    let dir = std::env::temp_dir().join(format!("beet_db-sync-{}", std::process::id()));
+    let profile = SyncProfile {
+        name: "car".to_string(),
+        query: "genre:rock".to_string(),
+        destination: dir.clone(),
+        formats: vec!["MP3".to_string(), "AAC".to_string()],
+        size_cap: Some(std::fs::metadata("Cargo.toml")?.len()),
+        priority: SyncPriority::Plays,
+        ..SyncProfile::default()
+    };
+    profile.save(&db)?;
+    assert_eq!(SyncProfile::load(&db, "car")?.as_ref(), Some(&profile));
+    assert_eq!(SyncProfile::list(&db)?, std::slice::from_ref(&profile));
+
+    // a player that is not mounted is not synced into its mount point,
+    // which has no manifest until the first sync is asked for
+    assert!(profile.init().is_err());
+    std::fs::create_dir_all(&dir)?;
+    assert!(profile.plan(&items).is_err());
+    profile.init()?;
+    let plan = profile.plan(&items)?;
+    let excluded: Vec<_> = plan
+        .excluded
+        .iter()
+        .map(|(item, why)| (item.id, *why))
+        .collect();
+    assert_eq!(
+        excluded,
+        [
+            (
+                2,
+                SyncExclusion::SizeCap {
+                    size: std::fs::metadata("tests/test.db")?.len(),
+                    free: 0
+                }
+            ),
+            (3, SyncExclusion::Format),
+            (4, SyncExclusion::Missing)
+        ]
+    );
+    assert_eq!(plan.copy.len(), 1);
+    assert_eq!(plan.copy[0].1, Path::new("Artist/Album/01 One.toml"));
+    plan.run()?;
+    // a file the profile did not copy is never deleted
+    std::fs::write(dir.join("notes.txt"), "mine")?;
+
+    let roomy = SyncProfile {
+        size_cap: None,
+        ..profile.clone()
+    };
+    let plan = roomy.plan(&items[..2])?;
+    assert_eq!((plan.copy.len(), plan.keep.len()), (1, 1));
+    plan.run()?;
+    assert!(dir.join("Artist/Album/02 Two.db").exists());
+
+    let plan = roomy.plan(&items[1..2])?;
+    assert_eq!(plan.delete, [PathBuf::from("Artist/Album/01 One.toml")]);
+    plan.run()?;
+    assert!(!dir.join("Artist/Album/01 One.toml").exists());
+    assert!(dir.join("notes.txt").exists());
+
+    let plan = roomy.plan(&[])?;
+    assert_eq!(plan.delete.len(), 1);
+    plan.run()?;
+    // directories left empty are removed as well
+    assert!(!dir.join("Artist").exists());
+
+    assert!(SyncProfile::delete(&db, "car")?);
+    assert!(SyncProfile::list(&db)?.is_empty());
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn sync_profiles_move_out_of_the_library() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("beet_db-sync-legacy-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("library.db");
+    std::fs::copy("tests/test.db", &path)?;
+    // as saved in the library, before profiles had a priority
+    Connection::open(&path)?.execute_batch(
+        "CREATE TABLE berts_sync_profiles (
+            name TEXT PRIMARY KEY, query TEXT NOT NULL, destination TEXT NOT NULL,
+            formats TEXT NOT NULL, size_cap INTEGER, template TEXT NOT NULL,
+            ascii INTEGER NOT NULL);
+        INSERT INTO berts_sync_profiles VALUES ('car', '', '/car', 'MP3', NULL, '$title', 0);",
+    )?;
+    let car = SyncProfile {
+        name: "car".to_string(),
+        destination: "/car".into(),
+        formats: vec!["MP3".to_string()],
+        template: "$title".parse()?,
+        ..SyncProfile::default()
+    };
+    assert_eq!(
+        SyncProfile::list(&Database::open(&path)?)?,
+        std::slice::from_ref(&car)
+    );
+
+    let db = Database::open_writable(&path)?;
+    let stick = SyncProfile {
+        name: "stick".to_string(),
+        ..car.clone()
+    };
+    stick.save(&db)?;
+    assert_eq!(SyncProfile::list(&db)?, [car, stick]);
+    // the library is left as it was
+    let count: i64 = Connection::open(&path)?.query_row(
+        "SELECT count(*) FROM berts_sync_profiles",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(count, 1);
+
+    drop(db);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn sync_replaces_files_of_the_same_size() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("beet_db-sync-stale-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let items = [Item {
+        path: "Cargo.toml".into(),
+        title: "Roygbiv".to_string(),
+        ..Item::default()
+    }];
+    let profile = SyncProfile {
+        destination: dir.clone(),
+        template: "$title".parse()?,
+        ..SyncProfile::default()
+    };
+    profile.init()?;
+    profile.plan(&items)?.run()?;
+    assert_eq!(profile.plan(&items)?.keep.len(), 1);
+
+    // a different file of the same size, e.g. left by a renumbered name
+    let stale = dir.join("Roygbiv.toml");
+    std::fs::write(&stale, vec![b'x'; std::fs::read("Cargo.toml")?.len()])?;
+    std::fs::File::options()
+        .write(true)
+        .open(&stale)?
+        .set_modified(std::time::UNIX_EPOCH)?;
+    let plan = profile.plan(&items)?;
+    assert_eq!(plan.copy.len(), 1);
+    plan.run()?;
+    assert_eq!(std::fs::read(&stale)?, std::fs::read("Cargo.toml")?);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn failed_sync_still_writes_the_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("beet_db-sync-fail-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("player"))?;
+    let mut items = vec![];
+    for title in ["a", "b"] {
+        let path = dir.join(title);
+        std::fs::write(&path, title)?;
+        items.push(Item {
+            path,
+            title: title.to_string(),
+            ..Item::default()
+        });
+    }
+    let profile = SyncProfile {
+        destination: dir.join("player"),
+        template: "$title".parse()?,
+        ..SyncProfile::default()
+    };
+    profile.init()?;
+    let plan = profile.plan(&items)?;
+    // gone by the time it is copied
+    std::fs::remove_file(dir.join("b"))?;
+    assert!(plan.run().is_err());
+    assert!(dir.join("player/a").exists());
+
+    // the file copied before the failure is still the profile's to delete
+    let plan = profile.plan(&[])?;
+    assert_eq!(plan.delete, [PathBuf::from("a"), PathBuf::from("b")]);
+    plan.run()?;
+    assert!(!dir.join("player/a").exists());
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn sync_size_cap_keeps_highest_priority() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("beet_db-sync-cap-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("player"))?;
+    let mut items = vec![];
+    for (id, size, added) in [
+        (1, 600, 10.0),
+        (2, 300, 40.0),
+        (3, 300, 30.0),
+        (4, 100, 20.0),
+    ] {
+        let path = dir.join(format!("{id}.mp3"));
+        std::fs::write(&path, vec![0; size])?;
+        items.push(Item {
+            id,
+            path,
+            title: id.to_string(),
+            added,
+            ..Item::default()
+        });
+    }
+    let rating = |value: &str| {
+        vec![Attribute {
+            key: "rating".to_string(),
+            value: value.to_string(),
+            ..Attribute::default()
+        }]
+    };
+    let attributes = [rating("0.9"), rating("0.5"), rating("0.8"), vec![]];
+    let mut profile = SyncProfile {
+        destination: dir.join("player"),
+        size_cap: Some(700),
+        priority: SyncPriority::Rating,
+        template: "$title".parse()?,
+        ..SyncProfile::default()
+    };
+    profile.init()?;
+    let fit = |profile: &SyncProfile| -> Result<_, Box<dyn std::error::Error>> {
+        let with_attributes = items
+            .iter()
+            .zip(&attributes)
+            .map(|(item, attributes)| (item, attributes.as_slice()));
+        let plan = profile.plan_with_attributes(with_attributes)?;
+        let copied: Vec<u32> = plan.copy.iter().map(|(item, _)| item.id).collect();
+        let excluded: Vec<_> = plan
+            .excluded
+            .iter()
+            .map(|(item, why)| (item.id, *why))
+            .collect();
+        Ok((copied, excluded, plan.bytes))
+    };
+
+    // 3 is rated above 2 but no longer fits after 1, and 4 fills the rest
+    let size_cap = |size, free| SyncExclusion::SizeCap { size, free };
+    let (copied, excluded, bytes) = fit(&profile)?;
+    assert_eq!(copied, [1, 4]);
+    assert_eq!(excluded, [(2, size_cap(300, 100)), (3, size_cap(300, 100))]);
+    assert_eq!(bytes, 700);
+
+    profile.priority = SyncPriority::Recent;
+    let (copied, excluded, _) = fit(&profile)?;
+    assert_eq!(copied, [2, 3, 4]);
+    assert_eq!(excluded, [(1, size_cap(600, 0))]);
+
+    profile.priority = SyncPriority::Order;
+    assert_eq!(fit(&profile)?.0, [1, 4]);
+    profile.size_cap = None;
+    assert_eq!(fit(&profile)?.0, [1, 2, 3, 4]);
+
+    assert_eq!("plays".parse(), Ok(SyncPriority::Plays));
+    assert_eq!(SyncPriority::Recent.to_string(), "recent");
+    assert!("loudest".parse::<SyncPriority>().is_err());
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn distinct_values() -> Result<(), Error> {
+    let (albums, items) = read_all("tests/test.db")?;
+    let library = Library { albums, items };
+
+    let formats = library.distinct_item_values("format").unwrap();
+    assert!(formats.contains(&FieldValue::Text("FLAC".into())));
+    assert!(formats.windows(2).all(|pair| pair[0] < pair[1]));
+    let years = library.distinct_album_values("year").unwrap();
+    assert!(years.len() < library.albums.len());
+    assert_eq!(library.distinct_item_values("no_such_field"), None);
+    Ok(())
+}
+
+#[test]
+fn watcher_notices_writes() -> std::io::Result<()> {
+    use std::io::Write as _;
+
+    let path = std::env::temp_dir().join(format!("beet_db-watch-{}.db", std::process::id()));
+    std::fs::write(&path, b"before")?;
+    let mut watcher = Watcher::new(&path)?;
+    assert!(!watcher.changed()?);
+
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)?
+        .write_all(b", after")?;
+    assert!(watcher.changed()?);
+    assert!(!watcher.changed()?);
+
+    std::fs::remove_file(&path)?;
+    assert!(watcher.changed()?);
+    Ok(())
+}
+
+#[cfg(feature = "remote")]
+#[test]
+fn fetch_url_revalidates_by_etag() -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/library.db", listener.local_addr()?);
+    let server = std::thread::spawn(move || -> std::io::Result<Vec<u16>> {
+        let body = std::fs::read("tests/test.db")?;
+        let mut statuses = vec![];
+        for stream in listener.incoming().take(2) {
+            let mut stream = stream?;
+            let mut request = BufReader::new(stream.try_clone()?);
+            let mut cached = false;
+            loop {
+                let mut line = String::new();
+                request.read_line(&mut line)?;
+                if line.trim().is_empty() {
+                    break;
+                }
+                cached |= line.eq_ignore_ascii_case("if-none-match: \"v1\"\r\n");
+            }
+            if cached {
+                write!(
+                    stream,
+                    "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n"
+                )?;
+                statuses.push(304);
+            } else {
+                let length = body.len();
+                write!(stream, "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {length}\r\nConnection: close\r\n\r\n")?;
+                stream.write_all(&body)?;
+                statuses.push(200);
+            }
+        }
+        Ok(statuses)
+    });
+
+    let cache = std::env::temp_dir().join(format!("beet_db-remote-{}", std::process::id()));
+    let path = fetch_url(&url, &cache)?;
+    assert_eq!(fetch_url(&url, &cache)?, path);
+    let (albums, _) = OpenOptions::new().open(&path)?.read_all()?;
+    assert!(!albums.is_empty());
+    assert_eq!(server.join().unwrap()?, [200, 304]);
+    assert!(matches!(
+        fetch_url("ftp://example.com/library.db", &cache),
+        Err(RemoteError::Url(_))
+    ));
+    std::fs::remove_dir_all(cache)?;
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn snapshot_serves_until_database_changes() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("beet_db-cache-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("library.db");
+    std::fs::copy("tests/test.db", &path)?;
+
+    let fresh = read_all(&path)?;
+    assert_eq!(read_all_cached(&path)?, fresh);
+    assert!(snapshot_path(&path).exists());
+    assert_eq!(read_all_cached(&path)?, fresh);
+
+    let conn = Connection::open(&path)?;
+    conn.execute(
+        "UPDATE items SET title = 'Retitled' WHERE id = ?1",
+        [fresh.1[0].id],
+    )?;
+    drop(conn);
+    let (_, items) = read_all_cached(&path)?;
+    assert_eq!(items[0].title, "Retitled");
+
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn items_changed_since() -> Result<(), Error> {
+    let conn = Connection::open_with_flags("tests/test.db", OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let items = Item::read_all(&conn)?;
+    let mut stamps: Vec<f64> = items
+        .iter()
+        .map(|item| item.added.max(item.mtime))
+        .collect();
+    stamps.sort_by(f64::total_cmp);
+    let since = stamps[stamps.len() / 2];
+
+    let changed: Vec<u32> = Item::read_changed_since(&conn, since)?
+        .iter()
+        .map(|item| item.id)
+        .collect();
+    let expected: Vec<u32> = items
+        .iter()
+        .filter(|item| item.added > since || item.mtime > since)
+        .map(|item| item.id)
+        .collect();
+    assert!(!changed.is_empty() && changed.len() < items.len());
+    assert_eq!(changed, expected);
+    Ok(())
+}
+
+#[test]
+fn attributes_upsert_and_delete() -> Result<(), Error> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connection();
+    let value_of = |entity: Entity| -> Result<Vec<String>, Error> {
+        let sql = format!(
+            "SELECT value FROM {} WHERE entity_id = ?1 AND key = 'mood'",
+            entity.attribute_table()
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let values = stmt
+            .query_map([entity.id()], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(values)
+    };
+
+    set_attribute(conn, Entity::Item(7), "mood", "calm")?;
+    set_attribute(conn, Entity::Item(7), "mood", "restless")?;
+    set_attribute(conn, Entity::Album(7), "mood", "sunny")?;
+    assert_eq!(value_of(Entity::Item(7))?, ["restless"]);
+    assert_eq!(value_of(Entity::Album(7))?, ["sunny"]);
+
+    assert!(delete_attribute(conn, Entity::Item(7), "mood")?);
+    assert!(!delete_attribute(conn, Entity::Item(7), "mood")?);
+    assert!(value_of(Entity::Item(7))?.is_empty());
+    assert_eq!(value_of(Entity::Album(7))?, ["sunny"]);
+    Ok(())
+}
+
+#[test]
+fn attribute_maps_by_entity() -> Result<(), Error> {
+    let conn = Connection::open_with_flags("tests/test.db", OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let items = Attribute::read_item_map(&conn)?;
+    assert_eq!(items.len(), 7569);
+    assert_eq!(items.values().map(Vec::len).sum::<usize>(), 14443);
+    assert!(items.iter().all(|(id, attributes)| attributes
+        .iter()
+        .all(|attribute| attribute.entity_id == *id)));
+    assert!(Attribute::read_album_map(&conn)?.is_empty());
+
+    let db = Database::open_in_memory()?;
+    db.connection().execute(
+        "INSERT INTO album_attributes (entity_id, key, value) VALUES (3, 'rating', 4)",
+        (),
+    )?;
+    let albums = Attribute::read_album_map(db.connection())?;
+    assert_eq!(albums[&3][0].value, "4");
+    Ok(())
+}
+
+#[test]
+fn album_totals() -> Result<(), Error> {
+    let conn = Connection::open_with_flags("tests/test.db", OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let totals = Album::aggregate(&conn)?;
+    let (albums, items) = read_all("tests/test.db")?;
+    assert_eq!(totals.len(), albums.len());
+    for album in totals.iter().step_by(25) {
+        let tracks: Vec<&Item> = items
+            .iter()
+            .filter(|item| item.album_id == Some(album.album_id))
+            .collect();
+        assert_eq!(album.tracks as usize, tracks.len());
+        let length: f64 = tracks.iter().map(|item| item.length).sum();
+        assert!((album.length - length).abs() < 1e-6);
+        assert_eq!(album.size, None);
+    }
+
+    let db = Database::open_in_memory()?;
+    db.connection().execute_batch(
+        "INSERT INTO albums (id) VALUES (1), (2);
+        INSERT INTO items (album_id, path, length, bitrate)
+            VALUES (1, 'Cargo.toml', 10, 1000), (1, 'gone.flac', 20, 2000);",
+    )?;
+    let totals = Album::aggregate_with_size(db.connection())?;
+    let size = std::fs::metadata("Cargo.toml").unwrap().len();
+    assert_eq!(
+        totals,
+        [
+            AlbumTotals {
+                album_id: 1,
+                tracks: 2,
+                length: 30.0,
+                average_bitrate: 1500,
+                size: Some(size),
+            },
+            AlbumTotals {
+                album_id: 2,
+                size: Some(0),
+                ..AlbumTotals::default()
+            },
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn artist_stats_totals() {
+    let item = |artist: &str, genre: &str, year: u32, length: f64| Item {
+        artist: artist.to_string(),
+        genre: genre.to_string(),
+        year,
+        length,
+        ..Item::default()
+    };
+    let library = Library {
+        albums: vec![Album {
+            albumartist: "Floating Points".to_string(),
+            ..Album::default()
+        }],
+        items: vec![
+            item("Floating Points feat. Pharoah Sanders", "Jazz", 2021, 300.0),
+            item("Floating Points", "Electronic", 2015, 200.0),
+            item("Floating Points", "Jazz", 0, 100.0),
+        ],
+    };
+
+    let stats = library.artist_stats(&ArtistSplitter::new());
+    assert_eq!(
+        stats,
+        [
+            ArtistStats {
+                artist: "Floating Points".to_string(),
+                albums: 1,
+                tracks: 3,
+                length: 600.0,
+                years: Some((2015, 2021)),
+                genre: Some("Jazz".to_string()),
+            },
+            ArtistStats {
+                artist: "Pharoah Sanders".to_string(),
+                albums: 0,
+                tracks: 1,
+                length: 300.0,
+                years: Some((2021, 2021)),
+                genre: Some("Jazz".to_string()),
+            },
+        ]
+    );
+}
+
+#[test]
+fn library_stats_totals() {
+    let item = |genre: &str, format: &str, year: u32| Item {
+        genre: genre.to_string(),
+        format: format.to_string(),
+        year,
+        length: 120.0,
+        ..Item::default()
+    };
+    let album = |id: u32, added: f64| Album {
+        id,
+        added,
+        ..Album::default()
+    };
+    let library = Library {
+        albums: vec![album(1, 100.0), album(2, 300.0), album(3, 200.0)],
+        items: vec![
+            item("Jazz", "FLAC", 1959),
+            item("Jazz", "MP3", 1959),
+            item("Ambient", "FLAC", 1978),
+            item("", "FLAC", 0),
+        ],
+    };
+
+    let stats = library.stats();
+    assert_eq!((stats.items, stats.albums), (4, 3));
+    assert!((stats.length - 480.0).abs() < f64::EPSILON);
+    assert_eq!(
+        LibraryStats::ranked(&stats.genres),
+        [("Jazz".to_string(), 2), ("Ambient".to_string(), 1)]
+    );
+    assert_eq!(
+        LibraryStats::ranked(&stats.formats),
+        [("FLAC".to_string(), 3), ("MP3".to_string(), 1)]
+    );
+    assert_eq!(
+        stats.years.into_iter().collect::<Vec<_>>(),
+        [(1959, 2), (1978, 1)]
+    );
+    let recent: Vec<_> = stats.recently_added.iter().map(|album| album.id).collect();
+    assert_eq!(recent, [2, 3, 1]);
+}
+
+#[test]
+fn year_in_review() {
+    // 2023-03-10, 2023-03-20, 2023-11-05 and 2022-12-31 (UTC)
+    let item = |id: u32, artist: &str, genre: &str, added: f64| Item {
+        id,
+        artist: artist.to_string(),
+        genre: genre.to_string(),
+        length: 240.0,
+        added,
+        ..Item::default()
+    };
+    let library = Library {
+        albums: vec![Album {
+            added: 1_678_406_400.0,
+            ..Album::default()
+        }],
+        items: vec![
+            item(1, "Burial", "Dubstep", 1_678_406_400.0),
+            item(2, "Burial", "Dubstep", 1_679_270_400.0),
+            item(3, "Kode9 & <Spaceape>", "Grime", 1_699_142_400.0),
+            item(4, "Burial", "Garage", 1_672_444_800.0),
+        ],
+    };
+    let mut attributes = std::collections::HashMap::new();
+    attributes.insert(
+        3,
+        vec![Attribute {
+            entity_id: 3,
+            key: "play_count".to_string(),
+            value: "12".to_string(),
+            ..Attribute::default()
+        }],
+    );
+    let plays = play_counts(&attributes);
+
+    let review = library.year_in_review(2023, &plays);
+    assert_eq!(review.albums_added, 1);
+    assert_eq!(review.items_added, 3);
+    assert_eq!(review.items_by_month[2], 2);
+    assert_eq!(review.biggest_month, Some(3));
+    assert_eq!(review.top_genre.as_deref(), Some("Dubstep"));
+    assert_eq!(review.top_artists[0].artist, "Burial");
+    assert_eq!(review.top_artists[0].count, 2);
+    assert_eq!(
+        review.top_played_artists,
+        [RankedArtist {
+            artist: "Kode9 & <Spaceape>".to_string(),
+            count: 12,
+        }]
+    );
+    let html = review.to_html();
+    assert!(html.contains("<li>Kode9 &amp; &lt;Spaceape&gt; (12 plays)</li>"));
+    assert!(html.contains("Biggest month: March"));
+
+    assert_eq!(library.year_in_review(2022, &plays).items_added, 1);
+}
+
+#[test]
+fn civil_date_out_of_range_is_the_epoch() {
+    use crate::review::civil_date;
+
+    assert_eq!(civil_date(1_699_142_400.0), (2023, 11, 5));
+    assert_eq!(civil_date(-86_400.0), (1969, 12, 31));
+    for timestamp in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 1e300, -1e300] {
+        assert_eq!(civil_date(timestamp), (1970, 1, 1));
+    }
+}
+
+#[test]
+fn playlist_presets() {
+    // 2015-01-01, 2015-06-01 and 2023-03-10 (UTC)
+    let item = |id: u32, year: u32, original_year: u32, added: f64| Item {
+        id,
+        year,
+        original_year,
+        added,
+        ..Item::default()
+    };
+    let library = Library {
+        albums: vec![],
+        items: vec![
+            item(1, 2012, 1994, 1_420_070_400.0),
+            item(2, 1999, 0, 1_433_116_800.0),
+            item(3, 2021, 0, 1_678_406_400.0),
+            item(4, 0, 0, 1_678_406_400.0),
+        ],
+    };
+    let plays = std::collections::HashMap::from([(2, 5)]);
+    // 2024-01-01 (UTC)
+    let now = 1_704_067_200.0;
+
+    assert_eq!(
+        library.presets(&plays, now),
+        [
+            (PlaylistPreset::Decade(1990), 2),
+            (PlaylistPreset::Decade(2020), 1),
+            (PlaylistPreset::Added(2015), 2),
+            (PlaylistPreset::Added(2023), 2),
+            (PlaylistPreset::ForgottenGems, 1),
+        ]
+    );
+    let ids = |preset: &str| -> Vec<u32> {
+        library
+            .preset_items(preset.parse().unwrap(), &plays, now)
+            .iter()
+            .map(|item| item.id)
+            .collect()
+    };
+    assert_eq!(ids("1990s"), [1, 2]);
+    assert_eq!(ids("added-2023"), [3, 4]);
+    // item 2 was played too often to be forgotten
+    assert_eq!(ids("forgotten-gems"), [1]);
+
+    for preset in ["1990s", "added-2015", "forgotten-gems"] {
+        assert_eq!(
+            preset.parse::<PlaylistPreset>().unwrap().to_string(),
+            preset
+        );
+    }
+    assert!("1995s".parse::<PlaylistPreset>().is_err());
+    assert!("oldies".parse::<PlaylistPreset>().is_err());
+}
+
+#[test]
+fn quality_audit_findings() {
+    let item = |id: u32, format: &str, bitrate: u32, samplerate: u32, bitdepth: u32| Item {
+        id,
+        album_id: Some(1),
+        format: format.to_string(),
+        bitrate,
+        samplerate,
+        bitdepth,
+        ..Item::default()
+    };
+    let mut library = Library {
+        albums: vec![],
+        items: vec![
+            item(1, "FLAC", 900_000, 44_100, 16),
+            item(2, "FLAC", 1_400_000, 96_000, 0),
+            item(3, "MP3", 128_000, 44_100, 0),
+            Item {
+                album_id: None,
+                ..item(4, "AAC", 256_000, 0, 0)
+            },
+        ],
+    };
+
+    assert_eq!(
+        library.quality_audit(&QualityThresholds::default()),
+        [
+            QualityFinding::MissingBitDepth { item_id: 2 },
+            QualityFinding::LowBitrate {
+                item_id: 3,
+                bitrate: 128_000,
+            },
+            QualityFinding::LossyInLosslessAlbum {
+                album_id: 1,
+                item_id: 3,
+                format: "MP3".to_string(),
+            },
+            QualityFinding::MixedSampleRates {
+                album_id: 1,
+                samplerates: vec![44_100, 96_000],
+            },
+        ]
+    );
+
+    library.items[1].format = "MP3".to_string();
+    let findings = library.quality_audit(&QualityThresholds {
+        min_bitrate: 100_000,
+    });
+    assert!(!findings
+        .iter()
+        .any(|finding| matches!(finding, QualityFinding::LossyInLosslessAlbum { .. })));
+}
+
+#[test]
+fn album_consistency_findings() {
+    let track = |disc: u32, track: u32, year: u32| Item {
+        album_id: Some(5),
+        albumartist: "Stereolab".to_string(),
+        disc,
+        disctotal: 1,
+        track,
+        tracktotal: 4,
+        year,
+        ..Item::default()
+    };
+    let mut library = Library {
+        albums: vec![Album {
+            id: 5,
+            albumartist: "Stereolab".to_string(),
+            year: 1996,
+            disctotal: 1,
+            ..Album::default()
+        }],
+        items: vec![track(1, 1, 1996), track(1, 2, 1996), track(1, 4, 1996)],
+    };
+    assert_eq!(
+        library.consistency_check(),
+        [ConsistencyFinding::TrackGaps {
+            album_id: 5,
+            disc: 1,
+            missing: vec![3],
+        }]
+    );
+
+    library.items.push(Item {
+        albumartist: "Stereolab & Nurse With Wound".to_string(),
+        ..track(2, 3, 1997)
+    });
+    assert_eq!(
+        library.consistency_check(),
+        [
+            ConsistencyFinding::AlbumArtist {
+                album_id: 5,
+                albumartists: vec![
+                    "Stereolab".to_string(),
+                    "Stereolab & Nurse With Wound".to_string(),
+                ],
+            },
+            ConsistencyFinding::Year {
+                album_id: 5,
+                years: vec![1996, 1997],
+            },
+            ConsistencyFinding::TrackGaps {
+                album_id: 5,
+                disc: 1,
+                missing: vec![3],
+            },
+            ConsistencyFinding::TrackGaps {
+                album_id: 5,
+                disc: 2,
+                missing: vec![1, 2, 4],
+            },
+            ConsistencyFinding::Disctotal {
+                album_id: 5,
+                disctotals: vec![1],
+                last_disc: 2,
+            },
+        ]
+    );
+}
+
+#[test]
+fn album_consistency_ignores_garbage_tracktotal() {
+    let track = |track: u32| Item {
+        album_id: Some(5),
+        track,
+        tracktotal: u32::MAX,
+        ..Item::default()
+    };
+    let library = Library {
+        albums: vec![Album {
+            id: 5,
+            ..Album::default()
+        }],
+        items: vec![track(1), track(3), track(4)],
+    };
+    assert_eq!(
+        library.consistency_check(),
+        [ConsistencyFinding::TrackGaps {
+            album_id: 5,
+            disc: 1,
+            missing: vec![2],
+        }]
+    );
+}
+
+#[cfg(feature = "art")]
+#[test]
+fn probe_art_flags_small_and_oblong_covers() -> std::io::Result<()> {
+    let dir = std::env::temp_dir().join(format!("beet_db-art-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    // just the headers, which is all that is read
+    let png = |width: u32, height: u32| {
+        let mut bytes = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes.extend_from_slice(&[8, 2, 0, 0, 0]);
+        bytes
+    };
+    let mut gif = b"GIF89a".to_vec();
+    gif.extend_from_slice(&[0x2c, 0x01, 0xc8, 0x00, 0, 0, 0]); // 300x200
+    let covers = [
+        ("big.png", png(1200, 1200)),
+        ("near.png", png(1000, 995)),
+        ("small.gif", gif),
+        ("text.jpg", b"not an image".to_vec()),
+    ];
+    let mut library = Library::default();
+    for (id, (name, bytes)) in (1..).zip(&covers) {
+        std::fs::write(dir.join(name), bytes)?;
+        library.albums.push(Album {
+            id,
+            artpath: Some(dir.join(name)),
+            ..Album::default()
+        });
+    }
+    library.albums.push(Album {
+        id: 9,
+        ..Album::default()
+    });
+
+    let report = library.probe_art(&ArtThresholds::default());
+    std::fs::remove_dir_all(&dir)?;
+    let sizes: Vec<_> = report
+        .probes
+        .iter()
+        .map(|probe| {
+            (
+                probe.album_id,
+                probe.width,
+                probe.height,
+                probe.format.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        sizes,
+        [
+            (1, 1200, 1200, "PNG"),
+            (2, 1000, 995, "PNG"),
+            (3, 300, 200, "GIF")
+        ]
+    );
+    assert!(matches!(
+        report.findings.as_slice(),
+        [
+            ArtFinding::Tiny {
+                album_id: 3,
+                width: 300,
+                height: 200,
+            },
+            ArtFinding::NonSquare { album_id: 3, .. },
+            ArtFinding::Unreadable { album_id: 4, .. },
+        ]
+    ));
+    Ok(())
+}
+
+#[test]
+fn query_builder_matches_filtering_in_memory() -> Result<(), Error> {
+    let conn = rusqlite::Connection::open("tests/test.db")?;
+    let items = Item::read_all(&conn)?;
+    let artist = items[0].artist.clone();
+
+    let query = Item::query()
+        .artist_eq(&artist)
+        .year_between(1995, 2005)
+        .order_by("id", false);
+    assert_eq!(query.params().len(), 3);
+    let expected: Vec<_> = items
+        .iter()
+        .rev()
+        .filter(|item| item.artist == artist && (1995..=2005).contains(&item.year))
+        .cloned()
+        .collect();
+    assert_eq!(query.run(&conn)?, expected);
+
+    // wildcards in the text are matched literally
+    let titled: Vec<_> = items
+        .iter()
+        .filter(|item| item.title.to_lowercase().contains("the"))
+        .cloned()
+        .collect();
+    assert_eq!(Item::query().contains("title", "THE").run(&conn)?, titled);
+    assert!(Item::query().contains("title", "%").run(&conn)?.len() < items.len());
+
+    let albums = Album::query().comp(true).limit(2).run(&conn)?;
+    assert!(albums.len() <= 2 && albums.iter().all(|album| album.comp));
+
+    let err = Item::query().eq("nonsense", 1).run(&conn).unwrap_err();
+    assert!(matches!(
+        err.source,
+        Some(rusqlite::Error::InvalidColumnName(ref column)) if column == "nonsense"
+    ));
+    Ok(())
+}
+
+#[test]
+fn database_query_uses_the_hooks() -> Result<(), Error> {
+    use std::sync::{Arc, Mutex};
+
+    let traced = Arc::new(Mutex::new(vec![]));
+    let mut db = OpenOptions::new().open("tests/test.db")?;
+    let sink = Arc::clone(&traced);
+    db.set_trace_hook(move |trace: &QueryTrace| {
+        sink.lock().unwrap().push(trace.rows);
+    });
+    let query = Item::query().year_between(1995, 2005);
+    let found = db.query(&query)?;
+    assert_eq!(found, query.run(db.connection())?);
+    assert_eq!(*traced.lock().unwrap(), [found.len()]);
+
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    db.set_cancellation_token(cancel);
+    assert!(db.query(&query).unwrap_err().is_cancelled());
+    Ok(())
+}
+
+#[test]
+fn queue_gains_and_crossfades() {
+    let track = |id, track, length| Item {
+        id,
+        album_id: Some(1),
+        disc: 1,
+        track,
+        length,
+        rg_track_gain: Some(-3.0),
+        rg_album_gain: Some(-6.0),
+        rg_album_peak: Some(0.25),
+        ..Item::default()
+    };
+    let library = Library {
+        albums: vec![Album {
+            id: 1,
+            ..Album::default()
+        }],
+        items: vec![
+            track(2, 2, 200.0),
+            track(1, 1, 180.0),
+            Item {
+                id: 3,
+                length: 20.0,
+                r128_track_gain: R128Gain::from_db(-2.5),
+                ..Item::default()
+            },
+            Item {
+                id: 4,
+                length: 240.0,
+                ..Item::default()
+            },
+        ],
+    };
+
+    let queue = Queue::new(&library)
+        .album(1)
+        .items(|item| item.album_id.is_none())
+        .crossfade(8.0)
+        .preamp(3.0)
+        .build();
+    let entries: Vec<_> = queue
+        .entries
+        .iter()
+        .map(|entry| (entry.item_id, entry.gain, entry.crossfade))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            // album gain, gapless into the next track
+            (1, Some(-3.0), 0.0),
+            // crossfading into a short track
+            (2, Some(-3.0), 5.0),
+            // R128, raised to the ReplayGain reference
+            (3, Some(5.5), 5.0),
+            (4, None, 0.0),
+        ]
+    );
+    assert!((queue.length() - 630.0).abs() < 1e-9);
+
+    // the album peaks at a quarter of full scale, so gains stop near +12 dB
+    let loud = Queue::new(&library).album(1).preamp(20.0).build();
+    let gain = loud.entries[0].gain.unwrap();
+    assert!((gain - 12.041).abs() < 1e-3, "{}", gain);
+
+    let tracks = Queue::new(&library)
+        .album(1)
+        .gain_mode(GainMode::Track)
+        .build();
+    assert_eq!(tracks.entries[0].gain, Some(-3.0));
+}
+
+#[test]
+fn sessions_round_trip() -> Result<(), Error> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connection();
+    assert_eq!(Session::load(conn, "kitchen")?, None);
+    assert!(Session::list(conn)?.is_empty());
+
+    let library = Library {
+        items: (1..=3)
+            .map(|id| Item {
+                id,
+                ..Item::default()
+            })
+            .collect(),
+        ..Library::default()
+    };
+    let mut session = Session::new("kitchen", &Queue::new(&library).items(|_| true).build());
+    session.advance();
+    session.elapsed = 42.5;
+    assert_eq!(session.current(), Some(2));
+    session.save(conn)?;
+    Session::new("porch", &PlayQueue::default()).save(conn)?;
+
+    assert_eq!(Session::load(conn, "kitchen")?.as_ref(), Some(&session));
+    let mut names = Session::list(conn)?;
+    names.sort();
+    assert_eq!(names, ["kitchen", "porch"]);
+
+    session.advance();
+    session.advance();
+    session.advance();
+    assert_eq!(
+        (session.current(), session.history.as_slice()),
+        (None, &[1, 2, 3][..])
+    );
+    session.save(conn)?;
+    assert_eq!(Session::load(conn, "kitchen")?, Some(session));
+
+    assert!(Session::delete(conn, "porch")?);
+    assert!(!Session::delete(conn, "porch")?);
+    assert_eq!(Session::list(conn)?, ["kitchen"]);
+    Ok(())
+}
+
+#[test]
+fn records_with_attributes() -> Result<(), Error> {
+    let conn = Connection::open_with_flags("tests/test.db", OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    assert_eq!(Attribute::read_all_for_items(&conn)?.len(), 14443);
+    assert!(Attribute::read_all_for_albums(&conn)?.is_empty());
+
+    let items = Item::read_all_with_attributes(&conn)?;
+    assert_eq!(
+        items.iter().map(|item| &item.record).collect::<Vec<_>>(),
+        Item::read_all(&conn)?.iter().collect::<Vec<_>>()
+    );
+    let with_attributes = items.iter().filter(|item| !item.attributes.is_empty());
+    assert_eq!(with_attributes.count(), 7569);
+
+    let db = Database::open_in_memory()?;
+    let conn = db.connection();
+    conn.execute("INSERT INTO albums (id, album) VALUES (3, 'Geogaddi')", ())?;
+    set_attribute(conn, Entity::Album(3), "rating", "5")?;
+    let albums = Album::read_all_with_attributes(conn)?;
+    assert_eq!(albums[0].record.album, "Geogaddi");
+    assert_eq!(albums[0].attributes["rating"], "5");
+    Ok(())
+}
+
+#[test]
+fn collections_crud_and_export() -> Result<(), Error> {
+    let db = Database::open_in_memory()?;
+    let conn = db.connection();
+    assert!(Collection::list(conn)?.is_empty());
+    assert_eq!(Collection::load(conn, "favorites")?, None);
+
+    assert!(Collection::add(conn, "favorites", Entity::Item(4))?);
+    assert!(Collection::add(conn, "favorites", Entity::Album(1))?);
+    assert!(!Collection::add(conn, "favorites", Entity::Item(4))?);
+    assert!(Collection::create(conn, "later")?);
+    assert!(!Collection::create(conn, "later")?);
+    assert_eq!(Collection::list(conn)?, ["favorites", "later"]);
+    assert_eq!(
+        Collection::load(conn, "later")?.map(|later| later.members),
+        Some(vec![])
+    );
+
+    let track = |id, track| Item {
+        id,
+        album_id: Some(1),
+        track,
+        ..Item::default()
+    };
+    let library = Library {
+        albums: vec![Album {
+            id: 1,
+            ..Album::default()
+        }],
+        items: vec![
+            track(1, 2),
+            track(2, 1),
+            Item {
+                id: 4,
+                ..Item::default()
+            },
+        ],
+    };
+    let favorites = Collection::load(conn, "favorites")?.unwrap();
+    assert_eq!(favorites.members, [Entity::Item(4), Entity::Album(1)]);
+    let ids = |items: Vec<&Item>| items.iter().map(|item| item.id).collect::<Vec<_>>();
+    assert_eq!(ids(favorites.items(&library)), [4, 2, 1]);
+    let queue = favorites.to_queue(&library);
+    let queued: Vec<_> = queue.entries.iter().map(|entry| entry.item_id).collect();
+    assert_eq!(queued, [4, 2, 1]);
+
+    assert!(Collection::remove(conn, "favorites", Entity::Item(4))?);
+    assert!(!Collection::remove(conn, "favorites", Entity::Item(4))?);
+    assert!(Collection::rename(conn, "favorites", "best")?);
+    assert_eq!(
+        Collection::load(conn, "best")?.map(|best| best.members),
+        Some(vec![Entity::Album(1)])
+    );
+    assert!(Collection::delete(conn, "best")?);
+    assert!(!Collection::delete(conn, "best")?);
+    assert_eq!(Collection::list(conn)?, ["later"]);
+    Ok(())
+}
+
+#[test]
+fn library_tree_nests_items() -> Result<(), Error> {
+    let db = OpenOptions::new().open("tests/test.db")?;
+    let (albums, items) = db.read_all()?;
+    let tree = db.read_tree()?;
+    assert_eq!(tree.albums.len(), albums.len());
+    assert_eq!(tree.items().count(), items.len());
+    assert!(tree.orphans.is_empty());
+    assert!(tree.singletons.iter().all(|item| item.album_id.is_none()));
+    for node in &tree.albums {
+        assert!(node
+            .items
+            .iter()
+            .all(|item| item.album_id == Some(node.album.id)));
+        assert!(node
+            .items
+            .windows(2)
+            .all(|pair| (pair[0].disc, pair[0].track) <= (pair[1].disc, pair[1].track)));
+    }
+
+    let library = Library {
+        albums: vec![Album {
+            id: 1,
+            ..Album::default()
+        }],
+        items: vec![
+            Item {
+                id: 1,
+                album_id: Some(2),
+                ..Item::default()
+            },
+            Item {
+                id: 2,
+                album_id: Some(1),
+                ..Item::default()
+            },
+        ],
+    };
+    let tree = LibraryTree::from(library.clone());
+    assert_eq!(tree.album(1).map(|node| node.items.len()), Some(1));
+    assert_eq!(tree.orphans[0].id, 1);
+    let mut flat = Library::from(tree);
+    flat.items.sort_by_key(|item| item.id);
+    assert_eq!(flat, library);
+    Ok(())
+}
+
+#[test]
+fn database_reads_each_table() -> Result<(), Error> {
+    let db = Database::open("tests/test.db")?;
+    let (albums, items) = read_all("tests/test.db")?;
+    assert_eq!(db.albums()?, albums);
+    assert_eq!(db.items()?, items);
+
+    let item_attributes = db.item_attributes()?;
+    assert_eq!(item_attributes.len(), 7569);
+    assert!(db.album_attributes()?.is_empty());
+    let (&id, expected) = item_attributes.iter().next().unwrap();
+    assert_eq!(&db.attributes(Entity::Item(id))?, expected);
+    assert!(db.attributes(Entity::Album(id))?.is_empty());
+
+    assert!(Database::open("tests/missing.db").is_err());
+    Ok(())
+}
+
+#[test]
+fn redaction_strips_private_fields() {
+    let library = Library {
+        albums: vec![Album {
+            id: 1,
+            album: "Kind of Blue".to_string(),
+            artpath: Some("/home/me/Music/Miles Davis/Kind of Blue/cover.jpg".into()),
+            ..Album::default()
+        }],
+        items: vec![Item {
+            id: 1,
+            album_id: Some(1),
+            title: "So What".to_string(),
+            path: "/home/me/Music/Miles Davis/Kind of Blue/01 So What.flac".into(),
+            lyrics: "(instrumental)".to_string(),
+            comments: "ripped at home".to_string(),
+            ..Item::default()
+        }],
+    };
+    let public = Redaction::public().library(&library);
+    assert_eq!(public.albums[0].album, "Kind of Blue");
+    assert_eq!(
+        public.albums[0].artpath.as_deref(),
+        Some(std::path::Path::new("cover.jpg"))
+    );
+    let item = &public.items[0];
+    assert_eq!(item.title, "So What");
+    assert_eq!(item.path, std::path::Path::new("01 So What.flac"));
+    assert!(item.lyrics.is_empty() && item.comments.is_empty());
+    // the original is untouched
+    assert_eq!(library.items[0].lyrics, "(instrumental)");
+
+    let mut redaction = Redaction::new();
+    redaction.strip("path").unwrap().strip("album").unwrap();
+    assert!(redaction.strip("no_such_field").is_err());
+    let item = redaction.item(&library.items[0]);
+    assert_eq!(item.path, std::path::PathBuf::new());
+    assert_eq!(item.lyrics, "(instrumental)");
+    assert!(redaction.album(&library.albums[0]).album.is_empty());
+}
+
+#[test]
+fn mime_types_from_format() {
+    let mime_type = |format: &str| {
+        Item {
+            format: format.to_string(),
+            ..Item::default()
+        }
+        .mime_type()
+    };
+    assert_eq!(mime_type("MP3"), "audio/mpeg");
+    assert_eq!(mime_type("FLAC"), "audio/flac");
+    assert_eq!(mime_type("ALAC"), "audio/mp4");
+    assert_eq!(mime_type("Opus"), "audio/ogg; codecs=opus");
+    assert_eq!(mime_type(""), "application/octet-stream");
+}
+
+#[test]
+fn records_write_their_attributes() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("beet_db-attributes-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("library.db");
+    std::fs::copy("tests/test.db", &path)?;
+
+    let read_only = Database::open(&path)?;
+    let item = Item::read_all(read_only.connection())?.remove(0);
+    assert!(set_attribute(read_only.connection(), Entity::Item(item.id), "rating", 4).is_err());
+
+    let db = Database::open_writable(&path)?;
+    item.set_attribute(&db, "rating", 4)?;
+    item.set_attribute(&db, "rating", 5)?;
+    item.set_attribute(&db, "loudness", -14.5)?;
+    let attributes = db.attributes(Entity::Item(item.id))?;
+    let value = |key: &str| {
+        attributes
+            .iter()
+            .find(|attribute| attribute.key == key)
+            .map(|attribute| attribute.value.as_str())
+    };
+    assert_eq!(value("rating"), Some("5"));
+    assert_eq!(value("loudness"), Some("-14.5"));
+    assert!(item.remove_attribute(&db, "rating")?);
+    assert!(!item.remove_attribute(&db, "rating")?);
+
+    let album = db.albums()?.remove(0);
+    album.set_attribute(&db, "mood", "sunny")?;
+    assert!(db
+        .attributes(Entity::Album(album.id))?
+        .iter()
+        .any(|attribute| attribute.key == "mood" && attribute.value == "sunny"));
+
+    drop((read_only, db));
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn items_and_albums_round_trip_through_writes() -> Result<(), Error> {
+    let db = Database::open_in_memory()?;
+    let album = Album {
+        album: "Geogaddi".to_string(),
+        albumartist: "Boards of Canada".to_string(),
+        artpath: Some("/music/geogaddi/cover.jpg".into()),
+        year: 2002,
+        ..Album::default()
+    };
+    let album_id = db.transaction(|db| album.insert(db))?;
+    let mut item = Item {
+        album_id: Some(album_id),
+        path: "/music/geogaddi/03 Music Is Math.flac".into(),
+        title: "Music Is Math".to_string(),
+        track: 3,
+        r128_track_gain: R128Gain::from_db(-2.5),
+        ..Item::default()
+    };
+    item.id = db.transaction(|db| item.insert(db))?;
+    item.set_attribute(&db, "rating", 5)?;
+
+    let (albums, items) = db.read_all()?;
+    assert_eq!(
+        albums,
+        [Album {
+            id: album_id,
+            ..album
+        }]
+    );
+    assert_eq!(items, [item.clone()]);
+    // beets stores paths as BLOBs
+    let kind: String = db.connection().query_row(
+        "SELECT typeof(path) FROM items WHERE id = ?1",
+        [item.id],
+        |row| row.get(0),
+    )?;
+    assert_eq!(kind, "blob");
+
+    item.title = "Music is Math".to_string();
+    assert!(item.update(&db)?);
+    assert_eq!(db.items()?, [item.clone()]);
+    assert!(item.insert(&db).is_err());
+
+    // a failed transaction leaves nothing behind
+    let failed: Result<(), Error> = db.transaction(|db| {
+        Item::default().insert(db)?;
+        item.insert(db)?;
+        Ok(())
+    });
+    assert!(failed.is_err());
+    assert_eq!(db.items()?.len(), 1);
+
+    assert!(db.albums()?[0].delete(&db)?);
+    assert_eq!(db.read_all()?, (vec![], vec![]));
+    assert!(db.attributes(Entity::Item(item.id))?.is_empty());
+    assert!(!item.delete(&db)?);
+    Ok(())
+}
+
+#[test]
+fn timestamps_as_system_times() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let item = Item {
+        added: 1_600_000_000.5,
+        mtime: f64::NAN,
+        ..Item::default()
+    };
+    assert_eq!(
+        item.added_systemtime(),
+        UNIX_EPOCH + Duration::from_millis(1_600_000_000_500)
+    );
+    assert_eq!(item.mtime_systemtime(), UNIX_EPOCH);
+}
+
+#[cfg(all(feature = "chrono", feature = "serde"))]
+#[test]
+fn timestamps_serialize_as_rfc_3339() -> Result<(), Box<dyn std::error::Error>> {
+    let item = Item {
+        added: 1_600_000_000.0,
+        mtime: 1_600_000_060.0,
+        ..Item::default()
+    };
+    assert_eq!(
+        item.added_datetime().to_rfc3339(),
+        "2020-09-13T12:26:40+00:00"
+    );
+
+    let json = serde_json::to_value(Timestamped::from(item))?;
+    assert_eq!(json["added"], "2020-09-13T12:26:40Z");
+    assert_eq!(json["mtime"], "2020-09-13T12:27:40Z");
+    assert_eq!(json["id"], 0);
+
+    let json = serde_json::to_value(Timestamped::from(Album::default()))?;
+    assert_eq!(json["added"], "1970-01-01T00:00:00Z");
+    assert!(json.get("mtime").is_none());
+    Ok(())
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn timestamps_out_of_chrono_range_are_the_epoch() {
+    let item = Item {
+        added: 1e13,
+        mtime: -1e13,
+        ..Item::default()
+    };
+    assert_eq!(item.added_datetime().timestamp(), 0);
+    assert_eq!(item.mtime_datetime().timestamp(), 0);
+
+    let item = Item {
+        added: -1.5,
+        ..Item::default()
+    };
+    assert_eq!(item.added_datetime().timestamp_millis(), -1500);
+}
+
+#[test]
+fn read_all_from_bytes_matches_sqlite() -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = std::fs::read("tests/test.db")?;
+    assert_eq!(read_all_from_bytes(&bytes)?, read_all("tests/test.db")?);
+
+    // enough rows for interior pages, and lyrics long enough to overflow
+    let db = Database::open_in_memory()?;
+    let album_id = Album {
+        album: "Geogaddi".to_string(),
+        artpath: Some("/music/geogaddi/cover.jpg".into()),
+        ..Album::default()
+    }
+    .insert(&db)?;
+    db.transaction(|db| {
+        for track in 1..=400 {
+            Item {
+                album_id: Some(album_id),
+                path: format!("/music/geogaddi/{track:03}.flac").into(),
+                title: format!("Track {track}"),
+                track,
+                lyrics: "la ".repeat(track as usize * 20),
+                added: 1_600_000_000.25,
+                r128_track_gain: R128Gain::from_db(-2.5),
+                ..Item::default()
+            }
+            .insert(db)?;
+        }
+        Ok(())
+    })?;
+    let dir = std::env::temp_dir().join(format!("beet_db-bytes-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("library.db");
+    db.connection()
+        .execute("VACUUM INTO ?1", [path.to_string_lossy()])?;
+    let bytes = std::fs::read(&path)?;
+    std::fs::remove_dir_all(dir)?;
+    assert_eq!(read_all_from_bytes(&bytes)?, db.read_all()?);
+
+    assert_eq!(
+        read_all_from_bytes(b"not a database"),
+        Err(BytesError::NotADatabase)
+    );
+    assert_eq!(
+        read_all_from_bytes(&bytes[..bytes.len() / 2]),
+        Err(BytesError::Corrupt)
+    );
+    Ok(())
+}
+
+#[test]
+fn read_all_from_bytes_rejects_shared_pages() {
+    // a root page whose children are all the same empty leaf, as a file
+    // crafted to make the scan revisit pages exponentially would have
+    let mut bytes = vec![0; 1024];
+    bytes[..16].copy_from_slice(b"SQLite format 3\0");
+    bytes[16..18].copy_from_slice(&512_u16.to_be_bytes());
+    bytes[56..60].copy_from_slice(&1_u32.to_be_bytes());
+    bytes[100] = 0x05;
+    bytes[103..105].copy_from_slice(&2_u16.to_be_bytes());
+    bytes[108..112].copy_from_slice(&2_u32.to_be_bytes());
+    for (pointer, cell) in [(112, 200_u16), (114, 210)] {
+        bytes[pointer..pointer + 2].copy_from_slice(&cell.to_be_bytes());
+        let cell = usize::from(cell);
+        bytes[cell..cell + 4].copy_from_slice(&2_u32.to_be_bytes());
+    }
+    bytes[512] = 0x0d;
+    assert_eq!(read_all_from_bytes(&bytes), Err(BytesError::Corrupt));
+}
+
+#[test]
+fn writes_check_for_hazards_first() -> Result<(), Box<dyn std::error::Error>> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let mut db = Database::open_in_memory()?;
+    assert_eq!(db.write_hazards()?, []);
+
+    // columns from a newer beets are only reported
+    db.connection()
+        .execute_batch("ALTER TABLE items ADD COLUMN mood TEXT")?;
+    let reported = Arc::new(AtomicUsize::new(0));
+    let hook_reported = reported.clone();
+    db.set_hazard_hook(move |hazard| {
+        assert!(!hazard.is_blocking());
+        hook_reported.fetch_add(1, Ordering::Relaxed);
+    });
+    // checked once for the whole transaction
+    db.transaction(|db| {
+        Item::default().insert(db)?;
+        Item::default().insert(db)
+    })?;
+    assert_eq!(reported.load(Ordering::Relaxed), 1);
+
+    // columns from an older beets are refused
+    db.connection()
+        .execute_batch("ALTER TABLE albums DROP COLUMN genre")?;
+    let err = Album::default().insert(&db).unwrap_err();
+    assert_eq!(
+        err.write_hazard(),
+        Some(&WriteHazard::MissingColumns {
+            table: "albums",
+            columns: vec!["genre".to_string()],
+        })
+    );
+    db.set_safety_checks(false);
+    assert!(Album::default().insert(&db).is_err());
+
+    // as is writing while another connection is
+    let dir = std::env::temp_dir().join(format!("beet_db-hazards-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("library.db");
+    std::fs::copy("tests/test.db", &path)?;
+    let db = Database::open_writable(&path)?;
+    db.connection().busy_timeout(std::time::Duration::ZERO)?;
+    let other = Connection::open(&path)?;
+    other.execute_batch("BEGIN IMMEDIATE")?;
+    let err = Item::default().insert(&db).unwrap_err();
+    assert_eq!(err.write_hazard(), Some(&WriteHazard::Locked));
+    // the hazard is the cause, rather than an SQLite error that never was
+    let cause = std::error::Error::source(&err).map(ToString::to_string);
+    assert_eq!(cause.as_deref(), Some("another connection is writing"));
+    other.execute_batch("ROLLBACK")?;
+    Item::default().insert(&db)?;
+
+    drop((db, other));
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[cfg(feature = "wasm-bindings")]
+#[test]
+fn parse_library_for_javascript() -> Result<(), Box<dyn std::error::Error>> {
+    let (albums, items) = read_all("tests/test.db")?;
+    let bytes = std::fs::read("tests/test.db")?;
+    let library = parse_library(&bytes).map_err(|_| "not a library")?;
+    assert_eq!(library.albums().len(), albums.len());
+    assert_eq!(library.items().len(), items.len());
+
+    let item = &items[0];
+    let js_item = library.item(item.id).expect("item is in the library");
+    assert_eq!(js_item.title(), item.title);
+    assert_eq!(js_item.album_id(), item.album_id);
+    assert_eq!(js_item.path(), item.path.to_string_lossy());
+    assert!(JsItem::fields().iter().any(|field| field == "title"));
+
+    #[cfg(feature = "serde")]
+    {
+        // JSON is read too
+        let json = serde_json::to_vec(&Library { albums, items })?;
+        let library = parse_library(&json).map_err(|_| "not a library")?;
+        assert_eq!(Library::from(library), Library::from_json_bytes(&json)?);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "typescript")]
+#[test]
+fn typescript_declarations_match_serde() -> Result<(), Box<dyn std::error::Error>> {
+    // an empty record serializes only the properties that are not optional
+    fn required(declaration: &str) -> Vec<String> {
+        let mut names: Vec<String> = declaration
+            .lines()
+            .filter_map(|line| line.trim().split_once(": "))
+            .map(|(name, _)| name.to_string())
+            .filter(|name| !name.ends_with('?'))
+            .collect();
+        names.sort_unstable();
+        names
+    }
+    fn keys(value: serde_json::Value) -> Vec<String> {
+        let serde_json::Value::Object(object) = value else {
+            panic!("records serialize as objects");
+        };
+        object.keys().cloned().collect()
+    }
+    assert_eq!(
+        required(&Album::declaration()),
+        keys(serde_json::to_value(Album::default())?)
+    );
+    assert_eq!(
+        required(&Item::declaration()),
+        keys(serde_json::to_value(Item::default())?)
+    );
+    assert!(Item::declaration().contains("  album_id?: number;\n"));
+    assert!(Album::declaration().contains("  artpath: string | null;\n"));
+    assert!(!Item::declaration().contains("mtime"));
+
+    // the checked-in declarations are up to date
+    let path = "bindings/beet_db.d.ts";
+    if std::env::var_os("UPDATE_BINDINGS").is_some() {
+        std::fs::write(path, typescript_declarations())?;
+    }
+    assert_eq!(
+        std::fs::read_to_string(path)?,
+        typescript_declarations(),
+        "run the tests with UPDATE_BINDINGS=1 to update {path}"
+    );
+    Ok(())
+}
+
+#[test]
+fn writes_can_be_undone() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("beet_db-undo-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("library.db");
+    std::fs::copy("tests/test.db", &path)?;
+    let original = read_all(&path)?;
+
+    let db = Database::open_writable(&path)?;
+    let album = db.albums()?.remove(0);
+    let mut item = db.items()?.remove(1);
+    let attributes = db.item_attributes()?;
+    assert!(album.delete(&db)?);
+    item.title = "Retitled".to_string();
+    item.update(&db)?;
+    item.set_attribute(&db, "rating", 5)?;
+    db.transaction(|db| {
+        item.year = 1999;
+        item.update(db)?;
+        item.remove_attribute(db, "rating")
+    })?;
+    let inserted = Item::default().insert(&db)?;
+    // a write that fails records nothing
+    let duplicate = Item {
+        id: inserted,
+        ..Item::default()
+    };
+    assert!(duplicate.insert(&db).is_err());
+    let descriptions: Vec<_> = db
+        .undo_journal()?
+        .into_iter()
+        .map(|entry| entry.description)
+        .collect();
+    assert_eq!(
+        descriptions,
+        [
+            "insert item".to_string(),
+            "transaction".to_string(),
+            format!("set rating of item {}", item.id),
+            format!("update item {}", item.id),
+            format!("delete album {}", album.id),
+        ]
+    );
+
+    assert_eq!(db.undo_last(1)?, 1);
+    assert!(!db.items()?.iter().any(|item| item.id == inserted));
+    drop(db);
+
+    // the journal outlives the handle
+    assert!(dir.join("library.db.undo").exists());
+    let db = Database::open_writable(&path)?;
+    assert_eq!(db.undo_last(10)?, 4);
+    assert!(db.undo_journal()?.is_empty());
+    assert_eq!(db.read_all()?, original);
+    assert_eq!(db.item_attributes()?, attributes);
+
+    // unless there is none
+    let db = OpenOptions::new()
+        .undo_journal(false)
+        .open_writable(&path)?;
+    item.update(&db)?;
+    assert!(db.undo_journal()?.is_empty());
+    assert_eq!(db.undo_last(1)?, 0);
+
+    // or only the latest writes are kept
+    let db = OpenOptions::new().undo_limit(2).open_writable(&path)?;
+    for year in 2000..2005 {
+        item.year = year;
+        item.update(&db)?;
+    }
+    assert_eq!(db.undo_journal()?.len(), 2);
+    assert_eq!(db.undo_last(10)?, 2);
+    assert_eq!(
+        db.items()?.iter().find(|i| i.id == item.id).map(|i| i.year),
+        Some(2002)
+    );
+
+    drop(db);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn deletes_can_be_restored_from_trash() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("beet_db-trash-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("library.db");
+    std::fs::copy("tests/test.db", &path)?;
+    let original = read_all(&path)?;
+
+    let db = OpenOptions::new().trash(true).open_writable(&path)?;
+    let album = db.albums()?.remove(0);
+    let album_items: Vec<_> = db
+        .items()?
+        .into_iter()
+        .filter(|item| item.album_id == Some(album.id))
+        .collect();
+    let single = db
+        .items()?
+        .into_iter()
+        .find(|item| item.album_id != Some(album.id))
+        .expect("an item of another album");
+    single.set_attribute(&db, "rating", 4)?;
+    let rated = db.item_attributes()?;
+
+    assert!(single.delete(&db)?);
+    assert!(album.delete(&db)?);
+    assert_eq!(db.trashed_albums()?, vec![album.clone()]);
+    let mut trashed = db.trashed_items()?;
+    trashed.sort_by_key(|item| item.id);
+    let mut expected = album_items.clone();
+    expected.push(single.clone());
+    expected.sort_by_key(|item| item.id);
+    assert_eq!(trashed, expected);
+
+    // the trash outlives the handle
+    drop(db);
+    let db = OpenOptions::new().trash(true).open_writable(&path)?;
+    assert!(db.restore_album(album.id)?);
+    assert!(!db.restore_album(album.id)?);
+    assert!(db.restore_item(single.id)?);
+    assert_eq!(db.read_all()?, original);
+    assert_eq!(db.item_attributes()?, rated);
+    assert!(db.trashed_items()?.is_empty());
+
+    // restoring is undone like any write, back into the trash
+    assert_eq!(db.undo_last(1)?, 1);
+    assert_eq!(db.trashed_items()?, vec![single.clone()]);
+    assert!(db.restore_item(single.id)?);
+
+    // trashing again replaces the copy from before
+    assert!(single.delete(&db)?);
+    assert!(single.delete(&db).map(|deleted| !deleted)?);
+    single.insert(&db)?;
+    assert!(single.delete(&db)?);
+    assert_eq!(db.trashed_items()?, vec![single.clone()]);
+    assert_eq!(db.empty_trash()?, 1);
+    assert!(db.trashed_items()?.is_empty());
+    assert!(!db.restore_item(single.id)?);
+    // nor can undoing the deletes bring it back
+    db.undo_last(usize::MAX)?;
+    assert!(!db.items()?.iter().any(|item| item.id == single.id));
+    assert!(db.trashed_items()?.is_empty());
+
+    // without a trash, deleting is for good
+    let db = Database::open_writable(&path)?;
+    assert!(album.delete(&db)?);
+    assert!(db.trashed_albums()?.is_empty());
+
+    drop(db);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn split_albums_are_found_and_merged() -> Result<(), Box<dyn std::error::Error>> {
+    let db = Database::open_in_memory()?;
+    let part = |year| Album {
+        album: "Geogaddi".to_string(),
+        albumartist: "Boards of Canada".to_string(),
+        year,
+        ..Album::default()
+    };
+    let first = part(2002).insert(&db)?;
+    let second = part(2002).insert(&db)?;
+    let other_year = part(2013).insert(&db)?;
+    for (album_id, disc, track) in [
+        (first, 1, 1),
+        (first, 1, 2),
+        (second, 2, 1),
+        (other_year, 1, 1),
+    ] {
+        Item {
+            album_id: Some(album_id),
+            disc,
+            track,
+            ..Item::default()
+        }
+        .insert(&db)?;
+    }
+    let (albums, items) = db.read_all()?;
+    albums[1].set_attribute(&db, "genre_note", "disc two")?;
+    albums[0].set_attribute(&db, "source", "cd")?;
+    albums[1].set_attribute(&db, "source", "vinyl")?;
+    let original = db.read_all()?;
+    let attributes = db.album_attributes()?;
+
+    let library = Library { albums, items };
+    let split = library.split_albums();
+    assert_eq!(split.len(), 1);
+    assert_eq!(split[0].keep.id, first);
+    assert_eq!(split[0].part_ids(), [second]);
+
+    // halves with a track in common are copies, not halves
+    let mut copies = library.clone();
+    copies.items[2].disc = 1;
+    assert!(copies.split_albums().is_empty());
+    // nor are different releases
+    let mut releases = library.clone();
+    releases.albums[0].mb_albumid = "a".to_string();
+    releases.albums[1].mb_albumid = "b".to_string();
+    assert!(releases.split_albums().is_empty());
+
+    assert!(db.merge_albums(first, &[second])?);
+    let (albums, items) = db.read_all()?;
+    assert!(!albums.iter().any(|album| album.id == second));
+    let merged = items
+        .iter()
+        .filter(|item| item.album_id == Some(first))
+        .count();
+    assert_eq!(merged, 3);
+    // attributes move along unless the album kept has its own
+    let mut kept: Vec<_> = db.album_attributes()?[&first]
+        .iter()
+        .map(|attribute| (attribute.key.clone(), attribute.value.clone()))
+        .collect();
+    kept.sort();
+    assert_eq!(
+        kept,
+        [
+            ("genre_note".to_string(), "disc two".to_string()),
+            ("source".to_string(), "cd".to_string()),
+        ]
+    );
+    assert!(!db.album_attributes()?.contains_key(&second));
+    assert!(!db.merge_albums(second, &[first])?);
+
+    assert_eq!(db.undo_last(1)?, 1);
+    assert_eq!(db.read_all()?, original);
+    assert_eq!(db.album_attributes()?, attributes);
+    Ok(())
+}
+
+#[test]
+fn changelog_records_changes_by_other_programs() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("beet_db-changelog-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("library.db");
+    std::fs::copy("tests/test.db", &path)?;
+
+    let db = Database::open_writable(&path)?;
+    assert!(db.changelog(0.0)?.is_empty());
+    db.enable_changelog()?;
+    // enabling again only logs what changed in between, which is nothing
+    db.enable_changelog()?;
+    let item = db.items()?.remove(0);
+
+    // another program, writing to the library as beets does, with the
+    // changelog read after each write as changes in between merge
+    let other = Connection::open(&path)?;
+    other.execute(
+        "UPDATE items SET genre = 'Shoegaze', title = title WHERE id = ?1",
+        [item.id],
+    )?;
+    db.changelog(0.0)?;
+    other.execute(
+        "INSERT INTO item_attributes (entity_id, key, value) VALUES (?1, 'mood', 'calm')",
+        [item.id],
+    )?;
+    db.changelog(0.0)?;
+    other.execute(
+        "INSERT INTO item_attributes (entity_id, key, value) VALUES (?1, 'mood', 'tense')",
+        [item.id],
+    )?;
+    db.changelog(0.0)?;
+    other.execute("DELETE FROM items WHERE id = ?1", [item.id])?;
+
+    let changes: Vec<_> = db
+        .changelog(0.0)?
+        .into_iter()
+        .map(|change| {
+            (
+                change.table,
+                change.id,
+                change.kind,
+                change.field,
+                change.old,
+                change.new,
+            )
+        })
+        .collect();
+    let text = |t: &str| FieldValue::Text(t.to_string().into());
+    assert_eq!(
+        changes,
+        [
+            (
+                "items".to_string(),
+                item.id,
+                ChangeKind::Update,
+                Some("genre".to_string()),
+                text(&item.genre),
+                text("Shoegaze"),
+            ),
+            (
+                "item_attributes".to_string(),
+                item.id,
+                ChangeKind::Insert,
+                Some("mood".to_string()),
+                FieldValue::Null,
+                text("calm"),
+            ),
+            (
+                "item_attributes".to_string(),
+                item.id,
+                ChangeKind::Update,
+                Some("mood".to_string()),
+                text("calm"),
+                text("tense"),
+            ),
+            (
+                "items".to_string(),
+                item.id,
+                ChangeKind::Delete,
+                None,
+                FieldValue::Null,
+                FieldValue::Null,
+            ),
+        ]
+    );
+
+    // nothing of the changelog is kept in the library itself
+    let count: i64 = other.query_row(
+        "SELECT count(*) FROM sqlite_master WHERE name LIKE 'berts%'",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(count, 0);
+    assert_eq!(db.changelog(0.0)?.len(), 4);
+    assert!(db.changelog(f64::MAX)?.is_empty());
+
+    db.disable_changelog()?;
+    other.execute("DELETE FROM albums", [])?;
+    assert_eq!(db.changelog(0.0)?.len(), 4);
+
+    drop((db, other));
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn provenance_records_who_changed_a_field() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("beet_db-provenance-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("library.db");
+    std::fs::copy("tests/test.db", &path)?;
+
+    let mut db = Database::open_writable(&path)?;
+    let mut item = db.items()?.remove(0);
+    let title = item.title.clone();
+
+    // nothing is recorded until a tool is named
+    item.genre = "Shoegaze".to_string();
+    item.update(&db)?;
+    let entity = Entity::Item(item.id);
+    assert!(Provenance::read_for(db.connection(), entity)?.is_empty());
+
+    db.set_provenance(Some("tagger".to_string()));
+    item.title = "Renamed".to_string();
+    item.update(&db)?;
+    let provenance = Provenance::read_for(db.connection(), entity)?;
+    assert_eq!(provenance.keys().collect::<Vec<_>>(), vec!["title"]);
+    let title_provenance = &provenance["title"];
+    assert_eq!(title_provenance.tool, "tagger");
+    assert_eq!(title_provenance.previous, title);
+    assert!(title_provenance.time > 0.0);
+    assert_eq!(
+        Provenance::parse(&title_provenance.to_string()).as_ref(),
+        Some(title_provenance)
+    );
+
+    // the provenance is undone with the change it describes
+    assert_eq!(db.undo_last(1)?, 1);
+    assert_eq!(db.items()?.remove(0).title, title);
+    assert!(Provenance::read_for(db.connection(), entity)?.is_empty());
+
+    drop(db);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn mbid_worklist_puts_albums_missing_most_first() {
+    struct Resolver;
+
+    impl MbidResolver for Resolver {
+        fn releases(&self, album: &Album) -> Vec<MbidSuggestion> {
+            vec![MbidSuggestion {
+                mbid: "release".to_string(),
+                title: album.album.clone(),
+                artist: album.albumartist.clone(),
+                score: 90,
+            }]
+        }
+
+        fn recordings(&self, item: &Item) -> Vec<MbidSuggestion> {
+            vec![MbidSuggestion {
+                mbid: "recording".to_string(),
+                title: item.title.clone(),
+                artist: item.artist.clone(),
+                score: 80,
+            }]
+        }
+    }
+
+    let album = |id, mb_albumid: &str| Album {
+        id,
+        mb_albumid: mb_albumid.to_string(),
+        ..Album::default()
+    };
+    let item = |id, album_id, mb_trackid: &str| Item {
+        id,
+        album_id,
+        mb_trackid: mb_trackid.to_string(),
+        ..Item::default()
+    };
+    let library = Library {
+        albums: vec![album(1, ""), album(2, "tagged"), album(3, "tagged")],
+        items: vec![
+            item(1, Some(1), "tagged"),
+            item(2, Some(2), ""),
+            item(3, Some(2), ""),
+            item(4, Some(3), "tagged"),
+            item(5, None, ""),
+            item(6, None, "tagged"),
+        ],
+    };
+
+    let mut worklist = library.mbid_worklist();
+    let summary: Vec<_> = worklist
+        .iter()
+        .map(|work| {
+            let items: Vec<_> = work.items.iter().map(|item| item.id).collect();
+            (work.album.map(|album| album.id), items, work.missing())
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (Some(2), vec![2, 3], 2),
+            (Some(1), vec![], 1),
+            (None, vec![5], 1)
+        ]
+    );
+
+    for work in &mut worklist {
+        work.suggest(&Resolver);
+    }
+    assert_eq!(worklist[0].suggestions[0].mbid, "release");
+    assert_eq!(worklist[2].suggestions[0].mbid, "recording");
+}
+
+#[test]
+fn lyrics_are_filled_from_files_beside_items() -> Result<(), Box<dyn std::error::Error>> {
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+
+    // the futures of `LyricsFiles` are ready at once
+    fn ready<T>(future: impl Future<Output = T>) -> T {
+        let mut future = std::pin::pin!(future);
+        match future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        {
+            Poll::Ready(value) => value,
+            Poll::Pending => panic!("{}", "the future was not ready"),
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("beet_db-lyrics-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("library.db");
+    std::fs::copy("tests/test.db", &path)?;
+
+    // beets stores paths as bytes
+    Connection::open(&path)?.execute(
+        "UPDATE items SET lyrics = '', path = CAST(?1 || id || '.flac' AS BLOB)",
+        [format!("{}/", dir.display())],
+    )?;
+    let db = Database::open_writable(&path)?;
+    let items = db.items()?;
+    let library = Library {
+        albums: vec![],
+        items: items.clone(),
+    };
+    assert_eq!(library.items_without_lyrics().len(), items.len());
+    std::fs::write(dir.join(format!("{}.lrc", items[0].id)), "[00:01.00]Hello")?;
+    std::fs::write(dir.join(format!("{}.txt", items[1].id)), "  \n")?;
+
+    let filled = ready(db.fill_lyrics(&LyricsFiles))?;
+    assert_eq!(filled.filled, vec![items[0].id]);
+    assert_eq!(filled.not_found.len(), items.len() - 1);
+    assert!(filled.failed.is_empty());
+    let items = db.items()?;
+    assert_eq!(items[0].lyrics, "[00:01.00]Hello");
+    assert!(items[1].lyrics.is_empty());
+
+    drop(db);
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn playlists_remap_and_relativize_paths() -> Result<(), Box<dyn std::error::Error>> {
+    let item = |path: &str, title: &str| Item {
+        path: path.into(),
+        artist: "Boards of Canada".to_string(),
+        title: title.to_string(),
+        length: 151.0,
+        ..Item::default()
+    };
+    let items = [
+        item("/srv/music/Geogaddi/01.flac", "Ready Lets Go"),
+        item("/podcasts/a.mp3", "Roygbiv\n"),
+    ];
+
+    let mut out = vec![];
+    write_m3u(&mut out, &items, &PlaylistOptions::default())?;
+    assert_eq!(
+        String::from_utf8(out)?,
+        "#EXTM3U\n\
+        #EXTINF:151,Boards of Canada - Ready Lets Go\n\
+        /srv/music/Geogaddi/01.flac\n\
+        #EXTINF:151,Boards of Canada - Roygbiv \n\
+        /podcasts/a.mp3\n"
+    );
+
+    let options = PlaylistOptions {
+        extended: false,
+        remap: vec![("/srv/music".into(), "/media/usb".into())],
+        relative_to: Some("/media/usb/lists".into()),
+    };
+    let mut out = vec![];
+    write_m3u(&mut out, &items, &options)?;
+    assert_eq!(
+        String::from_utf8(out)?,
+        "../Geogaddi/01.flac\n../../../podcasts/a.mp3\n"
+    );
+
+    let options = PlaylistOptions {
+        extended: false,
+        remap: vec![],
+        relative_to: Some("/srv/music/./lists/../lists/".into()),
+    };
+    let items = [Item {
+        path: "/srv/music/Geogaddi/../Geogaddi/./01.flac".into(),
+        ..Item::default()
+    }];
+    let mut out = vec![];
+    write_m3u(&mut out, &items, &options)?;
+    assert_eq!(String::from_utf8(out)?, "../Geogaddi/01.flac\n");
+    Ok(())
+}
+
+#[test]
+fn itunes_xml_lists_tracks() -> Result<(), Box<dyn std::error::Error>> {
+    let items = [
+        Item {
+            id: 12,
+            path: "/srv/music/Boards of Canada/Geogaddi/01 Ready Lets Go.flac".into(),
+            artist: "Boards of Canada".to_string(),
+            album: "Geogaddi".to_string(),
+            title: "Ready <Lets> Go & Stay".to_string(),
+            format: "FLAC".to_string(),
+            length: 59.5,
+            track: 1,
+            bitrate: 900_000,
+            added: 1_614_834_367.0,
+            comp: true,
+            ..Item::default()
+        },
+        Item {
+            id: 13,
+            path: "/srv/music/Sigur Rós/01.mp3".into(),
+            format: "MP3".to_string(),
+            ..Item::default()
+        },
+    ];
+    let mut out = vec![];
+    write_itunes_xml(&mut out, &items)?;
+    let xml = String::from_utf8(out)?;
+    assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE plist"));
+    let first = "\t\t<key>12</key>\n\t\t<dict>\n\
+        \t\t\t<key>Track ID</key><integer>12</integer>\n\
+        \t\t\t<key>Name</key><string>Ready &lt;Lets&gt; Go &amp; Stay</string>\n\
+        \t\t\t<key>Artist</key><string>Boards of Canada</string>\n\
+        \t\t\t<key>Album</key><string>Geogaddi</string>\n\
+        \t\t\t<key>Kind</key><string>FLAC audio file</string>\n\
+        \t\t\t<key>Total Time</key><integer>59500</integer>\n\
+        \t\t\t<key>Track Number</key><integer>1</integer>\n\
+        \t\t\t<key>Date Added</key><date>2021-03-04T05:06:07Z</date>\n\
+        \t\t\t<key>Bit Rate</key><integer>900</integer>\n\
+        \t\t\t<key>Compilation</key><true/>\n\
+        \t\t\t<key>Persistent ID</key><string>BE3750000000000C</string>\n\
+        \t\t\t<key>Track Type</key><string>File</string>\n\
+        \t\t\t<key>Location</key><string>file://localhost/srv/music/Boards%20of%20Canada/Geogaddi/01%20Ready%20Lets%20Go.flac</string>\n\
+        \t\t</dict>\n";
+    assert!(xml.contains(first), "{}", xml);
+    assert!(xml.contains(
+        "<key>Location</key><string>file://localhost/srv/music/Sigur%20R%C3%B3s/01.mp3</string>"
+    ));
+    assert!(xml.contains(
+        "\t\t\t\t<dict><key>Track ID</key><integer>12</integer></dict>\n\
+        \t\t\t\t<dict><key>Track ID</key><integer>13</integer></dict>\n"
+    ));
+    assert!(xml.ends_with("</dict>\n</plist>\n"));
+    Ok(())
+}
+
+#[test]
+fn rekordbox_xml_keeps_bpm_and_key() -> Result<(), Box<dyn std::error::Error>> {
+    let items = [
+        Item {
+            id: 7,
+            path: "/srv/music/Daft Punk/Discovery/03 Digital Love.mp3".into(),
+            artist: "Daft Punk".to_string(),
+            album: "Discovery".to_string(),
+            title: "Digital \"Love\" & More".to_string(),
+            format: "MP3".to_string(),
+            length: 301.6,
+            track: 3,
+            bpm: 125.0,
+            initial_key: Some("C#m".to_string()),
+            bitrate: 320_000,
+            added: 1_614_834_367.0,
+            ..Item::default()
+        },
+        Item {
+            id: 8,
+            path: "/srv/music/unknown.m4a".into(),
+            format: "AAC".to_string(),
+            initial_key: Some("12B".to_string()),
+            ..Item::default()
+        },
+    ];
+    let mut out = vec![];
+    write_rekordbox_xml(&mut out, &items)?;
+    let xml = String::from_utf8(out)?;
+    assert!(xml.starts_with(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<DJ_PLAYLISTS Version=\"1.0.0\">\n"
+    ));
+    assert!(xml.contains("  <COLLECTION Entries=\"2\">\n"));
+    let first = "    <TRACK TrackID=\"7\" Name=\"Digital &quot;Love&quot; &amp; More\" \
+        Artist=\"Daft Punk\" Album=\"Discovery\" Kind=\"MP3 File\" TotalTime=\"302\" \
+        TrackNumber=\"3\" AverageBpm=\"125.00\" DateAdded=\"2021-03-04\" BitRate=\"320\" \
+        Tonality=\"Dbm\" \
+        Location=\"file://localhost/srv/music/Daft%20Punk/Discovery/03%20Digital%20Love.mp3\"/>\n";
+    assert!(xml.contains(first), "{}", xml);
+    assert!(xml.contains(
+        "    <TRACK TrackID=\"8\" Kind=\"M4A File\" Tonality=\"E\" \
+        Location=\"file://localhost/srv/music/unknown.m4a\"/>\n"
+    ));
+    assert!(xml.contains("        <TRACK Key=\"7\"/>\n        <TRACK Key=\"8\"/>\n      </NODE>\n"));
+    assert!(xml.ends_with("  </PLAYLISTS>\n</DJ_PLAYLISTS>\n"));
+    // every key on the wheel is named as it is read back
+    for number in 1..=12 {
+        for minor in [false, true] {
+            let key = CamelotKey::new(number, minor).unwrap();
+            assert_eq!(key.name().parse::<CamelotKey>(), Ok(key));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "language")]
+#[test]
+fn languages_are_tagged_as_attributes() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("beet_db-language-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("library.db");
+    std::fs::copy("tests/test.db", &path)?;
+    let conn = Connection::open(&path)?;
+    conn.execute_batch(
+        "DELETE FROM items WHERE id NOT IN (SELECT min(id) FROM items);
+        UPDATE items SET title = '', lyrics =
+            'Der Mond ist aufgegangen, die goldnen Sternlein prangen am Himmel hell und klar.'",
+    )?;
+
+    let db = Database::open_writable(&path)?;
+    let analyzer = LanguageAnalyzer::default();
+    assert_eq!(analyzer.detect(""), None);
+    assert_eq!(db.tag_languages(&analyzer)?, 1);
+    // nothing changes the second time
+    assert_eq!(db.tag_languages(&analyzer)?, 0);
+    let id = db.items()?[0].id;
+    let attributes = Attribute::read_for(db.connection(), Entity::Item(id))?;
+    let tags: Vec<_> = attributes
+        .iter()
+        .map(|attribute| (attribute.key.as_str(), attribute.value.as_str()))
+        .collect();
+    assert_eq!(tags, vec![(LYRICS_LANGUAGE, "deu")]);
+
+    drop((db, conn));
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[test]
+fn camelot_keys_parse_and_mix() {
+    let key = |key: &str| key.parse::<CamelotKey>().map(|key| key.to_string());
+    assert_eq!(key("C"), Ok("8B".to_string()));
+    assert_eq!(key("Am"), Ok("8A".to_string()));
+    assert_eq!(key("F#m"), Ok("11A".to_string()));
+    assert_eq!(key("Gbmin"), Ok("11A".to_string()));
+    assert_eq!(key("Bb major"), Ok("6B".to_string()));
+    assert_eq!(key("E"), Ok("12B".to_string()));
+    assert_eq!(key("12b"), Ok("12B".to_string()));
+    for invalid in ["", "H", "Cx", "13A", "0B"] {
+        assert!(key(invalid).is_err(), "{:?}", invalid);
+    }
+
+    let a_minor: CamelotKey = "8A".parse().unwrap();
+    let neighbors: Vec<_> = a_minor
+        .neighbors()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(neighbors, vec!["7A", "9A", "8B"]);
+    assert!(a_minor.is_compatible("C".parse().unwrap()));
+    assert!(!a_minor.is_compatible("10A".parse().unwrap()));
+    let wraps: CamelotKey = "1B".parse().unwrap();
+    assert!(wraps.is_compatible("12B".parse().unwrap()));
+}
+
+#[test]
+fn crates_ramp_tempo_and_mix_harmonically() {
+    let item = |id, bpm, key: &str| Item {
+        id,
+        bpm,
+        initial_key: Some(key.to_string()).filter(|key| !key.is_empty()),
+        length: 300.0,
+        ..Item::default()
+    };
+    let items = [
+        item(1, 126.0, "Am"),
+        item(2, 122.0, "Em"),
+        item(3, 140.0, "Am"),
+        item(4, 120.0, "Dbm"),
+        item(5, 124.0, "C"),
+        item(6, 121.0, ""),
+        item(7, 0.0, "Am"),
+    ];
+    let ids =
+        |crate_items: Vec<&Item>| -> Vec<u32> { crate_items.iter().map(|item| item.id).collect() };
+
+    assert_eq!(
+        ids(CrateBuilder::new().bpm(120.0, 128.0).build(&items)),
+        vec![4, 6, 2, 5, 1]
+    );
+    // only keys beside 9A, leaving out 12A and 8B
+    assert_eq!(
+        ids(CrateBuilder::new()
+            .bpm(120.0, 128.0)
+            .key("9A".parse().unwrap())
+            .harmonic(true)
+            .build(&items)),
+        vec![2, 1]
+    );
+    assert_eq!(
+        ids(CrateBuilder::new()
+            .harmonic(true)
+            .duration(900.0)
+            .build(&items)),
+        vec![7, 2, 1]
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn ndjson_is_written_a_row_at_a_time() -> Result<(), Box<dyn std::error::Error>> {
+    let db = Database::open("tests/test.db")?;
+    let mut out = vec![];
+    let written = db.write_items_ndjson(&mut out)?;
+    let items = db.items()?;
+    assert_eq!(written, items.len());
+    let out = String::from_utf8(out)?;
+    assert_eq!(out.lines().count(), written);
+    // `added` and `mtime` are not serialized
+    let first: Item = serde_json::from_str(out.lines().next().unwrap_or_default())?;
+    assert_eq!((first.id, &first.title), (items[0].id, &items[0].title));
+
+    // the rows before a failure are kept
+    let failed = rusqlite::Error::InvalidQuery.into();
+    let mut out = vec![];
+    let rows = vec![Ok(items[0].clone()), Err(failed)];
+    assert!(matches!(
+        write_ndjson(&mut out, rows),
+        Err(NdjsonError::Read(_))
+    ));
+    assert_eq!(String::from_utf8(out)?.lines().count(), 1);
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet_files_have_a_typed_column_per_field() -> Result<(), Box<dyn std::error::Error>> {
+    use parquet::basic::Type as PhysicalType;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+    use std::convert::TryFrom;
+
+    let (albums, mut items) = read_all("tests/test.db")?;
+    items[0].r128_track_gain = Some(R128Gain::from_q78(-1280));
+    items[0].r128_album_gain = None;
+    let library = Library { albums, items };
+    let dir = std::env::temp_dir().join(format!("beet_db-parquet-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    library.write_parquet(&dir)?;
+
+    let reader = SerializedFileReader::new(std::fs::File::open(dir.join("items.parquet"))?)?;
+    let metadata = reader.metadata().file_metadata();
+    assert_eq!(metadata.num_rows(), i64::try_from(library.items.len())?);
+    let schema = metadata.schema_descr();
+    let names: Vec<_> = schema
+        .columns()
+        .iter()
+        .map(|column| column.name())
+        .collect();
+    assert_eq!(names, Item::COLUMNS);
+    let index = |name: &str| {
+        Item::COLUMNS
+            .iter()
+            .position(|column| *column == name)
+            .unwrap()
+    };
+    assert_eq!(
+        schema.column(index("id")).physical_type(),
+        PhysicalType::INT64
+    );
+    assert_eq!(
+        schema.column(index("bpm")).physical_type(),
+        PhysicalType::DOUBLE
+    );
+    assert!(!schema.column(index("path")).self_type().is_optional());
+    assert!(schema.column(index("album_id")).self_type().is_optional());
+
+    let first = reader.get_row_iter(None)?.next().unwrap()?;
+    let item = &library.items[0];
+    assert_eq!(first.get_long(index("id"))?, i64::from(item.id));
+    assert_eq!(
+        first.get_string(index("path"))?,
+        &item.path.to_string_lossy()
+    );
+    assert_eq!(first.get_string(index("title"))?, &item.title);
+    assert_eq!(first.get_short(index("r128_track_gain"))?, -1280);
+    assert!(first.get_short(index("r128_album_gain")).is_err());
+
+    let reader = SerializedFileReader::new(std::fs::File::open(dir.join("albums.parquet"))?)?;
+    let metadata = reader.metadata().file_metadata();
+    assert_eq!(metadata.num_rows(), i64::try_from(library.albums.len())?);
+    assert_eq!(metadata.schema_descr().num_columns(), Album::COLUMNS.len());
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[cfg(feature = "arrow")]
+#[test]
+fn record_batches_have_an_array_per_field() -> Result<(), Error> {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Int16Type, UInt32Type};
+    use arrow_array::Array;
+    use arrow_schema::DataType;
+
+    let (albums, mut items) = read_all("tests/test.db")?;
+    items[0].r128_track_gain = Some(R128Gain::from_q78(-1280));
+    items[0].r128_album_gain = None;
+    let batch = Item::to_record_batch(&items);
+    assert_eq!(batch.num_rows(), items.len());
+    let schema = batch.schema();
+    let names: Vec<_> = schema.fields().iter().map(|field| field.name()).collect();
+    assert_eq!(names, Item::COLUMNS);
+
+    let column = |name: &str| batch.column_by_name(name).unwrap();
+    assert_eq!(column("bpm").data_type(), &DataType::Float64);
+    assert!(!schema.field_with_name("path").unwrap().is_nullable());
+    assert!(schema.field_with_name("album_id").unwrap().is_nullable());
+    assert_eq!(
+        column("id").as_primitive::<UInt32Type>().value(0),
+        items[0].id
+    );
+    assert_eq!(column("title").as_string::<i32>().value(0), items[0].title);
+    let gains = column("r128_track_gain").as_primitive::<Int16Type>();
+    assert_eq!(gains.value(0), -1280);
+    assert!(column("r128_album_gain").is_null(0));
+
+    assert_eq!(Album::to_record_batch(&albums).num_rows(), albums.len());
+    let empty = Album::to_record_batch(&[]);
+    assert_eq!(empty.num_rows(), 0);
+    assert_eq!(empty.num_columns(), Album::COLUMNS.len());
+    Ok(())
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_round_trips_like_json() -> Result<(), Box<dyn std::error::Error>> {
+    let (albums, items) = read_all("tests/test.db")?;
+    let library = Library { albums, items };
+    let bytes = library.to_msgpack()?;
+    let json = serde_json::to_vec(&library)?;
+    assert!(bytes.len() < json.len());
+    // fields skipped in JSON come back as their defaults in both
+    let decoded = Library::from_msgpack_bytes(&bytes)?;
+    assert_eq!(decoded, Library::from_json_bytes(&json)?);
+    assert!(decoded.items.iter().all(|item| item.added == 0.0));
+
+    let item = Item {
+        id: 7,
+        title: "Dayvan Cowboy".to_string(),
+        track: 3,
+        added: 1_600_000_000.0,
+        r128_track_gain: R128Gain::from_db(-2.5),
+        ..Item::default()
+    };
+    let decoded: Item = from_msgpack(&to_msgpack(&item)?)?;
+    assert_eq!(
+        decoded,
+        Item {
+            added: 0.0,
+            ..item.clone()
+        }
+    );
+    assert_eq!(
+        from_msgpack::<Item>(&to_msgpack(&Item::default())?)?,
+        Item::default()
+    );
+
+    // without field names, the fields left out put the rest out of place
+    let positional = rmp_serde::to_vec(&item)?;
+    assert_ne!(from_msgpack::<Item>(&positional).ok(), Some(decoded));
+    Ok(())
+}
+
+#[cfg(feature = "schemars")]
+#[test]
+fn json_schemas_match_serde() -> Result<(), Box<dyn std::error::Error>> {
+    use serde_json::Value;
+
+    // the types of the values a record serializes to are the ones declared
+    fn conforms(root: &Value, schema: &Value, value: &Value) -> bool {
+        if let Some(name) = schema["$ref"].as_str() {
+            let name = name.trim_start_matches("#/$defs/");
+            return conforms(root, &root["$defs"][name], value);
+        }
+        if let Some(any_of) = schema["anyOf"].as_array() {
+            return any_of.iter().any(|schema| conforms(root, schema, value));
+        }
+        let types = match &schema["type"] {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            types => types.as_str().into_iter().collect::<Vec<_>>(),
+        };
+        types.into_iter().any(|ty| match (ty, value) {
+            ("null", Value::Null)
+            | ("boolean", Value::Bool(_))
+            | ("string", Value::String(_))
+            | ("number", Value::Number(_)) => true,
+            ("integer", Value::Number(n)) => n.as_i64().is_some_and(|n| {
+                schema["minimum"].as_i64().is_none_or(|min| n >= min)
+                    && schema["maximum"].as_i64().is_none_or(|max| n <= max)
+            }),
+            _ => false,
+        })
+    }
+    fn check<T>(records: Vec<T>) -> Result<(), Box<dyn std::error::Error>>
+    where
+        T: schemars::JsonSchema + serde::Serialize + serde::de::DeserializeOwned,
+    {
+        let schema = schemars::schema_for!(T).to_value();
+        let required: Vec<_> = schema["required"]
+            .as_array()
+            .expect("records require fields")
+            .iter()
+            .map(|name| name.as_str().expect("names are strings"))
+            .collect();
+        for record in records {
+            let Value::Object(object) = serde_json::to_value(&record)? else {
+                panic!("records serialize as objects");
+            };
+            for (name, value) in &object {
+                assert!(
+                    conforms(&schema, &schema["properties"][name], value),
+                    "{}: {}",
+                    name,
+                    value
+                );
+            }
+            // only the required properties are needed to deserialize
+            let minimal: serde_json::Map<_, _> = object
+                .into_iter()
+                .filter(|(name, _)| required.contains(&name.as_str()))
+                .collect();
+            for name in &required {
+                let mut missing = minimal.clone();
+                missing.remove(*name);
+                assert!(serde_json::from_value::<T>(missing.into()).is_err());
+            }
+            serde_json::from_value::<T>(minimal.into())?;
+        }
+        Ok(())
+    }
+    let (albums, items) = read_all("tests/test.db")?;
+    check(albums)?;
+    check(items)?;
+    assert!(schemars::schema_for!(Item).to_value()["properties"]
+        .get("mtime")
+        .is_none());
+
+    // the checked-in schemas are up to date
+    for (path, schema) in [
+        ("bindings/Album.schema.json", schemars::schema_for!(Album)),
+        ("bindings/Item.schema.json", schemars::schema_for!(Item)),
+    ] {
+        let schema = serde_json::to_string_pretty(&schema)? + "\n";
+        if std::env::var_os("UPDATE_BINDINGS").is_some() {
+            std::fs::write(path, &schema)?;
+        }
+        assert_eq!(
+            std::fs::read_to_string(path)?,
+            schema,
+            "run the tests with UPDATE_BINDINGS=1 to update {path}"
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+#[cfg(feature = "chrono")]
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+
+use crate::{Album, Item};
+
+/// The time `secs` after the Unix epoch, as beets stores it. A timestamp
+/// that is not a number or out of range is taken as the epoch itself.
+fn system_time(secs: f64) -> SystemTime {
+    let offset = Duration::try_from_secs_f64(secs.abs()).unwrap_or_default();
+    let time = if secs < 0.0 {
+        UNIX_EPOCH.checked_sub(offset)
+    } else {
+        UNIX_EPOCH.checked_add(offset)
+    };
+    time.unwrap_or(UNIX_EPOCH)
+}
+
+/// `time` as a date-time, or the epoch if it is out of the range `chrono`
+/// can represent.
+#[cfg(feature = "chrono")]
+fn date_time(time: SystemTime) -> DateTime<Utc> {
+    let (secs, nanos) = match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => (i64::try_from(after.as_secs()).ok(), after.subsec_nanos()),
+        Err(before) => {
+            let before = before.duration();
+            let secs = i64::try_from(before.as_secs()).ok().map(|secs| -secs);
+            match before.subsec_nanos() {
+                0 => (secs, 0),
+                nanos => (secs.map(|secs| secs - 1), 1_000_000_000 - nanos),
+            }
+        }
+    };
+    secs.and_then(|secs| DateTime::from_timestamp(secs, nanos))
+        .unwrap_or_default()
+}
+
+impl Item {
+    /// When the item was imported.
+    #[must_use]
+    pub fn added_systemtime(&self) -> SystemTime {
+        system_time(self.added)
+    }
+
+    /// When the file was last modified, as of the last time beets read or
+    /// wrote its tags.
+    #[must_use]
+    pub fn mtime_systemtime(&self) -> SystemTime {
+        system_time(self.mtime)
+    }
+}
+
+impl Album {
+    /// When the album was imported.
+    #[must_use]
+    pub fn added_systemtime(&self) -> SystemTime {
+        system_time(self.added)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl Item {
+    /// [`Item::added_systemtime`] as a date-time.
+    #[must_use]
+    pub fn added_datetime(&self) -> DateTime<Utc> {
+        date_time(self.added_systemtime())
+    }
+
+    /// [`Item::mtime_systemtime`] as a date-time.
+    #[must_use]
+    pub fn mtime_datetime(&self) -> DateTime<Utc> {
+        date_time(self.mtime_systemtime())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl Album {
+    /// [`Album::added_systemtime`] as a date-time.
+    #[must_use]
+    pub fn added_datetime(&self) -> DateTime<Utc> {
+        date_time(self.added_systemtime())
+    }
+}
+
+/// A record serialized with its timestamps as RFC 3339 strings, which it
+/// otherwise leaves out.
+///
+/// ```
+/// # use beet_db::{Item, Timestamped};
+/// let item = Item { added: 1_600_000_000.0, ..Item::default() };
+/// let json = serde_json::to_value(Timestamped::from(item)).unwrap();
+/// assert_eq!(json["added"], "2020-09-13T12:26:40Z");
+/// ```
+#[cfg(all(feature = "chrono", feature = "serde"))]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Timestamped<T> {
+    #[serde(flatten)]
+    pub record: T,
+    pub added: DateTime<Utc>,
+    /// Only items have one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<DateTime<Utc>>,
+}
+
+#[cfg(all(feature = "chrono", feature = "serde"))]
+impl From<Item> for Timestamped<Item> {
+    fn from(item: Item) -> Self {
+        Self {
+            added: item.added_datetime(),
+            mtime: Some(item.mtime_datetime()),
+            record: item,
+        }
+    }
+}
+
+#[cfg(all(feature = "chrono", feature = "serde"))]
+impl From<Album> for Timestamped<Album> {
+    fn from(album: Album) -> Self {
+        Self {
+            added: album.added_datetime(),
+            mtime: None,
+            record: album,
+        }
+    }
+}
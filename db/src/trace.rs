@@ -0,0 +1,148 @@
+use std::time::{Duration, Instant};
+
+use rusqlite::{Connection, Params, Row};
+
+use crate::{CancellationToken, Error, Progress, ProgressSink, PROGRESS_INTERVAL};
+
+/// Details of one finished query, as passed to a [`TraceHook`].
+#[derive(Clone, Copy, Debug)]
+pub struct QueryTrace<'a> {
+    /// The SQL text of the statement.
+    pub sql: &'a str,
+    /// The number of parameters bound to the statement.
+    pub parameters: usize,
+    /// The number of rows the statement returned.
+    pub rows: usize,
+    /// Time spent preparing the statement and reading every row.
+    pub elapsed: Duration,
+}
+
+/// A callback invoked after each query, e.g. to log slow library operations.
+pub type TraceHook = dyn Fn(&QueryTrace) + Send + Sync;
+
+/// Callbacks to report to while running a query.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Hooks<'a> {
+    pub trace: Option<&'a TraceHook>,
+    pub progress: Option<&'a dyn ProgressSink>,
+    pub cancel: Option<&'a CancellationToken>,
+}
+
+/// Run a query on `table` to completion, binding every row and reporting to
+/// the hooks.
+pub(crate) fn query_all<T>(
+    conn: &Connection,
+    table: &str,
+    sql: &str,
+    params: impl Params,
+    mut from_row: impl FnMut(&Row) -> Result<T, Error>,
+    hooks: Hooks,
+) -> Result<Vec<T>, Error> {
+    let start = Instant::now();
+
+    let total = match hooks.progress {
+        Some(_) => Some(count_rows(conn, table)?),
+        None => None,
+    };
+    let mut progress = Progress {
+        table,
+        rows: 0,
+        total,
+    };
+    let from_row = |row: &Row| {
+        if let Some(cancel) = hooks.cancel {
+            cancel.check()?;
+        }
+        let value = from_row(row)?;
+        if let Some(sink) = hooks.progress {
+            progress.rows += 1;
+            if progress.rows.is_multiple_of(PROGRESS_INTERVAL) {
+                sink.progress(&progress);
+            }
+        }
+        Ok::<T, Error>(value)
+    };
+
+    let mut stmt = conn.prepare(sql)?;
+    let parameters = stmt.parameter_count();
+    let rows = stmt
+        .query_and_then(params, from_row)
+        .map_err(Error::query)?;
+
+    let mut v = Vec::new();
+    for row in rows {
+        v.push(row?);
+    }
+
+    if let Some(sink) = hooks.progress {
+        sink.progress(&Progress {
+            table,
+            rows: v.len() as u64,
+            total,
+        });
+    }
+    if let Some(hook) = hooks.trace {
+        hook(&QueryTrace {
+            sql,
+            parameters,
+            rows: v.len(),
+            elapsed: start.elapsed(),
+        });
+    }
+
+    Ok(v)
+}
+
+/// Report the rows of `table` that `rows` reads to the hooks as [`query_all`]
+/// does, for rows read lazily, and stop with an error once cancelled.
+#[cfg(feature = "serde")]
+pub(crate) fn watch<'a, T: 'a>(
+    conn: &Connection,
+    table: &'a str,
+    rows: impl Iterator<Item = Result<T, Error>> + 'a,
+    hooks: Hooks<'a>,
+) -> Result<impl Iterator<Item = Result<T, Error>> + 'a, Error> {
+    let total = match hooks.progress {
+        Some(_) => Some(count_rows(conn, table)?),
+        None => None,
+    };
+    let mut progress = Progress {
+        table,
+        rows: 0,
+        total,
+    };
+    let mut rows = rows.fuse();
+    let mut finished = false;
+    Ok(std::iter::from_fn(move || {
+        if let Some(cancel) = hooks.cancel {
+            if let Err(err) = cancel.check() {
+                return Some(Err(err));
+            }
+        }
+        let row = rows.next();
+        if let Some(sink) = hooks.progress {
+            match row {
+                Some(Ok(_)) => {
+                    progress.rows += 1;
+                    if progress.rows.is_multiple_of(PROGRESS_INTERVAL) {
+                        sink.progress(&progress);
+                    }
+                }
+                None if !finished => {
+                    finished = true;
+                    sink.progress(&progress);
+                }
+                _ => {}
+            }
+        }
+        row
+    }))
+}
+
+/// The number of rows in `table`, as the expected total for progress updates.
+fn count_rows(conn: &Connection, table: &str) -> Result<u64, Error> {
+    conn.query_row(&format!("SELECT count(*) FROM {table}"), (), |row| {
+        row.get(0)
+    })
+    .map_err(Error::query)
+}
@@ -0,0 +1,310 @@
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection, OptionalExtension};
+
+use crate::undo::{forget, now, recorded, Rows};
+use crate::{schema, Album, Database, Error, Item, ReadWrite};
+
+/// The tables of the library, each with the table in the sidecar its
+/// deleted rows are moved to.
+const TRASH: &[(&str, &str)] = &[
+    ("items", "trash_items"),
+    ("albums", "trash_albums"),
+    ("item_attributes", "trash_item_attributes"),
+    ("album_attributes", "trash_album_attributes"),
+];
+
+fn trash_table(table: &str) -> &'static str {
+    TRASH
+        .iter()
+        .find(|(library, _)| *library == table)
+        .map(|(_, trash)| *trash)
+        .expect("only the tables of the library have a trash")
+}
+
+/// The columns of `table` in the database `schema`, in order.
+fn columns(conn: &Connection, schema: &str, table: &str) -> Result<Vec<String>, Error> {
+    let mut stmt = conn
+        .prepare_cached("SELECT name FROM pragma_table_info(?1, ?2) ORDER BY cid")
+        .map_err(Error::query)?;
+    let names = stmt
+        .query_map([table, schema], |row| row.get(0))
+        .map_err(Error::query)?;
+    names.collect::<Result<_, _>>().map_err(Error::query)
+}
+
+/// Whether the trash of `table` exists, i.e. anything was ever trashed.
+fn exists(conn: &Connection, table: &str) -> Result<bool, Error> {
+    conn.query_row(
+        "SELECT 1 FROM berts_undo.sqlite_master WHERE name = ?1",
+        [trash_table(table)],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|found| found.is_some())
+    .map_err(Error::query)
+}
+
+/// Whether the trash of `table` has a row with `id`.
+fn contains(conn: &Connection, table: &str, id: u32) -> Result<bool, Error> {
+    if !exists(conn, table)? {
+        return Ok(false);
+    }
+    let sql = format!(
+        "SELECT 1 FROM berts_undo.{} WHERE id = ?1",
+        trash_table(table)
+    );
+    conn.query_row(&sql, [id], |_| Ok(()))
+        .optional()
+        .map(|found| found.is_some())
+        .map_err(Error::query)
+}
+
+/// The columns `table` shares with its trash, quoted and separated by
+/// commas. Those beets adds after the trash was created are left out.
+fn shared_columns(conn: &Connection, table: &str) -> Result<String, Error> {
+    let trash = columns(conn, "berts_undo", trash_table(table))?;
+    let shared: Vec<_> = columns(conn, "main", table)?
+        .into_iter()
+        .filter(|column| trash.contains(column))
+        .map(|column| format!("\"{}\"", column.replace('"', "\"\"")))
+        .collect();
+    Ok(shared.join(", "))
+}
+
+impl Database<ReadWrite> {
+    /// Copy the `rows` of `table` to its trash before a write deletes them,
+    /// if the handle keeps one, over any copies of the same rows trashed
+    /// before. Only rows by [`Rows::Id`] or [`Rows::EntityId`] are moved, so
+    /// those copies are exactly the ones replaced.
+    pub(crate) fn move_to_trash(&self, table: &'static str, rows: &Rows) -> Result<(), Error> {
+        if !self.journal.trash {
+            return Ok(());
+        }
+        let conn = self.connection();
+        let trash = trash_table(table);
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS berts_undo.{trash} AS
+            SELECT *, 0.0 AS trashed FROM main.{table} WHERE 0"
+        );
+        conn.execute_batch(&sql).map_err(Error::write)?;
+
+        self.record(trash, rows)?;
+        let sql = format!(
+            "DELETE FROM berts_undo.{trash} WHERE {}",
+            rows.filter(trash)
+        );
+        conn.execute(&sql, params_from_iter(rows.params()))
+            .map_err(Error::write)?;
+
+        let columns = shared_columns(conn, table)?;
+        let mut params = rows.params();
+        params.push(Value::Real(now()));
+        let sql = format!(
+            "INSERT INTO berts_undo.{trash} ({columns}, trashed)
+            SELECT {columns}, ?{} FROM main.{table} WHERE {}",
+            params.len(),
+            rows.filter(table)
+        );
+        conn.execute(&sql, params_from_iter(params))
+            .map_err(Error::write)?;
+        Ok(())
+    }
+
+    /// Put the trashed `rows` of `table` back in the library. They stay in
+    /// the trash until [`Self::drop_trashed`].
+    fn untrash(&self, table: &'static str, rows: &Rows) -> Result<(), Error> {
+        let conn = self.connection();
+        self.record(table, rows)?;
+        let columns = shared_columns(conn, table)?;
+        let sql = format!(
+            "INSERT INTO main.{table} ({columns})
+            SELECT {columns} FROM berts_undo.{} WHERE {}",
+            trash_table(table),
+            rows.filter(trash_table(table))
+        );
+        conn.execute(&sql, params_from_iter(rows.params()))
+            .map_err(Error::write)?;
+        Ok(())
+    }
+
+    /// Remove the `rows` of the trash of `table`.
+    fn drop_trashed(&self, table: &'static str, rows: &Rows) -> Result<(), Error> {
+        let trash = trash_table(table);
+        self.record(trash, rows)?;
+        let sql = format!(
+            "DELETE FROM berts_undo.{trash} WHERE {}",
+            rows.filter(trash)
+        );
+        self.connection()
+            .execute(&sql, params_from_iter(rows.params()))
+            .map_err(Error::write)?;
+        Ok(())
+    }
+
+    /// Read the trash of `table` as records of type `T`, most recently
+    /// trashed first.
+    fn trashed<T>(
+        &self,
+        table: &str,
+        fields: &[&str],
+        from_row: fn(&rusqlite::Row) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        let conn = self.connection();
+        if !self.journal.attached || !exists(conn, table)? {
+            return Ok(vec![]);
+        }
+        let present = columns(conn, "berts_undo", trash_table(table))?;
+        // columns beets added after the trash was created read as `NULL`,
+        // like those of an older library
+        let select: Vec<_> = fields
+            .iter()
+            .map(|field| {
+                if present.iter().any(|column| column == field)
+                    || !schema::is_optional(table, field)
+                {
+                    (*field).to_string()
+                } else {
+                    format!("NULL AS {field}")
+                }
+            })
+            .collect();
+        let sql = format!(
+            "SELECT {},id FROM berts_undo.{} ORDER BY trashed DESC, id",
+            select.join(","),
+            trash_table(table)
+        );
+        let mut stmt = conn.prepare(&sql).map_err(Error::query)?;
+        let mut rows = stmt.query([]).map_err(Error::query)?;
+        let mut records = vec![];
+        while let Some(row) = rows.next().map_err(Error::query)? {
+            records.push(from_row(row)?);
+        }
+        Ok(records)
+    }
+
+    /// The items in the [trash](crate::OpenOptions::trash), most recently
+    /// deleted first, including those deleted along with their album.
+    ///
+    /// # Errors
+    /// Returns an error if the trash cannot be read
+    pub fn trashed_items(&self) -> Result<Vec<Item>, Error> {
+        self.trashed("items", Item::COLUMNS, Item::from_row)
+    }
+
+    /// The albums in the [trash](crate::OpenOptions::trash), most recently
+    /// deleted first.
+    ///
+    /// # Errors
+    /// Returns an error if the trash cannot be read
+    pub fn trashed_albums(&self) -> Result<Vec<Album>, Error> {
+        self.trashed("albums", Album::COLUMNS, Album::from_row)
+    }
+
+    /// Put the item with `id` back from the trash, with its flexible
+    /// attributes, returning whether it was there. Its album is not
+    /// restored with it.
+    ///
+    /// # Errors
+    /// Returns an error if an item with the same id was added since, or
+    /// one for which [`Error::write_hazard`] is `Some` if writing is not safe
+    pub fn restore_item(&self, id: u32) -> Result<bool, Error> {
+        if !self.journal.attached || !contains(self.connection(), "items", id)? {
+            return Ok(false);
+        }
+        self.write(&format!("restore item {id}"), || {
+            self.untrash("items", &Rows::Id(id))?;
+            if exists(self.connection(), "item_attributes")? {
+                self.untrash("item_attributes", &Rows::EntityId(id))?;
+                self.drop_trashed("item_attributes", &Rows::EntityId(id))?;
+            }
+            self.drop_trashed("items", &Rows::Id(id))?;
+            Ok(true)
+        })
+    }
+
+    /// Put the album with `id` back from the trash, with its items and all
+    /// of their flexible attributes, returning whether it was there.
+    ///
+    /// # Errors
+    /// Returns an error if an album or item with the same id was added
+    /// since, or one for which [`Error::write_hazard`] is `Some` if writing
+    /// is not safe
+    pub fn restore_album(&self, id: u32) -> Result<bool, Error> {
+        if !self.journal.attached || !contains(self.connection(), "albums", id)? {
+            return Ok(false);
+        }
+        self.write(&format!("restore album {id}"), || {
+            self.untrash("albums", &Rows::Id(id))?;
+            // in the order the journal can take back: the items before
+            // their attributes, which are found by the items' album
+            let moved: &[(&'static str, Rows)] = &[
+                ("album_attributes", Rows::EntityId(id)),
+                ("items", Rows::AlbumId(id)),
+                ("item_attributes", Rows::AlbumItems(id)),
+            ];
+            for (table, rows) in moved {
+                if exists(self.connection(), table)? {
+                    self.untrash(table, rows)?;
+                }
+            }
+            for (table, rows) in moved.iter().rev() {
+                if exists(self.connection(), table)? {
+                    self.drop_trashed(table, rows)?;
+                }
+            }
+            self.drop_trashed("albums", &Rows::Id(id))?;
+            Ok(true)
+        })
+    }
+
+    /// Delete everything in the trash for good, returning how many items
+    /// and albums there were. This cannot be undone, and the writes in the
+    /// [undo journal](Self::undo_journal) that moved anything into or out of
+    /// the trash are forgotten, as undoing them would bring back what was
+    /// deleted.
+    ///
+    /// # Errors
+    /// Returns an error if the trash cannot be written, or one for which
+    /// [`Error::write_hazard`] is `Some` if writing is not safe
+    pub fn empty_trash(&self) -> Result<usize, Error> {
+        if !self.journal.attached {
+            return Ok(0);
+        }
+        let conn = self.connection();
+        self.checked(|| {
+            crate::write::atomically(conn, || {
+                let mut emptied = 0;
+                for (table, trash) in TRASH {
+                    if !exists(conn, table)? {
+                        continue;
+                    }
+                    let deleted = conn
+                        .execute(&format!("DELETE FROM berts_undo.{trash}"), [])
+                        .map_err(Error::write)?;
+                    if !table.ends_with("attributes") {
+                        emptied += deleted;
+                    }
+                }
+                if !recorded(conn)? {
+                    return Ok(emptied);
+                }
+                // the rows they saved include those just emptied
+                let mut entries = conn
+                    .prepare(
+                        "SELECT DISTINCT entry FROM berts_undo.images
+                        WHERE tbl LIKE 'trash\\_%' ESCAPE '\\'",
+                    )
+                    .map_err(Error::query)?;
+                let entries = entries
+                    .query_map([], |row| row.get(0))
+                    .map_err(Error::query)?
+                    .collect::<Result<Vec<i64>, _>>()
+                    .map_err(Error::query)?;
+                for entry in entries {
+                    forget(conn, entry)?;
+                }
+                Ok(emptied)
+            })
+        })
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use crate::{Album, Attribute, Item, R128Gain, SerdeField};
+
+/// A record that can declare the shape it is serialized in as a TypeScript
+/// interface, for frontends that read it as JSON.
+pub trait TypeScript {
+    /// The `export interface` declaring the record, with a property for every
+    /// serialized field. Fields serde skips when empty are optional, and those
+    /// it always skips are left out.
+    fn declaration() -> String;
+}
+
+/// The TypeScript type of a serialized field.
+pub(crate) trait TsType {
+    fn ts_type() -> String;
+
+    /// The type when the field is left out rather than serialized empty.
+    fn ts_optional_type() -> String {
+        Self::ts_type()
+    }
+}
+
+macro_rules! ts_type {
+    ( $ts:literal: $($typ:ty),* ) => {
+        $( impl TsType for $typ {
+            fn ts_type() -> String {
+                $ts.to_string()
+            }
+        } )*
+    };
+}
+
+ts_type!("number": u32, f64, R128Gain);
+ts_type!("string": String, PathBuf);
+ts_type!("boolean": bool);
+
+impl<T: TsType> TsType for Option<T> {
+    fn ts_type() -> String {
+        format!("{} | null", T::ts_type())
+    }
+
+    // skipped when `None`, so never `null`
+    fn ts_optional_type() -> String {
+        T::ts_type()
+    }
+}
+
+/// One property of an interface, from the field's `#[serde]` attributes as
+/// written, or nothing if serde skips the field.
+pub(crate) fn property<T: TsType>(name: &str, docs: &[&str], serde: &[&str]) -> String {
+    let field = SerdeField::parse(serde);
+    if field.skip {
+        return String::new();
+    }
+    let mut property = String::new();
+    if !docs.is_empty() {
+        property.push_str("  /**");
+        for line in docs {
+            property.push_str("\n   *");
+            if !line.is_empty() {
+                property.push_str(line);
+            }
+        }
+        property.push_str("\n   */\n");
+    }
+    if field.skip_if {
+        let _ = writeln!(property, "  {name}?: {};", T::ts_optional_type());
+    } else {
+        let _ = writeln!(property, "  {name}: {};", T::ts_type());
+    }
+    property
+}
+
+/// The declarations of [`Album`], [`Item`] and [`Attribute`], as a `.d.ts`
+/// file.
+#[must_use]
+pub fn typescript_declarations() -> String {
+    format!(
+        "// Generated by beet_db {}; do not edit.\n\n{}\n{}\n{}",
+        env!("CARGO_PKG_VERSION"),
+        Album::declaration(),
+        Item::declaration(),
+        Attribute::declaration(),
+    )
+}
@@ -0,0 +1,548 @@
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::types::Value;
+use rusqlite::{ffi, params_from_iter, Connection, OptionalExtension};
+
+use crate::write::atomically;
+use crate::{Database, Error, ErrorKind, ReadWrite};
+
+/// The tables whose rows the journal restores: those of the library, and
+/// those of its [trash](crate::OpenOptions::trash) in the sidecar.
+const TABLES: &[&str] = &[
+    "items",
+    "albums",
+    "item_attributes",
+    "album_attributes",
+    "trash_items",
+    "trash_albums",
+    "trash_item_attributes",
+    "trash_album_attributes",
+];
+
+/// `table` with the schema it is in, the library's or the sidecar's.
+pub(crate) fn qualified(table: &str) -> String {
+    if table.starts_with("trash_") {
+        format!("berts_undo.{table}")
+    } else {
+        format!("main.{table}")
+    }
+}
+
+/// How many writes the journal keeps unless
+/// [`OpenOptions::undo_limit`](crate::OpenOptions::undo_limit) says otherwise.
+pub(crate) const DEFAULT_LIMIT: usize = 100;
+
+/// Each entry is a write, each image the rows of one table it changed as
+/// they were before, and each cell one column of one of those rows.
+const CREATE_TABLES: &str = "
+    CREATE TABLE IF NOT EXISTS berts_undo.entries (
+        id INTEGER PRIMARY KEY,
+        time REAL NOT NULL,
+        description TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS berts_undo.images (
+        id INTEGER PRIMARY KEY,
+        entry INTEGER NOT NULL,
+        tbl TEXT NOT NULL,
+        rows TEXT NOT NULL,
+        entity INTEGER NOT NULL,
+        key TEXT
+    );
+    CREATE TABLE IF NOT EXISTS berts_undo.cells (
+        image INTEGER NOT NULL,
+        row INTEGER NOT NULL,
+        col TEXT NOT NULL,
+        value
+    );
+    CREATE INDEX IF NOT EXISTS berts_undo.images_entry ON images (entry);
+    CREATE INDEX IF NOT EXISTS berts_undo.cells_image ON cells (image);
+";
+
+/// A write recorded in the undo journal, which
+/// [`Database::undo_last`] can take back.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UndoEntry {
+    pub id: i64,
+    /// When the write was made, as a Unix timestamp like those beets stores.
+    pub time: f64,
+    /// What the write did, e.g. `update item 12`.
+    pub description: String,
+}
+
+/// The rows of a table a write changes.
+#[derive(Clone, Debug)]
+pub(crate) enum Rows {
+    Id(u32),
+    AlbumId(u32),
+    EntityId(u32),
+    EntityKey(u32, String),
+    /// The attributes of the items of an album.
+    AlbumItems(u32),
+}
+
+impl Rows {
+    fn kind(&self) -> &'static str {
+        match self {
+            Rows::Id(_) => "id",
+            Rows::AlbumId(_) => "album_id",
+            Rows::EntityId(_) => "entity_id",
+            Rows::EntityKey(..) => "entity_key",
+            Rows::AlbumItems(_) => "album_items",
+        }
+    }
+
+    fn parse(kind: &str, entity: u32, key: Option<String>) -> Option<Self> {
+        Some(match (kind, key) {
+            ("id", _) => Rows::Id(entity),
+            ("album_id", _) => Rows::AlbumId(entity),
+            ("entity_id", _) => Rows::EntityId(entity),
+            ("entity_key", Some(key)) => Rows::EntityKey(entity, key),
+            ("album_items", _) => Rows::AlbumItems(entity),
+            _ => return None,
+        })
+    }
+
+    fn entity(&self) -> u32 {
+        match self {
+            Rows::Id(id)
+            | Rows::AlbumId(id)
+            | Rows::EntityId(id)
+            | Rows::EntityKey(id, _)
+            | Rows::AlbumItems(id) => *id,
+        }
+    }
+
+    fn key(&self) -> Option<&str> {
+        match self {
+            Rows::EntityKey(_, key) => Some(key),
+            _ => None,
+        }
+    }
+
+    /// The condition selecting the rows of `table`.
+    pub(crate) fn filter(&self, table: &str) -> String {
+        match self {
+            Rows::Id(_) => "id = ?1".to_string(),
+            Rows::AlbumId(_) => "album_id = ?1".to_string(),
+            Rows::EntityId(_) => "entity_id = ?1".to_string(),
+            Rows::EntityKey(..) => "entity_id = ?1 AND key = ?2".to_string(),
+            Rows::AlbumItems(_) => {
+                // the items beside the attributes, in the trash or not
+                let items = if table.starts_with("trash_") {
+                    "berts_undo.trash_items"
+                } else {
+                    "main.items"
+                };
+                format!("entity_id IN (SELECT id FROM {items} WHERE album_id = ?1)")
+            }
+        }
+    }
+
+    pub(crate) fn params(&self) -> Vec<Value> {
+        let mut params = vec![Value::Integer(self.entity().into())];
+        if let Some(key) = self.key() {
+            params.push(Value::Text(key.to_string()));
+        }
+        params
+    }
+}
+
+/// The undo journal of a writable handle.
+#[derive(Debug, Default)]
+pub(crate) struct Journal {
+    /// Whether the sidecar is attached, as `berts_undo`.
+    pub(crate) attached: bool,
+    /// Whether writes are recorded in it.
+    pub(crate) recording: bool,
+    /// Whether deleted rows are moved to its trash.
+    pub(crate) trash: bool,
+    /// How many of the latest writes are kept, the older ones forgotten.
+    pub(crate) limit: usize,
+    /// The entry of the outermost write in progress, which the writes it is
+    /// made of are recorded in.
+    entry: Cell<Option<i64>>,
+}
+
+impl Journal {
+    /// Attach the journal kept beside the library `conn` is connected to,
+    /// as `library.db.undo`, or in memory for a library that is.
+    pub(crate) fn attach(conn: &Connection) -> Result<Self, Error> {
+        attach_sidecar(conn)?;
+        Ok(Self {
+            attached: true,
+            recording: true,
+            trash: false,
+            limit: DEFAULT_LIMIT,
+            entry: Cell::new(None),
+        })
+    }
+}
+
+/// Attach the sidecar beside the library `conn` is connected to, which
+/// holds the journal, trash and changelog, unless it is attached already.
+pub(crate) fn attach_sidecar(conn: &Connection) -> Result<(), Error> {
+    if sidecar_attached(conn)? {
+        return Ok(());
+    }
+    let path = match conn.path() {
+        Some(path) if !path.is_empty() => {
+            let path = format!("{path}.undo");
+            // libraries are opened without the flag to create files,
+            // which attached databases share
+            Connection::open(&path).map_err(open_err)?;
+            path
+        }
+        _ => ":memory:".to_string(),
+    };
+    conn.execute("ATTACH DATABASE ?1 AS berts_undo", [path])
+        .map_err(open_err)?;
+    Ok(())
+}
+
+/// Attach the sidecar like [`attach_sidecar`] if there is one already, as
+/// handles that only read must not create it, returning whether it is
+/// attached.
+pub(crate) fn attach_existing_sidecar(conn: &Connection) -> Result<bool, Error> {
+    if sidecar_attached(conn)? {
+        return Ok(true);
+    }
+    let path = match conn.path() {
+        Some(path) if !path.is_empty() => format!("{path}.undo"),
+        _ => return Ok(false),
+    };
+    if !Path::new(&path).is_file() {
+        return Ok(false);
+    }
+    conn.execute("ATTACH DATABASE ?1 AS berts_undo", [path])
+        .map_err(open_err)?;
+    Ok(true)
+}
+
+fn sidecar_attached(conn: &Connection) -> Result<bool, Error> {
+    conn.query_row(
+        "SELECT 1 FROM pragma_database_list WHERE name = 'berts_undo'",
+        [],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|found| found.is_some())
+    .map_err(Error::query)
+}
+
+fn open_err(source: rusqlite::Error) -> Error {
+    Error {
+        source: Some(source),
+        kind: ErrorKind::Open,
+    }
+}
+
+/// Clears [`Journal::entry`] once the outermost write ends, even by panic.
+struct Recording<'a>(&'a Cell<Option<i64>>);
+
+impl Drop for Recording<'_> {
+    fn drop(&mut self) {
+        self.0.set(None);
+    }
+}
+
+fn corrupt(message: &str) -> Error {
+    Error {
+        source: Some(rusqlite::Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_CORRUPT),
+            Some(message.to_string()),
+        )),
+        kind: ErrorKind::Write,
+    }
+}
+
+/// Whether anything was ever recorded, so the tables exist.
+pub(crate) fn recorded(conn: &Connection) -> Result<bool, Error> {
+    conn.query_row(
+        "SELECT 1 FROM berts_undo.sqlite_master WHERE name = 'entries'",
+        [],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|found| found.is_some())
+    .map_err(Error::query)
+}
+
+pub(crate) fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |since| since.as_secs_f64())
+}
+
+/// Put back the rows of one image, as they were before its write.
+fn restore(conn: &Connection, image: i64, table: &str, rows: &Rows) -> Result<(), Error> {
+    let sql = format!(
+        "DELETE FROM {} WHERE {}",
+        qualified(table),
+        rows.filter(table)
+    );
+    conn.execute(&sql, params_from_iter(rows.params()))
+        .map_err(Error::write)?;
+
+    let mut cells = conn
+        .prepare_cached(
+            "SELECT row, col, value FROM berts_undo.cells WHERE image = ?1 ORDER BY row, rowid",
+        )
+        .map_err(Error::query)?;
+    let cells = cells
+        .query_map([image], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Value>(2)?,
+            ))
+        })
+        .map_err(Error::query)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Error::query)?;
+    for row in cells.chunk_by(|a, b| a.0 == b.0) {
+        // quoted, as they are read back from the journal file
+        let columns: Vec<_> = row
+            .iter()
+            .map(|(_, column, _)| format!("\"{}\"", column.replace('"', "\"\"")))
+            .collect();
+        let placeholders: Vec<_> = (1..=columns.len()).map(|i| format!("?{i}")).collect();
+        let sql = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            qualified(table),
+            columns.join(", "),
+            placeholders.join(", ")
+        );
+        let values = row.iter().map(|(_, _, value)| value);
+        conn.execute(&sql, params_from_iter(values))
+            .map_err(Error::write)?;
+    }
+    Ok(())
+}
+
+/// Remove `entry` from the journal, with the rows it saved.
+pub(crate) fn forget(conn: &Connection, entry: i64) -> Result<(), Error> {
+    conn.execute(
+        "DELETE FROM berts_undo.cells
+        WHERE image IN (SELECT id FROM berts_undo.images WHERE entry = ?1)",
+        [entry],
+    )
+    .map_err(Error::write)?;
+    conn.execute("DELETE FROM berts_undo.images WHERE entry = ?1", [entry])
+        .map_err(Error::write)?;
+    conn.execute("DELETE FROM berts_undo.entries WHERE id = ?1", [entry])
+        .map_err(Error::write)?;
+    Ok(())
+}
+
+/// Forget all but the latest `limit` entries.
+fn prune(conn: &Connection, limit: usize) -> Result<(), Error> {
+    let mut old = conn
+        .prepare_cached("SELECT id FROM berts_undo.entries ORDER BY id DESC LIMIT -1 OFFSET ?1")
+        .map_err(Error::query)?;
+    let old = old
+        .query_map([i64::try_from(limit).unwrap_or(i64::MAX)], |row| row.get(0))
+        .map_err(Error::query)?
+        .collect::<Result<Vec<i64>, _>>()
+        .map_err(Error::query)?;
+    old.into_iter().try_for_each(|entry| forget(conn, entry))
+}
+
+/// Take back the write of one entry, removing it from the journal.
+fn undo_entry(conn: &Connection, entry: i64) -> Result<(), Error> {
+    let mut images = conn
+        .prepare_cached(
+            "SELECT id, tbl, rows, entity, key FROM berts_undo.images
+            WHERE entry = ?1 ORDER BY id DESC",
+        )
+        .map_err(Error::query)?;
+    let images = images
+        .query_map([entry], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, u32>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })
+        .map_err(Error::query)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Error::query)?;
+    // in reverse, so each image is restored over the rows as it left them
+    for (image, table, kind, entity, key) in images {
+        let Some(table) = TABLES.iter().find(|known| **known == table) else {
+            return Err(corrupt("undo journal names an unknown table"));
+        };
+        let Some(rows) = Rows::parse(&kind, entity, key) else {
+            return Err(corrupt("undo journal has an unknown kind of rows"));
+        };
+        restore(conn, image, table, &rows)?;
+    }
+    forget(conn, entry)
+}
+
+impl Database<ReadWrite> {
+    /// Run the write `f` once the library is found safe to write to, all
+    /// together or not at all, recording it in the undo journal as
+    /// `description`.
+    pub(crate) fn write<T>(
+        &self,
+        description: &str,
+        f: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let conn = self.connection();
+        self.checked(|| {
+            atomically(conn, || {
+                if !self.journal.recording || self.journal.entry.get().is_some() {
+                    return f();
+                }
+                conn.execute_batch(CREATE_TABLES).map_err(Error::write)?;
+                conn.execute(
+                    "INSERT INTO berts_undo.entries (time, description) VALUES (?1, ?2)",
+                    (now(), description),
+                )
+                .map_err(Error::write)?;
+                let entry = conn.last_insert_rowid();
+                self.journal.entry.set(Some(entry));
+                let _recording = Recording(&self.journal.entry);
+                let value = f()?;
+                // a write that changed nothing through this crate has
+                // nothing to undo
+                conn.execute(
+                    "DELETE FROM berts_undo.entries WHERE id = ?1
+                    AND NOT EXISTS (SELECT 1 FROM berts_undo.images WHERE entry = ?1)",
+                    [entry],
+                )
+                .map_err(Error::write)?;
+                prune(conn, self.journal.limit)?;
+                Ok(value)
+            })
+        })
+    }
+
+    /// Save the `rows` of `table` as they are before a write changes them.
+    pub(crate) fn record(&self, table: &'static str, rows: &Rows) -> Result<(), Error> {
+        let Some(image) = self.image(table, rows)? else {
+            return Ok(());
+        };
+        let conn = self.connection();
+        let sql = format!(
+            "SELECT * FROM {} WHERE {}",
+            qualified(table),
+            rows.filter(table)
+        );
+        let mut select = conn.prepare(&sql).map_err(Error::query)?;
+        let columns: Vec<String> = select
+            .column_names()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let mut insert = conn
+            .prepare_cached(
+                "INSERT INTO berts_undo.cells (image, row, col, value) VALUES (?1, ?2, ?3, ?4)",
+            )
+            .map_err(Error::write)?;
+        let mut found = select
+            .query(params_from_iter(rows.params()))
+            .map_err(Error::query)?;
+        let mut index = 0;
+        while let Some(row) = found.next().map_err(Error::query)? {
+            for (i, column) in columns.iter().enumerate() {
+                let value: Value = row.get(i).map_err(Error::query)?;
+                insert
+                    .execute((image, index, column, value))
+                    .map_err(Error::write)?;
+            }
+            index += 1;
+        }
+        Ok(())
+    }
+
+    /// Note that a write created the `rows` of `table`, which undoing it
+    /// removes.
+    pub(crate) fn record_created(&self, table: &'static str, rows: &Rows) -> Result<(), Error> {
+        self.image(table, rows).map(drop)
+    }
+
+    /// Add an image of `rows` to the entry being recorded, if there is one.
+    fn image(&self, table: &'static str, rows: &Rows) -> Result<Option<i64>, Error> {
+        let Some(entry) = self.journal.entry.get() else {
+            return Ok(None);
+        };
+        let conn = self.connection();
+        conn.execute(
+            "INSERT INTO berts_undo.images (entry, tbl, rows, entity, key)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            (entry, table, rows.kind(), rows.entity(), rows.key()),
+        )
+        .map_err(Error::write)?;
+        Ok(Some(conn.last_insert_rowid()))
+    }
+
+    /// The writes made through this crate that can be undone, newest first.
+    ///
+    /// They are kept beside the library, as `library.db.undo`, unless it was
+    /// opened without one with
+    /// [`OpenOptions::undo_journal`](crate::OpenOptions::undo_journal). Only
+    /// the latest 100 are kept, or as many as
+    /// [`OpenOptions::undo_limit`](crate::OpenOptions::undo_limit) sets.
+    /// Writes through a [`Connection`], such as
+    /// [`set_attribute`](crate::set_attribute()), are not recorded.
+    ///
+    /// # Errors
+    /// Returns an error if the journal cannot be read
+    pub fn undo_journal(&self) -> Result<Vec<UndoEntry>, Error> {
+        let conn = self.connection();
+        if !self.journal.recording || !recorded(conn)? {
+            return Ok(vec![]);
+        }
+        let mut entries = conn
+            .prepare("SELECT id, time, description FROM berts_undo.entries ORDER BY id DESC")
+            .map_err(Error::query)?;
+        let entries = entries
+            .query_map([], |row| {
+                Ok(UndoEntry {
+                    id: row.get(0)?,
+                    time: row.get(1)?,
+                    description: row.get(2)?,
+                })
+            })
+            .map_err(Error::query)?
+            .collect::<Result<_, _>>()
+            .map_err(Error::query);
+        entries
+    }
+
+    /// Take back the last `n` writes in the [journal](Self::undo_journal),
+    /// newest first, returning how many there were. Each puts back the rows
+    /// it changed as they were, over any change made to them since.
+    ///
+    /// # Errors
+    /// Returns an error if the journal cannot be read, or one for which
+    /// [`Error::write_hazard`] is `Some` if writing is not safe
+    pub fn undo_last(&self, n: usize) -> Result<usize, Error> {
+        let conn = self.connection();
+        if !self.journal.recording || !recorded(conn)? {
+            return Ok(0);
+        }
+        self.checked(|| {
+            atomically(conn, || {
+                let mut entries = conn
+                    .prepare("SELECT id FROM berts_undo.entries ORDER BY id DESC LIMIT ?1")
+                    .map_err(Error::query)?;
+                let entries = entries
+                    .query_map([i64::try_from(n).unwrap_or(i64::MAX)], |row| row.get(0))
+                    .map_err(Error::query)?
+                    .collect::<Result<Vec<i64>, _>>()
+                    .map_err(Error::query)?;
+                for entry in &entries {
+                    undo_entry(conn, *entry)?;
+                }
+                Ok(entries.len())
+            })
+        })
+    }
+}
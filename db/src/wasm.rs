@@ -0,0 +1,276 @@
+//! [`wasm_bindgen`] bindings, so a web frontend can use the records as they
+//! are instead of declaring them again in JavaScript.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{read_all_from_bytes, Album, FieldValue, Item, Library};
+
+#[allow(clippy::cast_precision_loss)]
+fn to_js(value: Option<FieldValue>) -> JsValue {
+    match value {
+        None | Some(FieldValue::Null) => JsValue::NULL,
+        Some(FieldValue::Bool(b)) => JsValue::from_bool(b),
+        // JavaScript numbers are doubles, which hold any id or count
+        Some(FieldValue::Integer(i)) => JsValue::from_f64(i as f64),
+        Some(FieldValue::Real(r)) => JsValue::from_f64(r),
+        Some(FieldValue::Text(t)) => JsValue::from_str(&t),
+    }
+}
+
+/// An [`Album`], as `Album` in JavaScript.
+#[wasm_bindgen(js_name = Album)]
+#[derive(Clone, Debug)]
+pub struct JsAlbum(Album);
+
+#[wasm_bindgen(js_class = Album)]
+impl JsAlbum {
+    /// Look up a field by its column name, `null` if there is none.
+    #[must_use]
+    pub fn get(&self, field: &str) -> JsValue {
+        to_js(self.0.field(field))
+    }
+
+    /// The names of every field.
+    #[must_use]
+    pub fn fields() -> Vec<String> {
+        Album::COLUMNS
+            .iter()
+            .map(|field| (*field).to_string())
+            .collect()
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn id(&self) -> u32 {
+        self.0.id
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn album(&self) -> String {
+        self.0.album.clone()
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn albumartist(&self) -> String {
+        self.0.albumartist.clone()
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn genre(&self) -> String {
+        self.0.genre.clone()
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn year(&self) -> u32 {
+        self.0.year
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn artpath(&self) -> Option<String> {
+        self.0
+            .artpath
+            .as_ref()
+            .map(|path| path.to_string_lossy().into_owned())
+    }
+}
+
+/// An [`Item`], as `Item` in JavaScript.
+#[wasm_bindgen(js_name = Item)]
+#[derive(Clone, Debug)]
+pub struct JsItem(Item);
+
+#[wasm_bindgen(js_class = Item)]
+impl JsItem {
+    /// Look up a field by its column name, `null` if there is none.
+    #[must_use]
+    pub fn get(&self, field: &str) -> JsValue {
+        to_js(self.0.field(field))
+    }
+
+    /// The names of every field.
+    #[must_use]
+    pub fn fields() -> Vec<String> {
+        Item::COLUMNS
+            .iter()
+            .map(|field| (*field).to_string())
+            .collect()
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn id(&self) -> u32 {
+        self.0.id
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter = albumId)]
+    pub fn album_id(&self) -> Option<u32> {
+        self.0.album_id
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn title(&self) -> String {
+        self.0.title.clone()
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn artist(&self) -> String {
+        self.0.artist.clone()
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn album(&self) -> String {
+        self.0.album.clone()
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn albumartist(&self) -> String {
+        self.0.albumartist.clone()
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn genre(&self) -> String {
+        self.0.genre.clone()
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn year(&self) -> u32 {
+        self.0.year
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn track(&self) -> u32 {
+        self.0.track
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn disc(&self) -> u32 {
+        self.0.disc
+    }
+
+    /// In seconds.
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> f64 {
+        self.0.length
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn format(&self) -> String {
+        self.0.format.clone()
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn path(&self) -> String {
+        self.0.path.to_string_lossy().into_owned()
+    }
+}
+
+/// A [`Library`], as `Library` in JavaScript.
+#[wasm_bindgen(js_name = Library)]
+#[derive(Clone, Debug)]
+pub struct JsLibrary(Library);
+
+/// Read a library from the bytes of its `library.db`, or (with the `serde`
+/// feature) of a library serialized as JSON, or (with the `msgpack` feature)
+/// as MessagePack.
+///
+/// # Errors
+/// Returns an error if the bytes are not a library
+#[wasm_bindgen(js_name = parseLibrary)]
+pub fn parse_library(bytes: &[u8]) -> Result<JsLibrary, JsError> {
+    // a serialized library is a map of at most two fields
+    #[cfg(feature = "msgpack")]
+    if matches!(bytes.first(), Some(0x80..=0x82)) {
+        return Ok(JsLibrary(Library::from_msgpack_bytes(bytes)?));
+    }
+    #[cfg(feature = "serde")]
+    if !bytes.starts_with(b"SQLite format 3\0") {
+        return Ok(JsLibrary(Library::from_json_bytes(bytes)?));
+    }
+    let (albums, items) = read_all_from_bytes(bytes)?;
+    Ok(JsLibrary(Library { albums, items }))
+}
+
+#[wasm_bindgen(js_class = Library)]
+impl JsLibrary {
+    #[must_use]
+    pub fn albums(&self) -> Vec<JsAlbum> {
+        self.0.albums.iter().cloned().map(JsAlbum).collect()
+    }
+
+    #[must_use]
+    pub fn items(&self) -> Vec<JsItem> {
+        self.0.items.iter().cloned().map(JsItem).collect()
+    }
+
+    #[must_use]
+    pub fn album(&self, id: u32) -> Option<JsAlbum> {
+        self.0.album(id).cloned().map(JsAlbum)
+    }
+
+    #[must_use]
+    pub fn item(&self, id: u32) -> Option<JsItem> {
+        self.0.item(id).cloned().map(JsItem)
+    }
+
+    /// The items of an album.
+    #[must_use]
+    #[wasm_bindgen(js_name = albumItems)]
+    pub fn album_items(&self, album_id: u32) -> Vec<JsItem> {
+        self.0.album_items(album_id).cloned().map(JsItem).collect()
+    }
+
+    /// The albums whose text fields contain `text`, as
+    /// [`Library::search_albums`] finds them.
+    #[must_use]
+    #[wasm_bindgen(js_name = searchAlbums)]
+    pub fn search_albums(&self, text: &str) -> Vec<JsAlbum> {
+        self.0
+            .search_albums(text)
+            .into_iter()
+            .cloned()
+            .map(JsAlbum)
+            .collect()
+    }
+
+    /// The items whose text fields contain `text`, as
+    /// [`Library::search_items`] finds them.
+    #[must_use]
+    #[wasm_bindgen(js_name = searchItems)]
+    pub fn search_items(&self, text: &str) -> Vec<JsItem> {
+        self.0
+            .search_items(text)
+            .into_iter()
+            .cloned()
+            .map(JsItem)
+            .collect()
+    }
+}
+
+impl From<Library> for JsLibrary {
+    fn from(library: Library) -> Self {
+        JsLibrary(library)
+    }
+}
+
+impl From<JsLibrary> for Library {
+    fn from(library: JsLibrary) -> Self {
+        library.0
+    }
+}
@@ -0,0 +1,118 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::CancellationToken;
+
+/// How often a [`Watcher`] checks the library by default.
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The size and modification time of a file, or `None` if it does not exist.
+pub(crate) type Stamp = Option<(u64, Option<SystemTime>)>;
+
+pub(crate) fn stamp(path: &Path) -> io::Result<Stamp> {
+    match fs::metadata(path) {
+        Ok(metadata) => Ok(Some((metadata.len(), metadata.modified().ok()))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// A database and its `-wal` file, which holds recent writes in WAL mode.
+pub(crate) fn with_wal(path: &Path) -> [PathBuf; 2] {
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    [path.to_path_buf(), wal.into()]
+}
+
+/// Notices when beets writes to a library database.
+///
+/// The database is polled rather than subscribed to, which works the same on
+/// every platform and on network storage. Writes land in the database file or,
+/// in WAL mode, its `-wal` file, so both are checked.
+#[derive(Debug)]
+pub struct Watcher {
+    files: [PathBuf; 2],
+    last: [Stamp; 2],
+    interval: Duration,
+}
+
+impl Watcher {
+    /// Start watching the database at `path` for changes from now on.
+    ///
+    /// # Errors
+    /// Returns an error if the files cannot be inspected
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        let files = with_wal(path.as_ref());
+        let last = [stamp(&files[0])?, stamp(&files[1])?];
+        Ok(Self {
+            files,
+            last,
+            interval: WATCH_INTERVAL,
+        })
+    }
+
+    /// Check every `interval` instead of every [`WATCH_INTERVAL`].
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Whether the database changed since it was last checked.
+    ///
+    /// # Errors
+    /// Returns an error if the files cannot be inspected
+    pub fn changed(&mut self) -> io::Result<bool> {
+        let current = [stamp(&self.files[0])?, stamp(&self.files[1])?];
+        let changed = current != self.last;
+        self.last = current;
+        Ok(changed)
+    }
+
+    /// Block until the database changes and then stays unchanged for one
+    /// interval, so a beets import that writes many times is seen once.
+    ///
+    /// # Errors
+    /// Returns an error if the files cannot be inspected
+    pub fn wait(&mut self) -> io::Result<()> {
+        self.wait_cancellable(&CancellationToken::new()).map(|_| ())
+    }
+
+    /// Like [`wait`](Self::wait), returning `false` once `cancel` is
+    /// cancelled instead of waiting on.
+    ///
+    /// # Errors
+    /// Returns an error if the files cannot be inspected
+    pub fn wait_cancellable(&mut self, cancel: &CancellationToken) -> io::Result<bool> {
+        while !self.changed()? {
+            if cancel.is_cancelled() {
+                return Ok(false);
+            }
+            thread::sleep(self.interval);
+        }
+        self.settle_cancellable(cancel)
+    }
+
+    /// Block until the database stays unchanged for one interval, e.g. after
+    /// [`changed`](Self::changed) returned `true`.
+    ///
+    /// # Errors
+    /// Returns an error if the files cannot be inspected
+    pub fn settle(&mut self) -> io::Result<()> {
+        self.settle_cancellable(&CancellationToken::new())
+            .map(|_| ())
+    }
+
+    fn settle_cancellable(&mut self, cancel: &CancellationToken) -> io::Result<bool> {
+        loop {
+            thread::sleep(self.interval);
+            if cancel.is_cancelled() {
+                return Ok(false);
+            }
+            if !self.changed()? {
+                return Ok(true);
+            }
+        }
+    }
+}
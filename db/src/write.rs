@@ -0,0 +1,290 @@
+use std::convert::TryFrom;
+
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+
+use crate::undo::Rows;
+use crate::{schema, Album, Database, Entity, Error, FieldValue, Item, ReadWrite};
+
+/// Columns beets stores as BLOBs of the path's bytes.
+const PATH_COLUMNS: &[&str] = &["path", "artpath"];
+
+/// The value to bind for `column`, with paths stored the way beets does.
+fn sql_value(column: &str, value: FieldValue<'_>) -> Value {
+    match value {
+        FieldValue::Null => Value::Null,
+        FieldValue::Bool(b) => Value::Integer(b.into()),
+        FieldValue::Integer(i) => Value::Integer(i),
+        FieldValue::Real(r) => Value::Real(r),
+        FieldValue::Text(t) if PATH_COLUMNS.contains(&column) => {
+            Value::Blob(t.into_owned().into_bytes())
+        }
+        FieldValue::Text(t) => Value::Text(t.into_owned()),
+    }
+}
+
+/// The columns to write besides `id`, and their values.
+fn row<'a>(
+    conn: &Connection,
+    table: &str,
+    columns: &[&'static str],
+    field: impl Fn(&str) -> Option<FieldValue<'a>>,
+) -> Result<(Vec<&'static str>, Vec<Value>), Error> {
+    let columns: Vec<_> = schema::writable_columns(conn, table, columns)
+        .map_err(Error::write)?
+        .into_iter()
+        .filter(|column| *column != "id")
+        .collect();
+    let values = columns
+        .iter()
+        .map(|column| sql_value(column, field(column).unwrap_or(FieldValue::Null)))
+        .collect();
+    Ok((columns, values))
+}
+
+fn insert<'a>(
+    conn: &Connection,
+    table: &str,
+    columns: &[&'static str],
+    id: u32,
+    field: impl Fn(&str) -> Option<FieldValue<'a>>,
+) -> Result<u32, Error> {
+    let (mut columns, mut values) = row(conn, table, columns, field)?;
+    if id != 0 {
+        columns.push("id");
+        values.push(Value::Integer(id.into()));
+    }
+    let placeholders: Vec<_> = (1..=columns.len()).map(|i| format!("?{i}")).collect();
+    let sql = format!(
+        "INSERT INTO {table} ({}) VALUES ({})",
+        columns.join(", "),
+        placeholders.join(", ")
+    );
+    conn.execute(&sql, params_from_iter(values))
+        .map_err(Error::write)?;
+    let id = conn.last_insert_rowid();
+    u32::try_from(id).map_err(|_| Error::write(rusqlite::Error::IntegralValueOutOfRange(0, id)))
+}
+
+fn update<'a>(
+    conn: &Connection,
+    table: &str,
+    columns: &[&'static str],
+    id: u32,
+    field: impl Fn(&str) -> Option<FieldValue<'a>>,
+) -> Result<bool, Error> {
+    let (columns, mut values) = row(conn, table, columns, field)?;
+    let assignments: Vec<_> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| format!("{column} = ?{}", i + 1))
+        .collect();
+    let sql = format!(
+        "UPDATE {table} SET {} WHERE id = ?{}",
+        assignments.join(", "),
+        columns.len() + 1
+    );
+    values.push(Value::Integer(id.into()));
+    let updated = conn
+        .execute(&sql, params_from_iter(values))
+        .map_err(Error::write)?;
+    Ok(updated > 0)
+}
+
+/// Run `f` in a savepoint, so its writes happen all together or not at all,
+/// even inside a transaction that is open already.
+pub(crate) fn atomically<T>(
+    conn: &Connection,
+    f: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+    conn.execute_batch("SAVEPOINT berts_write")
+        .map_err(Error::write)?;
+    match f() {
+        Ok(value) => {
+            conn.execute_batch("RELEASE berts_write")
+                .map_err(Error::write)?;
+            Ok(value)
+        }
+        Err(err) => {
+            // the error that caused the rollback is the one worth reporting
+            let _ = conn.execute_batch("ROLLBACK TO berts_write; RELEASE berts_write");
+            Err(err)
+        }
+    }
+}
+
+/// Delete the rows of `table` with `column` equal to `id`.
+fn delete_where(conn: &Connection, table: &str, column: &str, id: u32) -> Result<usize, Error> {
+    let sql = format!("DELETE FROM {table} WHERE {column} = ?1");
+    conn.execute(&sql, [id]).map_err(Error::write)
+}
+
+/// The ids of the items of the album with `id`.
+pub(crate) fn album_item_ids(conn: &Connection, id: u32) -> Result<Vec<u32>, Error> {
+    let mut stmt = conn
+        .prepare("SELECT id FROM items WHERE album_id = ?1")
+        .map_err(Error::write)?;
+    let ids = stmt
+        .query_map([id], |row| row.get(0))
+        .map_err(Error::write)?;
+    ids.collect::<Result<_, _>>().map_err(Error::write)
+}
+
+impl Item {
+    /// Add the item to the library, returning its id. An `id` of 0 lets the
+    /// database choose one, as beets does on import.
+    ///
+    /// # Errors
+    /// Returns an error if an item with the same id exists
+    pub fn insert(&self, db: &Database<ReadWrite>) -> Result<u32, Error> {
+        db.write("insert item", || {
+            let id = insert(db.connection(), "items", Self::COLUMNS, self.id, |name| {
+                self.field(name)
+            })?;
+            db.record_created("items", &Rows::Id(id))?;
+            Ok(id)
+        })
+    }
+
+    /// Write every field of the item over the row with its id, returning
+    /// whether there was one.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL statement fails
+    pub fn update(&self, db: &Database<ReadWrite>) -> Result<bool, Error> {
+        db.write(&format!("update item {}", self.id), || {
+            db.record("items", &Rows::Id(self.id))?;
+            let conn = db.connection();
+            let previous = match db.provenance {
+                Some(_) => Self::read_id_range(conn, self.id, self.id)?.pop(),
+                None => None,
+            };
+            let updated = update(conn, "items", Self::COLUMNS, self.id, |name| {
+                self.field(name)
+            })?;
+            if let Some(previous) = previous {
+                db.stamp_provenance(
+                    Entity::Item(self.id),
+                    Self::COLUMNS,
+                    |name| previous.field(name),
+                    |name| self.field(name),
+                )?;
+            }
+            Ok(updated)
+        })
+    }
+
+    /// Remove the item and its flexible attributes from the library, as
+    /// `beet remove` does (leaving the file alone), returning whether it was
+    /// there.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL statement fails
+    pub fn delete(&self, db: &Database<ReadWrite>) -> Result<bool, Error> {
+        let conn = db.connection();
+        let attributes = Entity::Item(self.id).attribute_table();
+        db.write(&format!("delete item {}", self.id), || {
+            db.record(attributes, &Rows::EntityId(self.id))?;
+            db.record("items", &Rows::Id(self.id))?;
+            db.move_to_trash("items", &Rows::Id(self.id))?;
+            db.move_to_trash(attributes, &Rows::EntityId(self.id))?;
+            delete_where(conn, attributes, "entity_id", self.id)?;
+            Ok(delete_where(conn, "items", "id", self.id)? > 0)
+        })
+    }
+}
+
+impl Album {
+    /// Add the album to the library, returning its id. An `id` of 0 lets the
+    /// database choose one, which its items then need as their `album_id`.
+    ///
+    /// # Errors
+    /// Returns an error if an album with the same id exists
+    pub fn insert(&self, db: &Database<ReadWrite>) -> Result<u32, Error> {
+        db.write("insert album", || {
+            let id = insert(db.connection(), "albums", Self::COLUMNS, self.id, |name| {
+                self.field(name)
+            })?;
+            db.record_created("albums", &Rows::Id(id))?;
+            Ok(id)
+        })
+    }
+
+    /// Write every field of the album over the row with its id, returning
+    /// whether there was one. Its items keep their own copies of album
+    /// fields, which beets updates alongside.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL statement fails
+    pub fn update(&self, db: &Database<ReadWrite>) -> Result<bool, Error> {
+        db.write(&format!("update album {}", self.id), || {
+            db.record("albums", &Rows::Id(self.id))?;
+            let conn = db.connection();
+            let previous = match db.provenance {
+                Some(_) => Self::read_id_range(conn, self.id, self.id)?.pop(),
+                None => None,
+            };
+            let updated = update(conn, "albums", Self::COLUMNS, self.id, |name| {
+                self.field(name)
+            })?;
+            if let Some(previous) = previous {
+                db.stamp_provenance(
+                    Entity::Album(self.id),
+                    Self::COLUMNS,
+                    |name| previous.field(name),
+                    |name| self.field(name),
+                )?;
+            }
+            Ok(updated)
+        })
+    }
+
+    /// Remove the album, its items, and all of their flexible attributes
+    /// from the library, as `beet remove -a` does (leaving the files alone),
+    /// returning whether the album was there.
+    ///
+    /// # Errors
+    /// Returns an error if the SQL statement fails
+    pub fn delete(&self, db: &Database<ReadWrite>) -> Result<bool, Error> {
+        let conn = db.connection();
+        let attributes = Entity::Album(self.id).attribute_table();
+        db.write(&format!("delete album {}", self.id), || {
+            db.record("item_attributes", &Rows::AlbumItems(self.id))?;
+            db.record("items", &Rows::AlbumId(self.id))?;
+            db.record(attributes, &Rows::EntityId(self.id))?;
+            db.record("albums", &Rows::Id(self.id))?;
+            db.move_to_trash("albums", &Rows::Id(self.id))?;
+            db.move_to_trash(attributes, &Rows::EntityId(self.id))?;
+            if db.journal.trash {
+                for item in album_item_ids(conn, self.id)? {
+                    db.move_to_trash("items", &Rows::Id(item))?;
+                    db.move_to_trash("item_attributes", &Rows::EntityId(item))?;
+                }
+            }
+            conn.execute(
+                "DELETE FROM item_attributes
+                WHERE entity_id IN (SELECT id FROM items WHERE album_id = ?1)",
+                [self.id],
+            )
+            .map_err(Error::write)?;
+            delete_where(conn, "items", "album_id", self.id)?;
+            delete_where(conn, attributes, "entity_id", self.id)?;
+            Ok(delete_where(conn, "albums", "id", self.id)? > 0)
+        })
+    }
+
+    /// Like [`delete`](Self::delete), leaving its items, for changesets that
+    /// remove them as changes of their own.
+    pub(crate) fn delete_alone(&self, db: &Database<ReadWrite>) -> Result<bool, Error> {
+        let conn = db.connection();
+        let attributes = Entity::Album(self.id).attribute_table();
+        db.write(&format!("delete album {}", self.id), || {
+            db.record(attributes, &Rows::EntityId(self.id))?;
+            db.record("albums", &Rows::Id(self.id))?;
+            db.move_to_trash("albums", &Rows::Id(self.id))?;
+            db.move_to_trash(attributes, &Rows::EntityId(self.id))?;
+            delete_where(conn, attributes, "entity_id", self.id)?;
+            Ok(delete_where(conn, "albums", "id", self.id)? > 0)
+        })
+    }
+}
@@ -1,6 +1,9 @@
+use std::path::Path;
 use std::str::FromStr;
 
-use beet_db::{Album, Item};
+#[cfg(not(target_arch = "wasm32"))]
+use beet_db::Filter;
+use beet_db::{Album, FieldValue, Item, SortKey};
 
 mod tests;
 
@@ -21,6 +24,67 @@ impl Query {
     pub fn match_item(&self, item: &Item) -> bool {
         self.keys.match_item(item)
     }
+
+    /// The sort terms of the query, such as `year-`, in the order given.
+    pub fn sort_keys(&self) -> Vec<SortKey<'_>> {
+        self.sort
+            .iter()
+            .map(|sort| SortKey {
+                field: &sort.field,
+                ascending: sort.ascending,
+            })
+            .collect()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Query {
+    /// The SQL filter over `albums` matching the same albums as
+    /// [`match_album`](Self::match_album).
+    ///
+    /// Text is compared ignoring ASCII case only, as SQLite's `LIKE` does.
+    pub fn album_filter(&self) -> Filter {
+        self.keys.to_filter(album_columns)
+    }
+
+    /// The SQL filter over `items` matching the same items as
+    /// [`match_item`](Self::match_item).
+    ///
+    /// Text is compared ignoring ASCII case only, as SQLite's `LIKE` does.
+    pub fn item_filter(&self) -> Filter {
+        self.keys.to_filter(item_columns)
+    }
+
+    /// A query of the matching albums, in the requested order.
+    ///
+    /// # Errors
+    /// Returns an error for sorting by something other than an album field
+    pub fn albums(&self) -> Result<beet_db::Query<Album>, Error> {
+        let query = Album::query().filter(self.album_filter());
+        self.sorted(query, Album::COLUMNS)
+    }
+
+    /// A query of the matching items, in the requested order.
+    ///
+    /// # Errors
+    /// Returns an error for sorting by something other than an item field
+    pub fn items(&self) -> Result<beet_db::Query<Item>, Error> {
+        let query = Item::query().filter(self.item_filter());
+        self.sorted(query, Item::COLUMNS)
+    }
+
+    fn sorted<T>(
+        &self,
+        query: beet_db::Query<T>,
+        columns: &'static [&'static str],
+    ) -> Result<beet_db::Query<T>, Error> {
+        self.sort
+            .iter()
+            .try_fold(query, |query, sort| match column(columns, &sort.field) {
+                [column] => Ok(query.order_by(column, sort.ascending)),
+                _ => Err(Error),
+            })
+    }
 }
 
 impl FromStr for Query {
@@ -48,7 +112,6 @@ impl FromStr for Query {
 }
 
 #[derive(Debug, PartialEq)]
-#[allow(dead_code)]
 struct Sort {
     field: String,
     ascending: bool,
@@ -93,6 +156,22 @@ impl KeyGroup {
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+impl KeyGroup {
+    fn to_filter(&self, columns: fn(Option<&str>) -> &'static [&'static str]) -> Filter {
+        let filters = self
+            .keys
+            .iter()
+            .map(|key| key.to_filter(columns(key.field.as_deref())))
+            .collect();
+        if self.all {
+            Filter::All(filters)
+        } else {
+            Filter::Any(filters)
+        }
+    }
+}
+
 impl Default for KeyGroup {
     fn default() -> Self {
         Self {
@@ -110,106 +189,121 @@ struct Keyword {
     negated: bool,
 }
 
+/// The album columns a keyword on `field` (or on no field) searches.
+fn album_columns(field: Option<&str>) -> &'static [&'static str] {
+    match field {
+        Some("album") => &["album", "albumdisambig"],
+        Some("albumartist") => &["albumartist", "albumartist_sort", "albumartist_credit"],
+        Some(field) => column(Album::COLUMNS, field),
+        None => &[
+            "album",
+            "albumartist",
+            "albumartist_sort",
+            "albumartist_credit",
+            "genre",
+        ],
+    }
+}
+
+/// The item columns a keyword on `field` (or on no field) searches.
+fn item_columns(field: Option<&str>) -> &'static [&'static str] {
+    match field {
+        Some("artist") => &["artist", "artist_sort", "artist_credit"],
+        Some("albumartist") => &["albumartist", "albumartist_sort", "albumartist_credit"],
+        Some("composer") => &["composer", "composer_sort"],
+        Some(field) => column(Item::COLUMNS, field),
+        None => &[
+            "title",
+            "album",
+            "artist",
+            "artist_sort",
+            "artist_credit",
+            "albumartist",
+            "albumartist_sort",
+            "albumartist_credit",
+            "genre",
+            "comments",
+        ],
+    }
+}
+
+/// Just `field`, if it is one of `columns`, otherwise nothing.
+fn column(columns: &'static [&'static str], field: &str) -> &'static [&'static str] {
+    columns
+        .iter()
+        .position(|column| *column == field)
+        .map_or(&[], |i| &columns[i..=i])
+}
+
 impl Keyword {
     fn match_album(&self, album: &Album) -> bool {
-        let year = format!("{}", album.year);
-        let month = format!("{}", album.month);
-        let day = format!("{}", album.day);
-        let disctotal = format!("{}", album.disctotal);
-
-        let txt = match self.field.as_ref().map(String::as_str) {
-            Some("album") => vec![&album.album, &album.albumdisambig],
-            Some("albumartist") => vec![
-                &album.albumartist,
-                &album.albumartist_sort,
-                &album.albumartist_credit,
-            ],
-            Some("genre") => vec![&album.genre],
-            Some("year") => vec![&year],
-            Some("month") => vec![&month],
-            Some("day") => vec![&day],
-            Some("disctotal") => vec![&disctotal],
-            Some("catalognum") => vec![&album.catalognum],
-            None => vec![
-                &album.album,
-                &album.albumartist,
-                &album.albumartist_sort,
-                &album.albumartist_credit,
-                &album.genre,
-            ],
-            _ => vec![],
-        };
-
-        self.negated
-            != match self.key_type {
-                Type::Basic => {
-                    let lower = self.text.to_lowercase();
-                    txt.iter().any(|s| s.to_lowercase().contains(&lower))
-                }
-                Type::Path => unimplemented!(),
-                // _ => unreachable!(),
-            }
+        self.matches(album_columns(self.field.as_deref()), |column| {
+            album.field(column)
+        })
     }
 
     fn match_item(&self, item: &Item) -> bool {
-        let year = format!("{}", item.year);
-        let month = format!("{}", item.month);
-        let day = format!("{}", item.day);
-        let track = format!("{}", item.track);
-        let tracktotal = format!("{}", item.tracktotal);
-        let disc = format!("{}", item.disc);
-        let disctotal = format!("{}", item.disctotal);
-        let bitrate = format!("{}", item.bitrate);
-
-        let txt = match self.field.as_ref().map(String::as_str) {
-            Some("title") => vec![&item.title],
-            Some("album") => vec![&item.album],
-            Some("artist") => vec![&item.artist, &item.artist_sort, &item.artist_credit],
-            Some("albumartist") => vec![
-                &item.albumartist,
-                &item.albumartist_sort,
-                &item.albumartist_credit,
-            ],
-            Some("genre") => vec![&item.genre],
-            Some("lyricist") => vec![&item.lyricist],
-            Some("composer") => vec![&item.composer, &item.composer_sort],
-            Some("arranger") => vec![&item.arranger],
-            Some("grouping") => vec![&item.grouping],
-            Some("year") => vec![&year],
-            Some("month") => vec![&month],
-            Some("day") => vec![&day],
-            Some("track") => vec![&track],
-            Some("tracktotal") => vec![&tracktotal],
-            Some("disc") => vec![&disc],
-            Some("disctotal") => vec![&disctotal],
-            Some("catalognum") => vec![&item.catalognum],
-            Some("format") => vec![&item.format],
-            Some("bitrate") => vec![&bitrate],
-            None => vec![
-                &item.title,
-                &item.album,
-                &item.artist,
-                &item.artist_sort,
-                &item.artist_credit,
-                &item.albumartist,
-                &item.albumartist_sort,
-                &item.albumartist_credit,
-                &item.genre,
-                &item.comments,
-            ],
-            _ => vec![],
-        };
+        self.matches(item_columns(self.field.as_deref()), |column| {
+            item.field(column)
+        })
+    }
 
+    fn matches<'a>(
+        &self,
+        columns: &[&str],
+        field: impl Fn(&str) -> Option<FieldValue<'a>>,
+    ) -> bool {
+        let mut values = columns.iter().filter_map(|column| field(column));
         self.negated
             != match self.key_type {
                 Type::Basic => {
                     let lower = self.text.to_lowercase();
-                    txt.iter().any(|s| s.to_lowercase().contains(&lower))
+                    values.any(|value| value.to_string().to_lowercase().contains(&lower))
                 }
-                Type::Path => unimplemented!(),
-                // _ => unreachable!(),
+                Type::NumRange(low, high) => values.any(|value| {
+                    let number = match value {
+                        FieldValue::Bool(b) => f64::from(u8::from(b)),
+                        FieldValue::Integer(i) => i as f64,
+                        FieldValue::Real(r) => r,
+                        _ => return false,
+                    };
+                    low.is_none_or(|low| number >= low) && high.is_none_or(|high| number <= high)
+                }),
+                Type::Path => values.any(|value| match value {
+                    FieldValue::Text(path) => Path::new(&*path).starts_with(&self.text),
+                    _ => false,
+                }),
             }
     }
+
+    /// The SQL equivalent of the keyword, searching `columns`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn to_filter(&self, columns: &'static [&'static str]) -> Filter {
+        let filters = columns
+            .iter()
+            .map(|&column| match self.key_type {
+                Type::Basic => Filter::Contains(column, self.text.clone()),
+                // SQLite orders text after every number, and comparing with
+                // `NULL` is `NULL`, so only numbers are compared, as in
+                // `matches`, and the negation matches the rest
+                Type::NumRange(low, high) => Filter::All(vec![
+                    Filter::IsNumber(column),
+                    Filter::Range {
+                        column,
+                        low: low.map(FieldValue::Real),
+                        high: high.map(FieldValue::Real),
+                    },
+                ]),
+                Type::Path => Filter::Under(column, self.text.clone()),
+            })
+            .collect();
+        let filter = Filter::Any(filters);
+        if self.negated {
+            Filter::Not(Box::new(filter))
+        } else {
+            filter
+        }
+    }
 }
 
 impl FromStr for Keyword {
@@ -226,31 +320,45 @@ impl FromStr for Keyword {
 
         if let Some(idx) = curr_str.find(':') {
             match &curr_str[..idx] {
-                "path" => new.key_type = Type::Path,
+                "path" => {
+                    new.key_type = Type::Path;
+                    new.field = Some("path".to_string());
+                }
                 // TODO: add regex support here
                 other => new.field = Some(other.to_string()),
             }
             curr_str = &curr_str[idx + 1..];
         }
 
-        // TODO: add num and date range support here
+        // TODO: add date range support here
+        if new.field.is_some() && new.key_type == Type::Basic {
+            if let Some((low, high)) = parse_range(curr_str) {
+                new.key_type = Type::NumRange(low, high);
+            }
+        }
         new.text = curr_str.to_string();
 
         Ok(new)
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// The bounds of a numeric range like `1990..1999`, `1990..` or `..1999`.
+fn parse_range(text: &str) -> Option<(Option<f64>, Option<f64>)> {
+    let (low, high) = text.split_once("..")?;
+    let bound = |bound: &str| match bound {
+        "" => Ok(None),
+        bound => bound.parse().map(Some),
+    };
+    Some((bound(low).ok()?, bound(high).ok()?))
+}
+
+#[derive(Debug, Default, PartialEq)]
 enum Type {
+    #[default]
     Basic,
     Path,
     // Regex,
-    // NumRange,
+    /// Inclusive bounds, either of which may be open.
+    NumRange(Option<f64>, Option<f64>),
     // DateRange,
 }
-
-impl Default for Type {
-    fn default() -> Self {
-        Type::Basic
-    }
-}
@@ -34,3 +34,187 @@ fn sort_only() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn sort_keys() -> Result<(), Error> {
+    let query = "artist- year+".parse::<Query>()?;
+    let keys = query.sort_keys();
+    assert_eq!(keys.len(), 2);
+    assert!(keys[0].field == "artist" && !keys[0].ascending);
+    Ok(())
+}
+
+#[test]
+fn ranges() -> Result<(), Error> {
+    let query = "year:1990..1999 ^live".parse::<Query>()?;
+    assert_eq!(
+        query.keys.keys[0].key_type,
+        Type::NumRange(Some(1990.0), Some(1999.0))
+    );
+    assert_eq!(query.keys.keys[1].key_type, Type::Basic);
+
+    let item = |year, title: &str| Item {
+        year,
+        title: title.to_string(),
+        ..Item::default()
+    };
+    assert!(query.match_item(&item(1995, "Roygbiv")));
+    assert!(!query.match_item(&item(1995, "Roygbiv (Live)")));
+    assert!(!query.match_item(&item(2005, "Roygbiv")));
+
+    let open = "year:2000..".parse::<Query>()?;
+    assert!(open.match_item(&item(2005, "")));
+    assert!(!open.match_item(&item(1999, "")));
+    Ok(())
+}
+
+#[test]
+fn sql_matches_in_memory() {
+    let db = beet_db::OpenOptions::new()
+        .open("../db/tests/test.db")
+        .expect("test library");
+    let conn = db.connection();
+    let (albums, items) = db.read_all().expect("read library");
+    for text in [
+        "the",
+        "year:1990..1999 ^live",
+        "genre:rock year:..2000",
+        "^a e",
+        "path:/",
+        "^path:/",
+        "album:1..",
+        "^genre:..2000",
+        "comp:1..",
+    ] {
+        let query = text.parse::<Query>().expect("parse");
+        let expected: Vec<_> = items.iter().filter(|item| query.match_item(item)).collect();
+        let found = query.items().expect("compile").run(conn).expect("run");
+        assert_eq!(found.iter().collect::<Vec<_>>(), expected, "{text}");
+
+        let expected: Vec<_> = albums
+            .iter()
+            .filter(|album| query.match_album(album))
+            .collect();
+        let found = query.albums().expect("compile").run(conn).expect("run");
+        assert_eq!(found.iter().collect::<Vec<_>>(), expected, "{text}");
+    }
+
+    let sorted = "year:1990.. year+"
+        .parse::<Query>()
+        .unwrap()
+        .items()
+        .unwrap();
+    let years: Vec<_> = sorted
+        .run(conn)
+        .unwrap()
+        .iter()
+        .map(|item| item.year)
+        .collect();
+    assert!(years.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert!("nonsense+".parse::<Query>().unwrap().items().is_err());
+}
+
+#[test]
+fn negated_range_matches_null() {
+    let db = beet_db::Database::open_in_memory().expect("in-memory library");
+    db.connection()
+        .execute(
+            "INSERT INTO items (id, title, rg_track_gain) \
+             VALUES (1, 'loud', -9.0), (2, 'quiet', 3.0), (3, 'unscanned', NULL)",
+            (),
+        )
+        .expect("insert items");
+    let (_, items) = db.read_all().expect("read library");
+    for (text, titles) in [
+        ("rg_track_gain:..0", &["loud"][..]),
+        ("^rg_track_gain:..0", &["quiet", "unscanned"][..]),
+    ] {
+        let query = text.parse::<Query>().expect("parse");
+        let expected: Vec<_> = items
+            .iter()
+            .filter(|item| query.match_item(item))
+            .map(|item| item.title.as_str())
+            .collect();
+        assert_eq!(expected, titles, "{text}");
+        let found = query
+            .items()
+            .expect("compile")
+            .run(db.connection())
+            .expect("run");
+        let found: Vec<_> = found.iter().map(|item| item.title.as_str()).collect();
+        assert_eq!(found, titles, "{text}");
+    }
+}
+
+#[test]
+fn paths_match_by_directory() {
+    let db = beet_db::Database::open_in_memory().expect("in-memory library");
+    db.connection()
+        .execute(
+            "INSERT INTO items (id, title, path) VALUES \
+             (1, 'top', CAST('/music' AS BLOB)), \
+             (2, 'within', CAST('/music/a_b/1.flac' AS BLOB)), \
+             (3, 'sibling', '/musical/2.flac'), \
+             (4, 'shouting', '/MUSIC/3.flac')",
+            (),
+        )
+        .expect("insert items");
+    let (_, items) = db.read_all().expect("read library");
+    for (text, titles) in [
+        ("path:/music", &["top", "within"][..]),
+        ("path:/music/", &["top", "within"][..]),
+        ("path:/music/a_b", &["within"][..]),
+        ("path:/music/a%b", &[][..]),
+        ("^path:/music", &["sibling", "shouting"][..]),
+        ("path:/", &["top", "within", "sibling", "shouting"][..]),
+    ] {
+        let query = text.parse::<Query>().expect("parse");
+        let expected: Vec<_> = items
+            .iter()
+            .filter(|item| query.match_item(item))
+            .map(|item| item.title.as_str())
+            .collect();
+        assert_eq!(expected, titles, "{text}");
+        let found = query
+            .items()
+            .expect("compile")
+            .run(db.connection())
+            .expect("run");
+        let found: Vec<_> = found.iter().map(|item| item.title.as_str()).collect();
+        assert_eq!(found, titles, "{text}");
+    }
+}
+
+#[test]
+fn ranges_skip_text_columns() {
+    let db = beet_db::Database::open_in_memory().expect("in-memory library");
+    db.connection()
+        .execute(
+            "INSERT INTO items (id, title, album, year) \
+             VALUES (1, '1999', 'Moon Safari', 1998), (2, 'Untitled', '1', 0)",
+            (),
+        )
+        .expect("insert items");
+    let (_, items) = db.read_all().expect("read library");
+    for (text, titles) in [
+        ("title:1..", &[][..]),
+        ("album:..2000", &[][..]),
+        ("^album:0..", &["1999", "Untitled"][..]),
+        ("year:1990..", &["1999"][..]),
+    ] {
+        let query = text.parse::<Query>().expect("parse");
+        let expected: Vec<_> = items
+            .iter()
+            .filter(|item| query.match_item(item))
+            .map(|item| item.title.as_str())
+            .collect();
+        assert_eq!(expected, titles, "{text}");
+        let found = query
+            .items()
+            .expect("compile")
+            .run(db.connection())
+            .expect("run");
+        let found: Vec<_> = found.iter().map(|item| item.title.as_str()).collect();
+        assert_eq!(found, titles, "{text}");
+    }
+}
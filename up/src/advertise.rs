@@ -0,0 +1,436 @@
+use std::convert::TryFrom;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use net2::UdpBuilder;
+
+/// The service type clients can browse for to find libraries.
+const SERVICE_TYPE: &str = "_berts._tcp.local";
+/// So the web player shows up in browsers and other generic HTTP clients.
+const HTTP_SERVICE_TYPE: &str = "_http._tcp.local";
+/// What DNS-SD clients ask for to list every service type.
+const SERVICE_TYPES: &str = "_services._dns-sd._udp.local";
+
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+/// Tells caches to replace what they had for a record only this host owns.
+const CACHE_FLUSH: u16 = 0x8000;
+/// The TTLs RFC 6762 recommends for records naming hosts and the rest.
+const HOST_TTL: u32 = 120;
+const OTHER_TTL: u32 = 4500;
+
+/// A name as length-prefixed labels.
+fn encode_name(packet: &mut Vec<u8>, name: &str) {
+    for label in name.split('.') {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        packet.push(u8::try_from(label.len()).unwrap_or(63));
+        packet.extend_from_slice(label);
+    }
+    packet.push(0);
+}
+
+/// Check `name` can be announced: a DNS label holds 1 to 63 bytes, and
+/// browsers show control characters poorly if at all.
+pub fn check_name(name: &str) -> Result<String, String> {
+    if name.trim().is_empty() {
+        Err("the name cannot be blank".to_string())
+    } else if name.len() > 63 {
+        Err(format!("{name:?} is longer than 63 bytes"))
+    } else if name.chars().any(char::is_control) {
+        Err(format!("{name:?} has control characters"))
+    } else {
+        Ok(name.to_string())
+    }
+}
+
+/// A label that is also a valid host name.
+fn host_label(name: &str) -> String {
+    let label: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    match label.trim_matches('-') {
+        "" => "beet-up".to_string(),
+        label => label.to_string(),
+    }
+}
+
+/// The records that describe the server, all sent at once in each response.
+struct Records {
+    packet: Vec<u8>,
+    /// The same records with no time to live, to withdraw them.
+    goodbye: Vec<u8>,
+    /// The lowercased names a question has to ask about to be answered.
+    names: Vec<String>,
+}
+
+impl Records {
+    fn new(name: &str, ip: Ipv4Addr, port: u16) -> Self {
+        // `.` separates labels, so it cannot be part of the instance name
+        let instance = name.replace('.', " ");
+        let host = format!("{}.local", host_label(name));
+        let txt = [concat!("version=", env!("CARGO_PKG_VERSION")), "path=/"];
+
+        let mut answers = 0u16;
+        let mut records = Vec::new();
+        let mut ttls = Vec::new();
+        let mut record = |name: &str, kind: u16, class: u16, ttl: u32, data: &[u8]| {
+            encode_name(&mut records, name);
+            records.extend_from_slice(&kind.to_be_bytes());
+            records.extend_from_slice(&class.to_be_bytes());
+            ttls.push(records.len());
+            records.extend_from_slice(&ttl.to_be_bytes());
+            let len = u16::try_from(data.len()).expect("records are short");
+            records.extend_from_slice(&len.to_be_bytes());
+            records.extend_from_slice(data);
+            answers += 1;
+        };
+        let mut names = vec![host.to_lowercase()];
+        for &service in &[SERVICE_TYPE, HTTP_SERVICE_TYPE] {
+            let full_name = format!("{instance}.{service}");
+            let mut data = Vec::new();
+            encode_name(&mut data, service);
+            record(SERVICE_TYPES, TYPE_PTR, CLASS_IN, OTHER_TTL, &data);
+            data.clear();
+            encode_name(&mut data, &full_name);
+            record(service, TYPE_PTR, CLASS_IN, OTHER_TTL, &data);
+            data.clear();
+            // priority, weight, port, target
+            data.extend_from_slice(&[0, 0, 0, 0]);
+            data.extend_from_slice(&port.to_be_bytes());
+            encode_name(&mut data, &host);
+            record(
+                &full_name,
+                TYPE_SRV,
+                CLASS_IN | CACHE_FLUSH,
+                HOST_TTL,
+                &data,
+            );
+            data.clear();
+            for entry in &txt {
+                data.push(u8::try_from(entry.len()).expect("entries are short"));
+                data.extend_from_slice(entry.as_bytes());
+            }
+            record(
+                &full_name,
+                TYPE_TXT,
+                CLASS_IN | CACHE_FLUSH,
+                OTHER_TTL,
+                &data,
+            );
+            names.push(service.to_string());
+            names.push(full_name.to_lowercase());
+        }
+        record(
+            &host,
+            TYPE_A,
+            CLASS_IN | CACHE_FLUSH,
+            HOST_TTL,
+            &ip.octets(),
+        );
+        names.push(SERVICE_TYPES.to_string());
+
+        // an authoritative response with no id
+        let mut packet = vec![0, 0, 0x84, 0, 0, 0];
+        packet.extend_from_slice(&answers.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 0]);
+        let header = packet.len();
+        packet.extend_from_slice(&records);
+        let mut goodbye = packet.clone();
+        for ttl in ttls {
+            goodbye[header + ttl..header + ttl + 4].copy_from_slice(&[0; 4]);
+        }
+        Self {
+            packet,
+            goodbye,
+            names,
+        }
+    }
+
+    /// Whether `query` asks about any of the records.
+    fn answer(&self, query: &[u8]) -> bool {
+        let is_query = query.len() >= 12 && query[2] & 0x80 == 0;
+        if !is_query {
+            return false;
+        }
+        let questions = u16::from_be_bytes([query[4], query[5]]);
+        let mut offset = 12;
+        for _ in 0..questions {
+            let Some(name) = read_name(query, &mut offset) else {
+                return false;
+            };
+            // type and class
+            offset += 4;
+            if self.names.contains(&name) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// The lowercased name at `offset` of `packet`, following compression
+/// pointers, leaving `offset` just after it.
+fn read_name(packet: &[u8], offset: &mut usize) -> Option<String> {
+    let mut labels = Vec::new();
+    let mut at = *offset;
+    let mut jumped = false;
+    // at most one jump per byte, so a pointer loop cannot hang the responder
+    for _ in 0..packet.len() {
+        let len = usize::from(*packet.get(at)?);
+        if len == 0 {
+            if !jumped {
+                *offset = at + 1;
+            }
+            return Some(labels.join(".").to_lowercase());
+        } else if len & 0xc0 == 0xc0 {
+            let pointer = ((len & 0x3f) << 8) | usize::from(*packet.get(at + 1)?);
+            if !jumped {
+                *offset = at + 2;
+            }
+            jumped = true;
+            at = pointer;
+        } else {
+            let label = packet.get(at + 1..at + 1 + len)?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            at += 1 + len;
+        }
+    }
+    None
+}
+
+/// The address other hosts on the network reach this one at, if the server
+/// listens on every interface.
+fn local_ipv4(host: IpAddr) -> io::Result<Ipv4Addr> {
+    match host {
+        IpAddr::V4(ip) if !ip.is_unspecified() => Ok(ip),
+        IpAddr::V4(_) => {
+            // routing a datagram picks the interface, without sending one
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+            socket.connect((MDNS_GROUP, MDNS_PORT))?;
+            match socket.local_addr()? {
+                SocketAddr::V4(addr) => Ok(*addr.ip()),
+                SocketAddr::V6(_) => Err(io::ErrorKind::AddrNotAvailable.into()),
+            }
+        }
+        IpAddr::V6(_) => Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            "only IPv4 addresses are announced",
+        )),
+    }
+}
+
+/// Announce the server listening on `host` and `port` as `name` on the local
+/// network over mDNS, as `_berts._tcp` and `_http._tcp`, and keep answering
+/// for it on a thread of its own while the server runs. When the server is
+/// interrupted or terminated, the records are withdrawn before it exits, so
+/// clients forget it at once rather than when their caches expire.
+///
+/// Only the protocols the server speaks are announced, so no DAAP or
+/// Subsonic types: clients of those would find it and fail to talk to it.
+///
+/// # Errors
+/// Returns an error if the multicast socket cannot be opened.
+pub fn advertise(name: &str, host: IpAddr, port: u16) -> io::Result<()> {
+    let records = Arc::new(Records::new(name, local_ipv4(host)?, port));
+    let builder = UdpBuilder::new_v4()?;
+    // shared with any other responder on this host
+    builder.reuse_address(true)?;
+    #[cfg(unix)]
+    net2::unix::UnixUdpBuilderExt::reuse_port(&builder, true)?;
+    let socket = builder.bind((Ipv4Addr::UNSPECIFIED, MDNS_PORT))?;
+    socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+
+    #[cfg(unix)]
+    {
+        let socket = socket.try_clone()?;
+        let records = records.clone();
+        goodbye_on_exit(move || {
+            if let Err(err) = socket.send_to(&records.goodbye, (MDNS_GROUP, MDNS_PORT)) {
+                log::warn!("could not withdraw the announcement: {err}");
+            }
+        })?;
+    }
+
+    let group = (MDNS_GROUP, MDNS_PORT);
+    thread::spawn(move || {
+        // announced twice, a second apart, as RFC 6762 asks
+        for _ in 0..2 {
+            if let Err(err) = socket.send_to(&records.packet, group) {
+                log::warn!("could not announce the server: {err}");
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+        let mut query = [0; 9000];
+        loop {
+            match socket.recv_from(&mut query) {
+                Ok((len, _)) if records.answer(&query[..len]) => {
+                    if let Err(err) = socket.send_to(&records.packet, group) {
+                        log::warn!("could not answer an mDNS query: {err}");
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    log::warn!("stopped answering mDNS queries: {err}");
+                    break;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Run `goodbye` and exit once the process is interrupted or terminated.
+///
+/// The signals are blocked here, so this has to run before the server starts
+/// its threads for them to leave the signals to the one waiting for them.
+#[cfg(unix)]
+fn goodbye_on_exit(goodbye: impl FnOnce() + Send + 'static) -> io::Result<()> {
+    use nix::sys::signal::{SigSet, Signal};
+
+    let mut signals = SigSet::empty();
+    signals.add(Signal::SIGINT);
+    signals.add(Signal::SIGTERM);
+    signals.thread_block().map_err(io::Error::from)?;
+    thread::spawn(move || {
+        if let Ok(signal) = signals.wait() {
+            goodbye();
+            std::process::exit(128 + signal as i32);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The name, type, class and TTL of each record in a response.
+    fn read_records(packet: &[u8]) -> Vec<(String, u16, u16, u32)> {
+        let count = u16::from_be_bytes([packet[6], packet[7]]);
+        let mut offset = 12;
+        (0..count)
+            .map(|_| {
+                let name = read_name(packet, &mut offset).expect("a name");
+                let field = |at: usize| [packet[offset + at], packet[offset + at + 1]];
+                let kind = u16::from_be_bytes(field(0));
+                let class = u16::from_be_bytes(field(2));
+                let ttl = u32::from_be_bytes([
+                    packet[offset + 4],
+                    packet[offset + 5],
+                    packet[offset + 6],
+                    packet[offset + 7],
+                ]);
+                let len = usize::from(u16::from_be_bytes(field(8)));
+                offset += 10 + len;
+                (name, kind, class, ttl)
+            })
+            .collect()
+    }
+
+    /// A query for `name`, of any type.
+    fn query(name: &str) -> Vec<u8> {
+        let mut packet = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        encode_name(&mut packet, name);
+        packet.extend_from_slice(&[0, 255, 0, 1]);
+        packet
+    }
+
+    #[test]
+    fn read_names() {
+        let mut packet = vec![0xff];
+        encode_name(&mut packet, "Beets.local");
+        // a pointer to the name, after a label of its own
+        packet.extend_from_slice(&[3, b'w', b'w', b'w', 0xc0, 1]);
+
+        let mut offset = 1;
+        assert_eq!(
+            read_name(&packet, &mut offset).as_deref(),
+            Some("beets.local")
+        );
+        assert_eq!(offset, 14);
+        assert_eq!(
+            read_name(&packet, &mut offset).as_deref(),
+            Some("www.beets.local")
+        );
+        assert_eq!(offset, packet.len());
+
+        // loops and names running off the end
+        assert_eq!(read_name(&[0xc0, 0], &mut 0), None);
+        assert_eq!(read_name(&[5, b'a'], &mut 0), None);
+        assert_eq!(read_name(&[0xc0], &mut 0), None);
+    }
+
+    #[test]
+    fn records_describe_the_server() {
+        let records = Records::new("My Music", Ipv4Addr::new(192, 168, 1, 2), 8337);
+        let read = read_records(&records.packet);
+        assert_eq!(read.len(), 9);
+        assert!(read.contains(&(
+            "my music._berts._tcp.local".to_string(),
+            TYPE_SRV,
+            CLASS_IN | CACHE_FLUSH,
+            HOST_TTL
+        )));
+        assert!(read.contains(&(
+            "my-music.local".to_string(),
+            TYPE_A,
+            CLASS_IN | CACHE_FLUSH,
+            HOST_TTL
+        )));
+        assert!(read.contains(&(
+            "_http._tcp.local".to_string(),
+            TYPE_PTR,
+            CLASS_IN,
+            OTHER_TTL
+        )));
+
+        let goodbye = read_records(&records.goodbye);
+        assert_eq!(goodbye.len(), read.len());
+        assert!(goodbye.iter().all(|&(.., ttl)| ttl == 0));
+        assert_eq!(
+            goodbye
+                .into_iter()
+                .map(|(name, ..)| name)
+                .collect::<Vec<_>>(),
+            read.into_iter().map(|(name, ..)| name).collect::<Vec<_>>(),
+        );
+    }
+
+    #[test]
+    fn answer_questions_about_the_server() {
+        let records = Records::new("beets", Ipv4Addr::new(10, 0, 0, 1), 80);
+        assert!(records.answer(&query("_berts._tcp.local")));
+        assert!(records.answer(&query("_HTTP._tcp.local")));
+        assert!(records.answer(&query("beets._berts._tcp.local")));
+        assert!(records.answer(&query("beets.local")));
+        assert!(records.answer(&query(SERVICE_TYPES)));
+        assert!(!records.answer(&query("_ipp._tcp.local")));
+
+        // responses, and queries cut short
+        let mut response = query("_berts._tcp.local");
+        response[2] |= 0x80;
+        assert!(!records.answer(&response));
+        assert!(!records.answer(&query("_berts._tcp.local")[..14]));
+        assert!(!records.answer(&[0; 4]));
+    }
+
+    #[test]
+    fn check_names() {
+        assert_eq!(check_name("Living room").as_deref(), Ok("Living room"));
+        assert!(check_name("").is_err());
+        assert!(check_name("  ").is_err());
+        assert!(check_name(&"a".repeat(64)).is_err());
+        assert!(check_name("a\nb").is_err());
+    }
+}
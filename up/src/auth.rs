@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use beet_db::{Album, Item};
+use beet_query::Query;
+
+/// The header a reverse proxy names the signed in user with. It is only
+/// believed from one of the [`Proxies`].
+pub const USER_HEADER: &str = "x-remote-user";
+
+/// The reverse proxies trusted to name the user in [`USER_HEADER`]. Anyone
+/// else could name any user, so the header is ignored on their requests.
+#[derive(Clone, Debug, Default)]
+pub struct Proxies(Vec<IpAddr>);
+
+impl Proxies {
+    /// Trust requests from `addrs`, or from this machine if there are none.
+    pub fn new(mut addrs: Vec<IpAddr>) -> Self {
+        if addrs.is_empty() {
+            addrs = vec![Ipv4Addr::LOCALHOST.into(), Ipv6Addr::LOCALHOST.into()];
+        }
+        Proxies(addrs)
+    }
+
+    /// The user named in a request from `peer`, if it is trusted to.
+    pub fn user<'a>(&self, peer: Option<SocketAddr>, header: Option<&'a str>) -> Option<&'a str> {
+        peer.filter(|peer| self.0.contains(&peer.ip())).and(header)
+    }
+}
+
+/// The part of the library a request may see: the items matching every
+/// query and, if there are any directories, under one of them.
+#[derive(Debug, Default)]
+pub struct Scope {
+    queries: Vec<Query>,
+    directories: Vec<PathBuf>,
+}
+
+impl Scope {
+    /// The whole library.
+    pub fn everything() -> Self {
+        Self::default()
+    }
+
+    /// Only allow what also matches `query`.
+    pub fn query(&mut self, query: Query) -> &mut Self {
+        self.queries.push(query);
+        self
+    }
+
+    /// Only allow items under `directory`, or another directory given.
+    pub fn directory(&mut self, directory: PathBuf) -> &mut Self {
+        self.directories.push(directory);
+        self
+    }
+
+    pub fn allows_item(&self, item: &Item) -> bool {
+        self.queries.iter().all(|q| q.match_item(item))
+            && (self.directories.is_empty()
+                || self
+                    .directories
+                    .iter()
+                    .any(|dir| item.path.starts_with(dir)))
+    }
+
+    /// Whether the album itself matches; it is only visible if some of its
+    /// items are too.
+    pub fn allows_album(&self, album: &Album) -> bool {
+        self.queries.iter().all(|q| q.match_album(album))
+    }
+}
+
+/// Decides what each request may see, so a shared server can show each
+/// user their part of the library.
+pub trait Authorize: Send + Sync {
+    /// The scope of requests by `user`, as named by one of the [`Proxies`],
+    /// or `None` to turn them away.
+    fn scope(&self, user: Option<&str>) -> Option<Arc<Scope>>;
+}
+
+/// Everyone sees everything.
+pub struct Open(Arc<Scope>);
+
+impl Default for Open {
+    fn default() -> Self {
+        Open(Arc::new(Scope::everything()))
+    }
+}
+
+impl Authorize for Open {
+    fn scope(&self, _: Option<&str>) -> Option<Arc<Scope>> {
+        Some(self.0.clone())
+    }
+}
+
+/// A fixed scope per user, and optionally one for everyone else.
+pub struct Roles {
+    users: HashMap<String, Arc<Scope>>,
+    guest: Option<Arc<Scope>>,
+}
+
+impl Roles {
+    /// Users not in `users` get the `guest` scope, or are turned away
+    /// without one.
+    pub fn new(users: HashMap<String, Scope>, guest: Option<Scope>) -> Self {
+        Self {
+            users: users
+                .into_iter()
+                .map(|(user, scope)| (user, Arc::new(scope)))
+                .collect(),
+            guest: guest.map(Arc::new),
+        }
+    }
+}
+
+impl Authorize for Roles {
+    fn scope(&self, user: Option<&str>) -> Option<Arc<Scope>> {
+        user.and_then(|user| self.users.get(user))
+            .or(self.guest.as_ref())
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_proxies_name_users() {
+        let proxy: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        let client: SocketAddr = "10.0.0.2:4000".parse().unwrap();
+        let local: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+
+        let proxies = Proxies::new(vec![proxy.ip()]);
+        assert_eq!(proxies.user(Some(proxy), Some("alice")), Some("alice"));
+        assert_eq!(proxies.user(Some(proxy), None), None);
+        assert_eq!(proxies.user(Some(client), Some("alice")), None);
+        assert_eq!(proxies.user(Some(local), Some("alice")), None);
+        assert_eq!(proxies.user(None, Some("alice")), None);
+
+        assert_eq!(Proxies::default().user(Some(local), Some("alice")), None);
+        assert_eq!(
+            Proxies::new(vec![]).user(Some(local), Some("alice")),
+            Some("alice")
+        );
+    }
+}
@@ -1,24 +1,56 @@
 #![deny(clippy::pedantic)]
 
+use std::collections::HashMap;
 use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
+use beet_db::Redaction;
+use beet_query::Query;
 use structopt::StructOpt;
 use warp::Filter;
 
+mod advertise;
+mod auth;
 mod model;
 mod router;
+mod transcode;
 
 const LOG_TARGET: &str = "beet_up::api";
 
 type Model = Arc<Mutex<model::Model>>;
+type Auth = Arc<dyn auth::Authorize>;
+
+/// Split a `USER=VALUE` option.
+fn parse_role<T: std::str::FromStr>(s: &str) -> Result<(String, T), String> {
+    let mut parts = s.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(user), Some(value)) if !user.is_empty() => value
+            .parse()
+            .map(|value| (user.to_string(), value))
+            .map_err(|_| format!("could not parse the value of {s:?}")),
+        _ => Err(format!("expected USER=VALUE, not {s:?}")),
+    }
+}
+
+fn parse_query(s: &str) -> Result<Query, String> {
+    s.parse()
+        .map_err(|_| format!("could not parse query {s:?}"))
+}
+
+fn parse_field(s: &str) -> Result<String, String> {
+    Redaction::new()
+        .strip(s)
+        .map(|_| s.to_string())
+        .map_err(|err| err.to_string())
+}
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "beet-up")]
 #[structopt(about = "a web player for beets")]
 #[structopt(raw(setting = "structopt::clap::AppSettings::ColoredHelp"))]
 #[structopt(rename_all = "kebab-case")]
+#[allow(clippy::struct_excessive_bools)] // each is a flag of its own
 struct Cli {
     /// The server hostname.
     #[structopt(long, parse(try_from_str), default_value = "0.0.0.0")]
@@ -35,9 +67,72 @@ struct Cli {
     /// Respect forwarded headers when behind a reverse proxy.
     #[structopt(long)]
     reverse_proxy: bool,
-    /// Include paths in item responses.
+    /// The address of a reverse proxy to believe forwarded headers from.
+    /// Defaults to this machine.
+    #[structopt(
+        long = "trusted-proxy",
+        value_name = "ADDR",
+        raw(number_of_values = "1"),
+        requires = "reverse-proxy"
+    )]
+    trusted_proxies: Vec<IpAddr>,
+    /// Include whole paths in album and item responses, rather than only
+    /// file names.
     #[structopt(long)]
     include_paths: bool,
+    /// Leave FIELD out of album and item responses, e.g. lyrics.
+    #[structopt(
+        long = "redact",
+        value_name = "FIELD",
+        raw(number_of_values = "1"),
+        parse(try_from_str = "parse_field")
+    )]
+    redact: Vec<String>,
+    /// Only show USER what matches QUERY, as USER=QUERY. With any roles,
+    /// users are named in the X-Remote-User header by a trusted proxy (see
+    /// --reverse-proxy), and those without a role are turned away.
+    #[structopt(
+        long = "role",
+        raw(number_of_values = "1"),
+        parse(try_from_str = "parse_role")
+    )]
+    roles: Vec<(String, Query)>,
+    /// Only show USER the items under DIR, as USER=DIR.
+    #[structopt(
+        long = "role-dir",
+        raw(number_of_values = "1"),
+        parse(try_from_str = "parse_role")
+    )]
+    role_dirs: Vec<(String, PathBuf)>,
+    /// Show what matches QUERY to users without a role, rather than turning
+    /// them away.
+    #[structopt(long, parse(try_from_str = "parse_query"))]
+    guest: Option<Query>,
+    /// Transcode streams that ask for another format or a lower bitrate with
+    /// COMMAND, run without a shell after filling in {input}, {output},
+    /// {format} and {bitrate} (in kbps), e.g.
+    /// "ffmpeg -v error -i {input} -vn -b:a {bitrate}k {output}".
+    #[structopt(long, value_name = "COMMAND")]
+    transcode: Option<String>,
+    /// Where to keep transcoded files. Defaults to a directory in the user's
+    /// cache directory.
+    #[structopt(long, parse(from_os_str), requires = "transcode")]
+    transcode_cache: Option<PathBuf>,
+    /// How many megabytes of transcoded files to keep, removing the least
+    /// recently used first.
+    #[structopt(long, value_name = "MB", default_value = "1024")]
+    transcode_cache_size: u64,
+    /// Announce the server on the local network over mDNS (as
+    /// `_berts._tcp` and `_http._tcp`), so clients can find it.
+    #[structopt(long)]
+    advertise: bool,
+    /// The name to announce the server as.
+    #[structopt(
+        long,
+        default_value = "beets",
+        parse(try_from_str = "advertise::check_name")
+    )]
+    advertise_name: String,
     /// Path to your beet database.
     #[structopt(parse(from_os_str))]
     db_path: PathBuf,
@@ -47,11 +142,80 @@ fn main() {
     pretty_env_logger::init();
     let cli = Cli::from_args();
 
-    let model = model::Model::new(cli.db_path);
+    let mut redaction = Redaction::new();
+    if !cli.include_paths {
+        for field in &["path", "artpath"] {
+            redaction
+                .file_name_only(field)
+                .expect("albums and items have paths");
+        }
+    }
+    for field in &cli.redact {
+        redaction
+            .strip(field)
+            .expect("fields are checked when parsed");
+    }
+    let model = model::Model::new(cli.db_path, redaction);
+    let auth: Auth = if cli.roles.is_empty() && cli.role_dirs.is_empty() && cli.guest.is_none() {
+        Arc::new(auth::Open::default())
+    } else {
+        let mut users: HashMap<String, auth::Scope> = HashMap::new();
+        for (user, query) in cli.roles {
+            users.entry(user).or_default().query(query);
+        }
+        for (user, dir) in cli.role_dirs {
+            users.entry(user).or_default().directory(dir);
+        }
+        let guest = cli.guest.map(|query| {
+            let mut scope = auth::Scope::everything();
+            scope.query(query);
+            scope
+        });
+        Arc::new(auth::Roles::new(users, guest))
+    };
+
+    let cache_dir = cli.transcode_cache.or_else(transcode::default_cache_dir);
+    let cache_size = cli.transcode_cache_size.saturating_mul(1024 * 1024);
+    let transcoder = match (cli.transcode, cache_dir) {
+        (Some(command), Some(cache_dir)) => {
+            transcode::Transcoder::new(&command, cache_dir, cache_size).map(Arc::new)
+        }
+        (Some(_), None) => {
+            log::warn!("not transcoding without a cache directory, see --transcode-cache");
+            None
+        }
+        (None, _) => None,
+    };
+
+    let proxies = if cli.reverse_proxy {
+        auth::Proxies::new(cli.trusted_proxies)
+    } else {
+        auth::Proxies::default()
+    };
 
     let addr = SocketAddr::new(cli.host, cli.port);
-    println!("Now listening at http://{}.", addr);
+    println!("Now listening at http://{addr}.");
+
+    if cli.advertise {
+        if let Err(err) = advertise::advertise(&cli.advertise_name, cli.host, cli.port) {
+            log::warn!("could not announce the server: {}", err);
+        }
+    }
 
-    warp::serve(router::router(&Arc::new(Mutex::new(model))).with(warp::log::log(LOG_TARGET)))
-        .run(addr)
+    let routes = router::router(&Arc::new(Mutex::new(model)), &auth, proxies, transcoder)
+        .with(warp::log::log(LOG_TARGET));
+    match cli.cors {
+        Some(origin) => {
+            let cors = warp::cors()
+                .allow_methods(vec!["GET"])
+                .allow_credentials(cli.cors_supports_credentials);
+            let cors = if origin == "*" {
+                cors.allow_any_origin()
+            } else {
+                cors.allow_origin(origin.as_str())
+            };
+            warp::serve(routes.with(cors)).run(addr);
+        }
+        None => warp::serve(routes).run(addr),
+    }
 }
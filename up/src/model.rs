@@ -1,15 +1,24 @@
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashSet;
+use std::fs;
+use std::hash::{Hash, Hasher};
 use std::path::PathBuf;
+use std::time::SystemTime;
 
 use serde_derive::Serialize;
 
-use beet_db::{read_all, Album, Item};
+use beet_db::{read_all, Album, Item, Redaction};
 use beet_query::Query;
 
+use crate::auth::Scope;
+
 pub struct Model {
     albums: Vec<Album>,
     items: Vec<Item>,
     legal_paths: HashSet<PathBuf>,
+    revision: u64,
+    last_modified: SystemTime,
+    redaction: Redaction,
 }
 
 #[derive(Serialize)]
@@ -19,8 +28,13 @@ pub struct Stats {
 }
 
 impl Model {
-    pub fn new(db_path: PathBuf) -> Self {
-        let err_msg = format!("Could not read database at {:?}", db_path);
+    pub fn new(db_path: PathBuf, redaction: Redaction) -> Self {
+        let err_msg = format!("Could not read database at {}", db_path.display());
+        // taken before reading, so a write during the read is not missed
+        let meta = fs::metadata(&db_path).expect(&err_msg);
+        let last_modified = meta.modified().unwrap_or_else(|_| SystemTime::now());
+        let mut hasher = DefaultHasher::new();
+        (meta.len(), last_modified).hash(&mut hasher);
         let (albums, items) = read_all(db_path).expect(&err_msg);
 
         let legal_paths = albums
@@ -33,83 +47,117 @@ impl Model {
             albums,
             items,
             legal_paths,
+            revision: hasher.finish(),
+            last_modified,
+            redaction,
         }
     }
 
-    pub fn get_stats(&self) -> Stats {
+    /// Identifies the state of the database the model was read from.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// When the database the model was read from was last written.
+    pub fn last_modified(&self) -> SystemTime {
+        self.last_modified
+    }
+
+    /// What to hide from the albums and items the server sends as JSON. The
+    /// model itself keeps everything, as streaming needs the real paths.
+    pub fn redaction(&self) -> &Redaction {
+        &self.redaction
+    }
+
+    /// The albums `scope` allows, which need an item it allows too.
+    fn albums<'a>(&'a self, scope: &'a Scope) -> impl Iterator<Item = &'a Album> + 'a {
+        let with_items = self
+            .items(scope)
+            .filter_map(|Item { album_id, .. }| *album_id)
+            .collect::<HashSet<_>>();
+        self.albums
+            .iter()
+            .filter(move |album| with_items.contains(&album.id) && scope.allows_album(album))
+    }
+
+    fn items<'a>(&'a self, scope: &'a Scope) -> impl Iterator<Item = &'a Item> + 'a {
+        self.items
+            .iter()
+            .filter(move |item| scope.allows_item(item))
+    }
+
+    pub fn get_stats(&self, scope: &Scope) -> Stats {
         Stats {
-            albums: self.albums.len(),
-            items: self.items.len(),
+            albums: self.albums(scope).count(),
+            items: self.items(scope).count(),
         }
     }
 
-    pub fn get_all_albums(&self) -> Vec<Album> {
-        self.albums.clone()
+    pub fn get_all_albums(&self, scope: &Scope) -> Vec<Album> {
+        self.albums(scope).cloned().collect()
     }
 
-    pub fn check_path(&self, path: &PathBuf) -> bool {
+    pub fn check_path(&self, path: &PathBuf, scope: &Scope) -> bool {
         self.legal_paths.contains(path)
+            && (self.items(scope).any(|item| &item.path == path)
+                || self
+                    .albums(scope)
+                    .any(|album| album.artpath.as_ref() == Some(path)))
     }
 
-    pub fn get_album_items_id(&self, id: u32) -> Vec<Item> {
-        self.items
-            .iter()
-            .filter(|Item { album_id, .. }| match &album_id {
-                Some(i) if *i == id => true,
-                _ => false,
-            })
+    pub fn get_album_items_id(&self, id: u32, scope: &Scope) -> Vec<Item> {
+        if self.get_album_id(id, scope).is_none() {
+            return vec![];
+        }
+        self.items(scope)
+            .filter(|Item { album_id, .. }| *album_id == Some(id))
             .cloned()
             .collect()
     }
 
-    pub fn get_album_id(&self, id: u32) -> Option<Album> {
-        self.albums.iter().find(|a| a.id == id).cloned()
+    pub fn get_album_id(&self, id: u32, scope: &Scope) -> Option<Album> {
+        self.albums(scope).find(|a| a.id == id).cloned()
     }
 
-    pub fn get_album_ids(&self, ids: &[u32]) -> Vec<Album> {
+    pub fn get_album_ids(&self, ids: &[u32], scope: &Scope) -> Vec<Album> {
         let s = ids.iter().collect::<HashSet<_>>();
-        self.albums
-            .iter()
+        self.albums(scope)
             .filter(|Album { id, .. }| s.contains(id))
             .cloned()
             .collect()
     }
 
-    pub fn get_all_items(&self) -> Vec<Item> {
-        self.items.clone()
+    pub fn get_all_items(&self, scope: &Scope) -> Vec<Item> {
+        self.items(scope).cloned().collect()
     }
 
-    pub fn get_item_id(&self, id: u32) -> Option<Item> {
-        self.items.iter().find(|i| i.id == id).cloned()
+    pub fn get_item_id(&self, id: u32, scope: &Scope) -> Option<Item> {
+        self.items(scope).find(|i| i.id == id).cloned()
     }
 
-    pub fn get_item_ids(&self, ids: &[u32]) -> Vec<Item> {
+    pub fn get_item_ids(&self, ids: &[u32], scope: &Scope) -> Vec<Item> {
         let s = ids.iter().collect::<HashSet<_>>();
-        self.items
-            .iter()
+        self.items(scope)
             .filter(|Item { id, .. }| s.contains(id))
             .cloned()
             .collect()
     }
 
-    pub fn get_item_path(&self, pth: &PathBuf) -> Option<Item> {
-        self.items
-            .iter()
+    pub fn get_item_path(&self, pth: &PathBuf, scope: &Scope) -> Option<Item> {
+        self.items(scope)
             .find(|Item { path, .. }| path == pth)
             .cloned()
     }
 
-    pub fn query_albums(&self, q: &Query) -> Vec<Album> {
-        self.albums
-            .iter()
+    pub fn query_albums(&self, q: &Query, scope: &Scope) -> Vec<Album> {
+        self.albums(scope)
             .filter(|album| q.match_album(album))
             .cloned()
             .collect()
     }
 
-    pub fn query_items(&self, q: &Query) -> Vec<Item> {
-        self.items
-            .iter()
+    pub fn query_items(&self, q: &Query, scope: &Scope) -> Vec<Item> {
+        self.items(scope)
             .filter(|item| q.match_item(item))
             .cloned()
             .collect()
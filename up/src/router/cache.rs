@@ -0,0 +1,118 @@
+use std::time::SystemTime;
+
+use headers::{HeaderMapExt, IfModifiedSince, LastModified};
+use warp::{
+    filters::BoxedFilter,
+    header,
+    http::{
+        header::{ETAG, IF_NONE_MATCH, VARY},
+        HeaderMap, HeaderValue, StatusCode,
+    },
+    path::{self, FullPath},
+    query,
+    reject::custom,
+    reply::Response,
+    Filter, Rejection, Reply,
+};
+
+use super::super::auth::USER_HEADER;
+use super::super::Model;
+use super::Error;
+
+/// What a client can revalidate a response with. The model never changes
+/// while the server runs, so the same request by the same user always gets
+/// the same response, and its tag only needs to tell the database revision,
+/// user and URL apart.
+pub struct Validators {
+    etag: String,
+    last_modified: SystemTime,
+    not_modified: bool,
+}
+
+/// A tag for `parts`, the same from one build or run of the server to the
+/// next (unlike `DefaultHasher`), so clients can keep using it.
+fn tag(parts: &[&[u8]]) -> String {
+    // 64-bit FNV-1a, ending each part with a byte no text contains
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in parts.iter().flat_map(|part| part.iter().chain(&[0xff])) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("\"{hash:016x}\"")
+}
+
+/// Whether `etag` is one of the tags of an `If-None-Match` header, which
+/// compares tags weakly.
+fn none_match(tags: &str, etag: &str) -> bool {
+    tags.split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+pub fn validators(model: Model) -> BoxedFilter<(Validators,)> {
+    let query = query::raw().or(warp::any().map(String::new)).unify();
+    path::full()
+        .and(query)
+        .and(header::optional::<String>(USER_HEADER))
+        .and(header::headers_cloned())
+        .and_then(
+            move |path: FullPath, query: String, user: Option<String>, headers: HeaderMap| {
+                let (revision, last_modified) = model
+                    .lock()
+                    .map(|m| (m.revision(), m.last_modified()))
+                    .map_err(|_| custom(Error::Sync))?;
+                let etag = tag(&[
+                    &revision.to_le_bytes(),
+                    path.as_str().as_bytes(),
+                    query.as_bytes(),
+                    user.as_ref().map_or(&[0xff][..], String::as_bytes),
+                ]);
+                // If-Modified-Since only counts without If-None-Match
+                let not_modified = match headers.get(IF_NONE_MATCH) {
+                    Some(tags) => tags.to_str().is_ok_and(|tags| none_match(tags, &etag)),
+                    None => headers
+                        .typed_get::<IfModifiedSince>()
+                        .is_some_and(|since| !since.is_modified(last_modified)),
+                };
+                Ok::<_, Rejection>(Validators {
+                    etag,
+                    last_modified,
+                    not_modified,
+                })
+            },
+        )
+        .boxed()
+}
+
+/// Tag a successful `reply`, or answer `304 Not Modified` in its place if
+/// the client has it already.
+pub fn reply(validators: Validators, reply: impl Reply) -> Response {
+    let mut response = reply.into_response();
+    if !response.status().is_success() {
+        return response;
+    }
+    if validators.not_modified {
+        response = StatusCode::NOT_MODIFIED.into_response();
+    }
+    let headers = response.headers_mut();
+    if let Ok(etag) = HeaderValue::from_shared(validators.etag.into()) {
+        headers.insert(ETAG, etag);
+    }
+    headers.typed_insert(LastModified::from(validators.last_modified));
+    headers.insert(VARY, HeaderValue::from_static(USER_HEADER));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_stable() {
+        assert_eq!(tag(&[]), "\"cbf29ce484222325\"");
+        assert_eq!(tag(&[b"a"]), tag(&[b"a"]));
+        assert_ne!(tag(&[b"ab", b""]), tag(&[b"a", b"b"]));
+        assert!(none_match("W/\"x\", \"y\"", "\"x\""));
+        assert!(!none_match("\"y\"", "\"x\""));
+    }
+}
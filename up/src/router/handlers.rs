@@ -1,6 +1,7 @@
 #![allow(clippy::needless_pass_by_value)]
 
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use url::percent_encoding::{percent_decode, utf8_percent_encode, DEFAULT_ENCODE_SET};
 use warp::{
@@ -11,9 +12,11 @@ use warp::{
     Rejection, Reply,
 };
 
+use beet_db::{Album, Item};
 use beet_query::Query;
 
-use super::super::Model;
+use super::super::auth::Scope;
+use super::super::{model, Model};
 use super::Error;
 
 fn req_err<T>(msg: &'static str) -> impl FnOnce(T) -> Rejection {
@@ -24,7 +27,29 @@ fn sync_err<T>(_: T) -> Rejection {
     custom(Error::Sync)
 }
 
-pub fn check_path(tail: Peek, model: Model) -> Result<(), Rejection> {
+/// `albums` as JSON, redacted as the model asks.
+fn albums_json(m: &model::Model, albums: &[Album]) -> impl Reply {
+    let redaction = m.redaction();
+    json(
+        &albums
+            .iter()
+            .map(|album| redaction.album(album))
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// `items` as JSON, redacted as the model asks.
+fn items_json(m: &model::Model, items: &[Item]) -> impl Reply {
+    let redaction = m.redaction();
+    json(
+        &items
+            .iter()
+            .map(|item| redaction.item(item))
+            .collect::<Vec<_>>(),
+    )
+}
+
+pub fn check_path(tail: Peek, model: Model, scope: Arc<Scope>) -> Result<(), Rejection> {
     let path = PathBuf::from(
         percent_decode(tail.as_str().as_bytes())
             .decode_utf8()
@@ -32,7 +57,7 @@ pub fn check_path(tail: Peek, model: Model) -> Result<(), Rejection> {
             .to_string(),
     );
     model.lock().map_err(sync_err).and_then(|m| {
-        if m.check_path(&path) {
+        if m.check_path(&path, &scope) {
             Ok(())
         } else {
             Err(custom(Error::BadRequest("Path was not found in library.")))
@@ -50,41 +75,48 @@ pub fn get_wasm() -> impl Reply {
     )
 }
 
-pub fn get_stats(model: Model) -> Result<impl Reply, Rejection> {
-    model.lock().map_err(sync_err).map(|m| json(&m.get_stats()))
+pub fn get_stats(model: Model, scope: Arc<Scope>) -> Result<impl Reply, Rejection> {
+    model
+        .lock()
+        .map_err(sync_err)
+        .map(|m| json(&m.get_stats(&scope)))
 }
 
-pub fn get_all_albums(model: Model) -> Result<impl Reply, Rejection> {
+pub fn get_all_albums(model: Model, scope: Arc<Scope>) -> Result<impl Reply, Rejection> {
     model
         .lock()
         .map_err(sync_err)
-        .map(|m| json(&m.get_all_albums()))
+        .map(|m| albums_json(&m, &m.get_all_albums(&scope)))
 }
 
-pub fn get_album_items_id(id: u32, qstr: String, model: Model) -> Result<impl Reply, Rejection> {
+pub fn get_album_items_id(
+    id: u32,
+    qstr: String,
+    model: Model,
+    scope: Arc<Scope>,
+) -> Result<impl Reply, Rejection> {
     if qstr.trim() == "expand" {
-        let tracks = model.lock().map_err(sync_err)?.get_album_items_id(id);
+        let m = model.lock().map_err(sync_err)?;
+        let tracks = m.get_album_items_id(id, &scope);
         if tracks.is_empty() {
             Err(not_found())
         } else {
-            Ok(json(&tracks))
+            Ok(items_json(&m, &tracks))
         }
     } else {
         Err(not_found())
     }
 }
 
-pub fn get_album_id(id: u32, model: Model) -> Result<impl Reply, Rejection> {
-    model
-        .lock()
-        .map_err(sync_err)?
-        .get_album_id(id)
+pub fn get_album_id(id: u32, model: Model, scope: Arc<Scope>) -> Result<impl Reply, Rejection> {
+    let m = model.lock().map_err(sync_err)?;
+    m.get_album_id(id, &scope)
         .ok_or_else(not_found)
-        .map(|a| json(&a))
+        .map(|a| json(&m.redaction().album(&a)))
 }
 
-pub fn get_album_art(id: u32, model: Model) -> Result<impl Reply, Rejection> {
-    match model.lock().map_err(sync_err)?.get_album_id(id) {
+pub fn get_album_art(id: u32, model: Model, scope: Arc<Scope>) -> Result<impl Reply, Rejection> {
+    match model.lock().map_err(sync_err)?.get_album_id(id, &scope) {
         Some(beet_db::Album {
             artpath: Some(path),
             ..
@@ -100,20 +132,18 @@ pub fn get_album_art(id: u32, model: Model) -> Result<impl Reply, Rejection> {
     }
 }
 
-pub fn get_all_items(model: Model) -> Result<impl Reply, Rejection> {
+pub fn get_all_items(model: Model, scope: Arc<Scope>) -> Result<impl Reply, Rejection> {
     model
         .lock()
         .map_err(sync_err)
-        .map(|m| json(&m.get_all_items()))
+        .map(|m| items_json(&m, &m.get_all_items(&scope)))
 }
 
-pub fn get_item_id(id: u32, model: Model) -> Result<impl Reply, Rejection> {
-    model
-        .lock()
-        .map_err(sync_err)?
-        .get_item_id(id)
+pub fn get_item_id(id: u32, model: Model, scope: Arc<Scope>) -> Result<impl Reply, Rejection> {
+    let m = model.lock().map_err(sync_err)?;
+    m.get_item_id(id, &scope)
         .ok_or_else(not_found)
-        .map(|i| json(&i))
+        .map(|i| json(&m.redaction().item(&i)))
 }
 
 pub fn get_ids(ids: String) -> Result<Vec<u32>, Rejection> {
@@ -125,39 +155,46 @@ pub fn get_ids(ids: String) -> Result<Vec<u32>, Rejection> {
         .collect()
 }
 
-pub fn get_album_ids(ids: Vec<u32>, model: Model) -> Result<impl Reply, Rejection> {
+pub fn get_album_ids(
+    ids: Vec<u32>,
+    model: Model,
+    scope: Arc<Scope>,
+) -> Result<impl Reply, Rejection> {
     model
         .lock()
         .map_err(sync_err)
-        .map(|m| json(&m.get_album_ids(&ids)))
+        .map(|m| albums_json(&m, &m.get_album_ids(&ids, &scope)))
 }
 
-pub fn get_item_ids(ids: Vec<u32>, model: Model) -> Result<impl Reply, Rejection> {
+pub fn get_item_ids(
+    ids: Vec<u32>,
+    model: Model,
+    scope: Arc<Scope>,
+) -> Result<impl Reply, Rejection> {
     model
         .lock()
         .map_err(sync_err)
-        .map(|m| json(&m.get_item_ids(&ids)))
+        .map(|m| items_json(&m, &m.get_item_ids(&ids, &scope)))
 }
 
-pub fn get_item_path(path: Tail, model: Model) -> Result<impl Reply, Rejection> {
-    model
-        .lock()
-        .map_err(sync_err)?
-        .get_item_path(&PathBuf::from(
-            percent_decode(path.as_str().as_bytes())
-                .decode_utf8()
-                .map_err(req_err("could not decode path to item"))?
-                .to_string(),
-        ))
+pub fn get_item_path(path: Tail, model: Model, scope: Arc<Scope>) -> Result<impl Reply, Rejection> {
+    let path = PathBuf::from(
+        percent_decode(path.as_str().as_bytes())
+            .decode_utf8()
+            .map_err(req_err("could not decode path to item"))?
+            .to_string(),
+    );
+    let m = model.lock().map_err(sync_err)?;
+    m.get_item_path(&path, &scope)
         .ok_or_else(not_found)
-        .map(|item| json(&item))
+        .map(|item| json(&m.redaction().item(&item)))
 }
 
-pub fn get_item_file(id: u32, model: Model) -> Result<impl Reply, Rejection> {
+pub fn get_item_file(id: u32, model: Model, scope: Arc<Scope>) -> Result<impl Reply, Rejection> {
     model
         .lock()
         .map_err(sync_err)?
-        .get_item_id(id)
+        .get_item_id(id, &scope)
         .ok_or_else(not_found)
         .and_then(|beet_db::Item { path, .. }| {
             Ok(warp::redirect(
@@ -179,16 +216,16 @@ pub fn parse_query(q: String) -> Result<Query, Rejection> {
         .map_err(req_err("could not parse query from path"))
 }
 
-pub fn query_albums(q: Query, model: Model) -> Result<impl Reply, Rejection> {
+pub fn query_albums(q: Query, model: Model, scope: Arc<Scope>) -> Result<impl Reply, Rejection> {
     model
         .lock()
         .map_err(sync_err)
-        .map(|m| json(&m.query_albums(&q)))
+        .map(|m| albums_json(&m, &m.query_albums(&q, &scope)))
 }
 
-pub fn query_items(q: Query, model: Model) -> Result<impl Reply, Rejection> {
+pub fn query_items(q: Query, model: Model, scope: Arc<Scope>) -> Result<impl Reply, Rejection> {
     model
         .lock()
         .map_err(sync_err)
-        .map(|m| json(&m.query_items(&q)))
+        .map(|m| items_json(&m, &m.query_items(&q, &scope)))
 }
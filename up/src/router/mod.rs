@@ -1,27 +1,39 @@
 use std::fmt;
+use std::sync::Arc;
 use warp::{
     filters::BoxedFilter,
     http::StatusCode,
     path,
+    reject::custom,
     reply::{html, with_status},
     Filter, Rejection, Reply,
 };
 
-use super::Model;
+use super::auth::{Proxies, Scope, USER_HEADER};
+use super::transcode::Transcoder;
+use super::{Auth, Model};
 
+mod cache;
 mod handlers;
+mod stream;
 
 #[derive(Copy, Clone, Debug)]
 pub enum Error {
     BadRequest(&'static str),
+    Forbidden,
     Sync,
+    Blocking,
+    Transcode,
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Error::BadRequest(s) => write!(f, "Bad request: {}", s),
+            Error::BadRequest(s) => write!(f, "Bad request: {s}"),
+            Error::Forbidden => write!(f, "Forbidden."),
             Error::Sync => write!(f, "Could not acquire lock on data store."),
+            Error::Blocking => write!(f, "Could not find a thread to read files on."),
+            Error::Transcode => write!(f, "Could not transcode the file."),
         }
     }
 }
@@ -32,7 +44,8 @@ fn customize_error(err: Rejection) -> Result<impl Reply, Rejection> {
     if let Some(&err) = err.find_cause::<Error>() {
         let code = match err {
             Error::BadRequest(_) => StatusCode::BAD_REQUEST,
-            Error::Sync => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Forbidden => StatusCode::FORBIDDEN,
+            Error::Sync | Error::Blocking | Error::Transcode => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
         Ok(with_status(err.to_string(), code))
@@ -41,27 +54,68 @@ fn customize_error(err: Rejection) -> Result<impl Reply, Rejection> {
     }
 }
 
-pub fn router(model: &Model) -> BoxedFilter<(impl Reply,)> {
+pub fn router(
+    model: &Model,
+    auth: &Auth,
+    proxies: Proxies,
+    transcoder: Option<Arc<Transcoder>>,
+) -> BoxedFilter<(impl Reply,)> {
+    let scope = scope(auth.clone(), proxies);
+    let json = route_items(model, &scope)
+        .or(route_albums(model, &scope))
+        .or(route_stats(model, &scope));
     route_static()
-        .or(route_items(model.clone()))
-        .or(route_albums(model.clone()))
-        .or(route_stats(model.clone()))
-        .or(route_files(model.clone()))
+        .or(cache::validators(model.clone()).and(json).map(cache::reply))
+        .or(route_stream(model, &scope, transcoder))
+        .or(route_files(model, &scope))
         .recover(customize_error)
         .boxed()
 }
 
-fn route_files(model: Model) -> BoxedFilter<(impl Reply,)> {
+/// The scope of the requesting user, as named by one of the `proxies`,
+/// turning away those `auth` refuses.
+fn scope(auth: Auth, proxies: Proxies) -> BoxedFilter<(Arc<Scope>,)> {
+    warp::addr::remote()
+        .and(warp::header::optional::<String>(USER_HEADER))
+        .and_then(move |peer, user: Option<String>| {
+            auth.scope(proxies.user(peer, user.as_deref()))
+                .ok_or_else(|| custom(Error::Forbidden))
+        })
+        .boxed()
+}
+
+fn route_files(model: &Model, scope: &BoxedFilter<(Arc<Scope>,)>) -> BoxedFilter<(impl Reply,)> {
+    let model = model.clone();
     let db = warp::any().map(move || model.clone());
     path("file")
         .and(path::peek())
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::check_path)
         .untuple_one()
         .and(warp::fs::dir("/"))
         .boxed()
 }
 
+fn route_stream(
+    model: &Model,
+    scope: &BoxedFilter<(Arc<Scope>,)>,
+    transcoder: Option<Arc<Transcoder>>,
+) -> BoxedFilter<(impl Reply,)> {
+    let model = model.clone();
+    let db = warp::any().map(move || model.clone());
+    let transcoder = warp::any().map(move || transcoder.clone());
+    path!("item" / u32 / "stream")
+        .and(path::end())
+        .and(warp::query::<stream::StreamOptions>())
+        .and(warp::header::optional::<String>("range"))
+        .and(db.clone())
+        .and(scope.clone())
+        .and(transcoder)
+        .and_then(stream::get_item_stream)
+        .boxed()
+}
+
 fn route_static() -> BoxedFilter<(impl Reply,)> {
     path::end()
         .map(|| html(include_str!("../../tmp_static/index.html")))
@@ -71,44 +125,53 @@ fn route_static() -> BoxedFilter<(impl Reply,)> {
         .boxed()
 }
 
-fn route_stats(model: Model) -> BoxedFilter<(impl Reply,)> {
+fn route_stats(model: &Model, scope: &BoxedFilter<(Arc<Scope>,)>) -> BoxedFilter<(impl Reply,)> {
+    let model = model.clone();
     let db = warp::any().map(move || model.clone());
     path("stats")
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::get_stats)
         .boxed()
 }
 
-fn route_albums(model: Model) -> BoxedFilter<(impl Reply,)> {
+fn route_albums(model: &Model, scope: &BoxedFilter<(Arc<Scope>,)>) -> BoxedFilter<(impl Reply,)> {
+    let model = model.clone();
     let db = warp::any().map(move || model.clone());
 
     let get_all = path::end()
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::get_all_albums);
     let get_items_by_id = path::param()
         .and(path::end())
         .and(warp::query::raw())
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::get_album_items_id);
     let get_by_id = path::param()
         .and(path::end())
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::get_album_id);
     let get_art_by_id = path::param()
         .and(path("art"))
         .and(path::end())
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::get_album_art);
     let get_by_ids = path::param()
         .and(path::end())
         .and_then(handlers::get_ids)
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::get_album_ids);
     let get_by_query = path("query")
         .and(path::param())
         .and(path::end())
         .and_then(handlers::parse_query)
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::query_albums);
 
     path("album")
@@ -123,34 +186,41 @@ fn route_albums(model: Model) -> BoxedFilter<(impl Reply,)> {
         .boxed()
 }
 
-fn route_items(model: Model) -> BoxedFilter<(impl Reply,)> {
+fn route_items(model: &Model, scope: &BoxedFilter<(Arc<Scope>,)>) -> BoxedFilter<(impl Reply,)> {
+    let model = model.clone();
     let db = warp::any().map(move || model.clone());
 
     let get_all = path::end()
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::get_all_items);
     let get_by_id = path!(u32)
         .and(path::end())
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::get_item_id);
     let get_file_by_id = path!(u32 / "file")
         .and(path::end())
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::get_item_file);
     let get_by_ids = path::param()
         .and(path::end())
         .and_then(handlers::get_ids)
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::get_item_ids);
     let get_by_path = path("path")
         .and(path::tail())
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::get_item_path);
     let get_by_query = path("query")
         .and(path::param())
         .and(path::end())
         .and_then(handlers::parse_query)
         .and(db.clone())
+        .and(scope.clone())
         .and_then(handlers::query_items);
 
     path("item")
@@ -164,3 +234,51 @@ fn route_items(model: Model) -> BoxedFilter<(impl Reply,)> {
         )
         .boxed()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use beet_db::Redaction;
+
+    use super::super::{auth::Open, model};
+    use super::*;
+
+    fn model(redaction: Redaction) -> Model {
+        let db = concat!(env!("CARGO_MANIFEST_DIR"), "/../db/tests/test.db");
+        Arc::new(Mutex::new(model::Model::new(db.into(), redaction)))
+    }
+
+    fn get(model: &Model, path: &str) -> String {
+        let auth: Auth = Arc::new(Open::default());
+        let router = router(model, &auth, Proxies::default(), None);
+        let response = warp::test::request().path(path).reply(&router);
+        assert_eq!(response.status(), StatusCode::OK, "{path}");
+        String::from_utf8_lossy(response.body()).into_owned()
+    }
+
+    #[test]
+    fn json_is_redacted() {
+        let everything = model(Redaction::new());
+        assert!(get(&everything, "/item/1").contains(r#""path":"/media/space/music/"#));
+        assert!(get(&everything, "/item/1").contains(r#""genre":"#));
+
+        let mut redaction = Redaction::new();
+        redaction.file_name_only("path").unwrap();
+        redaction.file_name_only("artpath").unwrap();
+        redaction.strip("genre").unwrap();
+        let redacted = model(redaction);
+        assert!(get(&redacted, "/item/1").contains(r##""path":"#5.mp3""##));
+        for path in &["/item/1", "/item/1,2", "/item/", "/album/1?expand"] {
+            let json = get(&redacted, path);
+            assert!(json.contains(r#""path":"#), "{}", path);
+            assert!(!json.contains("/media/"), "{}", path);
+            assert!(!json.contains(r#""genre":"#), "{}", path);
+        }
+        for path in &["/album/1", "/album/", "/album/1,2"] {
+            let json = get(&redacted, path);
+            assert!(json.contains(r#""artpath":"cover.jpg""#), "{}", path);
+            assert!(!json.contains("/media/"), "{}", path);
+        }
+    }
+}
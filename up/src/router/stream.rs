@@ -0,0 +1,260 @@
+#![allow(clippy::needless_pass_by_value)]
+
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use beet_db::Item;
+use futures::{future, stream, Async, Future};
+use hyper::Body;
+use serde_derive::Deserialize;
+use warp::{
+    http::{
+        header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE},
+        Response, StatusCode,
+    },
+    reject::{custom, not_found},
+    Rejection,
+};
+
+use super::super::auth::Scope;
+use super::super::transcode::{Transcoder, DEFAULT_BITRATE, DEFAULT_FORMAT};
+use super::super::Model;
+use super::Error;
+
+/// How much of a file to read at a time while streaming it.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The part of a file a `Range` header asks for.
+#[derive(Debug, PartialEq)]
+enum Span {
+    Whole,
+    /// Inclusive byte offsets.
+    Part(u64, u64),
+    Unsatisfiable,
+}
+
+impl Span {
+    /// Only single `bytes` ranges are supported; the whole file is sent for
+    /// anything else, as a server may do with any range it will not serve.
+    fn parse(range: &str, len: u64) -> Self {
+        let spec = match range.trim().strip_prefix("bytes=") {
+            Some(spec) if !spec.contains(',') => spec.trim(),
+            _ => return Span::Whole,
+        };
+        let (start, end) = match spec.find('-') {
+            Some(i) => (&spec[..i], &spec[i + 1..]),
+            None => return Span::Whole,
+        };
+        if start.is_empty() {
+            // the last `end` bytes
+            return match end.parse::<u64>() {
+                Ok(0) => Span::Unsatisfiable,
+                Ok(_) if len == 0 => Span::Unsatisfiable,
+                Ok(suffix) => Span::Part(len.saturating_sub(suffix), len - 1),
+                Err(_) => Span::Whole,
+            };
+        }
+        let Ok(start) = start.parse::<u64>() else {
+            return Span::Whole;
+        };
+        let end = match end {
+            "" => len.saturating_sub(1),
+            end => match end.parse::<u64>() {
+                Ok(end) if end >= start => end.min(len.saturating_sub(1)),
+                _ => return Span::Whole,
+            },
+        };
+        if start >= len {
+            Span::Unsatisfiable
+        } else {
+            Span::Part(start, end)
+        }
+    }
+}
+
+/// Run `f` where it may block, off the threads serving requests.
+fn blocking<T>(f: impl FnOnce() -> T) -> impl Future<Item = T, Error = Rejection> {
+    let mut f = Some(f);
+    future::poll_fn(move || tokio_threadpool::blocking(|| f.take().expect("called once ready")()))
+        .map_err(|err| {
+            log::error!("could not run blocking work: {err}");
+            custom(Error::Blocking)
+        })
+}
+
+/// The next `len` bytes of `file`, each chunk read where it may block.
+fn body(file: File, len: u64) -> Body {
+    let mut file = file.take(len);
+    let chunks = stream::poll_fn(move || {
+        let mut buf = vec![0; CHUNK_SIZE];
+        match tokio_threadpool::blocking(|| file.read(&mut buf)).map_err(io::Error::other)? {
+            Async::NotReady => Ok(Async::NotReady),
+            Async::Ready(Ok(0)) => Ok(Async::Ready(None)),
+            Async::Ready(Ok(n)) => {
+                buf.truncate(n);
+                Ok(Async::Ready(Some(buf)))
+            }
+            Async::Ready(Err(err)) => Err(err),
+        }
+    });
+    Body::wrap_stream(chunks)
+}
+
+/// What a client may ask a stream to be transcoded to.
+#[derive(Debug, Default, Deserialize)]
+pub struct StreamOptions {
+    format: Option<String>,
+    /// In kbps.
+    bitrate: Option<u32>,
+}
+
+/// Stream the file of an item, or the byte range the request asks for.
+///
+/// The file is only ever the one the library has for the item (or a
+/// transcoding of it), never a path from the request, so there is nothing to
+/// traverse out of. Without a transcoder, the item's own file is sent
+/// whatever format it is asked for in.
+pub fn get_item_stream(
+    id: u32,
+    options: StreamOptions,
+    range: Option<String>,
+    model: Model,
+    scope: Arc<Scope>,
+    transcoder: Option<Arc<Transcoder>>,
+) -> impl Future<Item = Response<Body>, Error = Rejection> {
+    let item = model
+        .lock()
+        .map_err(|_| custom(Error::Sync))
+        .and_then(|model| model.get_item_id(id, &scope).ok_or_else(not_found));
+    future::result(item)
+        .and_then(move |item| {
+            // transcoding runs a command to the end, so it waits there too
+            blocking(move || {
+                let (path, mime_type) = source(&item, &options, transcoder.as_deref())?;
+                serve(open(&path, range)?, mime_type)
+            })
+        })
+        .and_then(|response| response)
+}
+
+/// The file to send for `item` and its MIME type, transcoding it first if
+/// `options` ask for that.
+fn source(
+    item: &Item,
+    options: &StreamOptions,
+    transcoder: Option<&Transcoder>,
+) -> Result<(PathBuf, &'static str), Rejection> {
+    let wanted = match (&options.format, options.bitrate) {
+        (None, None) => None,
+        (format, bitrate) => Some((
+            format.as_deref().unwrap_or(DEFAULT_FORMAT),
+            bitrate.unwrap_or(DEFAULT_BITRATE),
+        )),
+    };
+    match (transcoder, wanted) {
+        (Some(transcoder), Some((format, bitrate)))
+            if Transcoder::needed(item, format, bitrate) =>
+        {
+            let output = transcoder.transcode(item, format, bitrate).map_err(|err| {
+                if err.kind() == ErrorKind::InvalidInput {
+                    custom(Error::BadRequest("unsupported format"))
+                } else {
+                    log::warn!("could not transcode {:?}: {err}", item.path);
+                    custom(Error::Transcode)
+                }
+            })?;
+            Ok((output.path, output.mime_type))
+        }
+        _ => Ok((item.path.clone(), item.mime_type())),
+    }
+}
+
+/// The file at `path` and its length, positioned at the start of what
+/// `range` asks for.
+fn open(path: &Path, range: Option<String>) -> Result<(File, u64, Span), Rejection> {
+    let mut file = File::open(path).map_err(|_| not_found())?;
+    let meta = file.metadata().map_err(|_| not_found())?;
+    if !meta.is_file() {
+        return Err(not_found());
+    }
+    let len = meta.len();
+    let span = range.map_or(Span::Whole, |r| Span::parse(&r, len));
+    if let Span::Part(start, _) = span {
+        file.seek(SeekFrom::Start(start)).map_err(|_| not_found())?;
+    }
+    Ok((file, len, span))
+}
+
+/// The `span` of an opened file of `len` bytes.
+fn serve(
+    (file, len, span): (File, u64, Span),
+    mime_type: &str,
+) -> Result<Response<Body>, Rejection> {
+    let mut response = Response::builder();
+    response
+        .header(CONTENT_TYPE, mime_type)
+        .header(ACCEPT_RANGES, "bytes");
+    let (start, end) = match span {
+        Span::Whole => (0, len),
+        Span::Part(start, end) => {
+            response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(CONTENT_RANGE, format!("bytes {start}-{end}/{len}"));
+            (start, end + 1)
+        }
+        Span::Unsatisfiable => {
+            return response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(CONTENT_RANGE, format!("bytes */{len}"))
+                .body(Body::empty())
+                .map_err(|_| not_found());
+        }
+    };
+    response
+        .header(CONTENT_LENGTH, (end - start).to_string())
+        .body(body(file, end - start))
+        .map_err(|_| not_found())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Span;
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(Span::parse("bytes=0-99", 1000), Span::Part(0, 99));
+        assert_eq!(Span::parse(" bytes= 10-", 1000), Span::Part(10, 999));
+        assert_eq!(Span::parse("bytes=900-2000", 1000), Span::Part(900, 999));
+        assert_eq!(Span::parse("bytes=-100", 1000), Span::Part(900, 999));
+        assert_eq!(Span::parse("bytes=-2000", 1000), Span::Part(0, 999));
+        assert_eq!(Span::parse("bytes=999-999", 1000), Span::Part(999, 999));
+    }
+
+    #[test]
+    fn parse_unsatisfiable_ranges() {
+        assert_eq!(Span::parse("bytes=1000-", 1000), Span::Unsatisfiable);
+        assert_eq!(Span::parse("bytes=1000-1001", 1000), Span::Unsatisfiable);
+        assert_eq!(Span::parse("bytes=-0", 1000), Span::Unsatisfiable);
+        assert_eq!(Span::parse("bytes=-10", 0), Span::Unsatisfiable);
+        assert_eq!(Span::parse("bytes=0-", 0), Span::Unsatisfiable);
+    }
+
+    #[test]
+    fn parse_unsupported_ranges() {
+        for range in &[
+            "",
+            "bytes=",
+            "bytes=-",
+            "bytes=5",
+            "bytes=a-b",
+            "bytes=10-5",
+            "bytes=0-1,5-6",
+            "items=0-1",
+            "bytes=-x",
+        ] {
+            assert_eq!(Span::parse(range, 1000), Span::Whole, "{range}");
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use beet_db::Item;
+
+/// The formats a client may ask for, with the file extension and MIME type
+/// of each.
+const FORMATS: &[(&str, &str, &str)] = &[
+    ("mp3", "mp3", "audio/mpeg"),
+    ("ogg", "ogg", "audio/ogg"),
+    ("opus", "opus", "audio/ogg; codecs=opus"),
+    ("aac", "m4a", "audio/mp4"),
+];
+
+/// The format to transcode to when a client only asks for a lower bitrate.
+pub const DEFAULT_FORMAT: &str = "mp3";
+/// The bitrate in kbps to transcode to when a client only asks for another
+/// format.
+pub const DEFAULT_BITRATE: u32 = 192;
+/// The bitrates in kbps files are transcoded at. Others are rounded down to
+/// one of these, so clients cannot fill the cache with one file per bitrate.
+const BITRATES: &[u32] = &[64, 96, 128, 160, 192, 256, 320];
+
+/// Tells apart the partial outputs of transcoders running at the same time.
+static PARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// `arg` with each placeholder replaced by its value, in one pass so that
+/// values are never filled in themselves.
+fn fill(arg: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(arg.len());
+    let mut rest = arg;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some((key, value)) = values.iter().find(|(key, _)| rest.starts_with(key)) {
+            filled.push_str(value);
+            rest = &rest[key.len()..];
+        } else {
+            filled.push('{');
+            rest = &rest[1..];
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// The highest of [`BITRATES`] up to `bitrate`, or the lowest.
+fn round_bitrate(bitrate: u32) -> u32 {
+    BITRATES
+        .iter()
+        .rev()
+        .copied()
+        .find(|&allowed| allowed <= bitrate)
+        .unwrap_or(BITRATES[0])
+}
+
+/// The directory to keep transcoded files in by default, in the user's own
+/// cache directory, or `None` if there is none.
+pub fn default_cache_dir() -> Option<PathBuf> {
+    let cache = if cfg!(windows) {
+        PathBuf::from(env::var_os("LOCALAPPDATA")?)
+    } else if let Some(dir) = env::var_os("XDG_CACHE_HOME") {
+        PathBuf::from(dir)
+    } else {
+        Path::new(&env::var_os("HOME")?).join(".cache")
+    };
+    Some(cache.join("beet-up").join("transcode"))
+}
+
+/// Create `dir`, readable only by the user where permissions allow.
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+/// A file to serve in place of an item's own.
+pub struct Transcoded {
+    pub path: PathBuf,
+    pub mime_type: &'static str,
+}
+
+/// Pipes items through an external command into other formats, keeping the
+/// results in a cache directory for later requests.
+///
+/// The command is a template split on whitespace and run without a shell,
+/// with `{input}`, `{output}`, `{format}` and `{bitrate}` (in kbps) filled in
+/// within each argument, e.g.
+/// `ffmpeg -v error -i {input} -vn -b:a {bitrate}k {output}`.
+///
+/// The least recently used files are removed once the cache holds more than
+/// its size in bytes.
+#[derive(Debug)]
+pub struct Transcoder {
+    command: Vec<String>,
+    cache_dir: PathBuf,
+    cache_size: u64,
+}
+
+impl Transcoder {
+    /// `None` if `command` is blank.
+    pub fn new(command: &str, cache_dir: PathBuf, cache_size: u64) -> Option<Self> {
+        let command: Vec<String> = command.split_whitespace().map(String::from).collect();
+        if command.is_empty() {
+            None
+        } else {
+            Some(Self {
+                command,
+                cache_dir,
+                cache_size,
+            })
+        }
+    }
+
+    /// Whether `item` needs transcoding to be sent as `format` at up to
+    /// `bitrate` kbps, which it does not when it is already both.
+    pub fn needed(item: &Item, format: &str, bitrate: u32) -> bool {
+        let same_format = FORMATS
+            .iter()
+            .any(|&(name, _, mime_type)| name == format && mime_type == item.mime_type());
+        !same_format || item.bitrate > bitrate.saturating_mul(1000)
+    }
+
+    /// The file to serve for `item` as `format` at `bitrate` kbps (rounded to
+    /// one of [`BITRATES`]), running the command unless an earlier request
+    /// did already. This blocks until the command finishes.
+    ///
+    /// # Errors
+    /// Returns an error of kind `InvalidInput` for a format not in
+    /// [`FORMATS`], or any error from running the command.
+    pub fn transcode(&self, item: &Item, format: &str, bitrate: u32) -> io::Result<Transcoded> {
+        let bitrate = round_bitrate(bitrate);
+        let &(_, extension, mime_type) = FORMATS
+            .iter()
+            .find(|(name, ..)| *name == format)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unsupported format"))?;
+
+        // a moved or retagged file gets a new entry
+        let mut hasher = DefaultHasher::new();
+        (&item.path, item.mtime.to_bits()).hash(&mut hasher);
+        let name = format!("{}-{:016x}-{bitrate}k", item.id, hasher.finish());
+        let path = self.cache_dir.join(format!("{name}.{extension}"));
+        if let Ok(file) = fs::File::options().append(true).open(&path) {
+            // keeps it from being the next to go
+            let _ = file.set_modified(SystemTime::now());
+            return Ok(Transcoded { path, mime_type });
+        }
+
+        create_private_dir(&self.cache_dir)?;
+        // keeps the extension, which transcoders choose the container by
+        let part = self.cache_dir.join(format!(
+            "{}.part{}.{}",
+            name,
+            PARTS.fetch_add(1, Ordering::Relaxed),
+            extension
+        ));
+        let input = item.path.to_string_lossy();
+        let output = part.to_string_lossy();
+        let bitrate = bitrate.to_string();
+        let values = [
+            ("{input}", &*input),
+            ("{output}", &*output),
+            ("{format}", format),
+            ("{bitrate}", &*bitrate),
+        ];
+        let args: Vec<String> = self.command.iter().map(|arg| fill(arg, &values)).collect();
+        let status = Command::new(&args[0])
+            .args(&args[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .status();
+        match status {
+            Ok(status) if status.success() => {
+                fs::rename(&part, &path)?;
+                if let Err(err) = self.prune(&path) {
+                    log::warn!("could not prune the transcode cache: {err}");
+                }
+                Ok(Transcoded { path, mime_type })
+            }
+            Ok(status) => {
+                let _ = fs::remove_file(&part);
+                Err(io::Error::other(format!("transcoder {status}")))
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&part);
+                Err(err)
+            }
+        }
+    }
+
+    /// Remove the least recently used files other than `keep` until the
+    /// cache fits in its size. Files still being written are left alone.
+    fn prune(&self, keep: &Path) -> io::Result<()> {
+        let mut files = Vec::new();
+        let mut total = 0;
+        for entry in fs::read_dir(&self.cache_dir)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            if !meta.is_file() {
+                continue;
+            }
+            total += meta.len();
+            let path = entry.path();
+            let partial = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().contains(".part"));
+            if !partial && path != keep {
+                files.push((meta.modified()?, meta.len(), path));
+            }
+        }
+        files.sort();
+        for (_, len, path) in files {
+            if total <= self.cache_size {
+                break;
+            }
+            fs::remove_file(path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_placeholders() {
+        let values = [("{input}", "a {output}.flac"), ("{output}", "b.mp3")];
+        assert_eq!(fill("{input}", &values), "a {output}.flac");
+        assert_eq!(fill("-o{output}!", &values), "-ob.mp3!");
+        assert_eq!(fill("{input}{output}", &values), "a {output}.flacb.mp3");
+        assert_eq!(fill("{other} {", &values), "{other} {");
+        assert_eq!(fill("", &values), "");
+    }
+
+    #[test]
+    fn transcode_only_when_needed() {
+        let mut item = Item {
+            format: "MP3".to_string(),
+            bitrate: 128_000,
+            ..Item::default()
+        };
+        assert!(!Transcoder::needed(&item, "mp3", 192));
+        assert!(!Transcoder::needed(&item, "mp3", 128));
+        assert!(Transcoder::needed(&item, "mp3", 96));
+        assert!(Transcoder::needed(&item, "opus", 192));
+
+        item.format = "FLAC".to_string();
+        item.bitrate = 900_000;
+        assert!(Transcoder::needed(&item, "mp3", 320));
+    }
+
+    #[test]
+    fn round_bitrates() {
+        assert_eq!(round_bitrate(0), 64);
+        assert_eq!(round_bitrate(100), 96);
+        assert_eq!(round_bitrate(192), 192);
+        assert_eq!(round_bitrate(u32::MAX), 320);
+    }
+}